@@ -1,5 +1,5 @@
 use clap::{Parser, ValueEnum};
-use std::{fmt::Display, path::PathBuf};
+use std::{fmt::Display, net::SocketAddr, path::PathBuf};
 
 #[derive(ValueEnum, Clone)]
 pub enum BehaviorMode {
@@ -7,6 +7,12 @@ pub enum BehaviorMode {
   Dynamic,
   #[clap(name = "idle")]
   Idle,
+  #[clap(name = "scenario")]
+  Scenario,
+  #[clap(name = "replay")]
+  Replay,
+  #[clap(name = "load-test")]
+  LoadTest,
 }
 
 impl Display for BehaviorMode {
@@ -14,6 +20,9 @@ impl Display for BehaviorMode {
     f.write_str(match self {
       Self::Idle => "Idle",
       Self::Dynamic => "Dynamic",
+      Self::Scenario => "Scenario",
+      Self::Replay => "Replay",
+      Self::LoadTest => "LoadTest",
     })
   }
 }
@@ -23,6 +32,11 @@ impl BehaviorMode {
     match self {
       Self::Idle => "Mode Idle waits on commands from a CSMS",
       Self::Dynamic => "Mode Dynamic sends messages immediately after initialization",
+      Self::Scenario => "Mode Scenario runs the steps of a scenario file and exits",
+      Self::Replay => "Mode Replay sends the charge point side of a recording again and exits",
+      Self::LoadTest => {
+        "Mode LoadTest ramps dynamic charge points up and down and reports the answer times"
+      }
     }
   }
 }
@@ -34,4 +48,41 @@ pub struct Args {
   pub mode: BehaviorMode,
   #[arg(long)]
   pub config_path: PathBuf,
+  /// Scenario file (TOML or YAML) run in scenario mode
+  #[arg(long, required_if_eq("mode", "scenario"))]
+  pub scenario_path: Option<PathBuf>,
+  /// JUnit XML report of the scenario checks
+  #[arg(long)]
+  pub junit_path: Option<PathBuf>,
+  /// Address of the HTTP control API, e.g. 127.0.0.1:8080, disabled when omitted
+  #[arg(long)]
+  pub control_addr: Option<SocketAddr>,
+  /// Address of the Prometheus `/metrics` endpoint, e.g. 127.0.0.1:9100, disabled when omitted
+  #[arg(long)]
+  pub metrics_addr: Option<SocketAddr>,
+  /// Serves the charge points with the built-in mock CSMS at the server URL of the config
+  #[arg(long)]
+  pub mock_csms: bool,
+  /// Terminal dashboard of the charge points (idle and scenario mode)
+  #[arg(long)]
+  pub tui: bool,
+  /// Writes the log to the file instead of the terminal
+  #[arg(long)]
+  pub log_path: Option<PathBuf>,
+  /// Records every frame sent and received to the JSONL file
+  #[arg(long)]
+  pub record_path: Option<PathBuf>,
+  /// Recording (JSONL) replayed in replay mode
+  #[arg(long, required_if_eq("mode", "replay"))]
+  pub replay_path: Option<PathBuf>,
+  /// Factor the recorded timing is sped up by, e.g. 10 replays ten times faster
+  #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+  pub replay_speed: f64,
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+  match speed.parse::<f64>() {
+    Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+    _ => Err(format!("{speed} is not a positive factor")),
+  }
 }
@@ -0,0 +1,192 @@
+//! HTTP/JSON API listing the running charge points and sending them commands
+
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+  Json, Router,
+  extract::{Path, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  routing::{get, post},
+};
+use cp::control::{ControlCommand, ControlError, ControlRegistry};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+/// Command for several charge points, all of them when none are listed
+#[derive(Deserialize)]
+struct BulkCommand {
+  #[serde(default)]
+  charge_points: Vec<String>,
+  command: ControlCommand,
+}
+
+#[derive(Serialize)]
+struct BulkOutcome {
+  accepted: Vec<String>,
+  rejected: BTreeMap<String, String>,
+}
+
+/// Binds the address and serves the API until the simulator stops
+pub async fn spawn(addr: SocketAddr, registry: Arc<ControlRegistry>) -> Result<()> {
+  let listener = TcpListener::bind(addr).await?;
+  let router = router(registry);
+
+  info!("Control API listening on http://{addr}");
+  tokio::spawn(async move {
+    if let Err(e) = axum::serve(listener, router).await {
+      error!("Control API failed: {:?}", e);
+    }
+  });
+
+  Ok(())
+}
+
+fn router(registry: Arc<ControlRegistry>) -> Router {
+  Router::new()
+    .route("/charge-points", get(list))
+    .route("/charge-points/{id}", get(show))
+    .route("/charge-points/{id}/commands", post(command))
+    .route("/commands", post(bulk_command))
+    .with_state(registry)
+}
+
+async fn list(State(registry): State<Arc<ControlRegistry>>) -> Response {
+  Json(registry.states()).into_response()
+}
+
+async fn show(State(registry): State<Arc<ControlRegistry>>, Path(id): Path<String>) -> Response {
+  match registry.state(&id) {
+    Some(state) => Json(state).into_response(),
+    None => rejection(ControlError::UnknownChargePoint),
+  }
+}
+
+async fn command(
+  State(registry): State<Arc<ControlRegistry>>,
+  Path(id): Path<String>,
+  Json(command): Json<ControlCommand>,
+) -> Response {
+  match registry.send(&id, command) {
+    Ok(()) => StatusCode::ACCEPTED.into_response(),
+    Err(e) => rejection(e),
+  }
+}
+
+async fn bulk_command(
+  State(registry): State<Arc<ControlRegistry>>,
+  Json(bulk): Json<BulkCommand>,
+) -> Response {
+  let ids = match bulk.charge_points.is_empty() {
+    true => registry.ids(),
+    false => bulk.charge_points,
+  };
+
+  let mut outcome = BulkOutcome {
+    accepted: Vec::new(),
+    rejected: BTreeMap::new(),
+  };
+  for id in ids {
+    match registry.send(&id, bulk.command.clone()) {
+      Ok(()) => outcome.accepted.push(id),
+      Err(e) => {
+        outcome.rejected.insert(id, e.to_string());
+      }
+    }
+  }
+
+  (StatusCode::ACCEPTED, Json(outcome)).into_response()
+}
+
+fn rejection(error: ControlError) -> Response {
+  let status = match error {
+    ControlError::UnknownChargePoint => StatusCode::NOT_FOUND,
+    ControlError::Stopped => StatusCode::CONFLICT,
+    ControlError::ScenarioOnly => StatusCode::UNPROCESSABLE_ENTITY,
+  };
+
+  (status, Json(json!({ "error": error.to_string() }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+  use cp::logs::{Logs, MessageTaps};
+  use serde_json::Value;
+
+  use super::*;
+
+  /// Serves the API for the registry on a free port, returns its url
+  async fn serve(registry: Arc<ControlRegistry>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router(registry)).await });
+    url
+  }
+
+  async fn post(url: &str, body: Value) -> (StatusCode, String) {
+    let response = reqwest::Client::new()
+      .post(url)
+      .header("Content-Type", "application/json")
+      .body(body.to_string())
+      .send()
+      .await
+      .unwrap();
+    (
+      StatusCode::from_u16(response.status().as_u16()).unwrap(),
+      response.text().await.unwrap(),
+    )
+  }
+
+  #[tokio::test]
+  async fn commands_are_accepted_for_running_charge_points() {
+    let registry = Arc::new(ControlRegistry::default());
+    let logs = Logs::new("CP1", 0, MessageTaps::default());
+    let _cp1 = registry.register("CP1", logs.messages().clone());
+    drop(registry.register("CP2", logs.messages().clone()));
+    let url = serve(Arc::clone(&registry)).await;
+
+    let states: Value = reqwest::get(format!("{url}/charge-points"))
+      .await
+      .unwrap()
+      .json()
+      .await
+      .unwrap();
+    assert_eq!(states[0]["id"], "CP1");
+    assert_eq!(states[0]["running"], true);
+    assert_eq!(states[1]["running"], false);
+
+    let plug_in = json!({ "action": "plug_in" });
+    let (status, _) = post(
+      &format!("{url}/charge-points/CP1/commands"),
+      plug_in.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, body) = post(
+      &format!("{url}/charge-points/CP2/commands"),
+      plug_in.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body, r#"{"error":"charge point stopped"}"#);
+    let (status, _) = post(
+      &format!("{url}/charge-points/CP3/commands"),
+      plug_in.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let wait = json!({ "action": "expect_call", "call": "Reset", "timeout": 10 });
+    let (status, _) = post(&format!("{url}/charge-points/CP1/commands"), wait).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, body) = post(&format!("{url}/commands"), json!({ "command": plug_in })).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(
+      serde_json::from_str::<Value>(&body).unwrap(),
+      json!({ "accepted": ["CP1"], "rejected": { "CP2": "charge point stopped" } })
+    );
+  }
+}
@@ -1,30 +1,73 @@
 pub mod cli;
+pub mod control_api;
+pub mod metrics_api;
 pub mod simulator;
+pub mod tui;
+
+use std::{fs::File, path::PathBuf, sync::Mutex};
 
 use anyhow::Result;
 use clap::Parser;
-use common::Config;
+use common::{Config, ScenarioFile};
+use cp::recording::{self, Recorder};
 use simulator::Simulator;
 use tracing::Level;
 
 use crate::cli::Args;
 
-fn init_tracing(debug_mode: bool) {
+const TUI_LOG_PATH: &str = "ocpp-charge-point-simulator.log";
+
+fn init_tracing(debug_mode: bool, log_path: Option<PathBuf>) -> Result<()> {
   let level = if debug_mode {
     Level::DEBUG
   } else {
     Level::INFO
   };
-  tracing_subscriber::fmt()
+  let subscriber = tracing_subscriber::fmt()
     .with_max_level(level)
-    .with_target(true)
-    .init();
+    .with_target(true);
+
+  match log_path {
+    Some(log_path) => {
+      colored::control::set_override(false);
+      subscriber
+        .with_ansi(false)
+        .with_writer(Mutex::new(File::create(log_path)?))
+        .init();
+    }
+    None => subscriber.init(),
+  }
+  Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
   let args = Args::parse();
   let config = Config::try_load(args.config_path)?;
-  init_tracing(config.general.debug_mode);
-  Simulator::new(args.mode, config).run().await
+  let scenario = args.scenario_path.map(ScenarioFile::try_load).transpose()?;
+  // The dashboard takes over the terminal, the log goes to a file instead
+  let log_path = match args.tui {
+    true => Some(args.log_path.unwrap_or(PathBuf::from(TUI_LOG_PATH))),
+    false => args.log_path,
+  };
+  init_tracing(config.general.debug_mode, log_path)?;
+  let recording = args
+    .replay_path
+    .as_deref()
+    .map(recording::load)
+    .transpose()?;
+  let recorder = args
+    .record_path
+    .as_deref()
+    .map(Recorder::create)
+    .transpose()?;
+  Simulator::new(args.mode, config, scenario, args.junit_path)
+    .with_control_api(args.control_addr)
+    .with_metrics_api(args.metrics_addr)
+    .with_tui(args.tui)
+    .with_mock_csms(args.mock_csms)
+    .with_replay(recording, args.replay_speed)
+    .with_recorder(recorder)
+    .run()
+    .await
 }
@@ -0,0 +1,33 @@
+//! Prometheus `/metrics` endpoint of the charge point metrics
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{Router, extract::State, http::header, response::IntoResponse, routing::get};
+use cp::metrics::Metrics;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+/// Binds the address and serves the metrics until the simulator stops
+pub async fn spawn(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+  let listener = TcpListener::bind(addr).await?;
+  let router = Router::new()
+    .route("/metrics", get(render))
+    .with_state(metrics);
+
+  info!("Metrics served on http://{addr}/metrics");
+  tokio::spawn(async move {
+    if let Err(e) = axum::serve(listener, router).await {
+      error!("Metrics endpoint failed: {:?}", e);
+    }
+  });
+
+  Ok(())
+}
+
+async fn render(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+  (
+    [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+    metrics.render(),
+  )
+}
@@ -1,24 +1,125 @@
-use std::sync::Arc;
+use std::{
+  collections::{BTreeMap, HashMap},
+  net::SocketAddr,
+  path::PathBuf,
+  sync::Arc,
+};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use futures_util::future::join_all;
-use tokio::task::JoinHandle;
-use tracing::{error, info};
+use tokio::{
+  select,
+  sync::oneshot,
+  task::JoinHandle,
+  time::{Duration, Instant, sleep, sleep_until},
+};
+use tracing::{error, info, warn};
 
 use colored::Colorize;
-use common::{ChargePointConfig, Config, ImplicitChargePointConfig};
-use cp::{dynamic::ChargePointDynamic, idle::ChargePointIdle};
+use common::{ChargePointConfig, Config, ImplicitChargePointConfig, ScenarioFile, log_budget};
+use cp::{
+  control::ControlRegistry,
+  dynamic::ChargePointDynamic,
+  id_tags::{AuthorizationStats, IdTagPool, IdTags},
+  idle::ChargePointIdle,
+  load_test::{start_offset, stop_offset},
+  logs::MessageTaps,
+  metrics::Metrics,
+  recording::{RecordedFrame, Recorder},
+  replay::ChargePointReplay,
+  scenario::{Scenario, ScenarioReport},
+  timers::TimerWheel,
+};
+use csms::MockCsms;
 
-use crate::cli::BehaviorMode;
+use crate::{cli::BehaviorMode, control_api, metrics_api, tui};
 
 pub struct Simulator {
   mode: BehaviorMode,
   config: Config,
+  scenario: Option<ScenarioFile>,
+  scenario_report: Option<Arc<ScenarioReport>>,
+  junit_path: Option<PathBuf>,
+  control_addr: Option<SocketAddr>,
+  control: Option<Arc<ControlRegistry>>,
+  tui: bool,
+  /// Recorded frames per charge point, replayed in replay mode
+  replay: Option<BTreeMap<String, Vec<RecordedFrame>>>,
+  replay_speed: f64,
+  metrics_addr: Option<SocketAddr>,
+  /// Recording and metrics the charge points pass their messages to
+  taps: MessageTaps,
+  /// Periodic timers of all charge points, turning on the runtime the simulator was created on
+  timers: Arc<TimerWheel>,
+  /// Serves the charge points at the server URL with the mock CSMS
+  mock_csms: bool,
 }
 
 impl Simulator {
-  pub fn new(mode: BehaviorMode, config: Config) -> Self {
-    Self { mode, config }
+  pub fn new(
+    mode: BehaviorMode,
+    config: Config,
+    scenario: Option<ScenarioFile>,
+    junit_path: Option<PathBuf>,
+  ) -> Self {
+    let scenario_report = match (&mode, &scenario) {
+      (BehaviorMode::Scenario, Some(scenario)) => {
+        Some(Arc::new(ScenarioReport::new(&scenario.name)))
+      }
+      _ => None,
+    };
+
+    Self {
+      mode,
+      config,
+      scenario,
+      scenario_report,
+      junit_path,
+      control_addr: None,
+      control: None,
+      tui: false,
+      replay: None,
+      replay_speed: 1.0,
+      metrics_addr: None,
+      taps: MessageTaps::default(),
+      timers: TimerWheel::spawn(),
+      mock_csms: false,
+    }
+  }
+
+  pub fn with_replay(
+    mut self,
+    recording: Option<BTreeMap<String, Vec<RecordedFrame>>>,
+    speed: f64,
+  ) -> Self {
+    self.replay = recording;
+    self.replay_speed = speed;
+    self
+  }
+
+  pub fn with_recorder(mut self, recorder: Option<Recorder>) -> Self {
+    self.taps.recorder = recorder.map(Arc::new);
+    self
+  }
+
+  pub fn with_metrics_api(mut self, addr: Option<SocketAddr>) -> Self {
+    self.metrics_addr = addr;
+    self
+  }
+
+  pub fn with_tui(mut self, tui: bool) -> Self {
+    self.tui = tui;
+    self
+  }
+
+  pub fn with_mock_csms(mut self, mock_csms: bool) -> Self {
+    self.mock_csms = mock_csms;
+    self
+  }
+
+  pub fn with_control_api(mut self, addr: Option<SocketAddr>) -> Self {
+    self.control_addr = addr;
+    self
   }
 
   pub async fn run(&mut self) -> Result<()> {
@@ -32,6 +133,18 @@ impl Simulator {
       self.mode.description()
     );
     info!("simulator running...");
+    log_budget::set_frame_log_rate(self.config.general.frame_log_rate);
+
+    let mock_csms = match self.mock_csms {
+      true => Some(
+        MockCsms::start_at_url(
+          &self.config.general.server_url,
+          self.config.mock_csms.clone().unwrap_or_default(),
+        )
+        .await?,
+      ),
+      false => None,
+    };
 
     if let Some(implicit_cps) = &self.config.implicit_charge_points {
       self
@@ -40,18 +153,147 @@ impl Simulator {
         .extend(Self::generate_implicit_cps(implicit_cps));
     }
 
+    // Only the charge points of the scenario run in scenario mode
+    if let (BehaviorMode::Scenario, Some(scenario)) = (&self.mode, &self.scenario)
+      && !scenario.charge_points.is_empty()
+    {
+      for id in &scenario.charge_points {
+        if !self
+          .config
+          .charge_points
+          .iter()
+          .any(|cp_config| &cp_config.id == id)
+        {
+          bail!("Scenario {}: unknown charge point {}", scenario.name, id);
+        }
+      }
+      self
+        .config
+        .charge_points
+        .retain(|cp_config| scenario.charge_points.contains(&cp_config.id));
+    }
+
+    // Only the recorded charge points run in replay mode
+    if let (BehaviorMode::Replay, Some(replay)) = (&self.mode, &self.replay) {
+      for id in replay.keys() {
+        if !self
+          .config
+          .charge_points
+          .iter()
+          .any(|cp_config| &cp_config.id == id)
+        {
+          bail!("Recorded charge point {id} is not configured");
+        }
+      }
+      self
+        .config
+        .charge_points
+        .retain(|cp_config| replay.contains_key(&cp_config.id));
+    }
+
+    let controllable = matches!(self.mode, BehaviorMode::Idle | BehaviorMode::Scenario);
+    if self.tui && !controllable {
+      bail!("The dashboard shows charge points in idle and scenario mode only");
+    }
+    if self.tui || self.control_addr.is_some() {
+      if !controllable {
+        warn!("The control API drives charge points in idle and scenario mode only");
+      } else {
+        self.control = Some(Arc::new(ControlRegistry::default()));
+      }
+    }
+    if let (Some(addr), Some(registry)) = (self.control_addr, &self.control) {
+      control_api::spawn(addr, Arc::clone(registry)).await?;
+    }
+    // The load test report is taken from the metrics
+    let metrics = match (&self.mode, &self.config.load_test) {
+      (BehaviorMode::LoadTest, Some(load_test)) => Some(Metrics::with_percentiles(
+        Duration::from_secs(load_test.response_timeout),
+      )),
+      (BehaviorMode::LoadTest, None) => {
+        bail!("Load-test mode needs a [load_test] section in the configuration")
+      }
+      _ => self.metrics_addr.map(|_| Metrics::default()),
+    };
+    self.taps.metrics = metrics.map(Arc::new);
+    if let (Some(addr), Some(metrics)) = (self.metrics_addr, &self.taps.metrics) {
+      metrics_api::spawn(addr, Arc::clone(metrics)).await?;
+    }
+
     let general_config = Arc::new(self.config.general.clone());
 
-    let handles = self
+    let id_tag_pools = self
       .config
-      .charge_points
+      .id_tag_pools
       .iter()
-      .map(|cp_config| self.spawn_cp(Arc::clone(&general_config), cp_config.clone()))
-      .collect::<Vec<JoinHandle<()>>>();
+      .map(|pool| Ok((pool.name.clone(), Arc::new(IdTagPool::from_config(pool)?))))
+      .collect::<Result<HashMap<String, Arc<IdTagPool>>>>()?;
+    let authorization_stats = Arc::new(AuthorizationStats::default());
+    let id_tags = |cp_config: &ChargePointConfig| {
+      let id_tag_pool = cp_config
+        .id_tag_pool
+        .as_ref()
+        .and_then(|name| id_tag_pools.get(name).cloned());
+      IdTags::new(id_tag_pool, Arc::clone(&authorization_stats))
+    };
 
-    for res in join_all(handles).await {
-      if let Err(err) = res {
-        error!("Charge point task error: {:?}", err);
+    let handles = match &self.mode {
+      BehaviorMode::LoadTest => Vec::new(),
+      _ => self
+        .config
+        .charge_points
+        .iter()
+        .map(|cp_config| {
+          self.spawn_cp(
+            Arc::clone(&general_config),
+            cp_config.clone(),
+            id_tags(cp_config),
+          )
+        })
+        .collect::<Result<Vec<JoinHandle<()>>>>()?,
+    };
+
+    // The load test runs until its ramp-down ended, the dashboard until it is closed and the
+    // charge points are dropped with it
+    match (&self.mode, self.tui, &self.control) {
+      (BehaviorMode::LoadTest, _, _) => self.run_load_test(general_config, id_tags).await,
+      (_, true, Some(registry)) => {
+        let registry = Arc::clone(registry);
+        tokio::task::spawn_blocking(move || tui::run(registry)).await??;
+      }
+      _ => {
+        for res in join_all(handles).await {
+          if let Err(err) = res {
+            error!("Charge point task error: {:?}", err);
+          }
+        }
+      }
+    }
+
+    authorization_stats.log_summary();
+    if let Some(mock_csms) = &mock_csms {
+      mock_csms.log_summary();
+    }
+
+    if let (BehaviorMode::LoadTest, Some(load_test), Some(metrics)) =
+      (&self.mode, &self.config.load_test, &self.taps.metrics)
+    {
+      let report = metrics.load_report();
+      report.log_summary();
+      if let Some(report_path) = &load_test.report_path {
+        report.write_json(report_path)?;
+      }
+    }
+
+    if let Some(report) = &self.scenario_report {
+      report.log_summary();
+      if let Some(junit_path) = &self.junit_path {
+        report.write_junit(junit_path)?;
+      }
+
+      let failures = report.failures();
+      if failures > 0 {
+        bail!("Scenario checks failed: {failures}");
       }
     }
 
@@ -62,21 +304,160 @@ impl Simulator {
     &self,
     general_config: Arc<common::GeneralConfig>,
     cp_config: ChargePointConfig,
-  ) -> JoinHandle<()> {
-    match self.mode {
-      BehaviorMode::Idle => tokio::spawn(async move {
-        if let Err(e) = ChargePointIdle::new(general_config, cp_config).run().await {
-          error!("Charge point [{}] failed: {:?}", BehaviorMode::Idle, e);
-        }
-      }),
-      BehaviorMode::Dynamic => tokio::spawn(async move {
-        if let Err(e) = ChargePointDynamic::new(general_config, cp_config)
-          .run()
-          .await
-        {
-          error!("Charge point [{}] failed: {:?}", BehaviorMode::Dynamic, e);
+    id_tags: IdTags,
+  ) -> Result<JoinHandle<()>> {
+    Ok(match self.mode {
+      BehaviorMode::Idle => {
+        let mut charge_point = self.idle_cp(general_config, cp_config, id_tags);
+
+        tokio::spawn(async move {
+          if let Err(e) = charge_point.run().await {
+            error!("Charge point [{}] failed: {:?}", BehaviorMode::Idle, e);
+          }
+        })
+      }
+      BehaviorMode::Dynamic => {
+        let mut charge_point = ChargePointDynamic::new(general_config, cp_config, id_tags)
+          .with_taps(self.taps.clone())
+          .with_timers(Arc::clone(&self.timers));
+
+        tokio::spawn(async move {
+          if let Err(e) = charge_point.run().await {
+            error!("Charge point [{}] failed: {:?}", BehaviorMode::Dynamic, e);
+          }
+        })
+      }
+      BehaviorMode::Scenario => {
+        let scenario = match (&self.scenario, &self.scenario_report) {
+          (Some(scenario), Some(report)) => {
+            Scenario::new(scenario, &cp_config.id, Arc::clone(report))?
+          }
+          _ => bail!("Scenario mode needs a scenario file"),
+        };
+
+        let mut charge_point = self
+          .idle_cp(general_config, cp_config, id_tags)
+          .with_scenario(scenario);
+
+        tokio::spawn(async move {
+          if let Err(e) = charge_point.run().await {
+            error!("Charge point [{}] failed: {:?}", BehaviorMode::Scenario, e);
+          }
+        })
+      }
+      BehaviorMode::LoadTest => bail!("Load-test charge points are started by the ramp-up"),
+      BehaviorMode::Replay => {
+        let Some(frames) = self
+          .replay
+          .as_ref()
+          .and_then(|replay| replay.get(&cp_config.id))
+        else {
+          bail!("Replay mode needs a recording");
+        };
+
+        let mut charge_point =
+          ChargePointReplay::new(general_config, cp_config, frames.clone(), self.replay_speed)
+            .with_taps(self.taps.clone());
+
+        tokio::spawn(async move {
+          if let Err(e) = charge_point.run().await {
+            error!("Charge point [{}] failed: {:?}", BehaviorMode::Replay, e);
+          }
+        })
+      }
+    })
+  }
+
+  /// Starts the dynamic charge points along the ramp-up, keeps them running for the steady
+  /// state and stops them along the ramp-down, or all at once on Ctrl-C
+  async fn run_load_test(
+    &self,
+    general_config: Arc<common::GeneralConfig>,
+    id_tags: impl Fn(&ChargePointConfig) -> IdTags,
+  ) {
+    let Some(load_test) = &self.config.load_test else {
+      return;
+    };
+    let count = self.config.charge_points.len();
+    info!(
+      "load test of {} charge points, {:?} ramp-up at {}/s",
+      count.to_string().cyan(),
+      load_test.ramp_up_profile,
+      load_test.ramp_up_rate
+    );
+
+    let start = Instant::now();
+    let mut running = Vec::new();
+    let ramp_up = async {
+      for (index, cp_config) in self.config.charge_points.iter().enumerate() {
+        sleep_until(start + start_offset(load_test, index)).await;
+
+        let (stop, stop_rx) = oneshot::channel();
+        let mut charge_point = ChargePointDynamic::new(
+          general_config.clone(),
+          cp_config.clone(),
+          id_tags(cp_config),
+        )
+        .with_taps(self.taps.clone())
+        .with_timers(Arc::clone(&self.timers))
+        .with_stop(stop_rx);
+        let handle = tokio::spawn(async move {
+          if let Err(e) = charge_point.run().await {
+            error!("Charge point [{}] failed: {:?}", BehaviorMode::LoadTest, e);
+          }
+        });
+        running.push((handle, stop));
+      }
+
+      info!(
+        "all charge points started after {:.1}s, steady state for {}s",
+        start.elapsed().as_secs_f64(),
+        load_test.steady_duration
+      );
+      sleep(Duration::from_secs(load_test.steady_duration)).await;
+    };
+
+    let mut interrupted = false;
+    select! {
+      _ = ramp_up => {},
+      _ = tokio::signal::ctrl_c() => interrupted = true,
+    }
+
+    info!("ramping down {} charge points", running.len());
+    let ramp_down = Instant::now();
+    let started = running.len();
+    let mut handles = Vec::new();
+    for (index, (handle, stop)) in running.into_iter().enumerate().rev() {
+      if !interrupted {
+        select! {
+          _ = sleep_until(ramp_down + stop_offset(load_test, index, started)) => {},
+          _ = tokio::signal::ctrl_c() => interrupted = true,
         }
-      }),
+      }
+      let _ = stop.send(());
+      handles.push(handle);
+    }
+
+    for res in join_all(handles).await {
+      if let Err(err) = res {
+        error!("Charge point task error: {:?}", err);
+      }
+    }
+  }
+
+  /// Idle charge point, taking commands when the control API is enabled
+  fn idle_cp(
+    &self,
+    general_config: Arc<common::GeneralConfig>,
+    cp_config: ChargePointConfig,
+    id_tags: IdTags,
+  ) -> ChargePointIdle {
+    let charge_point = ChargePointIdle::new(general_config, cp_config, id_tags)
+      .with_taps(self.taps.clone())
+      .with_timers(Arc::clone(&self.timers));
+    match &self.control {
+      Some(registry) => charge_point.with_control(Arc::clone(registry)),
+      None => charge_point,
     }
   }
 
@@ -101,6 +482,16 @@ impl Simulator {
         ),
         stop_tx_after: rand::random_range(cfg.stop_tx_after_range[0]..=cfg.stop_tx_after_range[1]),
         id_tag: cfg.id_tag.clone(),
+        id_tag_pool: cfg.id_tag_pool.clone(),
+        signed_meter_values: None,
+        meter: Default::default(),
+        faults: Vec::new(),
+        random_faults: None,
+        security: Default::default(),
+        security_events: Vec::new(),
+        plug_and_charge: None,
+        device_model: None,
+        variables: Vec::new(),
       })
       .collect()
   }
@@ -0,0 +1,332 @@
+//! Terminal dashboard of the running charge points, hotkeys send commands to the selected one
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use common::{ChargePointErrorCode, ScenarioAction};
+use cp::control::{ChargePointState, ControlCommand, ControlRegistry};
+use ratatui::{
+  DefaultTerminal, Frame,
+  crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+  layout::{Constraint, Layout},
+  style::{Color, Modifier, Style},
+  text::Line,
+  widgets::{Block, Cell, Paragraph, Row, Table, TableState},
+};
+
+/// How often the dashboard is redrawn without key presses
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Messages of the selected charge point kept for scrolling
+const LOG_LINES: usize = 1_000;
+const HELP: &str = "↑/↓ select  PgUp/PgDn scroll  p plug in  u unplug  a authorize  s stop  f fault  r recover  x reset  q quit";
+
+struct Dashboard {
+  registry: Arc<ControlRegistry>,
+  table: TableState,
+  /// Lines the log is scrolled up from its latest message
+  scroll: usize,
+  /// Outcome of the last hotkey
+  notice: String,
+}
+
+/// Shows the dashboard until `q` is pressed, blocking on terminal events
+pub fn run(registry: Arc<ControlRegistry>) -> Result<()> {
+  let mut terminal = ratatui::init();
+  let result = Dashboard::new(registry).run(&mut terminal);
+  ratatui::restore();
+  result
+}
+
+impl Dashboard {
+  fn new(registry: Arc<ControlRegistry>) -> Self {
+    Self {
+      registry,
+      table: TableState::default(),
+      scroll: 0,
+      notice: String::new(),
+    }
+  }
+
+  fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+    loop {
+      let states = self.registry.states();
+      // An empty table drops the selection while the charge points are still starting
+      if self.table.selected().is_none() && !states.is_empty() {
+        self.table.select(Some(0));
+      }
+      terminal.draw(|frame| self.draw(frame, &states))?;
+
+      if !event::poll(REFRESH_INTERVAL)? {
+        continue;
+      }
+      let Event::Key(key) = event::read()? else {
+        continue;
+      };
+      if key.kind != KeyEventKind::Press {
+        continue;
+      }
+
+      if !self.on_key(key, &states) {
+        return Ok(());
+      }
+    }
+  }
+
+  /// Handles a key press, `false` once the dashboard is to be closed
+  fn on_key(&mut self, key: KeyEvent, states: &[ChargePointState]) -> bool {
+    let command = match key.code {
+      KeyCode::Char('q') | KeyCode::Esc => return false,
+      KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+      KeyCode::Down | KeyCode::Char('j') => {
+        self.table.select_next();
+        self.scroll = 0;
+        None
+      }
+      KeyCode::Up | KeyCode::Char('k') => {
+        self.table.select_previous();
+        self.scroll = 0;
+        None
+      }
+      KeyCode::PageUp => {
+        self.scroll += 10;
+        None
+      }
+      KeyCode::PageDown => {
+        self.scroll = self.scroll.saturating_sub(10);
+        None
+      }
+      KeyCode::Char('p') => Some(ControlCommand::Step(ScenarioAction::PlugIn)),
+      KeyCode::Char('u') => Some(ControlCommand::Step(ScenarioAction::Unplug)),
+      KeyCode::Char('a') => Some(ControlCommand::Step(ScenarioAction::PresentTag {
+        id_tag: None,
+      })),
+      KeyCode::Char('s') => Some(ControlCommand::Step(ScenarioAction::StopTransaction)),
+      KeyCode::Char('f') => Some(ControlCommand::Step(ScenarioAction::Fault {
+        error_code: ChargePointErrorCode::OtherError,
+        vendor_error_code: None,
+        info: None,
+      })),
+      KeyCode::Char('r') => Some(ControlCommand::Step(ScenarioAction::Recover)),
+      KeyCode::Char('x') => Some(ControlCommand::Reset),
+      _ => None,
+    };
+
+    if let Some(command) = command
+      && let Some(state) = self.selected(states)
+    {
+      self.notice = match self.registry.send(&state.id, command.clone()) {
+        Ok(()) => format!("{}: {:?}", state.id, command),
+        Err(e) => format!("{}: {}", state.id, e),
+      };
+    }
+    true
+  }
+
+  fn selected<'a>(&self, states: &'a [ChargePointState]) -> Option<&'a ChargePointState> {
+    states.get(self.table.selected()?.min(states.len().saturating_sub(1)))
+  }
+
+  fn draw(&mut self, frame: &mut Frame, states: &[ChargePointState]) {
+    let [table_area, log_area, notice_area, help_area] = Layout::vertical([
+      Constraint::Length(states.len().clamp(1, 20) as u16 + 3),
+      Constraint::Min(5),
+      Constraint::Length(1),
+      Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let rows = states.iter().map(|state| {
+      let last_message = self
+        .registry
+        .messages(&state.id)
+        .and_then(|messages| messages.tail(1).pop())
+        .unwrap_or_default();
+
+      Row::new([
+        Cell::from(state.id.clone()),
+        connection(state),
+        Cell::from(state.boot_status.clone().unwrap_or("-".to_string())),
+        status(state),
+        Cell::from(
+          state
+            .transaction_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| if state.transaction { "yes" } else { "-" }.to_string()),
+        ),
+        Cell::from(format!("{:.2}", state.energy_wh / 1000.0)),
+        Cell::from(last_message),
+        Cell::from(state.call_errors.to_string()).style(match state.call_errors {
+          0 => Style::default(),
+          _ => Style::default().fg(Color::Red),
+        }),
+      ])
+    });
+
+    let table = Table::new(
+      rows,
+      [
+        Constraint::Length(14),
+        Constraint::Length(10),
+        Constraint::Length(9),
+        Constraint::Length(24),
+        Constraint::Length(11),
+        Constraint::Length(8),
+        Constraint::Fill(1),
+        Constraint::Length(6),
+      ],
+    )
+    .header(
+      Row::new([
+        "Charge point",
+        "Network",
+        "Boot",
+        "Connector",
+        "Transaction",
+        "kWh",
+        "Last message",
+        "Errors",
+      ])
+      .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .block(Block::bordered().title(" Charge points "));
+    frame.render_stateful_widget(table, table_area, &mut self.table);
+
+    let selected = self.selected(states);
+    let lines = selected
+      .and_then(|state| self.registry.messages(&state.id))
+      .map(|messages| messages.tail(LOG_LINES))
+      .unwrap_or_default();
+    let height = log_area.height.saturating_sub(2) as usize;
+    self.scroll = self.scroll.min(lines.len().saturating_sub(height));
+    let end = lines.len() - self.scroll;
+    let log = Paragraph::new(
+      lines[end.saturating_sub(height)..end]
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<_>>(),
+    )
+    .block(Block::bordered().title(format!(
+      " Messages {} ",
+      selected.map(|state| state.id.as_str()).unwrap_or_default()
+    )));
+    frame.render_widget(log, log_area);
+
+    frame.render_widget(
+      Paragraph::new(self.notice.as_str()).style(Style::default().fg(Color::Cyan)),
+      notice_area,
+    );
+    frame.render_widget(
+      Paragraph::new(HELP).style(Style::default().fg(Color::DarkGray)),
+      help_area,
+    );
+  }
+}
+
+fn connection(state: &ChargePointState) -> Cell<'static> {
+  match (state.running, state.connected) {
+    (false, _) => Cell::from("stopped").style(Style::default().fg(Color::DarkGray)),
+    (true, false) => Cell::from("offline").style(Style::default().fg(Color::Red)),
+    (true, true) => Cell::from("online").style(Style::default().fg(Color::Green)),
+  }
+}
+
+fn status(state: &ChargePointState) -> Cell<'static> {
+  let color = match state.status.as_str() {
+    "Charging" => Color::Green,
+    "Faulted" | "Unavailable" => Color::Red,
+    "Available" => Color::Reset,
+    _ => Color::Yellow,
+  };
+  let status = match &state.error_code {
+    Some(error_code) => format!("{} {}", state.status, error_code),
+    None => state.status.clone(),
+  };
+
+  Cell::from(status).style(Style::default().fg(color))
+}
+
+#[cfg(test)]
+mod tests {
+  use cp::{
+    control::next_command,
+    logs::{Logs, MessageTaps},
+  };
+  use ratatui::{Terminal, backend::TestBackend};
+
+  use super::*;
+
+  fn press(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+  }
+
+  #[tokio::test]
+  async fn hotkeys_send_commands_to_the_selected_charge_point() {
+    let registry = Arc::new(ControlRegistry::default());
+    let logs = Logs::new("CP1", 0, MessageTaps::default());
+    let _cp1 = registry.register("CP1", logs.messages().clone());
+    let mut cp2 = Some(registry.register("CP2", logs.messages().clone()));
+    let mut dashboard = Dashboard::new(Arc::clone(&registry));
+    dashboard.table.select(Some(0));
+    let states = registry.states();
+
+    assert!(dashboard.on_key(press(KeyCode::Down), &states));
+    assert!(dashboard.on_key(press(KeyCode::Char('p')), &states));
+    assert!(matches!(
+      next_command(&mut None, &mut cp2).await,
+      Some(ControlCommand::Step(ScenarioAction::PlugIn))
+    ));
+    assert_eq!(dashboard.notice, "CP2: Step(PlugIn)");
+
+    drop(cp2);
+    assert!(dashboard.on_key(press(KeyCode::Char('x')), &states));
+    assert_eq!(dashboard.notice, "CP2: charge point stopped");
+
+    assert!(!dashboard.on_key(press(KeyCode::Char('q')), &states));
+    assert!(!dashboard.on_key(
+      KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+      &states
+    ));
+  }
+
+  #[test]
+  fn table_shows_the_state_and_the_messages_of_the_selected_charge_point() {
+    let registry = Arc::new(ControlRegistry::default());
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    let _cp1 = registry.register("CP1", logs.messages().clone());
+    logs
+      .messages()
+      .record(true, r#"[2,"1","BootNotification",{}]"#);
+    logs
+      .messages()
+      .record(false, r#"[3,"1",{"status":"Accepted"}]"#);
+    let mut dashboard = Dashboard::new(Arc::clone(&registry));
+    dashboard.table.select(Some(0));
+
+    let mut terminal = Terminal::new(TestBackend::new(160, 16)).unwrap();
+    let states = registry.states();
+    terminal
+      .draw(|frame| dashboard.draw(frame, &states))
+      .unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let lines: Vec<String> = (0..buffer.area.height)
+      .map(|y| {
+        (0..buffer.area.width)
+          .map(|x| buffer[(x, y)].symbol())
+          .collect()
+      })
+      .collect();
+    let row = lines.iter().find(|line| line.contains("CP1")).unwrap();
+    assert!(row.contains("online"));
+    assert!(row.contains("Available"));
+    assert!(row.contains(r#"<- [3,"1",{"status":"Accepted"}]"#));
+    assert!(lines.iter().any(|line| line.contains(" Messages CP1 ")));
+    assert!(
+      lines
+        .iter()
+        .any(|line| line.contains(r#"-> [2,"1","BootNotification",{}]"#))
+    );
+    assert!(lines.last().unwrap().starts_with("↑/↓ select"));
+  }
+}
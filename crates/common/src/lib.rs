@@ -1,5 +1,11 @@
+pub mod log_budget;
 pub mod shared_data;
-use std::{fmt::Display, fs, path::Path};
+use std::{
+  collections::HashMap,
+  fmt::Display,
+  fs,
+  path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
 use serde::Deserialize;
@@ -42,6 +48,83 @@ impl Display for OcppVersion {
   }
 }
 
+/// Authorization outcome a tag is expected to get from the CSMS
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpectedAuthorization {
+  Accepted,
+  Blocked,
+  Expired,
+  Invalid,
+  ConcurrentTx,
+}
+
+/// Generated tags `<prefix><number>`, the number zero padded to `width` digits
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdTagRangeConfig {
+  pub prefix: String,
+  pub start: u64,
+  pub count: u64,
+  #[serde(default)]
+  pub width: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdTagGroupConfig {
+  /// Parent tag the CSMS is expected to return in `IdTagInfo`
+  pub parent_id_tag: Option<String>,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  pub range: Option<IdTagRangeConfig>,
+  /// Relative probability of drawing a tag of this group
+  #[serde(default = "IdTagGroupConfig::default_weight")]
+  pub weight: f64,
+  pub expected: Option<ExpectedAuthorization>,
+}
+
+impl IdTagGroupConfig {
+  fn default_weight() -> f64 {
+    1.0
+  }
+
+  pub fn has_tags(&self) -> bool {
+    !self.tags.is_empty() || self.range.as_ref().is_some_and(|range| range.count > 0)
+  }
+}
+
+/// Named set of tags that sessions draw from
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdTagPoolConfig {
+  pub name: String,
+  pub groups: Vec<IdTagGroupConfig>,
+}
+
+impl IdTagPoolConfig {
+  /// Weights must be finite and not negative, and at least one group with tags must have a
+  /// positive weight
+  pub fn validate_weights(&self) -> Result<()> {
+    if let Some(group) = self
+      .groups
+      .iter()
+      .find(|group| !(group.weight.is_finite() && group.weight >= 0.0))
+    {
+      anyhow::bail!("Id tag pool {}: invalid weight {}", self.name, group.weight);
+    }
+
+    if !self
+      .groups
+      .iter()
+      .any(|group| group.has_tags() && group.weight > 0.0)
+    {
+      anyhow::bail!(
+        "Id tag pool {}: no group with tags has a positive weight",
+        self.name
+      );
+    }
+
+    Ok(())
+  }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ImplicitChargePointConfig {
   pub count: usize,
@@ -52,6 +135,110 @@ pub struct ImplicitChargePointConfig {
   pub start_tx_after_range: [u64; 2],
   pub stop_tx_after_range: [u64; 2],
   pub id_tag: String,
+  pub id_tag_pool: Option<String>,
+}
+
+/// How the charge points of a load test are started
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RampUpProfile {
+  /// One after the other at the ramp-up rate
+  #[default]
+  Linear,
+  /// The charge points of `step_interval` seconds at once, every `step_interval` seconds
+  Stepped,
+}
+
+/// Load test run over the configured charge points in load-test mode
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoadTestConfig {
+  /// Charge points started per second
+  pub ramp_up_rate: f64,
+  #[serde(default)]
+  pub ramp_up_profile: RampUpProfile,
+  /// Seconds between the steps of a stepped ramp-up
+  #[serde(default = "LoadTestConfig::default_step_interval")]
+  pub step_interval: u64,
+  /// Seconds all charge points keep running once the last one started
+  pub steady_duration: u64,
+  /// Seconds the charge points are stopped one by one over, all at once when omitted
+  pub ramp_down: Option<u64>,
+  /// Seconds a call may wait for its answer before it counts as timed out
+  #[serde(default = "LoadTestConfig::default_response_timeout")]
+  pub response_timeout: u64,
+  /// JSON file the end-of-run report is written to
+  pub report_path: Option<PathBuf>,
+}
+
+impl LoadTestConfig {
+  fn default_step_interval() -> u64 {
+    10
+  }
+
+  fn default_response_timeout() -> u64 {
+    30
+  }
+}
+
+/// Mock CSMS for demos and tests, answering every call of the charge points
+#[derive(Debug, Deserialize, Clone)]
+pub struct MockCsmsConfig {
+  /// Heartbeat interval assigned in the `BootNotification` answer, seconds
+  #[serde(default = "MockCsmsConfig::default_heartbeat_interval")]
+  pub heartbeat_interval: u64,
+  /// Answer payloads by action, in place of the accepting defaults
+  #[serde(default)]
+  pub responses: HashMap<String, serde_json::Value>,
+  /// Actions answered with a CallError of the error code
+  #[serde(default)]
+  pub call_errors: HashMap<String, String>,
+  /// Calls sent to the charge points once they are connected
+  #[serde(default)]
+  pub commands: Vec<MockCsmsCommand>,
+  /// Passwords accepted with HTTP Basic auth, other credentials get a 401. Any connection is
+  /// accepted when empty
+  #[serde(default)]
+  pub authorization_keys: Vec<String>,
+}
+
+impl MockCsmsConfig {
+  fn default_heartbeat_interval() -> u64 {
+    60
+  }
+}
+
+impl Default for MockCsmsConfig {
+  fn default() -> Self {
+    Self {
+      heartbeat_interval: Self::default_heartbeat_interval(),
+      responses: HashMap::new(),
+      call_errors: HashMap::new(),
+      commands: Vec::new(),
+      authorization_keys: Vec::new(),
+    }
+  }
+}
+
+/// Call the mock CSMS sends on a schedule
+#[derive(Debug, Deserialize, Clone)]
+pub struct MockCsmsCommand {
+  pub call: String,
+  #[serde(default = "MockCsmsCommand::default_payload")]
+  pub payload: serde_json::Value,
+  /// Seconds after the charge point connected
+  #[serde(default)]
+  pub after: u64,
+  /// Seconds the call is repeated after, sent once when omitted
+  pub every: Option<u64>,
+  /// Charge points the call goes to, all of them when empty
+  #[serde(default)]
+  pub charge_points: Vec<String>,
+}
+
+impl MockCsmsCommand {
+  fn default_payload() -> serde_json::Value {
+    serde_json::Value::Object(Default::default())
+  }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -59,6 +246,307 @@ pub struct GeneralConfig {
   pub debug_mode: bool,
   pub server_url: String,
   pub ocpp_version: OcppVersion,
+  /// Messages each charge point keeps for its log, the oldest are dropped first. Each one
+  /// holds its frame, a few hundred bytes, so the log of 10,000 charge points at the default
+  /// takes about 300 MB. 0 keeps none: `GetLog` archives, the dashboard and the scenario
+  /// checks on answers then have nothing to go by
+  #[serde(default = "GeneralConfig::default_message_log_size")]
+  pub message_log_size: usize,
+  /// Frames logged per second at INFO over all charge points, the rest at DEBUG, 0 for all
+  #[serde(default = "GeneralConfig::default_frame_log_rate")]
+  pub frame_log_rate: u32,
+}
+
+impl GeneralConfig {
+  fn default_message_log_size() -> usize {
+    100
+  }
+
+  fn default_frame_log_rate() -> u32 {
+    100
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum SignedMeterFormat {
+  #[serde(rename = "OCMF")]
+  Ocmf,
+}
+
+impl Display for SignedMeterFormat {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      SignedMeterFormat::Ocmf => "OCMF",
+    })
+  }
+}
+
+/// Signed (German Eichrecht) meter values
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignedMeterConfig {
+  pub format: SignedMeterFormat,
+  /// PEM encoded PKCS#8 secp256r1 key, generated and stored here when the file does not exist
+  pub private_key_path: Option<PathBuf>,
+  /// The public key is exported here (PEM) on start-up so a CSMS verifier can be configured
+  pub public_key_path: Option<PathBuf>,
+  /// Serial number of the simulated meter, defaults to `<charge point id>-meter`
+  pub meter_serial: Option<String>,
+}
+
+/// ISO 15118 Plug & Charge with a simulated EV contract certificate (OCPP 2.0.1)
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlugAndChargeConfig {
+  /// Contract id (eMAID) of the EV, the idToken of its sessions
+  pub emaid: String,
+  /// OCSP responder of the contract certificate
+  #[serde(default = "PlugAndChargeConfig::default_ocsp_responder_url")]
+  pub ocsp_responder_url: String,
+  /// ISO 15118 schema version of the contract certificate requests
+  #[serde(default = "PlugAndChargeConfig::default_schema_version")]
+  pub schema_version: String,
+}
+
+impl PlugAndChargeConfig {
+  fn default_ocsp_responder_url() -> String {
+    "http://localhost/ocsp".to_string()
+  }
+
+  fn default_schema_version() -> String {
+    "urn:iso:15118:2:2013:MsgDef".to_string()
+  }
+}
+
+/// OCPP 1.6 security profiles (Security Extension)
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SecurityConfig {
+  /// 0: `auth_header` as is, 1: Basic auth, 2: Basic auth over TLS, 3: mutual TLS
+  pub profile: u8,
+  /// Basic auth password, the user name is the charge point id (profiles 1 and 2)
+  pub authorization_key: Option<String>,
+  /// CSMS url for profiles 2 and 3, defaults to `server_url` with the `wss` scheme
+  pub tls_server_url: Option<String>,
+  /// PEM CA the CSMS certificate is validated against, the system roots when omitted
+  pub ca_cert_path: Option<PathBuf>,
+  /// PEM client certificate (profile 3)
+  pub client_cert_path: Option<PathBuf>,
+  /// PEM encoded PKCS#8 key of the client certificate (profile 3)
+  pub client_key_path: Option<PathBuf>,
+  /// Directory keeping the installed certificates and the certificate signed by the CSMS
+  /// across runs, kept in memory only when omitted
+  pub certificate_store: Option<PathBuf>,
+  /// Organization (O) of the certificate signing requests, the common name is the charge
+  /// point id
+  pub organization_name: Option<String>,
+  /// PEM root the firmware signing certificates are validated against, in addition to
+  /// the installed `ManufacturerRootCertificate`s
+  pub manufacturer_root_cert_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum CurrentType {
+  #[serde(rename = "AC")]
+  Ac,
+  #[serde(rename = "DC")]
+  Dc,
+}
+
+impl Display for CurrentType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      CurrentType::Ac => "AC",
+      CurrentType::Dc => "DC",
+    })
+  }
+}
+
+/// Electrical profile of the simulated meter
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MeterConfig {
+  pub current_type: CurrentType,
+  /// Number of AC phases (1 or 3), ignored for DC
+  pub phases: u8,
+  /// Nominal voltage in V, phase to neutral for AC and battery voltage for DC
+  pub voltage: f64,
+  /// Maximum current in A (per phase for AC)
+  pub max_current: f64,
+  /// Maximum power in W reported as `Power.Offered`, derived from voltage and current when not set
+  pub max_power: Option<f64>,
+  /// Grid frequency in Hz (AC)
+  pub frequency: f64,
+  /// Temperature of the charge point body in °C when idle
+  pub temperature: f64,
+  /// EV battery capacity in Wh used to simulate `SoC` (DC)
+  pub battery_capacity: f64,
+  /// EV state of charge in % at the start of a transaction (DC)
+  pub initial_soc: f64,
+}
+
+impl Default for MeterConfig {
+  fn default() -> Self {
+    Self {
+      current_type: CurrentType::Ac,
+      phases: 3,
+      voltage: 230.0,
+      max_current: 32.0,
+      max_power: None,
+      frequency: 50.0,
+      temperature: 25.0,
+      battery_capacity: 60_000.0,
+      initial_soc: 20.0,
+    }
+  }
+}
+
+impl MeterConfig {
+  pub fn phases(&self) -> u8 {
+    match self.current_type {
+      CurrentType::Ac => self.phases,
+      CurrentType::Dc => 1,
+    }
+  }
+
+  pub fn max_power(&self) -> f64 {
+    self
+      .max_power
+      .unwrap_or_else(|| self.voltage * self.max_current * self.phases() as f64)
+  }
+}
+
+/// OCPP 1.6 `ChargePointErrorCode` values that can be injected as faults
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChargePointErrorCode {
+  ConnectorLockFailure,
+  EVCommunicationError,
+  GroundFailure,
+  HighTemperature,
+  InternalError,
+  LocalListConflict,
+  OtherError,
+  OverCurrentFailure,
+  OverVoltage,
+  PowerMeterFailure,
+  PowerSwitchFailure,
+  ReaderFailure,
+  ResetFailure,
+  UnderVoltage,
+  WeakSignal,
+}
+
+impl ChargePointErrorCode {
+  pub const ALL: [ChargePointErrorCode; 15] = [
+    Self::ConnectorLockFailure,
+    Self::EVCommunicationError,
+    Self::GroundFailure,
+    Self::HighTemperature,
+    Self::InternalError,
+    Self::LocalListConflict,
+    Self::OtherError,
+    Self::OverCurrentFailure,
+    Self::OverVoltage,
+    Self::PowerMeterFailure,
+    Self::PowerSwitchFailure,
+    Self::ReaderFailure,
+    Self::ResetFailure,
+    Self::UnderVoltage,
+    Self::WeakSignal,
+  ];
+
+  /// Whether the connector can no longer deliver energy, as opposed to a warning
+  /// reported while the connector stays operative
+  pub fn is_fatal(&self) -> bool {
+    !matches!(
+      self,
+      Self::LocalListConflict
+        | Self::OtherError
+        | Self::ReaderFailure
+        | Self::ResetFailure
+        | Self::WeakSignal
+    )
+  }
+}
+
+/// A fault injected at a fixed time after start-up
+#[derive(Debug, Deserialize, Clone)]
+pub struct FaultConfig {
+  pub error_code: ChargePointErrorCode,
+  /// Seconds after start-up
+  pub after: u64,
+  /// Seconds until the charge point recovers, it stays faulted when not set
+  pub recover_after: Option<u64>,
+  pub vendor_error_code: Option<String>,
+  pub info: Option<String>,
+}
+
+/// Faults injected at random
+#[derive(Debug, Deserialize, Clone)]
+pub struct RandomFaultConfig {
+  /// Mean time between faults in seconds (exponentially distributed)
+  pub mean_interval: u64,
+  pub recover_after_range: [u64; 2],
+  /// Error codes to choose from, all of them when empty
+  #[serde(default)]
+  pub error_codes: Vec<ChargePointErrorCode>,
+}
+
+/// A security event reported at a fixed time after start-up
+#[derive(Debug, Deserialize, Clone)]
+pub struct SecurityEventConfig {
+  /// Event type, e.g. `TamperDetectionActivated`, sent as is unless it is known under
+  /// another name in the OCPP version in use
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Seconds after start-up
+  pub after: u64,
+  pub tech_info: Option<String>,
+}
+
+/// Variable of the OCPP 2.0.1 device model, identified by its component and name. Entries
+/// of a `device_model` file define variables, entries of a charge point override them
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct VariableConfig {
+  pub component: String,
+  pub component_instance: Option<String>,
+  pub evse: Option<i32>,
+  pub connector: Option<i32>,
+  pub variable: String,
+  pub variable_instance: Option<String>,
+  /// Values of the `Actual`, `Target`, `MinSet` and `MaxSet` attributes
+  pub actual: Option<String>,
+  pub target: Option<String>,
+  pub min_set: Option<String>,
+  pub max_set: Option<String>,
+  /// `ReadOnly`, `WriteOnly` or `ReadWrite`, for all attributes
+  pub mutability: Option<String>,
+  pub persistent: Option<bool>,
+  pub constant: Option<bool>,
+  /// `string`, `decimal`, `integer`, `dateTime`, `boolean`, `OptionList`, `SequenceList`
+  /// or `MemberList`
+  pub data_type: Option<String>,
+  pub unit: Option<String>,
+  pub min_limit: Option<f64>,
+  pub max_limit: Option<f64>,
+  /// Allowed values of list types, comma separated
+  pub values_list: Option<String>,
+  pub supports_monitoring: Option<bool>,
+}
+
+/// OCPP 2.0.1 device model replacing the built-in one
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeviceModelFile {
+  pub variables: Vec<VariableConfig>,
+}
+
+impl DeviceModelFile {
+  pub fn try_load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+      .with_context(|| format!("Failed to read device model file: {}", path.display()))?;
+    toml::from_str(&content)
+      .with_context(|| format!("Failed to parse device model file: {}", path.display()))
+  }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -75,6 +563,25 @@ pub struct ChargePointConfig {
   pub start_tx_after: u64,
   pub stop_tx_after: u64,
   pub id_tag: String,
+  /// Name of the `id_tag_pools` entry sessions draw their tag from instead of `id_tag`
+  pub id_tag_pool: Option<String>,
+  #[serde(default)]
+  pub signed_meter_values: Option<SignedMeterConfig>,
+  #[serde(default)]
+  pub meter: MeterConfig,
+  #[serde(default)]
+  pub faults: Vec<FaultConfig>,
+  pub random_faults: Option<RandomFaultConfig>,
+  #[serde(default)]
+  pub security: SecurityConfig,
+  #[serde(default)]
+  pub security_events: Vec<SecurityEventConfig>,
+  pub plug_and_charge: Option<PlugAndChargeConfig>,
+  /// OCPP 2.0.1 device model file, the built-in model when omitted
+  pub device_model: Option<PathBuf>,
+  /// Variables of the device model overridden for this charge point
+  #[serde(default)]
+  pub variables: Vec<VariableConfig>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -82,6 +589,10 @@ pub struct Config {
   pub general: GeneralConfig,
   pub charge_points: Vec<ChargePointConfig>,
   pub implicit_charge_points: Option<ImplicitChargePointConfig>,
+  #[serde(default)]
+  pub id_tag_pools: Vec<IdTagPoolConfig>,
+  pub load_test: Option<LoadTestConfig>,
+  pub mock_csms: Option<MockCsmsConfig>,
 }
 
 impl Config {
@@ -89,7 +600,243 @@ impl Config {
     let path = config_path.as_ref();
     let content = fs::read_to_string(path)
       .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-    toml::from_str(&content)
-      .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    let config: Self = toml::from_str(&content)
+      .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  fn validate(&self) -> Result<()> {
+    if let Some(load_test) = &self.load_test
+      && !(load_test.ramp_up_rate > 0.0 && load_test.ramp_up_rate.is_finite())
+    {
+      anyhow::bail!("Load test ramp_up_rate must be positive");
+    }
+
+    for pool in &self.id_tag_pools {
+      if !pool.groups.iter().any(IdTagGroupConfig::has_tags) {
+        anyhow::bail!("Id tag pool {} has no tags", pool.name);
+      }
+      pool.validate_weights()?;
+    }
+
+    let pool_names = self
+      .charge_points
+      .iter()
+      .filter_map(|cp_config| cp_config.id_tag_pool.as_ref())
+      .chain(
+        self
+          .implicit_charge_points
+          .iter()
+          .filter_map(|cfg| cfg.id_tag_pool.as_ref()),
+      );
+
+    for pool_name in pool_names {
+      if !self.id_tag_pools.iter().any(|pool| &pool.name == pool_name) {
+        anyhow::bail!("Unknown id tag pool {}", pool_name);
+      }
+    }
+
+    for cp_config in &self.charge_points {
+      if cp_config.meter.current_type == CurrentType::Ac
+        && ![1, 3].contains(&cp_config.meter.phases)
+      {
+        anyhow::bail!(
+          "Charge point {}: meter phases must be 1 or 3, got {}",
+          cp_config.id,
+          cp_config.meter.phases
+        );
+      }
+
+      if let Some(random_faults) = &cp_config.random_faults {
+        if random_faults.mean_interval == 0 {
+          anyhow::bail!(
+            "Charge point {}: random_faults mean_interval must be positive",
+            cp_config.id
+          );
+        }
+
+        let [min, max] = random_faults.recover_after_range;
+        if min > max {
+          anyhow::bail!(
+            "Charge point {}: random_faults recover_after_range [{}, {}] must not be decreasing",
+            cp_config.id,
+            min,
+            max
+          );
+        }
+      }
+
+      let security = &cp_config.security;
+      match security.profile {
+        0 | 3 => {}
+        1 | 2 if security.authorization_key.is_some() => {}
+        1 | 2 => anyhow::bail!(
+          "Charge point {}: security profile {} needs an authorization_key",
+          cp_config.id,
+          security.profile
+        ),
+        profile => anyhow::bail!(
+          "Charge point {}: unknown security profile {}",
+          cp_config.id,
+          profile
+        ),
+      }
+
+      if security.profile == 3
+        && security.certificate_store.is_none()
+        && (security.client_cert_path.is_none() || security.client_key_path.is_none())
+      {
+        anyhow::bail!(
+          "Charge point {}: security profile 3 needs client_cert_path and client_key_path, or a certificate_store",
+          cp_config.id
+        );
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Timeline of a charge point in scenario mode, loaded from a TOML or YAML file
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScenarioFile {
+  pub name: String,
+  /// Charge points running the scenario, all of them when empty
+  #[serde(default)]
+  pub charge_points: Vec<String>,
+  /// Fails the scenario when the CSMS answers a call with a CallError
+  #[serde(default)]
+  pub no_call_errors: bool,
+  pub steps: Vec<ScenarioStep>,
+}
+
+impl ScenarioFile {
+  /// YAML for `.yaml` and `.yml` files, TOML otherwise
+  pub fn try_load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+      .with_context(|| format!("Failed to read scenario file: {}", path.display()))?;
+    let scenario: Self = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("yaml" | "yml") => serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse scenario file: {}", path.display()))?,
+      _ => toml::from_str(&content)
+        .with_context(|| format!("Failed to parse scenario file: {}", path.display()))?,
+    };
+
+    if scenario.steps.is_empty() {
+      anyhow::bail!("Scenario {} has no steps", scenario.name);
+    }
+    Ok(scenario)
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScenarioStep {
+  /// Seconds to wait once the previous step is done
+  #[serde(default)]
+  pub after: u64,
+  #[serde(flatten)]
+  pub action: ScenarioAction,
+}
+
+/// What a scenario step does, either an action of the EV, the driver or the charge point,
+/// or a wait for the CSMS
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioAction {
+  PlugIn,
+  Unplug,
+  /// The tag of the charge point (or its pool) when none is given
+  PresentTag {
+    id_tag: Option<String>,
+  },
+  /// The driver stops the transaction at the charge point
+  StopTransaction,
+  SuspendEv,
+  ResumeEv,
+  Fault {
+    error_code: ChargePointErrorCode,
+    vendor_error_code: Option<String>,
+    info: Option<String>,
+  },
+  Recover,
+  /// The network is lost for the given seconds
+  Disconnect {
+    duration: u64,
+  },
+  DataTransfer {
+    vendor_id: Option<String>,
+    message_id: Option<String>,
+    data: Option<String>,
+  },
+  /// The charge point records a security event, e.g. `SettingSystemTime`, and reports it
+  SecurityEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    tech_info: Option<String>,
+  },
+  /// Waits until the connector is in the status, e.g. `Charging`
+  WaitForStatus {
+    status: String,
+    timeout: u64,
+  },
+  /// Waits until the CSMS sends the call, e.g. `RemoteStartTransaction`
+  ExpectCall {
+    call: String,
+    timeout: u64,
+  },
+  /// Waits for the answer to a call of the charge point, e.g. `StartTransaction`, and
+  /// checks the value of a field given as a dotted path such as `idTagInfo.status`
+  ExpectResponse {
+    call: String,
+    field: Option<String>,
+    equals: Option<serde_json::Value>,
+    timeout: u64,
+  },
+}
+
+impl Display for ScenarioAction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::PlugIn => write!(f, "plug_in"),
+      Self::Unplug => write!(f, "unplug"),
+      Self::PresentTag { id_tag } => {
+        write!(
+          f,
+          "present_tag {}",
+          id_tag.as_deref().unwrap_or("(default)")
+        )
+      }
+      Self::StopTransaction => write!(f, "stop_transaction"),
+      Self::SuspendEv => write!(f, "suspend_ev"),
+      Self::ResumeEv => write!(f, "resume_ev"),
+      Self::Fault { error_code, .. } => write!(f, "fault {:?}", error_code),
+      Self::Recover => write!(f, "recover"),
+      Self::Disconnect { duration } => write!(f, "disconnect {duration}s"),
+      Self::DataTransfer { message_id, .. } => {
+        write!(f, "data_transfer {}", message_id.as_deref().unwrap_or(""))
+      }
+      Self::SecurityEvent { event_type, .. } => write!(f, "security_event {event_type}"),
+      Self::WaitForStatus { status, timeout } => {
+        write!(f, "wait_for_status {status} within {timeout}s")
+      }
+      Self::ExpectCall { call, timeout } => write!(f, "expect_call {call} within {timeout}s"),
+      Self::ExpectResponse {
+        call,
+        field,
+        equals,
+        timeout,
+      } => {
+        write!(f, "expect_response {call}")?;
+        if let Some(field) = field {
+          write!(f, " {field}")?;
+        }
+        if let Some(equals) = equals {
+          write!(f, " == {equals}")?;
+        }
+        write!(f, " within {timeout}s")
+      }
+    }
   }
 }
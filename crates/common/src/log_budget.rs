@@ -0,0 +1,135 @@
+//! Budget of the log lines for OCPP frames, so thousands of charge points do not flood the
+//! log: past the budget of a second the frames are logged at DEBUG only
+
+use std::{
+  sync::{
+    OnceLock,
+    atomic::{AtomicU32, AtomicU64, Ordering::Relaxed},
+  },
+  time::Instant,
+};
+
+#[doc(hidden)]
+pub use tracing;
+
+/// Frames logged at INFO per second, shared by all charge points
+static BUDGET: FrameBudget = FrameBudget::new();
+
+struct FrameBudget {
+  /// Frames logged at INFO per second, `u32::MAX` for all of them
+  rate: AtomicU32,
+  /// Second since `start` the used budget belongs to
+  window: AtomicU64,
+  used: AtomicU32,
+  suppressed: AtomicU64,
+  start: OnceLock<Instant>,
+}
+
+impl FrameBudget {
+  const fn new() -> Self {
+    Self {
+      rate: AtomicU32::new(u32::MAX),
+      window: AtomicU64::new(0),
+      used: AtomicU32::new(0),
+      suppressed: AtomicU64::new(0),
+      start: OnceLock::new(),
+    }
+  }
+
+  fn set_rate(&self, rate: u32) {
+    self
+      .rate
+      .store(if rate == 0 { u32::MAX } else { rate }, Relaxed);
+  }
+
+  /// Whether a frame logged at the time still fits into the budget of its second
+  fn allowed_at(&self, now: Instant) -> bool {
+    let rate = self.rate.load(Relaxed);
+    if rate == u32::MAX {
+      return true;
+    }
+
+    let second = now
+      .saturating_duration_since(*self.start.get_or_init(|| now))
+      .as_secs();
+    let window = self.window.load(Relaxed);
+    if second != window
+      && self
+        .window
+        .compare_exchange(window, second, Relaxed, Relaxed)
+        .is_ok()
+    {
+      self.used.store(0, Relaxed);
+      let suppressed = self.suppressed.swap(0, Relaxed);
+      if suppressed > 0 {
+        tracing::info!(
+          target: "simulator",
+          "{suppressed} frames logged at DEBUG only, over the limit of {rate}/s"
+        );
+      }
+    }
+
+    if self.used.fetch_add(1, Relaxed) < rate {
+      true
+    } else {
+      self.suppressed.fetch_add(1, Relaxed);
+      false
+    }
+  }
+}
+
+/// Sets the frames logged at INFO per second, 0 for all of them
+pub fn set_frame_log_rate(rate: u32) {
+  BUDGET.set_rate(rate);
+}
+
+/// Whether a frame still fits into the budget of the current second
+pub fn frame_log_allowed() -> bool {
+  BUDGET.allowed_at(Instant::now())
+}
+
+/// `info!` for a frame while the budget of the second lasts, `debug!` past it
+#[macro_export]
+macro_rules! frame_info {
+  ($($arg:tt)+) => {
+    if $crate::log_budget::frame_log_allowed() {
+      $crate::log_budget::tracing::info!($($arg)+)
+    } else {
+      $crate::log_budget::tracing::debug!($($arg)+)
+    }
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[test]
+  fn without_a_rate_every_frame_is_logged() {
+    let budget = FrameBudget::new();
+    budget.set_rate(0);
+    let now = Instant::now();
+
+    assert!((0..1_000).all(|_| budget.allowed_at(now)));
+  }
+
+  #[test]
+  fn the_budget_lasts_a_second() {
+    let budget = FrameBudget::new();
+    budget.set_rate(3);
+    let start = Instant::now();
+    budget.start.set(start).unwrap();
+
+    let allowed = (0..5)
+      .map(|frame| budget.allowed_at(start + Duration::from_millis(100 * frame)))
+      .collect::<Vec<_>>();
+    assert_eq!(allowed, [true, true, true, false, false]);
+    assert_eq!(budget.suppressed.load(Relaxed), 2);
+
+    assert!(budget.allowed_at(start + Duration::from_millis(1_200)));
+    assert_eq!(budget.suppressed.load(Relaxed), 0);
+    assert_eq!(budget.used.load(Relaxed), 1);
+  }
+}
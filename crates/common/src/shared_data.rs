@@ -1,15 +1,22 @@
 use std::{
-  collections::{HashMap, HashSet},
-  sync::Arc,
+  collections::{HashSet, VecDeque},
+  sync::{Arc, Mutex},
+  time::Duration,
 };
 
-use tokio::{sync::RwLock, time::Instant};
+use serde_json::Value;
+use tokio::time::Instant;
 
-use crate::ChargePointConfig;
+use crate::{ChargePointConfig, MeterConfig};
 
 type MsgId = String;
 type TagId = String;
 
+/// Calls waiting for their answer, beyond it the oldest are forgotten first
+const MAX_PENDING_CALLS: usize = 32;
+/// Time after which a call left unanswered is forgotten
+const PENDING_CALL_TIMEOUT: Duration = Duration::from_secs(300);
+
 pub trait SharedDataValue: Send + Sync {}
 impl<A: Send + Sync> SharedDataValue for A {}
 
@@ -63,6 +70,9 @@ pub struct ChargePointSettings {
 
   // 📊 Limits / Misc
   pub get_configuration_max_keys: u32,
+
+  // 🛡️ Security
+  pub security_profile: u32,
 }
 
 impl Default for ChargePointSettings {
@@ -124,29 +134,67 @@ impl Default for ChargePointSettings {
 
       // 📊 Limits / Misc
       get_configuration_max_keys: 50,
+
+      // 🛡️ Security
+      security_profile: 0,
     }
   }
 }
 
 pub struct ChargingSessionState {
+  /// Energy register, cumulative over all transactions
   pub energy_wh: f64,
+  /// Energy register at the previous meter sample, used for `*.Interval` measurands
+  pub last_sample_energy_wh: f64,
+  pub soc: f64,
   pub last_update: Instant,
 }
 
+impl ChargingSessionState {
+  /// Integrates the power drawn since the last update into the energy register and SoC
+  pub fn update_energy(&mut self, meter_config: &MeterConfig, power_w: f64) {
+    let now = Instant::now();
+    let delta_hours = now.duration_since(self.last_update).as_secs_f64() / 3600.0;
+    let delta_wh = power_w * delta_hours;
+
+    self.energy_wh += delta_wh;
+    self.soc = (self.soc + delta_wh / meter_config.battery_capacity * 100.0).min(100.0);
+    self.last_update = now;
+  }
+}
+
 impl Default for ChargingSessionState {
   fn default() -> Self {
     Self {
       energy_wh: 0.0,
+      last_sample_energy_wh: 0.0,
+      soc: 0.0,
       last_update: Instant::now(),
     }
   }
 }
 
+/// Call waiting for its answer
+struct PendingCall<A> {
+  msg_id: MsgId,
+  ocpp_action: A,
+  /// Tag the call was made for, its answer belongs to this tag
+  tag_id: Option<TagId>,
+  sent_at: Instant,
+}
+
 pub struct SharedState<A: SharedDataValue> {
-  msgs: HashMap<MsgId, A>,
+  /// Calls waiting for their answer, the oldest first
+  msgs: VecDeque<PendingCall<A>>,
 
   pub transaction_id: Option<i32>,
   pub tag_id: Option<TagId>,
+  /// OCPP 2.0.1: call starting the current transaction, its confirmation starts charging
+  pub start_transaction_msg_id: Option<MsgId>,
+  /// Transaction messages not yet confirmed by the CSMS, sent again after reconnecting:
+  /// `TransactionEvent` in OCPP 2.0.1, `StartTransaction`, `StopTransaction` and
+  /// `MeterValues` in OCPP 1.6
+  pub transaction_events: Vec<(MsgId, Value)>,
 
   pub charging_session_state: ChargingSessionState,
   pub settings: ChargePointSettings,
@@ -155,9 +203,11 @@ pub struct SharedState<A: SharedDataValue> {
 impl<A: SharedDataValue> SharedState<A> {
   fn new() -> Self {
     Self {
-      msgs: HashMap::new(),
+      msgs: VecDeque::with_capacity(MAX_PENDING_CALLS),
       transaction_id: None,
       tag_id: None,
+      start_transaction_msg_id: None,
+      transaction_events: Vec::new(),
       charging_session_state: ChargingSessionState::default(),
       settings: ChargePointSettings::default(),
     }
@@ -166,48 +216,161 @@ impl<A: SharedDataValue> SharedState<A> {
 
 #[derive(Clone)]
 pub struct SharedData<A: SharedDataValue> {
-  state: Arc<RwLock<SharedState<A>>>,
+  state: Arc<Mutex<SharedState<A>>>,
 }
 
 impl<A: SharedDataValue + Clone> Default for SharedData<A> {
   fn default() -> Self {
     Self {
-      state: Arc::new(RwLock::new(SharedState::new())),
+      state: Arc::new(Mutex::new(SharedState::new())),
     }
   }
 }
 
 impl<A: SharedDataValue + Clone> SharedData<A> {
   pub async fn from_cp_config(value: &ChargePointConfig) -> Self {
-    let state = Arc::new(RwLock::new(SharedState::new()));
-    state.write().await.tag_id = Some(value.id_tag.clone());
-    Self { state }
+    let mut state = SharedState::new();
+    state.tag_id = Some(value.id_tag.clone());
+    state.settings.security_profile = value.security.profile as u32;
+    Self {
+      state: Arc::new(Mutex::new(state)),
+    }
   }
 
+  /// Action of the call waiting for an answer with the message id
   pub async fn get_msg(&self, msg_id: &str) -> Option<A> {
-    self.state.read().await.msgs.get(msg_id).cloned()
+    self
+      .state
+      .lock()
+      .unwrap()
+      .msgs
+      .iter()
+      .find(|call| call.msg_id == msg_id)
+      .map(|call| call.ocpp_action.clone())
   }
 
-  pub async fn insert_msg(&self, msg_id: &String, ocpp_action: A) {
+  /// Action of the call the answer belongs to, the call is no longer waiting
+  pub async fn take_msg(&self, msg_id: &str) -> Option<A> {
     self
-      .state
-      .write()
+      .take_tagged_msg(msg_id)
       .await
-      .msgs
-      .insert(msg_id.to_string().clone(), ocpp_action);
+      .map(|(ocpp_action, _)| ocpp_action)
+  }
+
+  /// Action and tag of the call the answer belongs to, the call is no longer waiting
+  pub async fn take_tagged_msg(&self, msg_id: &str) -> Option<(A, Option<TagId>)> {
+    let msgs = &mut self.state.lock().unwrap().msgs;
+    let index = msgs.iter().position(|call| call.msg_id == msg_id)?;
+    msgs
+      .remove(index)
+      .map(|call| (call.ocpp_action, call.tag_id))
+  }
+
+  /// Keeps the call until its answer, the calls timed out are forgotten
+  pub async fn insert_msg(&self, msg_id: &String, ocpp_action: A) {
+    self.insert_tagged_msg(msg_id, ocpp_action, None).await;
   }
 
+  /// Keeps the call made for a tag until its answer
+  pub async fn insert_tagged_msg(&self, msg_id: &String, ocpp_action: A, tag_id: Option<TagId>) {
+    let now = Instant::now();
+    let msgs = &mut self.state.lock().unwrap().msgs;
+    while msgs
+      .front()
+      .is_some_and(|call| now.duration_since(call.sent_at) > PENDING_CALL_TIMEOUT)
+    {
+      msgs.pop_front();
+    }
+    if msgs.len() == MAX_PENDING_CALLS {
+      msgs.pop_front();
+    }
+    msgs.push_back(PendingCall {
+      msg_id: msg_id.to_string(),
+      ocpp_action,
+      tag_id,
+      sent_at: now,
+    });
+  }
+
+  /// The lock is held while `f` runs, it must not be kept across an await
   pub async fn read<F, R>(&self, f: F) -> R
   where
     F: FnOnce(&SharedState<A>) -> R,
   {
-    f(&*self.state.read().await)
+    f(&*self.state.lock().unwrap())
   }
 
   pub async fn write<F, R>(&self, f: F) -> R
   where
     F: FnOnce(&mut SharedState<A>) -> R,
   {
-    f(&mut *self.state.write().await)
+    f(&mut *self.state.lock().unwrap())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn msg_id(index: usize) -> String {
+    format!("msg-{index}")
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn the_answer_takes_its_call() {
+    let shared_data = SharedData::<&str>::default();
+    shared_data.insert_msg(&msg_id(1), "Heartbeat").await;
+
+    assert_eq!(shared_data.get_msg("msg-1").await, Some("Heartbeat"));
+    assert_eq!(shared_data.take_msg("msg-1").await, Some("Heartbeat"));
+    assert_eq!(shared_data.take_msg("msg-1").await, None);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn each_answer_takes_the_tag_of_its_call() {
+    let shared_data = SharedData::<&str>::default();
+    for (index, tag_id) in ["TAG1", "TAG2"].into_iter().enumerate() {
+      shared_data
+        .insert_tagged_msg(&msg_id(index), "Authorize", Some(tag_id.to_string()))
+        .await;
+    }
+
+    assert_eq!(
+      shared_data.take_tagged_msg(&msg_id(1)).await,
+      Some(("Authorize", Some("TAG2".to_string())))
+    );
+    assert_eq!(
+      shared_data.take_tagged_msg(&msg_id(0)).await,
+      Some(("Authorize", Some("TAG1".to_string())))
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn the_oldest_calls_are_forgotten_beyond_the_limit() {
+    let shared_data = SharedData::<usize>::default();
+    for index in 0..=MAX_PENDING_CALLS {
+      shared_data.insert_msg(&msg_id(index), index).await;
+    }
+
+    assert_eq!(shared_data.get_msg(&msg_id(0)).await, None);
+    assert_eq!(shared_data.get_msg(&msg_id(1)).await, Some(1));
+    assert_eq!(
+      shared_data.read(|data| data.msgs.len()).await,
+      MAX_PENDING_CALLS
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn unanswered_calls_are_forgotten_after_the_timeout() {
+    let shared_data = SharedData::<usize>::default();
+    shared_data.insert_msg(&msg_id(0), 0).await;
+    tokio::time::advance(PENDING_CALL_TIMEOUT / 2).await;
+    shared_data.insert_msg(&msg_id(1), 1).await;
+    tokio::time::advance(PENDING_CALL_TIMEOUT / 2 + Duration::from_secs(1)).await;
+    shared_data.insert_msg(&msg_id(2), 2).await;
+
+    assert_eq!(shared_data.get_msg(&msg_id(0)).await, None);
+    assert_eq!(shared_data.get_msg(&msg_id(1)).await, Some(1));
+    assert_eq!(shared_data.get_msg(&msg_id(2)).await, Some(2));
   }
 }
@@ -0,0 +1,299 @@
+//! Memory and CPU per charge point against a local echo CSMS
+//!
+//! ```bash
+//! ulimit -n 65536
+//! cargo run --release -p cp --example scale_bench -- 20000 60
+//! ```
+//!
+//! Starts the charge points (default 1000) in idle mode, waits until all of them are
+//! connected and measures the resident memory they added and the CPU time they take over the
+//! measuring window (default 60 seconds). The echo CSMS runs in a child process, so the
+//! figures are those of the charge points alone.
+
+use std::{
+  env, fs,
+  process::Stdio,
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering::Relaxed},
+  },
+  time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use common::{ChargePointConfig, GeneralConfig};
+use cp::{
+  id_tags::{AuthorizationStats, IdTags},
+  idle::ChargePointIdle,
+  timers::TimerWheel,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::{
+  io::{AsyncBufReadExt, BufReader},
+  net::TcpListener,
+  process::Command,
+  sync::mpsc,
+  time::{Instant, sleep},
+};
+use tokio_tungstenite::tungstenite::{
+  Message,
+  handshake::server::{ErrorResponse, Request, Response},
+};
+
+/// Heartbeat interval the CSMS assigns, seconds
+const HEARTBEAT_INTERVAL: u64 = 10;
+/// Charge points started per second, so the CSMS is not flooded with handshakes
+const RAMP_UP_RATE: u64 = 2_000;
+/// Time the boot and status notifications of the ramp-up have to settle
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  if args.first().map(String::as_str) == Some("echo-csms") {
+    return echo_csms().await;
+  }
+
+  let count = args.first().map_or(Ok(1_000), |count| count.parse())?;
+  let window = Duration::from_secs(args.get(1).map_or(Ok(60), |secs| secs.parse())?);
+
+  // 📡 Echo CSMS
+  let mut csms = Command::new(env::current_exe()?)
+    .arg("echo-csms")
+    .stdout(Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()?;
+  let mut lines = BufReader::new(csms.stdout.take().context("No CSMS output")?).lines();
+  let port = lines
+    .next_line()
+    .await?
+    .context("CSMS did not start")?
+    .parse::<u16>()?;
+  let connections = Arc::new(AtomicU64::new(0));
+  let frames = Arc::new(AtomicU64::new(0));
+  {
+    let (connections, frames) = (Arc::clone(&connections), Arc::clone(&frames));
+    tokio::spawn(async move {
+      while let Ok(Some(line)) = lines.next_line().await {
+        if let Some((connected, received)) = line.split_once(' ') {
+          connections.store(connected.parse().unwrap_or_default(), Relaxed);
+          frames.store(received.parse().unwrap_or_default(), Relaxed);
+        }
+      }
+    });
+  }
+
+  let general_config: Arc<GeneralConfig> = Arc::new(serde_json::from_value(json!({
+    "debug_mode": false,
+    "server_url": format!("ws://127.0.0.1:{port}"),
+    "ocpp_version": "ocpp1.6",
+  }))?);
+  let timers = TimerWheel::spawn();
+  let stats = Arc::new(AuthorizationStats::default());
+  let baseline = Usage::now()?;
+
+  // 🔌 Charge points
+  let started = Instant::now();
+  for index in 0..count {
+    let config = charge_point_config(index)?;
+    let mut charge_point = ChargePointIdle::new(
+      Arc::clone(&general_config),
+      config,
+      IdTags::new(None, Arc::clone(&stats)),
+    )
+    .with_timers(Arc::clone(&timers));
+    tokio::spawn(async move {
+      if let Err(e) = charge_point.run().await {
+        eprintln!("Charge point failed: {e:?}");
+      }
+    });
+
+    if (index + 1) % (RAMP_UP_RATE / 10) == 0 {
+      sleep(Duration::from_millis(100)).await;
+    }
+  }
+  while connections.load(Relaxed) < count {
+    if started.elapsed() > Duration::from_secs(60 + count / RAMP_UP_RATE) {
+      bail!(
+        "Only {} of {count} charge points connected, is the file limit high enough?",
+        connections.load(Relaxed)
+      );
+    }
+    sleep(Duration::from_millis(100)).await;
+  }
+  println!(
+    "{count} charge points connected after {:.1}s",
+    started.elapsed().as_secs_f64()
+  );
+  sleep(SETTLE_TIME).await;
+
+  // 📊 Measuring window
+  let start = Usage::now()?;
+  let start_frames = frames.load(Relaxed);
+  println!("measuring for {}s", window.as_secs());
+  sleep(window).await;
+  let end = Usage::now()?;
+  let frames = frames.load(Relaxed) - start_frames;
+
+  let per_cp = |value: f64| value / count as f64;
+  let cpu = (end.cpu - start.cpu).as_secs_f64() / window.as_secs_f64();
+  let rss = end.rss_kib.saturating_sub(baseline.rss_kib) as f64;
+  println!("{:<28} {count}", "charge points");
+  println!(
+    "{:<28} {:.1} MiB, {:.1} KiB per charge point",
+    "memory (RSS)",
+    rss / 1024.0,
+    per_cp(rss)
+  );
+  println!(
+    "{:<28} {:.1}% of a core, {:.1} µs/s per charge point",
+    "CPU",
+    cpu * 100.0,
+    per_cp(cpu * 1e6)
+  );
+  println!(
+    "{:<28} {:.0}/s",
+    "frames to the CSMS",
+    frames as f64 / window.as_secs_f64()
+  );
+  Ok(())
+}
+
+fn charge_point_config(index: u64) -> Result<ChargePointConfig> {
+  Ok(serde_json::from_value(json!({
+    "id": format!("BENCH{index:06}"),
+    "model": "bench",
+    "vendor": "bench",
+    "auth_header": "",
+    "boot_delay_interval": 0,
+    "heartbeat_interval": HEARTBEAT_INTERVAL,
+    "txn_meter_values_interval": 60,
+    "txn_meter_values_max_count": 10,
+    "status_interval": 60,
+    "start_tx_after": 60,
+    "stop_tx_after": 60,
+    "id_tag": "BENCH",
+  }))?)
+}
+
+/// Resident memory and CPU time of the process
+struct Usage {
+  rss_kib: u64,
+  cpu: Duration,
+}
+
+impl Usage {
+  fn now() -> Result<Self> {
+    let status = fs::read_to_string("/proc/self/status")?;
+    let rss_kib = status
+      .lines()
+      .find_map(|line| line.strip_prefix("VmRSS:"))
+      .and_then(|rss| rss.trim().trim_end_matches("kB").trim().parse().ok())
+      .context("No VmRSS in /proc/self/status")?;
+
+    // utime and stime follow the state, the first field after the command name
+    let stat = fs::read_to_string("/proc/self/stat")?;
+    let fields = stat
+      .rsplit_once(')')
+      .context("Invalid /proc/self/stat")?
+      .1
+      .split_whitespace()
+      .collect::<Vec<_>>();
+    let ticks = fields[11].parse::<u64>()? + fields[12].parse::<u64>()?;
+
+    Ok(Self {
+      rss_kib,
+      cpu: Duration::from_secs_f64(ticks as f64 / clock_ticks() as f64),
+    })
+  }
+}
+
+/// Clock ticks per second of the CPU times in `/proc`
+fn clock_ticks() -> u64 {
+  std::process::Command::new("getconf")
+    .arg("CLK_TCK")
+    .output()
+    .ok()
+    .and_then(|output| String::from_utf8(output.stdout).ok())
+    .and_then(|ticks| ticks.trim().parse().ok())
+    .unwrap_or(100)
+}
+
+/// Accepts every call: prints its port, then the open connections and the frames received
+/// once a second
+async fn echo_csms() -> Result<()> {
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  println!("{}", listener.local_addr()?.port());
+
+  let connections = Arc::new(AtomicU64::new(0));
+  let frames = Arc::new(AtomicU64::new(0));
+  {
+    let (connections, frames) = (Arc::clone(&connections), Arc::clone(&frames));
+    tokio::spawn(async move {
+      loop {
+        sleep(Duration::from_secs(1)).await;
+        println!("{} {}", connections.load(Relaxed), frames.load(Relaxed));
+      }
+    });
+  }
+
+  loop {
+    let (stream, _) = listener.accept().await?;
+    let (connections, frames) = (Arc::clone(&connections), Arc::clone(&frames));
+    tokio::spawn(async move {
+      let Ok(ws) = tokio_tungstenite::accept_hdr_async(stream, sub_protocol).await else {
+        return;
+      };
+      connections.fetch_add(1, Relaxed);
+
+      let (mut tx, mut rx) = ws.split();
+      let (answers, mut answers_rx) = mpsc::unbounded_channel::<String>();
+      tokio::spawn(async move {
+        while let Some(answer) = answers_rx.recv().await {
+          if tx.send(Message::Text(answer.into())).await.is_err() {
+            break;
+          }
+        }
+      });
+      while let Some(Ok(message)) = rx.next().await {
+        let Message::Text(text) = message else {
+          continue;
+        };
+        frames.fetch_add(1, Relaxed);
+        if let Some(answer) = answer(&text) {
+          let _ = answers.send(answer);
+        }
+      }
+      connections.fetch_sub(1, Relaxed);
+    });
+  }
+}
+
+/// The charge point expects the OCPP version it asked for
+#[allow(clippy::result_large_err)]
+fn sub_protocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+  if let Some(protocol) = request.headers().get("Sec-WebSocket-Protocol") {
+    response
+      .headers_mut()
+      .insert("Sec-WebSocket-Protocol", protocol.clone());
+  }
+  Ok(response)
+}
+
+fn answer(text: &str) -> Option<String> {
+  let frame: Value = serde_json::from_str(text).ok()?;
+  if frame[0] != 2 {
+    return None;
+  }
+  let payload = match frame[2].as_str()? {
+    "BootNotification" => json!({
+      "status": "Accepted",
+      "currentTime": chrono::Utc::now().to_rfc3339(),
+      "interval": HEARTBEAT_INTERVAL,
+    }),
+    "Heartbeat" => json!({ "currentTime": chrono::Utc::now().to_rfc3339() }),
+    _ => json!({}),
+  };
+  Some(json!([3, frame[1], payload]).to_string())
+}
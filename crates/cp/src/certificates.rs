@@ -0,0 +1,760 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use common::ChargePointConfig;
+use ocpp::{
+  generator::MessageGenerator,
+  handler::{MessageFrame, MessageFrameType},
+  types::{
+    CommonCertificateHashData, CommonCertificateStatus, CommonCertificateUse,
+    CommonTriggerMessageStatus, InstalledCertificate,
+  },
+  v1_6::{
+    handler::V16MessageHandler,
+    security::{
+      CertificateSignedRequest, DeleteCertificateRequest, ExtendedTriggerMessageRequest,
+      GetInstalledCertificateIdsRequest, InstallCertificateRequest, MessageTrigger,
+    },
+    types::OcppAction as V16OcppAction,
+  },
+  v2_0_1::{handler::V201MessageHandler, types::OcppAction as V201OcppAction},
+};
+use openssl::{
+  asn1::Asn1Time,
+  bn::BigNumContext,
+  ec::{EcGroup, EcKey, PointConversionForm},
+  hash::{MessageDigest, hash},
+  nid::Nid,
+  pkey::{Id, PKey, PKeyRef, Private, Public},
+  stack::Stack,
+  x509::{
+    X509, X509NameBuilder, X509ReqBuilder, X509StoreContext, X509VerifyResult,
+    store::X509StoreBuilder,
+  },
+};
+use rust_ocpp::v2_0_1::{
+  enumerations::message_trigger_enum_type::MessageTriggerEnumType,
+  messages::{
+    certificate_signed::CertificateSignedRequest as V201CertificateSignedRequest,
+    delete_certificate::DeleteCertificateRequest as V201DeleteCertificateRequest,
+    get_installed_certificate_ids::GetInstalledCertificateIdsRequest as V201GetInstalledCertificateIdsRequest,
+    install_certificate::InstallCertificateRequest as V201InstallCertificateRequest,
+    trigger_message::TriggerMessageRequest,
+  },
+};
+use serde_json::Value;
+use tracing::{error, info, warn};
+
+use crate::security::Security;
+
+/// File names of the certificate signed by the CSMS and its key
+const CLIENT_CERTIFICATE_FILE: &str = "ChargePointCertificate.pem";
+const CLIENT_KEY_FILE: &str = "ChargePointCertificate.key.pem";
+
+struct Installed {
+  certificate_use: CommonCertificateUse,
+  certificate: X509,
+  file_name: String,
+}
+
+/// Certificates of a charge point: the roots installed by the CSMS and the client
+/// certificate it signed, persisted in the `certificate_store` directory when configured
+pub struct CertificateStore {
+  cp_id: String,
+  organization_name: Option<String>,
+  directory: Option<PathBuf>,
+  installed: Vec<Installed>,
+  /// Key of the last certificate signing request, waiting for `CertificateSigned`
+  pending_key: Option<PKey<Private>>,
+  /// PEM certificate chain and PKCS#8 key signed by the CSMS
+  client: Option<(Vec<u8>, Vec<u8>)>,
+  /// Configured root of the firmware signing certificates
+  manufacturer_root: Option<X509>,
+}
+
+impl CertificateStore {
+  pub fn new(cp_config: &ChargePointConfig) -> Result<Self> {
+    let mut store = Self {
+      cp_id: cp_config.id.clone(),
+      organization_name: cp_config.security.organization_name.clone(),
+      directory: cp_config.security.certificate_store.clone(),
+      installed: Vec::new(),
+      pending_key: None,
+      client: None,
+      manufacturer_root: None,
+    };
+
+    if let Some(path) = &cp_config.security.manufacturer_root_cert_path {
+      let pem = fs::read(path)
+        .with_context(|| format!("Failed to read manufacturer root {}", path.display()))?;
+      store.manufacturer_root = Some(X509::from_pem(&pem)?);
+    }
+
+    if let Some(directory) = &store.directory {
+      fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create certificate store {}", directory.display()))?;
+      store.load(&directory.clone())?;
+    }
+
+    Ok(store)
+  }
+
+  /// Whether a client certificate has been signed by the CSMS
+  pub fn has_client_certificate(&self) -> bool {
+    self.client.is_some()
+  }
+
+  /// PEM certificate chain and PKCS#8 key signed by the CSMS
+  pub fn client_identity(&self) -> Option<(&[u8], &[u8])> {
+    self
+      .client
+      .as_ref()
+      .map(|(chain, key)| (chain.as_slice(), key.as_slice()))
+  }
+
+  /// DER encoded certificates installed for a given use
+  pub fn certificates(&self, certificate_use: CommonCertificateUse) -> Result<Vec<Vec<u8>>> {
+    self
+      .installed
+      .iter()
+      .filter(|installed| installed.certificate_use == certificate_use)
+      .map(|installed| Ok(installed.certificate.to_der()?))
+      .collect()
+  }
+
+  /// Parses a PEM firmware signing certificate, `None` when it is not issued by the
+  /// configured or an installed manufacturer root
+  pub fn firmware_signing_certificate(&self, certificate: &str) -> Option<X509> {
+    let certificate = X509::from_pem(certificate.as_bytes()).ok()?;
+
+    let mut roots = X509StoreBuilder::new().ok()?;
+    let installed = self
+      .installed
+      .iter()
+      .filter(|installed| installed.certificate_use == CommonCertificateUse::ManufacturerRoot)
+      .map(|installed| &installed.certificate);
+
+    for root in self.manufacturer_root.iter().chain(installed) {
+      roots.add_cert(root.clone()).ok()?;
+    }
+    let roots = roots.build();
+
+    let mut context = X509StoreContext::new().ok()?;
+    let chain = Stack::new().ok()?;
+    context
+      .init(&roots, &certificate, &chain, |context| {
+        context.verify_cert()
+      })
+      .ok()?
+      .then_some(certificate)
+  }
+
+  /// Generates a new P-256 key and returns the PEM certificate signing request for it
+  pub fn certificate_signing_request(&mut self) -> Result<String> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    if let Some(organization_name) = &self.organization_name {
+      name.append_entry_by_nid(Nid::ORGANIZATIONNAME, organization_name)?;
+    }
+    name.append_entry_by_nid(Nid::COMMONNAME, &self.cp_id)?;
+
+    let mut request = X509ReqBuilder::new()?;
+    request.set_subject_name(&name.build())?;
+    request.set_pubkey(&key)?;
+    request.sign(&key, MessageDigest::sha256())?;
+
+    let csr = String::from_utf8(request.build().to_pem()?)?;
+    self.pending_key = Some(key);
+    Ok(csr)
+  }
+
+  /// Accepts a certificate chain signed by the CSMS for the last signing request
+  pub fn certificate_signed(&mut self, certificate_chain: &str) -> CommonCertificateStatus {
+    let Ok(chain) = X509::stack_from_pem(certificate_chain.as_bytes()) else {
+      warn!("CertificateSigned rejected, invalid certificate chain");
+      return CommonCertificateStatus::Rejected;
+    };
+
+    let (Some(leaf), Some(key)) = (chain.first(), &self.pending_key) else {
+      warn!("CertificateSigned rejected, no pending certificate signing request");
+      return CommonCertificateStatus::Rejected;
+    };
+
+    let matches_key = leaf
+      .public_key()
+      .is_ok_and(|public_key| public_key.public_eq(key));
+
+    if !matches_key || is_expired(leaf) {
+      warn!("CertificateSigned rejected, the certificate does not match the request");
+      return CommonCertificateStatus::Rejected;
+    }
+
+    let client = key
+      .private_key_to_pem_pkcs8()
+      .map(|key| (certificate_chain.as_bytes().to_vec(), key));
+
+    let Ok((chain, key)) = client else {
+      return CommonCertificateStatus::Rejected;
+    };
+
+    if let Err(err) = self
+      .write(CLIENT_CERTIFICATE_FILE, &chain)
+      .and_then(|_| self.write(CLIENT_KEY_FILE, &key))
+    {
+      warn!("Failed to store the client certificate: {err}");
+      return CommonCertificateStatus::Rejected;
+    }
+
+    info!("Client certificate signed by the CSMS");
+    self.pending_key = None;
+    self.client = Some((chain, key));
+    CommonCertificateStatus::Accepted
+  }
+
+  /// Installs a PEM certificate, replacing an identical one
+  pub fn install(
+    &mut self,
+    certificate_use: CommonCertificateUse,
+    certificate: &str,
+  ) -> CommonCertificateStatus {
+    let Ok(certificate) = X509::from_pem(certificate.as_bytes()) else {
+      warn!("InstallCertificate rejected, invalid certificate");
+      return CommonCertificateStatus::Rejected;
+    };
+
+    if is_expired(&certificate) {
+      warn!("InstallCertificate rejected, expired certificate");
+      return CommonCertificateStatus::Rejected;
+    }
+
+    let Ok(serial_number) = serial_number(&certificate) else {
+      return CommonCertificateStatus::Rejected;
+    };
+
+    let file_name = format!("{:?}_{}.pem", certificate_use, serial_number);
+    let written = certificate
+      .to_pem()
+      .map_err(anyhow::Error::from)
+      .and_then(|pem| self.write(&file_name, &pem));
+
+    if let Err(err) = written {
+      warn!("Failed to store certificate {file_name}: {err}");
+      return CommonCertificateStatus::Failed;
+    }
+
+    info!(
+      "Installed {:?} certificate {}",
+      certificate_use, serial_number
+    );
+    self
+      .installed
+      .retain(|installed| installed.file_name != file_name);
+    self.installed.push(Installed {
+      certificate_use,
+      certificate,
+      file_name,
+    });
+    CommonCertificateStatus::Accepted
+  }
+
+  /// Deletes the installed certificate matching the hash data
+  pub fn delete(&mut self, hash_data: &CommonCertificateHashData) -> CommonCertificateStatus {
+    let position = self.installed.iter().position(|installed| {
+      self.hash_data(&installed.certificate).ok().as_ref() == Some(hash_data)
+    });
+
+    let Some(position) = position else {
+      return CommonCertificateStatus::NotFound;
+    };
+
+    if let Some(directory) = &self.directory {
+      let path = directory.join(&self.installed[position].file_name);
+      if let Err(err) = fs::remove_file(&path) {
+        warn!("Failed to delete {}: {err}", path.display());
+        return CommonCertificateStatus::Failed;
+      }
+    }
+
+    let installed = self.installed.remove(position);
+    info!(
+      "Deleted {:?} certificate {}",
+      installed.certificate_use, installed.file_name
+    );
+    CommonCertificateStatus::Accepted
+  }
+
+  /// Hash data of the installed certificates of the given uses, all of them when empty
+  pub fn installed_ids(
+    &self,
+    certificate_uses: &[CommonCertificateUse],
+  ) -> Vec<InstalledCertificate> {
+    self
+      .installed
+      .iter()
+      .filter(|installed| {
+        certificate_uses.is_empty() || certificate_uses.contains(&installed.certificate_use)
+      })
+      .filter_map(|installed| {
+        Some(InstalledCertificate {
+          certificate_type: installed.certificate_use,
+          hash_data: self.hash_data(&installed.certificate).ok()?,
+        })
+      })
+      .collect()
+  }
+
+  /// Hash data of an installed certificate, its issuer being looked up among the installed
+  /// certificates (self-signed certificates are their own issuer)
+  fn hash_data(&self, certificate: &X509) -> Result<CommonCertificateHashData> {
+    let issuer = self
+      .installed
+      .iter()
+      .map(|installed| &installed.certificate)
+      .chain(std::iter::once(certificate))
+      .find(|issuer| issuer.issued(certificate) == X509VerifyResult::OK)
+      .unwrap_or(certificate);
+
+    certificate_hash_data(certificate, issuer)
+  }
+
+  fn load(&mut self, directory: &Path) -> Result<()> {
+    let client_certificate = directory.join(CLIENT_CERTIFICATE_FILE);
+    let client_key = directory.join(CLIENT_KEY_FILE);
+    if client_certificate.exists() && client_key.exists() {
+      self.client = Some((fs::read(client_certificate)?, fs::read(client_key)?));
+    }
+
+    for entry in fs::read_dir(directory)? {
+      let file_name = entry?.file_name().to_string_lossy().to_string();
+
+      let Some(certificate_use) = file_name
+        .split_once('_')
+        .and_then(|(certificate_use, _)| parse_certificate_use(certificate_use))
+      else {
+        continue;
+      };
+
+      let certificate = X509::from_pem(&fs::read(directory.join(&file_name))?)
+        .with_context(|| format!("Invalid certificate {file_name}"))?;
+
+      self.installed.push(Installed {
+        certificate_use,
+        certificate,
+        file_name,
+      });
+    }
+
+    info!(
+      "Loaded {} certificates from {}",
+      self.installed.len(),
+      directory.display()
+    );
+    Ok(())
+  }
+
+  fn write(&self, file_name: &str, content: &[u8]) -> Result<()> {
+    if let Some(directory) = &self.directory {
+      fs::write(directory.join(file_name), content)?;
+    }
+    Ok(())
+  }
+}
+
+/// SHA-256 hashes of the issuer name and key of the certificate, with its serial number
+pub fn certificate_hash_data(
+  certificate: &X509,
+  issuer: &X509,
+) -> Result<CommonCertificateHashData> {
+  Ok(CommonCertificateHashData {
+    issuer_name_hash: hex::encode(hash(
+      MessageDigest::sha256(),
+      &certificate.issuer_name().to_der()?,
+    )?),
+    issuer_key_hash: hex::encode(hash(
+      MessageDigest::sha256(),
+      &public_key_bits(&*issuer.public_key()?)?,
+    )?),
+    serial_number: serial_number(certificate)?,
+  })
+}
+
+fn parse_certificate_use(value: &str) -> Option<CommonCertificateUse> {
+  match value {
+    "CentralSystemRoot" => Some(CommonCertificateUse::CentralSystemRoot),
+    "ManufacturerRoot" => Some(CommonCertificateUse::ManufacturerRoot),
+    "V2GRoot" => Some(CommonCertificateUse::V2GRoot),
+    "MORoot" => Some(CommonCertificateUse::MORoot),
+    "V2GCertificateChain" => Some(CommonCertificateUse::V2GCertificateChain),
+    _ => None,
+  }
+}
+
+fn is_expired(certificate: &X509) -> bool {
+  Asn1Time::days_from_now(0).is_ok_and(|now| certificate.not_after() < now)
+}
+
+fn serial_number(certificate: &X509) -> Result<String> {
+  Ok(
+    certificate
+      .serial_number()
+      .to_bn()?
+      .to_hex_str()?
+      .to_lowercase(),
+  )
+}
+
+/// Content of the subject public key bit string
+fn public_key_bits(key: &PKeyRef<Public>) -> Result<Vec<u8>> {
+  match key.id() {
+    Id::EC => {
+      let ec_key = key.ec_key()?;
+      let mut ctx = BigNumContext::new()?;
+      Ok(ec_key.public_key().to_bytes(
+        ec_key.group(),
+        PointConversionForm::UNCOMPRESSED,
+        &mut ctx,
+      )?)
+    }
+    Id::RSA => Ok(key.rsa()?.public_key_to_der_pkcs1()?),
+    _ => Ok(key.public_key_to_der()?),
+  }
+}
+
+/// Handles the certificate management calls of the CSMS, returns the messages to send or
+/// `None` when the frame is not one of them
+pub async fn handle_certificate_call(
+  frame: &MessageFrameType,
+  security: &mut Security,
+  generator: &dyn MessageGenerator,
+) -> Result<Option<Vec<Value>>> {
+  match frame {
+    MessageFrameType::V1_6(MessageFrame::Call {
+      msg_id,
+      action,
+      payload,
+    }) => match action {
+      V16OcppAction::CertificateSigned => {
+        let request: CertificateSignedRequest = V16MessageHandler::parse_payload(payload.clone())?;
+        let status = security.certificate_signed(&request.certificate_chain);
+        Ok(Some(vec![
+          generator.certificate_signed(msg_id, status).await,
+        ]))
+      }
+      V16OcppAction::InstallCertificate => {
+        let request: InstallCertificateRequest = V16MessageHandler::parse_payload(payload.clone())?;
+        let status = security
+          .certificates()
+          .install(request.certificate_type.into(), &request.certificate);
+        Ok(Some(vec![
+          generator.install_certificate(msg_id, status).await,
+        ]))
+      }
+      V16OcppAction::DeleteCertificate => {
+        let request: DeleteCertificateRequest = V16MessageHandler::parse_payload(payload.clone())?;
+        let status = security
+          .certificates()
+          .delete(&request.certificate_hash_data.into());
+        Ok(Some(vec![
+          generator.delete_certificate(msg_id, status).await,
+        ]))
+      }
+      V16OcppAction::GetInstalledCertificateIds => {
+        let request: GetInstalledCertificateIdsRequest =
+          V16MessageHandler::parse_payload(payload.clone())?;
+        let certificates = security
+          .certificates()
+          .installed_ids(&[request.certificate_type.into()]);
+        Ok(Some(vec![
+          generator
+            .get_installed_certificate_ids(msg_id, certificates)
+            .await,
+        ]))
+      }
+      V16OcppAction::ExtendedTriggerMessage => {
+        let request: ExtendedTriggerMessageRequest =
+          V16MessageHandler::parse_payload(payload.clone())?;
+
+        match request.requested_message {
+          MessageTrigger::SignChargePointCertificate => {
+            Ok(Some(sign_certificate(msg_id, security, generator).await))
+          }
+          requested_message => {
+            warn!(
+              "ExtendedTriggerMessage {:?} not implemented",
+              requested_message
+            );
+            Ok(Some(vec![
+              generator
+                .trigger_message(msg_id, CommonTriggerMessageStatus::NotImplemented)
+                .await,
+            ]))
+          }
+        }
+      }
+      _ => Ok(None),
+    },
+    MessageFrameType::V2_0_1(MessageFrame::Call {
+      msg_id,
+      action,
+      payload,
+    }) => match action {
+      V201OcppAction::CertificateSigned => {
+        let request: V201CertificateSignedRequest =
+          V201MessageHandler::parse_payload(payload.clone())?;
+        let status = security.certificate_signed(&request.certificate_chain);
+        Ok(Some(vec![
+          generator.certificate_signed(msg_id, status).await,
+        ]))
+      }
+      V201OcppAction::InstallCertificate => {
+        let request: V201InstallCertificateRequest =
+          V201MessageHandler::parse_payload(payload.clone())?;
+        let status = security
+          .certificates()
+          .install(request.certificate_type.into(), &request.certificate);
+        Ok(Some(vec![
+          generator.install_certificate(msg_id, status).await,
+        ]))
+      }
+      V201OcppAction::DeleteCertificate => {
+        let request: V201DeleteCertificateRequest =
+          V201MessageHandler::parse_payload(payload.clone())?;
+        let status = security
+          .certificates()
+          .delete(&request.certificate_hash_data.into());
+        Ok(Some(vec![
+          generator.delete_certificate(msg_id, status).await,
+        ]))
+      }
+      V201OcppAction::GetInstalledCertificateIds => {
+        let request: V201GetInstalledCertificateIdsRequest =
+          V201MessageHandler::parse_payload(payload.clone())?;
+        let certificate_uses = request
+          .certificate_type
+          .unwrap_or_default()
+          .into_iter()
+          .map(CommonCertificateUse::from)
+          .collect::<Vec<_>>();
+        let certificates = security.certificates().installed_ids(&certificate_uses);
+        Ok(Some(vec![
+          generator
+            .get_installed_certificate_ids(msg_id, certificates)
+            .await,
+        ]))
+      }
+      // Other triggers are answered by the charge point loops
+      V201OcppAction::TriggerMessage => {
+        let request: TriggerMessageRequest = V201MessageHandler::parse_payload(payload.clone())?;
+
+        match request.requested_message {
+          MessageTriggerEnumType::SignChargingStationCertificate => {
+            Ok(Some(sign_certificate(msg_id, security, generator).await))
+          }
+          _ => Ok(None),
+        }
+      }
+      _ => Ok(None),
+    },
+    _ => Ok(None),
+  }
+}
+
+/// Answers a trigger for a new client certificate, followed by the `SignCertificate` call
+async fn sign_certificate(
+  msg_id: &str,
+  security: &mut Security,
+  generator: &dyn MessageGenerator,
+) -> Vec<Value> {
+  match security.certificates().certificate_signing_request() {
+    Ok(csr) => vec![
+      generator
+        .trigger_message(msg_id, CommonTriggerMessageStatus::Accepted)
+        .await,
+      generator.sign_certificate(csr).await,
+    ],
+    Err(err) => {
+      error!("Failed to create a certificate signing request: {err}");
+      vec![
+        generator
+          .trigger_message(msg_id, CommonTriggerMessageStatus::Rejected)
+          .await,
+      ]
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use openssl::x509::X509Req;
+  use serde_json::json;
+
+  use super::*;
+  use crate::{
+    test_certificates::{ca, issue, key, pem},
+    test_config::charge_point_config,
+  };
+
+  fn store_directory() -> PathBuf {
+    std::env::temp_dir().join(format!("certificates-{}", rand::random::<u64>()))
+  }
+
+  /// Store kept in the directory, in memory only without one
+  fn store(directory: Option<&Path>) -> CertificateStore {
+    CertificateStore::new(&charge_point_config(json!({
+      "security": { "certificate_store": directory, "organization_name": "Test Operator" },
+    })))
+    .unwrap()
+  }
+
+  /// Public key of the certificate signing request, checked against its signature
+  fn requested_key(csr: &str) -> PKey<Public> {
+    let request = X509Req::from_pem(csr.as_bytes()).unwrap();
+    let public_key = request.public_key().unwrap();
+    assert!(request.verify(&public_key).unwrap());
+
+    let subject = request
+      .subject_name()
+      .entries()
+      .map(|entry| entry.data().as_utf8().unwrap().to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(subject, ["Test Operator", "CP1"]);
+    public_key
+  }
+
+  #[test]
+  fn certificate_signed_for_the_request_is_kept_across_runs() {
+    let directory = store_directory();
+    let mut store = store(Some(&directory));
+    let csms_ca = ca("CSMS CA");
+    let public_key = requested_key(&store.certificate_signing_request().unwrap());
+
+    let chain = pem(&issue("CP1", &public_key, &csms_ca, 30)) + &pem(&csms_ca.0);
+    assert_eq!(
+      store.certificate_signed(&chain),
+      CommonCertificateStatus::Accepted
+    );
+    let (stored_chain, stored_key) = store.client_identity().unwrap();
+    assert_eq!(stored_chain, chain.as_bytes());
+    let stored_key = PKey::private_key_from_pem(stored_key).unwrap();
+    assert!(stored_key.public_eq(&public_key));
+
+    // The request is answered, the same chain again is not
+    assert_eq!(
+      store.certificate_signed(&chain),
+      CommonCertificateStatus::Rejected
+    );
+    let reloaded = self::store(Some(&directory));
+    assert_eq!(reloaded.client_identity(), store.client_identity());
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn certificate_signed_for_another_key_or_not_valid_is_rejected() {
+    let mut store = store(None);
+    let csms_ca = ca("CSMS CA");
+    let public_key = requested_key(&store.certificate_signing_request().unwrap());
+
+    let other_key = issue("CP1", &key(), &csms_ca, 30);
+    let expired = issue("CP1", &public_key, &csms_ca, -1);
+    for chain in [
+      pem(&other_key) + &pem(&csms_ca.0),
+      pem(&expired) + &pem(&csms_ca.0),
+      "-----BEGIN CERTIFICATE-----\nnot a certificate\n-----END CERTIFICATE-----\n".to_string(),
+      String::new(),
+    ] {
+      assert_eq!(
+        store.certificate_signed(&chain),
+        CommonCertificateStatus::Rejected,
+        "{chain}"
+      );
+    }
+    assert!(!store.has_client_certificate());
+
+    // The request is still pending
+    let chain = pem(&issue("CP1", &public_key, &csms_ca, 30));
+    assert_eq!(
+      store.certificate_signed(&chain),
+      CommonCertificateStatus::Accepted
+    );
+  }
+
+  #[test]
+  fn installed_certificates_are_listed_and_deleted_by_hash() {
+    let directory = store_directory();
+    let mut store = store(Some(&directory));
+    let csms_root = ca("CSMS Root");
+    let manufacturer_root = ca("Manufacturer Root");
+
+    for _ in 0..2 {
+      assert_eq!(
+        store.install(CommonCertificateUse::CentralSystemRoot, &pem(&csms_root.0)),
+        CommonCertificateStatus::Accepted
+      );
+    }
+    assert_eq!(
+      store.install(
+        CommonCertificateUse::ManufacturerRoot,
+        &pem(&manufacturer_root.0)
+      ),
+      CommonCertificateStatus::Accepted
+    );
+    let expired = issue("Expired", &key(), &csms_root, -1);
+    assert_eq!(
+      store.install(CommonCertificateUse::CentralSystemRoot, &pem(&expired)),
+      CommonCertificateStatus::Rejected
+    );
+    assert_eq!(
+      store.install(CommonCertificateUse::CentralSystemRoot, "not a certificate"),
+      CommonCertificateStatus::Rejected
+    );
+
+    assert_eq!(store.installed_ids(&[]).len(), 2);
+    let installed = store.installed_ids(&[CommonCertificateUse::CentralSystemRoot]);
+    let [installed] = installed.as_slice() else {
+      panic!("{installed:?}");
+    };
+    let hash_data = certificate_hash_data(&csms_root.0, &csms_root.0).unwrap();
+    assert_eq!(installed.hash_data, hash_data);
+    assert_eq!(
+      store
+        .certificates(CommonCertificateUse::CentralSystemRoot)
+        .unwrap(),
+      [csms_root.0.to_der().unwrap()]
+    );
+    assert_eq!(self::store(Some(&directory)).installed_ids(&[]).len(), 2);
+
+    assert_eq!(store.delete(&hash_data), CommonCertificateStatus::Accepted);
+    assert_eq!(store.delete(&hash_data), CommonCertificateStatus::NotFound);
+    let remaining = self::store(Some(&directory)).installed_ids(&[]);
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(
+      remaining[0].certificate_type,
+      CommonCertificateUse::ManufacturerRoot
+    );
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn hash_data_of_an_issued_certificate_names_its_issuer() {
+    let mut store = store(None);
+    let root = ca("V2G Root");
+    let leaf = issue("SubCA", &key(), &root, 30);
+    store.install(CommonCertificateUse::V2GRoot, &pem(&root.0));
+    store.install(CommonCertificateUse::V2GCertificateChain, &pem(&leaf));
+
+    let installed = store.installed_ids(&[CommonCertificateUse::V2GCertificateChain]);
+    assert_eq!(
+      installed[0].hash_data,
+      certificate_hash_data(&leaf, &root.0).unwrap()
+    );
+    assert_ne!(
+      installed[0].hash_data,
+      certificate_hash_data(&leaf, &leaf).unwrap()
+    );
+  }
+}
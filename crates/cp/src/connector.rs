@@ -0,0 +1,764 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use common::{ChargePointErrorCode, shared_data::ChargePointSettings};
+use futures::SinkExt;
+use ocpp::{
+  generator::MessageGenerator,
+  types::{CommonConnectorStatusType, CommonStopReason, CommonTransactionTrigger, ConnectorFault},
+};
+use tokio::time::Instant;
+use tracing::warn;
+use tungstenite::Message;
+
+use crate::core::send;
+
+/// How long the driver takes to unplug the cable after a transaction has finished
+pub const EV_UNPLUG_DELAY: Duration = Duration::from_secs(5);
+
+/// Events of the EV, the driver or the EVSE itself that drive a connector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvEvent {
+  /// The cable is plugged into the EV
+  PlugIn,
+  /// The driver presented an accepted tag (or the CSMS started a transaction remotely)
+  Authorize,
+  /// The CSMS accepted `StartTransaction`, the contactor closes
+  TransactionStarted,
+  /// The EV stops taking energy, e.g. the battery is full
+  EvSuspend,
+  /// The EV takes energy again
+  EvResume,
+  /// The EVSE stops offering energy, e.g. a charging limit of 0
+  EvseSuspend,
+  /// The EVSE offers energy again
+  EvseResume,
+  /// The transaction is stopped by the driver or the CSMS
+  Stop(CommonStopReason),
+  /// The cable is unplugged from the EV
+  Unplug,
+  /// `ConnectionTimeOut` expired while preparing
+  ConnectionTimeout,
+  /// The EVSE detected an error
+  Fault(ConnectorFault),
+  /// All errors have been cleared
+  Recover,
+  /// The error with the code has been cleared, the others remain
+  ClearFault(ChargePointErrorCode),
+}
+
+/// What the charge point has to do after a connector transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectorAction {
+  StartTransaction,
+  StopTransaction(CommonStopReason),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Transition {
+  /// New connector status to be sent as `StatusNotification`
+  pub status: Option<CommonConnectorStatusType>,
+  pub action: Option<ConnectorAction>,
+}
+
+/// Connector state machine following the OCPP 1.6 connector status transitions
+pub struct Connector {
+  status: CommonConnectorStatusType,
+  plugged_in: bool,
+  authorized: bool,
+  transaction: bool,
+  preparing_since: Option<Instant>,
+  connection_timeout: Duration,
+  stop_transaction_on_ev_side_disconnect: bool,
+  /// Active errors, one per error code, the latest last
+  faults: Vec<ConnectorFault>,
+  /// The session of the faulted connector had a transaction, its EV only leaves after recovery
+  finishing: bool,
+  /// A charging profile of the CSMS limits charging to 0
+  zero_limit: bool,
+}
+
+impl Connector {
+  pub fn new(settings: &ChargePointSettings) -> Self {
+    Self {
+      status: CommonConnectorStatusType::Available,
+      plugged_in: false,
+      authorized: false,
+      transaction: false,
+      preparing_since: None,
+      connection_timeout: Duration::from_secs(settings.connection_timeout as u64),
+      stop_transaction_on_ev_side_disconnect: settings.stop_transaction_on_ev_side_disconnect,
+      faults: Vec::new(),
+      finishing: false,
+      zero_limit: false,
+    }
+  }
+
+  pub fn status(&self) -> CommonConnectorStatusType {
+    self.status
+  }
+
+  pub fn is_plugged_in(&self) -> bool {
+    self.plugged_in
+  }
+
+  pub fn has_transaction(&self) -> bool {
+    self.transaction
+  }
+
+  /// The error currently reported with the connector status, the latest of the active ones
+  pub fn fault(&self) -> Option<&ConnectorFault> {
+    self.faults.last()
+  }
+
+  pub fn set_connection_timeout(&mut self, seconds: u32) {
+    self.connection_timeout = Duration::from_secs(seconds as u64);
+  }
+
+  pub fn set_stop_transaction_on_ev_side_disconnect(&mut self, state: bool) {
+    self.stop_transaction_on_ev_side_disconnect = state;
+  }
+
+  /// Applies the charging limit of a profile set by the CSMS, `None` when it lifts any limit.
+  /// Returns the EVSE event to handle when charging is suspended or resumed.
+  pub fn set_charging_limit(&mut self, limit: Option<f64>) -> Option<EvEvent> {
+    use CommonConnectorStatusType::*;
+
+    self.zero_limit = limit.is_some_and(|limit| limit <= 0.0);
+    match self.status {
+      Charging if self.zero_limit => Some(EvEvent::EvseSuspend),
+      SuspendedEVSE if !self.zero_limit && self.faults.is_empty() => Some(EvEvent::EvseResume),
+      _ => None,
+    }
+  }
+
+  /// When the incipient transaction is cancelled because the EV was not plugged in or
+  /// the driver did not authorize in time
+  pub fn connection_timeout_deadline(&self) -> Option<Instant> {
+    match (self.preparing_since, self.transaction) {
+      (Some(since), false) if !self.connection_timeout.is_zero() => {
+        Some(since + self.connection_timeout)
+      }
+      _ => None,
+    }
+  }
+
+  pub fn handle(&mut self, event: EvEvent) -> Result<Transition> {
+    use CommonConnectorStatusType::*;
+
+    let mut action = None;
+    let mut notify = false;
+
+    let status = match (self.status, event) {
+      (status, EvEvent::Fault(fault)) => {
+        let error_code = fault.error_code;
+        self.faults.retain(|active| active.error_code != error_code);
+        self.faults.push(fault);
+        notify = true;
+        if status != Faulted {
+          self.finishing = matches!(status, Charging | SuspendedEV | SuspendedEVSE | Finishing);
+        }
+
+        match status {
+          // Derating: the EVSE stops offering energy but keeps the transaction
+          Charging | SuspendedEV | SuspendedEVSE
+            if error_code == ChargePointErrorCode::HighTemperature =>
+          {
+            SuspendedEVSE
+          }
+          Charging | SuspendedEV | SuspendedEVSE if error_code.is_fatal() => {
+            action = Some(ConnectorAction::StopTransaction(CommonStopReason::Other));
+            self.transaction = false;
+            self.authorized = false;
+            Faulted
+          }
+          Preparing if error_code.is_fatal() => {
+            self.authorized = false;
+            Faulted
+          }
+          _ if error_code.is_fatal() => Faulted,
+          status => status,
+        }
+      }
+      (status, EvEvent::Recover) if !self.faults.is_empty() => {
+        self.faults.clear();
+        notify = true;
+        self.recovered(status)
+      }
+      (status, EvEvent::ClearFault(error_code))
+        if self
+          .faults
+          .iter()
+          .any(|active| active.error_code == error_code) =>
+      {
+        self.faults.retain(|active| active.error_code != error_code);
+        notify = true;
+        self.recovered(status)
+      }
+      (Faulted, EvEvent::PlugIn) if !self.plugged_in => {
+        self.plugged_in = true;
+        Faulted
+      }
+      (Faulted, EvEvent::Unplug) if self.plugged_in => {
+        self.plugged_in = false;
+        self.finishing = false;
+        Faulted
+      }
+      (Available, EvEvent::PlugIn) if !self.plugged_in => {
+        self.plugged_in = true;
+        Preparing
+      }
+      (Available, EvEvent::Authorize) => {
+        self.authorized = true;
+        // The cable stayed in after a connection timeout
+        if self.plugged_in {
+          action = Some(ConnectorAction::StartTransaction);
+        }
+        Preparing
+      }
+      (Available, EvEvent::Unplug) if self.plugged_in => {
+        self.plugged_in = false;
+        Available
+      }
+      (Preparing, EvEvent::PlugIn) if !self.plugged_in => {
+        self.plugged_in = true;
+        if self.authorized {
+          action = Some(ConnectorAction::StartTransaction);
+        }
+        Preparing
+      }
+      (Preparing, EvEvent::Authorize) if !self.authorized => {
+        self.authorized = true;
+        if self.plugged_in {
+          action = Some(ConnectorAction::StartTransaction);
+        }
+        Preparing
+      }
+      (Preparing, EvEvent::TransactionStarted) if self.plugged_in && self.authorized => {
+        self.transaction = true;
+        if self.zero_limit {
+          SuspendedEVSE
+        } else {
+          Charging
+        }
+      }
+      (Preparing, EvEvent::Unplug) if self.plugged_in => {
+        self.plugged_in = false;
+        self.authorized = false;
+        Available
+      }
+      // The cable stays in until the driver unplugs it
+      (Preparing, EvEvent::ConnectionTimeout) if !self.transaction => {
+        self.authorized = false;
+        Available
+      }
+      (Charging, EvEvent::EvSuspend) | (SuspendedEVSE, EvEvent::EvSuspend) if self.plugged_in => {
+        SuspendedEV
+      }
+      (SuspendedEV, EvEvent::EvResume) if self.plugged_in => Charging,
+      (SuspendedEV, EvEvent::PlugIn) if !self.plugged_in => {
+        self.plugged_in = true;
+        Charging
+      }
+      (Charging, EvEvent::EvseSuspend) => SuspendedEVSE,
+      (SuspendedEVSE, EvEvent::EvseResume) => Charging,
+      (Charging | SuspendedEV | SuspendedEVSE, EvEvent::Stop(reason)) => {
+        action = Some(ConnectorAction::StopTransaction(reason));
+        self.finish_transaction()
+      }
+      (Charging | SuspendedEV | SuspendedEVSE, EvEvent::Unplug) if self.plugged_in => {
+        self.plugged_in = false;
+        if self.stop_transaction_on_ev_side_disconnect {
+          action = Some(ConnectorAction::StopTransaction(
+            CommonStopReason::EVDisconnected,
+          ));
+          self.finish_transaction()
+        } else {
+          // The transaction continues until it is stopped by the driver or the CSMS
+          SuspendedEV
+        }
+      }
+      (Finishing, EvEvent::Unplug) => {
+        self.plugged_in = false;
+        Available
+      }
+      (status, event) => bail!("Invalid connector transition {:?} in {:?}", event, status),
+    };
+
+    self.preparing_since = match status {
+      Preparing => self.preparing_since.or_else(|| Some(Instant::now())),
+      _ => None,
+    };
+
+    let changed = status != self.status;
+    self.status = status;
+
+    Ok(Transition {
+      status: (changed || notify).then_some(status),
+      action,
+    })
+  }
+
+  /// Status once errors have been cleared, those still active keep their effect
+  fn recovered(&self, status: CommonConnectorStatusType) -> CommonConnectorStatusType {
+    use CommonConnectorStatusType::*;
+
+    let active = |error_code: fn(&ChargePointErrorCode) -> bool| {
+      self
+        .faults
+        .iter()
+        .any(|fault| error_code(&fault.error_code))
+    };
+
+    match status {
+      Faulted if active(ChargePointErrorCode::is_fatal) => Faulted,
+      Faulted if !self.plugged_in => Available,
+      Faulted if self.finishing => Finishing,
+      Faulted => Preparing,
+      SuspendedEVSE
+        if self.transaction
+          && !self.zero_limit
+          && !active(|error_code| *error_code == ChargePointErrorCode::HighTemperature) =>
+      {
+        Charging
+      }
+      status => status,
+    }
+  }
+
+  fn finish_transaction(&mut self) -> CommonConnectorStatusType {
+    self.transaction = false;
+    self.authorized = false;
+
+    if self.plugged_in {
+      CommonConnectorStatusType::Finishing
+    } else {
+      CommonConnectorStatusType::Available
+    }
+  }
+}
+
+/// Applies an EV event to the connector and sends the resulting OCPP messages.
+/// Invalid transitions are logged and ignored.
+pub async fn handle_ev_event<S>(
+  connector: &mut Connector,
+  generator: &dyn MessageGenerator,
+  ws_tx: &mut S,
+  event: EvEvent,
+) -> Result<Option<ConnectorAction>>
+where
+  S: SinkExt<Message, Error = tungstenite::Error> + Unpin,
+{
+  let trigger = match &event {
+    EvEvent::PlugIn => Some(CommonTransactionTrigger::CablePluggedIn),
+    EvEvent::Authorize => Some(CommonTransactionTrigger::Authorized),
+    EvEvent::Unplug => Some(CommonTransactionTrigger::EVDeparted),
+    EvEvent::ConnectionTimeout => Some(CommonTransactionTrigger::EVConnectTimeout),
+    EvEvent::EvSuspend
+    | EvEvent::EvResume
+    | EvEvent::EvseSuspend
+    | EvEvent::EvseResume
+    | EvEvent::Fault(_)
+    | EvEvent::Recover
+    | EvEvent::ClearFault(_) => Some(CommonTransactionTrigger::ChargingStateChanged),
+    EvEvent::TransactionStarted | EvEvent::Stop(_) => None,
+  };
+
+  let transition = match connector.handle(event) {
+    Ok(transition) => transition,
+    Err(err) => {
+      warn!("{err}");
+      return Ok(None);
+    }
+  };
+
+  if let Some(ConnectorAction::StopTransaction(reason)) = transition.action {
+    send(ws_tx, generator.stop_transaction(reason).await).await?;
+  }
+
+  if let Some(status) = transition.status {
+    send(
+      ws_tx,
+      generator
+        .status_notification(status, connector.fault())
+        .await,
+    )
+    .await?;
+  }
+
+  if let Some(trigger) = trigger {
+    let transaction_event = generator
+      .transaction_trigger(trigger, connector.status())
+      .await;
+    if !transaction_event.is_null() {
+      send(ws_tx, transaction_event).await?;
+    }
+  }
+
+  if let Some(ConnectorAction::StartTransaction) = transition.action {
+    send(ws_tx, generator.start_transaction().await).await?;
+  }
+
+  Ok(transition.action)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use CommonConnectorStatusType::*;
+
+  fn fault(error_code: ChargePointErrorCode) -> ConnectorFault {
+    ConnectorFault {
+      error_code,
+      vendor_error_code: None,
+      info: None,
+    }
+  }
+
+  /// Connector with a transaction running
+  fn charging(settings: &ChargePointSettings) -> Connector {
+    let mut connector = Connector::new(settings);
+    connector.handle(EvEvent::PlugIn).unwrap();
+    connector.handle(EvEvent::Authorize).unwrap();
+    connector.handle(EvEvent::TransactionStarted).unwrap();
+    connector
+  }
+
+  #[test]
+  fn plug_in_then_authorize_starts_the_transaction() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+
+    let transition = connector.handle(EvEvent::PlugIn).unwrap();
+    assert_eq!(transition.status, Some(Preparing));
+    assert_eq!(transition.action, None);
+
+    let transition = connector.handle(EvEvent::Authorize).unwrap();
+    assert_eq!(transition.status, None);
+    assert_eq!(transition.action, Some(ConnectorAction::StartTransaction));
+
+    let transition = connector.handle(EvEvent::TransactionStarted).unwrap();
+    assert_eq!(transition.status, Some(Charging));
+    assert!(connector.has_transaction());
+  }
+
+  #[test]
+  fn authorize_then_plug_in_starts_the_transaction() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+
+    assert_eq!(
+      connector.handle(EvEvent::Authorize).unwrap().status,
+      Some(Preparing)
+    );
+    let transition = connector.handle(EvEvent::PlugIn).unwrap();
+    assert_eq!(transition.action, Some(ConnectorAction::StartTransaction));
+  }
+
+  #[test]
+  fn ev_and_evse_suspend_and_resume() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    assert_eq!(
+      connector.handle(EvEvent::EvSuspend).unwrap().status,
+      Some(SuspendedEV)
+    );
+    assert_eq!(
+      connector.handle(EvEvent::EvResume).unwrap().status,
+      Some(Charging)
+    );
+    assert_eq!(
+      connector.handle(EvEvent::EvseSuspend).unwrap().status,
+      Some(SuspendedEVSE)
+    );
+    assert_eq!(
+      connector.handle(EvEvent::EvseResume).unwrap().status,
+      Some(Charging)
+    );
+  }
+
+  #[test]
+  fn stop_then_unplug_frees_the_connector() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    let transition = connector
+      .handle(EvEvent::Stop(CommonStopReason::Remote))
+      .unwrap();
+    assert_eq!(transition.status, Some(Finishing));
+    assert_eq!(
+      transition.action,
+      Some(ConnectorAction::StopTransaction(CommonStopReason::Remote))
+    );
+
+    assert_eq!(
+      connector.handle(EvEvent::Unplug).unwrap().status,
+      Some(Available)
+    );
+    assert!(!connector.has_transaction());
+  }
+
+  #[test]
+  fn unplug_stops_the_transaction_on_ev_side_disconnect() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    let transition = connector.handle(EvEvent::Unplug).unwrap();
+    assert_eq!(transition.status, Some(Available));
+    assert_eq!(
+      transition.action,
+      Some(ConnectorAction::StopTransaction(
+        CommonStopReason::EVDisconnected
+      ))
+    );
+  }
+
+  #[test]
+  fn unplug_suspends_the_transaction_without_ev_side_disconnect() {
+    let settings = ChargePointSettings {
+      stop_transaction_on_ev_side_disconnect: false,
+      ..Default::default()
+    };
+    let mut connector = charging(&settings);
+
+    let transition = connector.handle(EvEvent::Unplug).unwrap();
+    assert_eq!(transition.status, Some(SuspendedEV));
+    assert_eq!(transition.action, None);
+
+    assert_eq!(
+      connector.handle(EvEvent::PlugIn).unwrap().status,
+      Some(Charging)
+    );
+  }
+
+  #[test]
+  fn connection_timeout_only_while_preparing() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    assert_eq!(connector.connection_timeout_deadline(), None);
+
+    connector.handle(EvEvent::PlugIn).unwrap();
+    assert!(connector.connection_timeout_deadline().is_some());
+
+    let transition = connector.handle(EvEvent::ConnectionTimeout).unwrap();
+    assert_eq!(transition.status, Some(Available));
+    assert_eq!(connector.connection_timeout_deadline(), None);
+
+    let mut connector = charging(&ChargePointSettings::default());
+    assert_eq!(connector.connection_timeout_deadline(), None);
+    assert!(connector.handle(EvEvent::ConnectionTimeout).is_err());
+  }
+
+  #[test]
+  fn cable_stays_in_after_a_connection_timeout() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector.handle(EvEvent::PlugIn).unwrap();
+    connector.handle(EvEvent::ConnectionTimeout).unwrap();
+    assert!(connector.is_plugged_in());
+
+    // The driver presents a tag, the transaction starts with the cable still in
+    assert!(connector.handle(EvEvent::PlugIn).is_err());
+    let transition = connector.handle(EvEvent::Authorize).unwrap();
+    assert_eq!(transition.status, Some(Preparing));
+    assert_eq!(transition.action, Some(ConnectorAction::StartTransaction));
+
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector.handle(EvEvent::PlugIn).unwrap();
+    connector.handle(EvEvent::ConnectionTimeout).unwrap();
+    assert_eq!(connector.handle(EvEvent::Unplug).unwrap().status, None);
+    assert!(!connector.is_plugged_in());
+    assert_eq!(
+      connector.handle(EvEvent::PlugIn).unwrap().status,
+      Some(Preparing)
+    );
+  }
+
+  #[test]
+  fn connection_timeout_of_0_never_expires() {
+    let settings = ChargePointSettings {
+      connection_timeout: 0,
+      ..Default::default()
+    };
+    let mut connector = Connector::new(&settings);
+    connector.handle(EvEvent::PlugIn).unwrap();
+
+    assert_eq!(connector.connection_timeout_deadline(), None);
+  }
+
+  #[test]
+  fn fatal_fault_stops_the_transaction() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    let transition = connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+    assert_eq!(transition.status, Some(Faulted));
+    assert_eq!(
+      transition.action,
+      Some(ConnectorAction::StopTransaction(CommonStopReason::Other))
+    );
+
+    assert_eq!(
+      connector.handle(EvEvent::Recover).unwrap().status,
+      Some(Finishing)
+    );
+    assert!(connector.fault().is_none());
+  }
+
+  #[test]
+  fn fatal_fault_while_preparing_recovers_to_preparing_with_the_cable_in() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector.handle(EvEvent::PlugIn).unwrap();
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+
+    assert_eq!(
+      connector.handle(EvEvent::Recover).unwrap().status,
+      Some(Preparing)
+    );
+    assert!(!connector.has_transaction());
+    assert!(connector.connection_timeout_deadline().is_some());
+  }
+
+  #[test]
+  fn fatal_fault_recovers_to_available_without_the_cable() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector.handle(EvEvent::Authorize).unwrap();
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+    assert_eq!(
+      connector.handle(EvEvent::Recover).unwrap().status,
+      Some(Available)
+    );
+
+    let mut connector = charging(&ChargePointSettings::default());
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+    connector.handle(EvEvent::Unplug).unwrap();
+    assert_eq!(
+      connector.handle(EvEvent::Recover).unwrap().status,
+      Some(Available)
+    );
+  }
+
+  #[test]
+  fn clearing_one_fault_keeps_the_others() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::OverVoltage)))
+      .unwrap();
+
+    let transition = connector
+      .handle(EvEvent::ClearFault(ChargePointErrorCode::GroundFailure))
+      .unwrap();
+    assert_eq!(transition.status, Some(Faulted));
+    assert_eq!(
+      connector.fault().map(|fault| fault.error_code),
+      Some(ChargePointErrorCode::OverVoltage)
+    );
+    assert!(
+      connector
+        .handle(EvEvent::ClearFault(ChargePointErrorCode::GroundFailure))
+        .is_err()
+    );
+
+    let transition = connector
+      .handle(EvEvent::ClearFault(ChargePointErrorCode::OverVoltage))
+      .unwrap();
+    assert_eq!(transition.status, Some(Available));
+    assert!(connector.fault().is_none());
+  }
+
+  #[test]
+  fn non_fatal_fault_left_does_not_keep_the_connector_faulted() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::GroundFailure)))
+      .unwrap();
+    connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::WeakSignal)))
+      .unwrap();
+
+    let transition = connector
+      .handle(EvEvent::ClearFault(ChargePointErrorCode::GroundFailure))
+      .unwrap();
+    assert_eq!(transition.status, Some(Available));
+    assert_eq!(
+      connector.fault().map(|fault| fault.error_code),
+      Some(ChargePointErrorCode::WeakSignal)
+    );
+  }
+
+  #[test]
+  fn high_temperature_derates_until_recovered() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    let transition = connector
+      .handle(EvEvent::Fault(fault(ChargePointErrorCode::HighTemperature)))
+      .unwrap();
+    assert_eq!(transition.status, Some(SuspendedEVSE));
+    assert_eq!(transition.action, None);
+
+    assert_eq!(
+      connector.handle(EvEvent::Recover).unwrap().status,
+      Some(Charging)
+    );
+  }
+
+  #[test]
+  fn zero_charging_limit_suspends_the_evse() {
+    let mut connector = charging(&ChargePointSettings::default());
+
+    assert_eq!(
+      connector.set_charging_limit(Some(0.0)),
+      Some(EvEvent::EvseSuspend)
+    );
+    connector.handle(EvEvent::EvseSuspend).unwrap();
+    assert_eq!(connector.set_charging_limit(Some(0.0)), None);
+
+    assert_eq!(
+      connector.set_charging_limit(None),
+      Some(EvEvent::EvseResume)
+    );
+    connector.handle(EvEvent::EvseResume).unwrap();
+    assert_eq!(connector.status(), Charging);
+  }
+
+  #[test]
+  fn zero_charging_limit_before_the_transaction_starts_suspended() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    assert_eq!(connector.set_charging_limit(Some(0.0)), None);
+
+    connector.handle(EvEvent::PlugIn).unwrap();
+    connector.handle(EvEvent::Authorize).unwrap();
+    let transition = connector.handle(EvEvent::TransactionStarted).unwrap();
+    assert_eq!(transition.status, Some(SuspendedEVSE));
+
+    assert_eq!(
+      connector.set_charging_limit(Some(11000.0)),
+      Some(EvEvent::EvseResume)
+    );
+  }
+
+  #[test]
+  fn invalid_transitions_are_rejected() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+
+    assert!(connector.handle(EvEvent::TransactionStarted).is_err());
+    assert!(connector.handle(EvEvent::EvSuspend).is_err());
+    assert!(
+      connector
+        .handle(EvEvent::Stop(CommonStopReason::Local))
+        .is_err()
+    );
+    assert!(connector.handle(EvEvent::Unplug).is_err());
+    assert!(connector.handle(EvEvent::Recover).is_err());
+
+    // Not authorized yet
+    connector.handle(EvEvent::PlugIn).unwrap();
+    assert!(connector.handle(EvEvent::TransactionStarted).is_err());
+    assert!(connector.handle(EvEvent::PlugIn).is_err());
+    assert_eq!(connector.status(), Preparing);
+  }
+}
@@ -0,0 +1,437 @@
+//! Runtime control of running charge points: commands sent from outside the simulator and
+//! the state the charge points report back
+
+use std::{
+  collections::BTreeMap,
+  fmt::Display,
+  future::pending,
+  sync::{Arc, Mutex},
+};
+
+use common::{ChargePointErrorCode, ScenarioAction};
+use ocpp::{
+  handler::MessageFrameType,
+  types::{CommonConnectorStatusType, CommonStopReason, ConnectorFault},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{select, sync::mpsc};
+use tracing::info;
+
+use crate::{
+  connector::{Connector, EvEvent},
+  core::sleep_until,
+  logs::MessageLog,
+  scenario::{Scenario, parse_status},
+};
+
+/// Command for a running charge point
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ControlCommand {
+  /// Connects again after a `disconnect` without duration
+  Connect,
+  /// Drops the connection for the duration in seconds, until `connect` without one
+  Disconnect { duration: Option<u64> },
+  /// Plugs in and presents the tag, the tag of the charge point or its pool when omitted
+  StartTransaction { id_tag: Option<String> },
+  /// Drives the connector to the status with the EV event leading there
+  SetStatus { status: String },
+  /// Sends a call of any charge point action with the payload as given
+  SendCall { call: String, payload: Value },
+  /// Reboots like a hard reset requested by the CSMS
+  Reset,
+  /// Action of a scenario step, e.g. `plug_in` or `present_tag`
+  #[serde(untagged)]
+  Step(ScenarioAction),
+}
+
+impl From<ScenarioAction> for ControlCommand {
+  fn from(action: ScenarioAction) -> Self {
+    match action {
+      ScenarioAction::Disconnect { duration } => Self::Disconnect {
+        duration: Some(duration),
+      },
+      action => Self::Step(action),
+    }
+  }
+}
+
+/// Why a command did not reach a charge point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlError {
+  UnknownChargePoint,
+  /// The charge point is not running anymore
+  Stopped,
+  /// Waiting and expecting steps only make sense in a scenario
+  ScenarioOnly,
+}
+
+impl Display for ControlError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      Self::UnknownChargePoint => "unknown charge point",
+      Self::Stopped => "charge point stopped",
+      Self::ScenarioOnly => "action only available in scenarios",
+    })
+  }
+}
+
+impl std::error::Error for ControlError {}
+
+/// State a charge point reports after every event it handled
+#[derive(Debug, Clone, Serialize)]
+pub struct ChargePointState {
+  pub id: String,
+  pub running: bool,
+  pub connected: bool,
+  /// Status of the last `BootNotification` answer
+  pub boot_status: Option<String>,
+  pub status: String,
+  pub plugged_in: bool,
+  pub transaction: bool,
+  pub transaction_id: Option<i32>,
+  /// Energy register of the session, Wh
+  pub energy_wh: f64,
+  pub error_code: Option<String>,
+  /// CallErrors the CSMS answered with
+  pub call_errors: u32,
+}
+
+struct ChargePointHandle {
+  commands: mpsc::UnboundedSender<ControlCommand>,
+  state: Arc<Mutex<ChargePointState>>,
+  messages: MessageLog,
+}
+
+impl ChargePointHandle {
+  fn state(&self) -> ChargePointState {
+    ChargePointState {
+      running: !self.commands.is_closed(),
+      ..self.state.lock().unwrap().clone()
+    }
+  }
+}
+
+/// Charge points that take commands, shared with the control API
+#[derive(Default)]
+pub struct ControlRegistry {
+  charge_points: Mutex<BTreeMap<String, ChargePointHandle>>,
+}
+
+impl ControlRegistry {
+  pub fn register(&self, charge_point_id: &str, messages: MessageLog) -> ChargePointControl {
+    let (commands, receiver) = mpsc::unbounded_channel();
+    let state = Arc::new(Mutex::new(ChargePointState {
+      id: charge_point_id.to_string(),
+      running: true,
+      connected: true,
+      boot_status: None,
+      status: format!("{:?}", CommonConnectorStatusType::Available),
+      plugged_in: false,
+      transaction: false,
+      transaction_id: None,
+      energy_wh: 0.0,
+      error_code: None,
+      call_errors: 0,
+    }));
+
+    self.charge_points.lock().unwrap().insert(
+      charge_point_id.to_string(),
+      ChargePointHandle {
+        commands,
+        state: Arc::clone(&state),
+        messages: messages.clone(),
+      },
+    );
+
+    ChargePointControl {
+      commands: receiver,
+      state,
+      messages,
+    }
+  }
+
+  pub fn ids(&self) -> Vec<String> {
+    self.charge_points.lock().unwrap().keys().cloned().collect()
+  }
+
+  pub fn states(&self) -> Vec<ChargePointState> {
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .values()
+      .map(ChargePointHandle::state)
+      .collect()
+  }
+
+  pub fn state(&self, charge_point_id: &str) -> Option<ChargePointState> {
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .get(charge_point_id)
+      .map(ChargePointHandle::state)
+  }
+
+  /// Message log of the charge point
+  pub fn messages(&self, charge_point_id: &str) -> Option<MessageLog> {
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .get(charge_point_id)
+      .map(|handle| handle.messages.clone())
+  }
+
+  pub fn send(&self, charge_point_id: &str, command: ControlCommand) -> Result<(), ControlError> {
+    if let ControlCommand::Step(
+      ScenarioAction::WaitForStatus { .. }
+      | ScenarioAction::ExpectCall { .. }
+      | ScenarioAction::ExpectResponse { .. },
+    ) = command
+    {
+      return Err(ControlError::ScenarioOnly);
+    }
+
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .get(charge_point_id)
+      .ok_or(ControlError::UnknownChargePoint)?
+      .commands
+      .send(command)
+      .map_err(|_| ControlError::Stopped)
+  }
+}
+
+/// Commands for one charge point and the state it reports
+pub struct ChargePointControl {
+  commands: mpsc::UnboundedReceiver<ControlCommand>,
+  state: Arc<Mutex<ChargePointState>>,
+  messages: MessageLog,
+}
+
+impl ChargePointControl {
+  pub fn update(&self, connector: &Connector, (transaction_id, energy_wh): (Option<i32>, f64)) {
+    let mut state = self.state.lock().unwrap();
+    state.status = format!("{:?}", connector.status());
+    state.plugged_in = connector.is_plugged_in();
+    state.transaction = connector.has_transaction();
+    state.transaction_id = transaction_id.filter(|_| state.transaction);
+    state.energy_wh = energy_wh;
+    state.error_code = connector
+      .fault()
+      .map(|fault| format!("{:?}", fault.error_code));
+  }
+
+  /// Takes the boot status and the CallErrors from the answers of the CSMS
+  pub fn observe_frame(&self, frame: &MessageFrameType) {
+    let frame = frame.to_frame();
+    match frame[0].as_u64() {
+      Some(3) => {
+        let Some(action) = frame[1]
+          .as_str()
+          .and_then(|msg_id| self.messages.sent_action(msg_id))
+        else {
+          return;
+        };
+        if action == "BootNotification" {
+          self.state.lock().unwrap().boot_status = frame[2]["status"].as_str().map(str::to_string);
+        }
+      }
+      Some(4) => self.state.lock().unwrap().call_errors += 1,
+      _ => {}
+    }
+  }
+
+  pub fn set_connected(&self, connected: bool) {
+    self.state.lock().unwrap().connected = connected;
+  }
+}
+
+/// Next command sent from outside, never when there is no control
+async fn recv(control: &mut Option<ChargePointControl>) -> Option<ControlCommand> {
+  let Some(control) = control else {
+    return pending().await;
+  };
+
+  let command = control.commands.recv().await;
+  if let Some(command) = &command {
+    info!("🕹️  {command:?}");
+  }
+  command
+}
+
+/// Next action of the scenario once it is due, or the next command sent from outside
+pub async fn next_command(
+  scenario: &mut Option<Scenario>,
+  control: &mut Option<ChargePointControl>,
+) -> Option<ControlCommand> {
+  select! {
+    _ = sleep_until(scenario.as_ref().and_then(Scenario::next_deadline)) => {
+      scenario.as_mut().and_then(Scenario::pop).map(ControlCommand::from)
+    },
+    Some(command) = recv(control) => Some(command),
+  }
+}
+
+/// EV event taking the connector to the status, `None` when no event leads there
+pub fn status_event(connector: &Connector, status: &str) -> Option<EvEvent> {
+  use CommonConnectorStatusType::*;
+
+  Some(match (parse_status(status)?, connector.status()) {
+    (Preparing, _) => EvEvent::PlugIn,
+    (Charging, SuspendedEV) => EvEvent::EvResume,
+    (Charging, SuspendedEVSE) => EvEvent::EvseResume,
+    (SuspendedEV, _) => EvEvent::EvSuspend,
+    (SuspendedEVSE, _) => EvEvent::EvseSuspend,
+    (Finishing, _) => EvEvent::Stop(CommonStopReason::Local),
+    (Available, Faulted) => EvEvent::Recover,
+    (Available, _) => EvEvent::Unplug,
+    // Only a fatal error faults the connector
+    (Faulted, _) => EvEvent::Fault(ConnectorFault {
+      error_code: ChargePointErrorCode::InternalError,
+      vendor_error_code: None,
+      info: None,
+    }),
+    _ => return None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use common::shared_data::ChargePointSettings;
+  use ocpp::{handler::MessageFrame, v1_6::types::OcppAction};
+  use serde_json::json;
+
+  use super::*;
+  use crate::logs::{Logs, MessageTaps};
+
+  fn command(value: Value) -> ControlCommand {
+    serde_json::from_value(value).unwrap()
+  }
+
+  #[test]
+  fn commands_and_scenario_steps_are_parsed() {
+    assert!(matches!(
+      command(json!({ "action": "disconnect" })),
+      ControlCommand::Disconnect { duration: None }
+    ));
+    assert!(matches!(
+      command(json!({ "action": "start_transaction", "id_tag": "TAG1" })),
+      ControlCommand::StartTransaction { id_tag: Some(id_tag) } if id_tag == "TAG1"
+    ));
+    assert!(matches!(
+      command(json!({ "action": "send_call", "call": "DataTransfer", "payload": {} })),
+      ControlCommand::SendCall { call, .. } if call == "DataTransfer"
+    ));
+    assert!(matches!(
+      command(json!({ "action": "plug_in" })),
+      ControlCommand::Step(ScenarioAction::PlugIn)
+    ));
+    assert!(serde_json::from_value::<ControlCommand>(json!({ "action": "fly" })).is_err());
+
+    // A scenario disconnect always has a duration
+    assert!(matches!(
+      ControlCommand::from(ScenarioAction::Disconnect { duration: 20 }),
+      ControlCommand::Disconnect { duration: Some(20) }
+    ));
+  }
+
+  #[tokio::test]
+  async fn commands_reach_the_registered_charge_point() {
+    let registry = ControlRegistry::default();
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    let mut control = Some(registry.register("CP1", logs.messages().clone()));
+
+    registry
+      .send("CP1", command(json!({ "action": "reset" })))
+      .unwrap();
+    assert!(matches!(
+      next_command(&mut None, &mut control).await,
+      Some(ControlCommand::Reset)
+    ));
+
+    assert_eq!(
+      registry.send("CP2", ControlCommand::Reset).unwrap_err(),
+      ControlError::UnknownChargePoint
+    );
+    let wait = command(json!({ "action": "wait_for_status", "status": "Charging", "timeout": 5 }));
+    assert_eq!(
+      registry.send("CP1", wait).unwrap_err(),
+      ControlError::ScenarioOnly
+    );
+
+    drop(control);
+    assert_eq!(
+      registry.send("CP1", ControlCommand::Reset).unwrap_err(),
+      ControlError::Stopped
+    );
+    assert!(!registry.state("CP1").unwrap().running);
+    assert_eq!(registry.ids(), ["CP1"]);
+  }
+
+  #[test]
+  fn state_follows_the_connector_and_the_answers_of_the_csms() {
+    let registry = ControlRegistry::default();
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    let control = registry.register("CP1", logs.messages().clone());
+
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    for event in [
+      EvEvent::PlugIn,
+      EvEvent::Authorize,
+      EvEvent::TransactionStarted,
+    ] {
+      connector.handle(event).unwrap();
+    }
+    control.update(&connector, (Some(7), 1500.0));
+
+    logs
+      .messages()
+      .record(true, r#"[2,"1","BootNotification",{}]"#);
+    control.observe_frame(&MessageFrameType::V1_6(MessageFrame::CallResult {
+      msg_id: "1".to_string(),
+      payload: json!({ "status": "Pending", "currentTime": "", "interval": 10 }),
+    }));
+    control.observe_frame(&MessageFrameType::V1_6(MessageFrame::CallError {
+      msg_id: "2".to_string(),
+      error_code: "InternalError".to_string(),
+      description: String::new(),
+    }));
+    control.observe_frame(&MessageFrameType::V1_6(MessageFrame::Call {
+      msg_id: "3".to_string(),
+      action: OcppAction::Reset,
+      payload: json!({ "type": "Hard" }),
+    }));
+
+    let state = registry.state("CP1").unwrap();
+    assert_eq!(state.status, "Charging");
+    assert!(state.plugged_in);
+    assert!(state.transaction);
+    assert_eq!(state.transaction_id, Some(7));
+    assert_eq!(state.energy_wh, 1500.0);
+    assert_eq!(state.boot_status.as_deref(), Some("Pending"));
+    assert_eq!(state.call_errors, 1);
+  }
+
+  #[test]
+  fn status_leads_to_the_event_taking_the_connector_there() {
+    let mut connector = Connector::new(&ChargePointSettings::default());
+    assert_eq!(status_event(&connector, "Preparing"), Some(EvEvent::PlugIn));
+    assert_eq!(status_event(&connector, "Available"), Some(EvEvent::Unplug));
+    assert_eq!(status_event(&connector, "Reserved"), None);
+    assert_eq!(status_event(&connector, "Parked"), None);
+
+    let fault = status_event(&connector, "Faulted").unwrap();
+    connector.handle(fault).unwrap();
+    assert_eq!(connector.status(), CommonConnectorStatusType::Faulted);
+    assert_eq!(
+      status_event(&connector, "Available"),
+      Some(EvEvent::Recover)
+    );
+  }
+}
@@ -1,36 +1,252 @@
-use std::{fmt::Display, str::FromStr, sync::Arc};
+use std::{
+  fmt::Display,
+  pin::Pin,
+  str::FromStr,
+  sync::Arc,
+  task::{Context, Poll},
+};
 
 use anyhow::Result;
 use colored::Colorize;
-use common::{ChargePointConfig, GeneralConfig, OcppVersion};
-use futures::SinkExt;
-use http::Uri;
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
-use tracing::info;
-use tungstenite::{ClientRequestBuilder, Message};
-
-pub async fn connect<'a>(
+use common::{ChargePointConfig, GeneralConfig};
+use futures::{Sink, SinkExt, Stream};
+use http::{StatusCode, Uri};
+use ocpp::{generator::MessageGenerator, types::CommonSecurityEvent};
+use tokio::{net::TcpStream, time::Instant};
+use tokio_tungstenite::{
+  Connector, MaybeTlsStream, WebSocketStream, connect_async_tls_with_config,
+};
+use tracing::{info, warn};
+use tungstenite::{ClientRequestBuilder, Message, protocol::WebSocketConfig};
+
+use crate::{logs::MessageLog, metrics::ConnectionGuard, security::Security};
+
+/// OCPP frames are small, the default 128 KiB buffers per connection would dominate the
+/// memory of thousands of charge points
+const READ_BUFFER_SIZE: usize = 4 * 1024;
+
+/// WebSocket to the CSMS, the text messages it carries are recorded in the message log
+pub struct WsStream {
+  /// `None` while the network is down
+  inner: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+  log: MessageLog,
+  /// Counts the connection as open until it is closed or dropped
+  connection: Option<ConnectionGuard>,
+}
+
+impl WsStream {
+  /// Stands in for the connection while the network is down: nothing is received and what
+  /// is sent is lost
+  pub fn offline(log: &MessageLog) -> Self {
+    Self {
+      inner: None,
+      log: log.clone(),
+      connection: None,
+    }
+  }
+}
+
+impl Stream for WsStream {
+  type Item = Result<Message, tungstenite::Error>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let Some(inner) = &mut self.inner else {
+      return Poll::Pending;
+    };
+    let next = Pin::new(inner).poll_next(cx);
+    if let Poll::Ready(Some(Ok(Message::Text(text)))) = &next {
+      self.log.record(false, text.as_str());
+    }
+    next
+  }
+}
+
+impl Sink<Message> for WsStream {
+  type Error = tungstenite::Error;
+
+  fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    match &mut self.inner {
+      Some(inner) => Pin::new(inner).poll_ready(cx),
+      None => Poll::Ready(Ok(())),
+    }
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+    let this = &mut *self;
+    let Some(inner) = &mut this.inner else {
+      return Ok(());
+    };
+    if let Message::Text(text) = &item {
+      this.log.record(true, text.as_str());
+    }
+    Pin::new(inner).start_send(item)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    match &mut self.inner {
+      Some(inner) => Pin::new(inner).poll_flush(cx),
+      None => Poll::Ready(Ok(())),
+    }
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    let Some(inner) = &mut self.inner else {
+      return Poll::Ready(Ok(()));
+    };
+    let closed = Pin::new(inner).poll_close(cx);
+    if closed.is_ready() {
+      self.connection = None;
+    }
+    closed
+  }
+}
+
+pub async fn connect(
   general_config: Arc<GeneralConfig>,
-  cp_config: &'a ChargePointConfig,
-) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+  cp_config: &ChargePointConfig,
+  security: &Security,
+  log: &MessageLog,
+) -> Result<WsStream> {
   let uri = Uri::from_str(&format!(
     "{}/{}",
-    general_config.server_url.trim_end_matches('/'),
+    security.server_url(&general_config).trim_end_matches('/'),
     cp_config.id
   ))?;
 
-  info!(target: "simulator", "connecting to CSMS at {}", uri.to_string().cyan());
+  info!(
+    target: "simulator",
+    "connecting to CSMS at {} (security profile {})",
+    uri.to_string().cyan(),
+    security.profile()
+  );
+
+  let mut request =
+    ClientRequestBuilder::new(uri).with_sub_protocol(general_config.ocpp_version.to_string());
 
-  let request = ClientRequestBuilder::new(uri)
-    .with_header("Authorization", &cp_config.auth_header)
-    .with_sub_protocol(OcppVersion::V1_6.to_string());
+  if let Some(authorization) = security.authorization_header(cp_config) {
+    request = request.with_header("Authorization", authorization);
+  }
 
-  let (ws_stream, _) = connect_async(request).await?;
+  let tls_connector = security.tls_connector()?.map(Connector::NativeTls);
+  let ws_config = WebSocketConfig::default()
+    .read_buffer_size(READ_BUFFER_SIZE)
+    .write_buffer_size(0);
+  // Without Nagle, frames sent back to back (a StatusNotification right after a
+  // StartTransaction) do not wait on the delayed ACK of the previous one
+  let (ws_stream, _) = connect_async_tls_with_config(request, Some(ws_config), true, tls_connector)
+    .await
+    .inspect_err(|_| log.connection_failed())?;
+
+  Ok(WsStream {
+    inner: Some(ws_stream),
+    log: log.clone(),
+    connection: log.connection(),
+  })
+}
+
+/// Connects again after a security change, falling back to the previous profile when the
+/// CSMS cannot be reached with the new one
+pub async fn reconnect(
+  general_config: Arc<GeneralConfig>,
+  cp_config: &ChargePointConfig,
+  security: &mut Security,
+  log: &MessageLog,
+) -> Result<WsStream> {
+  match connect(general_config.clone(), cp_config, security, log).await {
+    Ok(ws_stream) => {
+      security.confirm();
+      Ok(ws_stream)
+    }
+    Err(err) if security.revert() => {
+      warn!("Connection with the new security settings failed: {err}");
+      if let Some(event) = connection_security_event(&err) {
+        security.record_event(event, Some(err.to_string()));
+      }
+      connect(general_config, cp_config, security, log).await
+    }
+    Err(err) => Err(err),
+  }
+}
 
+/// Connects again as after a reboot and sends the `BootNotification`, followed by the
+/// transaction events left unconfirmed
+pub async fn reboot(
+  general_config: Arc<GeneralConfig>,
+  cp_config: &ChargePointConfig,
+  security: &mut Security,
+  log: &MessageLog,
+  generator: &dyn MessageGenerator,
+) -> Result<WsStream> {
+  info!("Rebooting");
+  let mut ws_stream = reconnect(general_config, cp_config, security, log).await?;
+  generator.set_offline(false).await;
+  send(&mut ws_stream, generator.boot_notification().await).await?;
+  send_queued_transaction_events(&mut ws_stream, generator).await?;
+  Ok(ws_stream)
+}
+
+/// Connects again once the network is back and sends the transaction events queued
+/// meanwhile
+pub async fn back_online(
+  general_config: Arc<GeneralConfig>,
+  cp_config: &ChargePointConfig,
+  security: &Security,
+  log: &MessageLog,
+  generator: &dyn MessageGenerator,
+) -> Result<WsStream> {
+  info!("Network back");
+  let mut ws_stream = connect(general_config, cp_config, security, log).await?;
+  generator.set_offline(false).await;
+  send_queued_transaction_events(&mut ws_stream, generator).await?;
   Ok(ws_stream)
 }
 
+/// Security event of a failed connection attempt, if the failure is security related
+fn connection_security_event(err: &anyhow::Error) -> Option<CommonSecurityEvent> {
+  match err.downcast_ref::<tungstenite::Error>()? {
+    tungstenite::Error::Tls(_) => Some(CommonSecurityEvent::InvalidCentralSystemCertificate),
+    tungstenite::Error::Http(response) if response.status() == StatusCode::UNAUTHORIZED => {
+      Some(CommonSecurityEvent::FailedToAuthenticateAtCentralSystem)
+    }
+    _ => None,
+  }
+}
+
+/// Sends a `SecurityEventNotification` for each recorded security event
+pub async fn send_security_events<S>(
+  ws_tx: &mut S,
+  security: &mut Security,
+  generator: &dyn MessageGenerator,
+) -> Result<()>
+where
+  S: SinkExt<Message, Error = tungstenite::Error> + Unpin,
+{
+  for (event, tech_info) in security.take_events() {
+    send(
+      ws_tx,
+      generator
+        .security_event_notification(&event, tech_info.as_deref())
+        .await,
+    )
+    .await?;
+  }
+  Ok(())
+}
+
+/// Sends again the transaction events the CSMS did not confirm before the connection was lost
+pub async fn send_queued_transaction_events<S>(
+  ws_tx: &mut S,
+  generator: &dyn MessageGenerator,
+) -> Result<()>
+where
+  S: SinkExt<Message, Error = tungstenite::Error> + Unpin,
+{
+  for message in generator.queued_transaction_events().await {
+    send(ws_tx, message).await?;
+  }
+  Ok(())
+}
+
 pub async fn send<S>(ws_tx: &mut S, msg: impl Display) -> Result<()>
 where
   S: SinkExt<Message, Error = tungstenite::Error> + Unpin,
@@ -42,3 +258,11 @@ where
 pub fn text(msg: impl Display) -> Message {
   Message::Text(msg.to_string().into())
 }
+
+/// Sleeps until the deadline, or forever when there is none
+pub async fn sleep_until(deadline: Option<Instant>) {
+  match deadline {
+    Some(deadline) => tokio::time::sleep_until(deadline).await,
+    None => std::future::pending::<()>().await,
+  }
+}
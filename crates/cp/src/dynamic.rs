@@ -4,93 +4,221 @@ use std::sync::Arc;
 
 use tokio::{
   select,
-  time::{self, Duration, Instant, interval, sleep},
+  sync::oneshot,
+  time::{Duration, Instant, sleep},
 };
 
-use ocpp::{OcppSession, types::CommonConnectorStatusType};
+use ocpp::{
+  OcppSession,
+  generator::MessageGenerator,
+  handler::{MessageFrame, MessageFrameType},
+  types::{
+    AuthorizationStatus, CommonConnectorStatusType, CommonFirmwareStatus, CommonOcppResponse,
+    CommonSecurityEvent, CommonSettingChange, CommonStopReason, CommonUpdateFirmwareStatus,
+    CommonUploadLogStatus,
+  },
+  v1_6::{handler::V16MessageHandler, security::SignedUpdateFirmwareRequest, types::OcppAction},
+  v2_0_1::{handler::V201MessageHandler, types::OcppAction as V201OcppAction},
+};
+use rust_ocpp::{
+  v1_6::{
+    messages::{reset::ResetRequest, update_firmware::UpdateFirmwareRequest},
+    types::ResetRequestStatus,
+  },
+  v2_0_1::{
+    enumerations::reset_enum_type::ResetEnumType,
+    messages::{
+      reset::ResetRequest as V201ResetRequest,
+      update_firmware::UpdateFirmwareRequest as V201UpdateFirmwareRequest,
+    },
+  },
+};
 
 use futures_util::{SinkExt, StreamExt};
-use tracing::{error, info};
+use serde_json::Value;
+use tracing::{error, info, warn};
 use tungstenite::Message;
 
-use crate::core::{connect, send};
+use crate::{
+  certificates::handle_certificate_call,
+  connector::{Connector, ConnectorAction, EV_UNPLUG_DELAY, EvEvent, handle_ev_event},
+  core::{
+    connect, reboot, reconnect, send, send_queued_transaction_events, send_security_events,
+    sleep_until,
+  },
+  fault::FaultInjector,
+  firmware::{FirmwareUpdate, SignedFirmwareRequest},
+  id_tags::IdTags,
+  logs::{Logs, MessageTaps},
+  plug_and_charge::PlugAndCharge,
+  security::Security,
+  security_events::SecurityEventInjector,
+  timers::TimerWheel,
+};
 
 pub struct ChargePointDynamic {
   general_config: Arc<GeneralConfig>,
   config: ChargePointConfig,
+  id_tags: IdTags,
+  taps: MessageTaps,
+  /// Periodic timers, shared with the other charge points of the simulator
+  timers: Option<Arc<TimerWheel>>,
+  /// Stops the charge point once sent, e.g. at the end of a load test
+  stop: Option<oneshot::Receiver<()>>,
 }
 
 impl ChargePointDynamic {
-  pub fn new(general_config: Arc<GeneralConfig>, config: ChargePointConfig) -> Self {
+  pub fn new(
+    general_config: Arc<GeneralConfig>,
+    config: ChargePointConfig,
+    id_tags: IdTags,
+  ) -> Self {
     Self {
       general_config,
       config,
+      id_tags,
+      taps: MessageTaps::default(),
+      timers: None,
+      stop: None,
     }
   }
 
+  /// Passes the message traffic to the recording and the metrics
+  pub fn with_taps(mut self, taps: MessageTaps) -> Self {
+    self.taps = taps;
+    self
+  }
+
+  pub fn with_timers(mut self, timers: Arc<TimerWheel>) -> Self {
+    self.timers = Some(timers);
+    self
+  }
+
+  pub fn with_stop(mut self, stop: oneshot::Receiver<()>) -> Self {
+    self.stop = Some(stop);
+    self
+  }
+
   pub async fn run(&mut self) -> Result<()> {
-    let ws_stream = connect(self.general_config.clone(), &self.config).await?;
+    let mut security = Security::new(&self.config)?;
+    let mut logs = Logs::new(
+      &self.config.id,
+      self.general_config.message_log_size,
+      self.taps.clone(),
+    );
+    let ws_stream = connect(
+      self.general_config.clone(),
+      &self.config,
+      &security,
+      logs.messages(),
+    )
+    .await?;
     let (mut ws_tx, mut ws_rx) = ws_stream.split();
 
     let OcppSession {
       generator,
       mut handler,
-    } = OcppSession::new(&self.general_config.ocpp_version, self.config.clone()).await;
+    } = OcppSession::new(&self.general_config.ocpp_version, self.config.clone()).await?;
+
+    let mut connector = Connector::new(&generator.settings().await);
 
-    let mut heartbeat_interval = interval(Duration::from_secs(self.config.heartbeat_interval));
+    let timers = self.timers.clone().unwrap_or_else(TimerWheel::spawn);
+    let mut heartbeat_interval =
+      timers.interval(Duration::from_secs(self.config.heartbeat_interval));
     let mut meter_values_interval =
-      interval(Duration::from_secs(self.config.txn_meter_values_interval));
+      timers.interval(Duration::from_secs(self.config.txn_meter_values_interval));
+    // Variable monitors are evaluated against values sampled every second, only while
+    // there are monitors (OCPP 2.0.1)
+    let mut monitoring_interval = timers.interval(Duration::from_secs(1));
+    let mut monitoring = generator.has_monitors().await;
+    // Transaction state and energy register last taken by the metrics
+    let mut metered_session = None;
 
-    let mut next_start_tx = Instant::now() + Duration::from_secs(self.config.start_tx_after);
+    let mut plug_in_deadline =
+      Some(Instant::now() + Duration::from_secs(self.config.start_tx_after));
     let mut stop_tx_deadline: Option<Instant> = None;
-    let mut transaction_active = false;
+    let mut unplug_deadline: Option<Instant> = None;
+    let mut faults = FaultInjector::new(&self.config);
+    let mut firmware = FirmwareUpdate::default();
+    let mut security_events = SecurityEventInjector::new(&self.config);
+    let mut plug_and_charge = PlugAndCharge::new(&self.config, &self.general_config.ocpp_version)?;
 
     let _ = sleep(Duration::from_millis(self.config.boot_delay_interval)).await;
 
     send(&mut ws_tx, generator.boot_notification().await).await?;
 
+    if let Some(csr) = security.boot_certificate_signing_request()? {
+      send(&mut ws_tx, generator.sign_certificate(csr).await).await?;
+    }
+
+    let mut stop = self.stop.take();
     loop {
       select! {
-        _ = time::sleep_until(next_start_tx), if !transaction_active => {
-          // Sets a connector to a `Preparing` status
-          send(&mut ws_tx, generator.status_notification(
-            CommonConnectorStatusType::Preparing
-          ).await).await?;
+        // No EV is plugged into a faulted connector, the driver comes once it has recovered
+        _ = sleep_until(plug_in_deadline), if connector.status() != CommonConnectorStatusType::Faulted => {
+          plug_in_deadline = None;
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::PlugIn).await?;
 
-          send(&mut ws_tx, generator.start_transaction().await).await?;
+          // The EV identifies itself with its contract, otherwise the driver presents the tag
+          let message = match &plug_and_charge {
+            Some(plug_and_charge) => plug_and_charge.plug_in(generator.as_ref()).await?,
+            None => self.authorize_tag(generator.as_ref()).await,
+          };
+          send(&mut ws_tx, message).await?;
+        },
 
-          // Simulate a HW timeout
-          tokio::time::sleep(Duration::from_secs(5)).await;
+        _ = sleep_until(connector.connection_timeout_deadline()) => {
+          // The driver gives up and leaves with the EV
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::ConnectionTimeout).await?;
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Unplug).await?;
+          plug_in_deadline = Some(Instant::now() + Duration::from_secs(self.config.start_tx_after));
+        },
 
-          // Sets a connector to a `Charging` status
-          send(&mut ws_tx, generator.status_notification(
-            CommonConnectorStatusType::Charging
-          ).await).await?;
+        _ = sleep_until(stop_tx_deadline) => {
+          stop_tx_deadline = None;
+          handle_ev_event(
+            &mut connector,
+            generator.as_ref(),
+            &mut ws_tx,
+            EvEvent::Stop(CommonStopReason::Local),
+          ).await?;
+          unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+        },
 
-          stop_tx_deadline = Some(Instant::now() + Duration::from_secs(self.config.stop_tx_after));
-          transaction_active = true;
+        _ = sleep_until(unplug_deadline) => {
+          unplug_deadline = None;
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Unplug).await?;
+          plug_in_deadline = Some(Instant::now() + Duration::from_secs(self.config.start_tx_after));
         },
 
-        _ = async {
-          if let Some(deadline) = stop_tx_deadline {
-            time::sleep_until(deadline).await;
-          } else {
-            futures::future::pending::<()>().await;
-          }
-        }, if stop_tx_deadline.is_some() => {
-          send(&mut ws_tx, generator.stop_transaction().await).await?;
+        _ = sleep_until(faults.next_deadline()) => {
+          if let Some(event) = faults.pop() {
+            let action = handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
 
-          // Sets a connector to an `Available` status
-          send(&mut ws_tx, generator.status_notification(
-            CommonConnectorStatusType::Available
-          ).await).await?;
+            if let Some(ConnectorAction::StopTransaction(_)) = action {
+              stop_tx_deadline = None;
+            }
 
-          transaction_active = false;
-          stop_tx_deadline = None;
-          next_start_tx = Instant::now() + Duration::from_secs(self.config.start_tx_after);
+            match connector.status() {
+              CommonConnectorStatusType::Finishing => {
+                unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+              }
+              CommonConnectorStatusType::Available if plug_in_deadline.is_none() => {
+                plug_in_deadline = Some(Instant::now() + Duration::from_secs(self.config.start_tx_after));
+              }
+              _ => {}
+            }
+          }
         },
 
-        _ = meter_values_interval.tick(), if transaction_active => {
+        // 📡 Monitoring
+        _ = monitoring_interval.tick(), if monitoring => {
+          for message in generator.monitoring_events().await {
+            send(&mut ws_tx, message).await?;
+          }
+        },
+
+        _ = meter_values_interval.tick(), if connector.has_transaction() => {
           let meter_value = generator.meter_values().await;
           if !meter_value.is_null() {
             send(&mut ws_tx, meter_value.to_string()).await?;
@@ -101,12 +229,316 @@ impl ChargePointDynamic {
           send(&mut ws_tx, generator.heartbeat().await).await?;
         },
 
+        _ = sleep_until(firmware.next_deadline()) => {
+          if let Some(status) = firmware.pop() {
+            // The new firmware is running once the charge point has rebooted
+            if status == CommonFirmwareStatus::Installed {
+              if connector.has_transaction() {
+                handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Stop(CommonStopReason::Reboot)).await?;
+                stop_tx_deadline = None;
+                unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+              }
+
+              ws_tx.close().await?;
+              (ws_tx, ws_rx) = reboot(self.general_config.clone(), &self.config, &mut security, logs.messages(), generator.as_ref()).await?.split();
+              security.record_event(CommonSecurityEvent::FirmwareUpdated, None);
+            }
+
+            send(&mut ws_tx, firmware.status_notification(generator.as_ref(), status).await).await?;
+
+            if status == CommonFirmwareStatus::Downloading {
+              firmware.download();
+            }
+            send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+          }
+        },
+        fetched = firmware.fetched(), if firmware.is_downloading() => {
+          firmware.downloaded(fetched, &mut security);
+          send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+        },
+
+        _ = sleep_until(security_events.next_deadline()) => {
+          if let Some((event, tech_info)) = security_events.pop() {
+            security.record_event(event, tech_info);
+            send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+          }
+        },
+        _ = sleep_until(logs.next_deadline()) => {
+          if let Some((status, request_id)) = logs.pop() {
+            send(&mut ws_tx, generator.log_status_notification(status, request_id).await).await?;
+
+            if status == CommonUploadLogStatus::Uploading {
+              logs.upload();
+            }
+          }
+        },
+        sent = logs.sent(), if logs.is_uploading() => {
+          logs.uploaded(sent);
+        },
+
         Some(msg) = ws_rx.next() => {
           match msg {
             Ok(Message::Text(text)) => {
-              if let Some(response_message) = handler.handle_text_message(&text).await? {
-                send(&mut ws_tx, response_message.clone()).await?;
+              let frame = handler.parse_raw_ocpp_msg(&text).await?;
+              let mut reset: Option<CommonStopReason> = None;
+
+              // 🔏 Certificate management
+              if let Some(responses) = handle_certificate_call(&frame, &mut security, generator.as_ref()).await? {
+                for response in responses {
+                  send(&mut ws_tx, response).await?;
+                }
+              } else if let Some(responses) = logs.handle_call(&frame, &mut security, generator.as_ref()).await? {
+                // 🗂️ Logs and customer information
+                for response in responses {
+                  send(&mut ws_tx, response).await?;
+                }
+              } else {
+                match frame {
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::ChangeConfiguration,
+                    payload,
+                  }) => {
+                    // Only the security keys are simulated in dynamic mode
+                    let request = V16MessageHandler::parse_change_configuration_payload(payload)?;
+                    let status = security.change_configuration(&request.key, &request.value);
+                    send(&mut ws_tx, generator.change_configuration(&msg_id, status).await).await?;
+                  },
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::Reset,
+                    payload,
+                  }) => {
+                    let request: ResetRequest = V16MessageHandler::parse_payload(payload)?;
+                    send(&mut ws_tx, generator.reset(&msg_id).await).await?;
+                    reset = Some(match request.kind {
+                      ResetRequestStatus::Hard => CommonStopReason::HardReset,
+                      ResetRequestStatus::Soft => CommonStopReason::SoftReset,
+                    });
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: V201OcppAction::Reset,
+                    payload,
+                  }) => {
+                    let request: V201ResetRequest = V201MessageHandler::parse_payload(payload)?;
+                    send(&mut ws_tx, generator.reset(&msg_id).await).await?;
+                    reset = Some(match request.request_type {
+                      ResetEnumType::Immediate => CommonStopReason::HardReset,
+                      ResetEnumType::OnIdle => CommonStopReason::SoftReset,
+                    });
+                  },
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::UpdateFirmware,
+                    payload,
+                  }) => {
+                    let request: UpdateFirmwareRequest = V16MessageHandler::parse_payload(payload)?;
+                    send(&mut ws_tx, generator.update_firmware(&msg_id, CommonUpdateFirmwareStatus::Accepted).await).await?;
+                    firmware.schedule(request.retrieve_date, None);
+                  },
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::SignedUpdateFirmware,
+                    payload,
+                  }) => {
+                    let request: SignedUpdateFirmwareRequest = V16MessageHandler::parse_payload(payload)?;
+                    let status = firmware.schedule_signed(request.into(), &mut security);
+                    send(&mut ws_tx, generator.signed_update_firmware(&msg_id, status).await).await?;
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: V201OcppAction::UpdateFirmware,
+                    payload,
+                  }) => {
+                    let request: V201UpdateFirmwareRequest = V201MessageHandler::parse_payload(payload)?;
+                    let status = match SignedFirmwareRequest::from_v2_0_1(&request) {
+                      Some(signed) => firmware.schedule_signed(signed, &mut security),
+                      None => {
+                        firmware.schedule(request.firmware.retrieve_date_time, Some(request.request_id));
+                        CommonUpdateFirmwareStatus::Accepted
+                      }
+                    };
+                    send(&mut ws_tx, generator.update_firmware(&msg_id, status).await).await?;
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: V201OcppAction::GetTransactionStatus,
+                    payload,
+                  }) => {
+                    send(&mut ws_tx, generator.get_transaction_status(&msg_id, payload).await).await?;
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: action @ (V201OcppAction::GetVariables
+                      | V201OcppAction::SetVariables
+                      | V201OcppAction::GetBaseReport
+                      | V201OcppAction::GetReport
+                      | V201OcppAction::SetVariableMonitoring
+                      | V201OcppAction::ClearVariableMonitoring
+                      | V201OcppAction::SetMonitoringBase
+                      | V201OcppAction::SetMonitoringLevel
+                      | V201OcppAction::GetMonitoringReport),
+                    payload,
+                  }) => {
+                    // 🧩 Device model and 📡 monitoring
+                    let messages = match action {
+                      V201OcppAction::GetVariables => vec![generator.get_variables(&msg_id, payload).await],
+                      V201OcppAction::SetVariables => {
+                        let (response, changes) = generator.set_variables(&msg_id, payload).await;
+                        for change in changes {
+                          match change {
+                            CommonSettingChange::HeartbeatInterval(value) => {
+                              heartbeat_interval.set_period(Duration::from_secs(value as u64));
+                            },
+                            CommonSettingChange::MeterValueSampleInterval(value) => {
+                              meter_values_interval.set_period(Duration::from_secs(value as u64));
+                            },
+                            CommonSettingChange::ConnectionTimeOut(value) => connector.set_connection_timeout(value),
+                            CommonSettingChange::StopTransactionOnEVSideDisconnect(value) => {
+                              connector.set_stop_transaction_on_ev_side_disconnect(value);
+                            },
+                            // The WebSocket is not pinged in dynamic mode
+                            CommonSettingChange::WebSocketPingInterval(_) => {},
+                          }
+                        }
+                        vec![response]
+                      },
+                      V201OcppAction::GetBaseReport => generator.get_base_report(&msg_id, payload).await,
+                      V201OcppAction::GetReport => generator.get_report(&msg_id, payload).await,
+                      V201OcppAction::SetVariableMonitoring => vec![generator.set_variable_monitoring(&msg_id, payload).await],
+                      V201OcppAction::ClearVariableMonitoring => vec![generator.clear_variable_monitoring(&msg_id, payload).await],
+                      V201OcppAction::SetMonitoringBase => vec![generator.set_monitoring_base(&msg_id, payload).await],
+                      V201OcppAction::SetMonitoringLevel => vec![generator.set_monitoring_level(&msg_id, payload).await],
+                      _ => generator.get_monitoring_report(&msg_id, payload).await,
+                    };
+                    monitoring = generator.has_monitors().await;
+
+                    for message in messages {
+                      send(&mut ws_tx, message).await?;
+                    }
+                  },
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::SetChargingProfile,
+                    payload,
+                  })
+                  | MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: V201OcppAction::SetChargingProfile,
+                    payload,
+                  }) => {
+                    // ⚡ Smart charging: a limit of 0 suspends the EVSE
+                    let (response, limit) = generator.set_charging_profile(&msg_id, payload).await;
+                    send(&mut ws_tx, response).await?;
+                    if let Some(event) = connector.set_charging_limit(limit) {
+                      handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                    }
+                  },
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action: OcppAction::ClearChargingProfile,
+                    payload,
+                  })
+                  | MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: V201OcppAction::ClearChargingProfile,
+                    payload,
+                  }) => {
+                    send(&mut ws_tx, generator.clear_charging_profile(&msg_id, payload).await).await?;
+                    if let Some(event) = connector.set_charging_limit(None) {
+                      handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                    }
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action: action @ (V201OcppAction::SetDisplayMessage
+                      | V201OcppAction::GetDisplayMessages
+                      | V201OcppAction::ClearDisplayMessage
+                      | V201OcppAction::CostUpdated),
+                    payload,
+                  }) => {
+                    // 🖥️ Display messages
+                    let messages = match action {
+                      V201OcppAction::SetDisplayMessage => vec![generator.set_display_message(&msg_id, payload).await],
+                      V201OcppAction::GetDisplayMessages => generator.get_display_messages(&msg_id, payload).await,
+                      V201OcppAction::ClearDisplayMessage => vec![generator.clear_display_message(&msg_id, payload).await],
+                      _ => vec![generator.cost_updated(&msg_id, payload).await],
+                    };
+
+                    for message in messages {
+                      send(&mut ws_tx, message).await?;
+                    }
+                  },
+                  MessageFrameType::V1_6(MessageFrame::CallResult { msg_id, payload })
+                  | MessageFrameType::V2_0_1(MessageFrame::CallResult { msg_id, payload }) => {
+                    match handler.handle_call_result(&msg_id, &payload).await? {
+                      Some(CommonOcppResponse::Authorize { id_tag, status, parent_id_tag }) => {
+                        self.id_tags.record(&id_tag, status, parent_id_tag.as_deref());
+
+                        if status == AuthorizationStatus::Accepted {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Authorize).await?;
+                        } else {
+                          // The driver gives up and leaves with the EV
+                          warn!("Authorization rejected: {:?}", status);
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Unplug).await?;
+                          plug_in_deadline = Some(Instant::now() + Duration::from_secs(self.config.start_tx_after));
+                        }
+                      },
+                      Some(CommonOcppResponse::StartTransaction { .. }) => {
+                        handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::TransactionStarted).await?;
+                        meter_values_interval.reset();
+                        stop_tx_deadline = Some(Instant::now() + Duration::from_secs(self.config.stop_tx_after));
+                      },
+                      // 🔌 ISO 15118 Plug & Charge, the driver presents the tag when it fails
+                      Some(CommonOcppResponse::Iso15118EVCertificate { accepted }) => {
+                        if let Some(plug_and_charge) = &mut plug_and_charge {
+                          let message = match plug_and_charge.certificate_installed(accepted, generator.as_ref()).await? {
+                            Some(message) => message,
+                            None => self.authorize_tag(generator.as_ref()).await,
+                          };
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
+                      Some(CommonOcppResponse::CertificateStatus { accepted }) => {
+                        if let Some(plug_and_charge) = &plug_and_charge {
+                          let message = match plug_and_charge.certificate_status(accepted, generator.as_ref()).await? {
+                            Some(message) => message,
+                            None => self.authorize_tag(generator.as_ref()).await,
+                          };
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
+                      _ => {}
+                    }
+                  },
+                  _ => {
+                    if let Some(response_message) = handler.handle_text_message(&text).await? {
+                      send(&mut ws_tx, response_message.clone()).await?;
+                    }
+                  },
+                }
+              }
+
+              if let Some(reason) = reset {
+                if connector.has_transaction() {
+                  handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Stop(reason)).await?;
+                  stop_tx_deadline = None;
+                  unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+                }
+
+                ws_tx.close().await?;
+                (ws_tx, ws_rx) = reboot(self.general_config.clone(), &self.config, &mut security, logs.messages(), generator.as_ref()).await?.split();
+                security.record_event(CommonSecurityEvent::ResetOrReboot, Some(format!("{:?}", reason)));
+              } else if security.take_reconnect() {
+                ws_tx.close().await?;
+                (ws_tx, ws_rx) = reconnect(self.general_config.clone(), &self.config, &mut security, logs.messages()).await?.split();
+                generator.security_profile(security.profile() as u32).await;
+                send_queued_transaction_events(&mut ws_tx, generator.as_ref()).await?;
               }
+
+              // 🚨 Security events
+              send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
             }
             Ok(Message::Close(_)) => {
               info!("CSMS closed connection");
@@ -120,8 +552,18 @@ impl ChargePointDynamic {
           }
         }
 
+        _ = stop_requested(&mut stop) => break,
+
         _ = tokio::signal::ctrl_c() => break
       }
+
+      if let Some(metrics) = &self.taps.metrics {
+        let session = (connector.has_transaction(), generator.session().await.1);
+        if metered_session != Some(session) {
+          metered_session = Some(session);
+          metrics.update_session(&self.config.id, session.0, session.1);
+        }
+      }
     }
 
     ws_tx.close().await?;
@@ -129,4 +571,21 @@ impl ChargePointDynamic {
 
     Ok(())
   }
+
+  /// `Authorize` with the tag the driver presents
+  async fn authorize_tag(&mut self, generator: &dyn MessageGenerator) -> Value {
+    let id_tag = self.id_tags.draw().map(|drawn| drawn.id_tag);
+    generator.authorize(id_tag.as_deref()).await
+  }
+}
+
+/// Resolves once the stop is sent, never without a stop signal
+async fn stop_requested(stop: &mut Option<oneshot::Receiver<()>>) {
+  if let Some(receiver) = stop {
+    if receiver.await.is_ok() {
+      return;
+    }
+    *stop = None;
+  }
+  std::future::pending().await
 }
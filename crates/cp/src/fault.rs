@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use common::{ChargePointConfig, ChargePointErrorCode, RandomFaultConfig};
+use ocpp::types::ConnectorFault;
+use rand::seq::IndexedRandom;
+use tokio::time::Instant;
+
+use crate::connector::EvEvent;
+
+struct ScheduledEvent {
+  deadline: Instant,
+  event: EvEvent,
+  random: bool,
+}
+
+/// Schedules the configured and random faults of a charge point
+pub struct FaultInjector {
+  /// Pending fault and recovery events ordered by their deadline
+  scheduled: Vec<ScheduledEvent>,
+  random: Option<RandomFaultConfig>,
+}
+
+impl FaultInjector {
+  pub fn new(cp_config: &ChargePointConfig) -> Self {
+    let now = Instant::now();
+
+    let mut injector = Self {
+      scheduled: Vec::new(),
+      random: cp_config.random_faults.clone(),
+    };
+
+    for fault_config in &cp_config.faults {
+      let fault_at = now + Duration::from_secs(fault_config.after);
+      injector.schedule(
+        fault_at,
+        EvEvent::Fault(ConnectorFault::from(fault_config)),
+        false,
+      );
+
+      if let Some(recover_after) = fault_config.recover_after {
+        injector.schedule(
+          fault_at + Duration::from_secs(recover_after),
+          EvEvent::ClearFault(fault_config.error_code),
+          false,
+        );
+      }
+    }
+
+    injector.schedule_random(now);
+    injector
+  }
+
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.scheduled.first().map(|scheduled| scheduled.deadline)
+  }
+
+  /// Takes the next due fault or recovery event
+  pub fn pop(&mut self) -> Option<EvEvent> {
+    let now = Instant::now();
+
+    if self.next_deadline()? > now {
+      return None;
+    }
+
+    let scheduled = self.scheduled.remove(0);
+
+    // The next random fault is drawn only after the previous one has recovered
+    if scheduled.random && matches!(scheduled.event, EvEvent::ClearFault(_)) {
+      self.schedule_random(now);
+    }
+
+    Some(scheduled.event)
+  }
+
+  fn schedule(&mut self, deadline: Instant, event: EvEvent, random: bool) {
+    let index = self
+      .scheduled
+      .partition_point(|scheduled| scheduled.deadline <= deadline);
+    self.scheduled.insert(
+      index,
+      ScheduledEvent {
+        deadline,
+        event,
+        random,
+      },
+    );
+  }
+
+  fn schedule_random(&mut self, from: Instant) {
+    let Some(random) = &self.random else {
+      return;
+    };
+
+    let mut rng = rand::rng();
+
+    // Exponentially distributed time between faults
+    let uniform = rand::random_range(f64::EPSILON..1.0);
+    let fault_at = from + Duration::from_secs_f64(-uniform.ln() * random.mean_interval as f64);
+
+    let error_code = *random
+      .error_codes
+      .choose(&mut rng)
+      .unwrap_or_else(|| ChargePointErrorCode::ALL.choose(&mut rng).unwrap());
+
+    let recover_after =
+      rand::random_range(random.recover_after_range[0]..=random.recover_after_range[1]);
+
+    let fault = ConnectorFault {
+      error_code,
+      vendor_error_code: None,
+      info: Some("Injected by simulator".to_string()),
+    };
+
+    self.schedule(fault_at, EvEvent::Fault(fault), true);
+    self.schedule(
+      fault_at + Duration::from_secs(recover_after),
+      EvEvent::ClearFault(error_code),
+      true,
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::test_config::charge_point_config;
+
+  #[tokio::test(start_paused = true)]
+  async fn scheduled_fault_is_raised_then_cleared() {
+    let start = Instant::now();
+    let mut faults = FaultInjector::new(&charge_point_config(json!({
+      "faults": [{ "error_code": "GroundFailure", "after": 10, "recover_after": 5 }],
+    })));
+    assert_eq!(
+      faults.next_deadline(),
+      Some(start + Duration::from_secs(10))
+    );
+
+    tokio::time::advance(Duration::from_secs(9)).await;
+    assert_eq!(faults.pop(), None);
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(matches!(
+      faults.pop(),
+      Some(EvEvent::Fault(ConnectorFault {
+        error_code: ChargePointErrorCode::GroundFailure,
+        ..
+      }))
+    ));
+
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert_eq!(
+      faults.pop(),
+      Some(EvEvent::ClearFault(ChargePointErrorCode::GroundFailure))
+    );
+    assert_eq!(faults.next_deadline(), None);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn scheduled_fault_without_recovery_stays() {
+    let mut faults = FaultInjector::new(&charge_point_config(json!({
+      "faults": [{ "error_code": "OverVoltage", "after": 0 }],
+    })));
+
+    assert!(matches!(faults.pop(), Some(EvEvent::Fault(_))));
+    assert_eq!(faults.next_deadline(), None);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn random_faults_are_exponentially_distributed() {
+    let start = Instant::now();
+    let random = json!({
+      "random_faults": { "mean_interval": 100, "recover_after_range": [1, 1] },
+    });
+
+    let draws = 4000;
+    let mut total = Duration::ZERO;
+    let mut below_mean = 0;
+    for _ in 0..draws {
+      let fault_at = FaultInjector::new(&charge_point_config(random.clone()))
+        .next_deadline()
+        .unwrap()
+        - start;
+      total += fault_at;
+      if fault_at < Duration::from_secs(100) {
+        below_mean += 1;
+      }
+    }
+
+    let mean = total.as_secs_f64() / draws as f64;
+    assert!((90.0..110.0).contains(&mean), "mean {mean}");
+    // P(X < mean) = 1 - 1/e for an exponential distribution
+    let below = below_mean as f64 / draws as f64;
+    assert!((0.60..0.66).contains(&below), "below the mean {below}");
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn next_random_fault_is_drawn_once_recovered() {
+    let mut faults = FaultInjector::new(&charge_point_config(json!({
+      "random_faults": {
+        "mean_interval": 60,
+        "recover_after_range": [5, 5],
+        "error_codes": ["WeakSignal"],
+      },
+    })));
+
+    let fault_at = faults.next_deadline().unwrap();
+    tokio::time::advance(fault_at - Instant::now()).await;
+    assert!(matches!(
+      faults.pop(),
+      Some(EvEvent::Fault(ConnectorFault {
+        error_code: ChargePointErrorCode::WeakSignal,
+        ..
+      }))
+    ));
+    assert_eq!(
+      faults.next_deadline(),
+      Some(fault_at + Duration::from_secs(5))
+    );
+
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert_eq!(
+      faults.pop(),
+      Some(EvEvent::ClearFault(ChargePointErrorCode::WeakSignal))
+    );
+    assert!(faults.next_deadline().unwrap() >= Instant::now());
+  }
+}
@@ -0,0 +1,436 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use ocpp::{
+  generator::MessageGenerator,
+  types::{CommonFirmwareStatus, CommonSecurityEvent, CommonUpdateFirmwareStatus},
+  v1_6::security::SignedUpdateFirmwareRequest,
+};
+use openssl::{hash::MessageDigest, sign::Verifier, x509::X509};
+use rust_ocpp::v2_0_1::messages::update_firmware::UpdateFirmwareRequest as V201UpdateFirmwareRequest;
+use serde_json::Value;
+use tokio::{task::JoinHandle, time::Instant};
+use tracing::{info, warn};
+
+use crate::security::Security;
+
+/// Simulated duration of the download and of the installation
+const DOWNLOAD_DURATION: Duration = Duration::from_secs(10);
+const INSTALL_DURATION: Duration = Duration::from_secs(10);
+/// Time limit for downloading a signed firmware image
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Firmware image of a signed update, downloaded and verified before the installation
+struct SignedImage {
+  location: String,
+  signing_certificate: X509,
+  signature: Vec<u8>,
+  retries: u32,
+  retry_interval: Duration,
+}
+
+/// `SignedUpdateFirmware` (1.6) or `UpdateFirmware` with a signature (2.0.1)
+pub struct SignedFirmwareRequest {
+  pub request_id: i32,
+  pub location: String,
+  pub retrieve_date: DateTime<Utc>,
+  /// PEM certificate the signature is made with
+  pub signing_certificate: String,
+  /// Base64 encoded signature of the image
+  pub signature: String,
+  pub retries: Option<u32>,
+  /// Seconds between two download attempts
+  pub retry_interval: Option<u32>,
+}
+
+impl From<SignedUpdateFirmwareRequest> for SignedFirmwareRequest {
+  fn from(request: SignedUpdateFirmwareRequest) -> Self {
+    Self {
+      request_id: request.request_id,
+      location: request.firmware.location,
+      retrieve_date: request.firmware.retrieve_date_time,
+      signing_certificate: request.firmware.signing_certificate,
+      signature: request.firmware.signature,
+      retries: request.retries,
+      retry_interval: request.retry_interval,
+    }
+  }
+}
+
+impl SignedFirmwareRequest {
+  /// `None` for an unsigned 2.0.1 update
+  pub fn from_v2_0_1(request: &V201UpdateFirmwareRequest) -> Option<Self> {
+    let firmware = &request.firmware;
+
+    Some(Self {
+      request_id: request.request_id,
+      location: firmware.location.clone(),
+      retrieve_date: firmware.retrieve_date_time,
+      signing_certificate: firmware.signing_certificate.clone()?,
+      signature: firmware.signature.clone()?,
+      retries: request.retries.map(|retries| retries.max(0) as u32),
+      retry_interval: request
+        .retry_interval
+        .map(|interval| interval.max(0) as u32),
+    })
+  }
+}
+
+/// Progress of a firmware update requested by the CSMS
+#[derive(Default)]
+pub struct FirmwareUpdate {
+  request_id: Option<i32>,
+  /// Set for signed updates, plain updates only simulate the download
+  image: Option<SignedImage>,
+  /// Pending status changes ordered by their deadline
+  steps: VecDeque<(Instant, CommonFirmwareStatus)>,
+  /// Download of the signed image, running outside of the charge point loop
+  fetching: Option<JoinHandle<Result<Vec<u8>>>>,
+}
+
+impl FirmwareUpdate {
+  /// Schedules a simulated download at the retrieve date, replacing a pending update
+  pub fn schedule(&mut self, retrieve_date: DateTime<Utc>, request_id: Option<i32>) {
+    self.cancel_download();
+    self.request_id = request_id;
+    self.image = None;
+    self.steps = VecDeque::from([(
+      download_at(retrieve_date),
+      CommonFirmwareStatus::Downloading,
+    )]);
+  }
+
+  /// Schedules the download of a signed firmware image at the retrieve date, once its
+  /// signing certificate is validated against the manufacturer roots
+  pub fn schedule_signed(
+    &mut self,
+    request: SignedFirmwareRequest,
+    security: &mut Security,
+  ) -> CommonUpdateFirmwareStatus {
+    let Some(signing_certificate) = security
+      .certificates()
+      .firmware_signing_certificate(&request.signing_certificate)
+    else {
+      warn!("Firmware update rejected, untrusted signing certificate");
+      security.record_event(CommonSecurityEvent::InvalidFirmwareSigningCertificate, None);
+      return CommonUpdateFirmwareStatus::InvalidCertificate;
+    };
+
+    let Ok(signature) = STANDARD.decode(&request.signature) else {
+      warn!("Firmware update rejected, signature is not base64");
+      return CommonUpdateFirmwareStatus::Rejected;
+    };
+
+    self.cancel_download();
+    self.request_id = Some(request.request_id);
+    self.image = Some(SignedImage {
+      location: request.location,
+      signing_certificate,
+      signature,
+      retries: request.retries.unwrap_or_default(),
+      retry_interval: Duration::from_secs(request.retry_interval.unwrap_or_default() as u64),
+    });
+    self.steps = VecDeque::from([(
+      download_at(request.retrieve_date),
+      CommonFirmwareStatus::Downloading,
+    )]);
+    CommonUpdateFirmwareStatus::Accepted
+  }
+
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.steps.front().map(|(deadline, _)| *deadline)
+  }
+
+  /// Takes the next due status, the charge point reboots before reporting `Installed`
+  pub fn pop(&mut self) -> Option<CommonFirmwareStatus> {
+    if self.next_deadline()? > Instant::now() {
+      return None;
+    }
+
+    self.steps.pop_front().map(|(_, status)| status)
+  }
+
+  /// Starts the download once `Downloading` has been reported, the image of a signed update
+  /// is fetched in the background and handed over by `fetched`
+  pub fn download(&mut self) {
+    let Some(image) = &self.image else {
+      let downloaded_at = Instant::now() + DOWNLOAD_DURATION;
+      self.steps = VecDeque::from([
+        (downloaded_at, CommonFirmwareStatus::Downloaded),
+        (downloaded_at, CommonFirmwareStatus::Installing),
+        (
+          downloaded_at + INSTALL_DURATION,
+          CommonFirmwareStatus::Installed,
+        ),
+      ]);
+      return;
+    };
+
+    let location = image.location.clone();
+    self.fetching = Some(tokio::spawn(async move { fetch(&location).await }));
+  }
+
+  pub fn is_downloading(&self) -> bool {
+    self.fetching.is_some()
+  }
+
+  /// Image of the running download once it has been fetched, cancel safe
+  pub async fn fetched(&mut self) -> Result<Vec<u8>> {
+    let Some(fetching) = &mut self.fetching else {
+      return std::future::pending().await;
+    };
+
+    let fetched = fetching.await.map_err(anyhow::Error::from).flatten();
+    self.fetching = None;
+    fetched
+  }
+
+  /// Verifies the fetched image and schedules the next steps, an invalid signature is
+  /// recorded as a security event
+  pub fn downloaded(&mut self, fetched: Result<Vec<u8>>, security: &mut Security) {
+    let Some(image) = &mut self.image else {
+      return;
+    };
+
+    let now = Instant::now();
+    let firmware = match fetched {
+      Ok(firmware) => firmware,
+      Err(err) if image.retries > 0 => {
+        warn!("Firmware download failed, retrying: {err:#}");
+        image.retries -= 1;
+        self.steps = VecDeque::from([(
+          now + image.retry_interval,
+          CommonFirmwareStatus::Downloading,
+        )]);
+        return;
+      }
+      Err(err) => {
+        warn!("Firmware download failed: {err:#}");
+        self.steps = VecDeque::from([(now, CommonFirmwareStatus::DownloadFailed)]);
+        return;
+      }
+    };
+
+    info!(
+      "Downloaded firmware {} ({} bytes)",
+      image.location,
+      firmware.len()
+    );
+
+    if verify(&image.signing_certificate, &firmware, &image.signature).unwrap_or(false) {
+      self.steps = VecDeque::from([
+        (now, CommonFirmwareStatus::Downloaded),
+        (now, CommonFirmwareStatus::SignatureVerified),
+        (now, CommonFirmwareStatus::Installing),
+        (now + INSTALL_DURATION, CommonFirmwareStatus::Installed),
+      ]);
+    } else {
+      warn!("Firmware signature verification failed");
+      security.record_event(
+        CommonSecurityEvent::InvalidFirmwareSignature,
+        Some(image.location.clone()),
+      );
+      self.steps = VecDeque::from([
+        (now, CommonFirmwareStatus::Downloaded),
+        (now, CommonFirmwareStatus::InvalidSignature),
+      ]);
+    }
+  }
+
+  fn cancel_download(&mut self) {
+    if let Some(fetching) = self.fetching.take() {
+      fetching.abort();
+    }
+  }
+
+  /// `SignedFirmwareStatusNotification` for signed updates in 1.6,
+  /// `FirmwareStatusNotification` otherwise
+  pub async fn status_notification(
+    &self,
+    generator: &dyn MessageGenerator,
+    status: CommonFirmwareStatus,
+  ) -> Value {
+    match (&self.image, self.request_id) {
+      (Some(_), Some(request_id)) => {
+        generator
+          .signed_firmware_status_notification(status, request_id)
+          .await
+      }
+      _ => {
+        generator
+          .firmware_status_notification(status, self.request_id)
+          .await
+      }
+    }
+  }
+}
+
+fn download_at(retrieve_date: DateTime<Utc>) -> Instant {
+  Instant::now() + (retrieve_date - Utc::now()).to_std().unwrap_or_default()
+}
+
+/// Reads a firmware image over HTTP(S) or from a `file://` location
+async fn fetch(location: &str) -> Result<Vec<u8>> {
+  if let Some(path) = location.strip_prefix("file://") {
+    return Ok(tokio::fs::read(path).await?);
+  }
+
+  let response = reqwest::Client::builder()
+    .timeout(DOWNLOAD_TIMEOUT)
+    .build()?
+    .get(location)
+    .send()
+    .await?
+    .error_for_status()?;
+
+  Ok(response.bytes().await?.to_vec())
+}
+
+/// Verifies the SHA-256 signature (RSA or ECDSA) of the image with the signing certificate
+fn verify(signing_certificate: &X509, firmware: &[u8], signature: &[u8]) -> Result<bool> {
+  let public_key = signing_certificate.public_key()?;
+  let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+  verifier.update(firmware)?;
+  Ok(verifier.verify(signature)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use ocpp::types::CommonCertificateUse;
+  use openssl::{
+    pkey::{PKey, Private},
+    sign::Signer,
+  };
+  use serde_json::json;
+
+  use super::*;
+  use crate::{
+    test_certificates::{ca, issue, key, pem},
+    test_config::charge_point_config,
+  };
+
+  /// Charge point trusting the manufacturer root
+  fn security(manufacturer_root: &X509) -> Security {
+    let mut security = Security::new(&charge_point_config(json!({}))).unwrap();
+    security.certificates().install(
+      CommonCertificateUse::ManufacturerRoot,
+      &pem(manufacturer_root),
+    );
+    security
+  }
+
+  /// Image written to a file, with the `SignedUpdateFirmware` of the CSMS for it
+  fn signed_update(
+    image: &[u8],
+    signing_certificate: &X509,
+    signing_key: &PKey<Private>,
+  ) -> SignedUpdateFirmwareRequest {
+    let path = std::env::temp_dir().join(format!("firmware-{}.bin", rand::random::<u64>()));
+    std::fs::write(&path, image).unwrap();
+
+    let mut signer = Signer::new(MessageDigest::sha256(), signing_key).unwrap();
+    signer.update(image).unwrap();
+    serde_json::from_value(json!({
+      "requestId": 7,
+      "firmware": {
+        "location": format!("file://{}", path.display()),
+        "retrieveDateTime": Utc::now(),
+        "signingCertificate": pem(signing_certificate),
+        "signature": STANDARD.encode(signer.sign_to_vec().unwrap()),
+      },
+    }))
+    .unwrap()
+  }
+
+  /// Downloads the image and returns the statuses due right after
+  async fn download(
+    firmware: &mut FirmwareUpdate,
+    security: &mut Security,
+  ) -> Vec<CommonFirmwareStatus> {
+    assert_eq!(firmware.pop(), Some(CommonFirmwareStatus::Downloading));
+    firmware.download();
+    let fetched = firmware.fetched().await;
+    firmware.downloaded(fetched, security);
+    std::iter::from_fn(|| firmware.pop()).collect()
+  }
+
+  #[tokio::test]
+  async fn image_signed_by_a_manufacturer_certificate_is_installed() {
+    let root = ca("Manufacturer Root");
+    let signing_key = key();
+    let signing_certificate = issue("Firmware Signing", &signing_key, &root, 30);
+    let mut security = security(&root.0);
+    let mut firmware = FirmwareUpdate::default();
+
+    let request = signed_update(b"firmware image", &signing_certificate, &signing_key);
+    assert_eq!(
+      firmware.schedule_signed(request.into(), &mut security),
+      CommonUpdateFirmwareStatus::Accepted
+    );
+    assert_eq!(
+      download(&mut firmware, &mut security).await,
+      [
+        CommonFirmwareStatus::Downloaded,
+        CommonFirmwareStatus::SignatureVerified,
+        CommonFirmwareStatus::Installing,
+      ]
+    );
+    assert!(security.take_events().is_empty());
+  }
+
+  #[tokio::test]
+  async fn tampered_signature_fails_the_signature_check() {
+    let root = ca("Manufacturer Root");
+    let signing_key = key();
+    let signing_certificate = issue("Firmware Signing", &signing_key, &root, 30);
+    let mut security = security(&root.0);
+    let mut firmware = FirmwareUpdate::default();
+
+    let mut request = signed_update(b"firmware image", &signing_certificate, &signing_key);
+    let mut signature = STANDARD.decode(&request.firmware.signature).unwrap();
+    *signature.last_mut().unwrap() ^= 1;
+    request.firmware.signature = STANDARD.encode(signature);
+    let location = request.firmware.location.clone();
+    assert_eq!(
+      firmware.schedule_signed(request.into(), &mut security),
+      CommonUpdateFirmwareStatus::Accepted
+    );
+    assert_eq!(
+      download(&mut firmware, &mut security).await,
+      [
+        CommonFirmwareStatus::Downloaded,
+        CommonFirmwareStatus::InvalidSignature,
+      ]
+    );
+    assert_eq!(
+      security.take_events(),
+      [(
+        CommonSecurityEvent::InvalidFirmwareSignature,
+        Some(location)
+      )]
+    );
+  }
+
+  #[tokio::test]
+  async fn signing_certificate_of_another_root_is_refused() {
+    let root = ca("Manufacturer Root");
+    let other_root = ca("Other Root");
+    let signing_key = key();
+    let signing_certificate = issue("Firmware Signing", &signing_key, &other_root, 30);
+    let mut security = security(&root.0);
+    let mut firmware = FirmwareUpdate::default();
+
+    let request = signed_update(b"firmware image", &signing_certificate, &signing_key);
+    assert_eq!(
+      firmware.schedule_signed(request.into(), &mut security),
+      CommonUpdateFirmwareStatus::InvalidCertificate
+    );
+    assert_eq!(firmware.next_deadline(), None);
+    assert_eq!(
+      security.take_events(),
+      [(CommonSecurityEvent::InvalidFirmwareSigningCertificate, None)]
+    );
+  }
+}
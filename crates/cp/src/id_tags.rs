@@ -0,0 +1,241 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use common::{ExpectedAuthorization, IdTagGroupConfig, IdTagPoolConfig};
+use ocpp::types::AuthorizationStatus;
+use rand::seq::IndexedRandom;
+use tracing::{info, warn};
+
+struct IdTagGroup {
+  parent_id_tag: Option<String>,
+  tags: Vec<String>,
+  weight: f64,
+  expected: Option<ExpectedAuthorization>,
+}
+
+impl From<&IdTagGroupConfig> for IdTagGroup {
+  fn from(value: &IdTagGroupConfig) -> Self {
+    let generated = value.range.iter().flat_map(|range| {
+      (range.start..range.start + range.count)
+        .map(move |number| format!("{}{:0width$}", range.prefix, number, width = range.width))
+    });
+
+    Self {
+      parent_id_tag: value.parent_id_tag.clone(),
+      tags: value.tags.iter().cloned().chain(generated).collect(),
+      weight: value.weight,
+      expected: value.expected,
+    }
+  }
+}
+
+/// A tag drawn from a pool together with what the CSMS should answer
+#[derive(Debug, Clone)]
+pub struct DrawnIdTag {
+  pub id_tag: String,
+  pub parent_id_tag: Option<String>,
+  pub expected: Option<ExpectedAuthorization>,
+}
+
+pub struct IdTagPool {
+  groups: Vec<IdTagGroup>,
+  /// Group index of every tag
+  index: HashMap<String, usize>,
+}
+
+impl IdTagPool {
+  pub fn from_config(config: &IdTagPoolConfig) -> Result<Self> {
+    config.validate_weights()?;
+
+    let groups = config
+      .groups
+      .iter()
+      .map(IdTagGroup::from)
+      .filter(|group| !group.tags.is_empty())
+      .collect::<Vec<IdTagGroup>>();
+
+    let index = groups
+      .iter()
+      .enumerate()
+      .flat_map(|(group_index, group)| group.tags.iter().map(move |tag| (tag.clone(), group_index)))
+      .collect();
+
+    Ok(Self { groups, index })
+  }
+
+  /// Picks a group by its weight and a tag of the group uniformly
+  pub fn draw(&self) -> Option<DrawnIdTag> {
+    let mut rng = rand::rng();
+    let group = self
+      .groups
+      .choose_weighted(&mut rng, |group| group.weight)
+      .ok()?;
+
+    Some(DrawnIdTag {
+      id_tag: group.tags.choose(&mut rng)?.clone(),
+      parent_id_tag: group.parent_id_tag.clone(),
+      expected: group.expected,
+    })
+  }
+
+  fn find(&self, id_tag: &str) -> Option<&IdTagGroup> {
+    self
+      .index
+      .get(id_tag)
+      .map(|group_index| &self.groups[*group_index])
+  }
+}
+
+#[derive(Default)]
+struct TagStats {
+  outcomes: HashMap<AuthorizationStatus, u64>,
+  expected: Option<ExpectedAuthorization>,
+  parent_id_tag: Option<String>,
+  unexpected: u64,
+}
+
+/// Authorization outcomes per tag over the whole run, shared by all charge points
+#[derive(Default)]
+pub struct AuthorizationStats {
+  tags: Mutex<BTreeMap<String, TagStats>>,
+}
+
+impl AuthorizationStats {
+  pub fn record(
+    &self,
+    drawn: Option<&DrawnIdTag>,
+    id_tag: &str,
+    status: AuthorizationStatus,
+    parent_id_tag: Option<&str>,
+  ) {
+    let mut tags = self.tags.lock().unwrap();
+    let stats = tags.entry(id_tag.to_string()).or_default();
+    *stats.outcomes.entry(status).or_default() += 1;
+
+    let Some(drawn) = drawn else {
+      return;
+    };
+
+    stats.expected = drawn.expected;
+    stats.parent_id_tag = drawn.parent_id_tag.clone();
+
+    if drawn
+      .expected
+      .is_some_and(|expected| !Self::matches(expected, status))
+    {
+      stats.unexpected += 1;
+      warn!(
+        "Tag {} expected {:?}, CSMS answered {:?}",
+        id_tag, drawn.expected, status
+      );
+    }
+
+    if let (Some(expected_parent), AuthorizationStatus::Accepted) = (&drawn.parent_id_tag, status)
+      && parent_id_tag != Some(expected_parent.as_str())
+    {
+      warn!(
+        "Tag {} expected parentIdTag {}, CSMS answered {:?}",
+        id_tag, expected_parent, parent_id_tag
+      );
+    }
+  }
+
+  fn matches(expected: ExpectedAuthorization, status: AuthorizationStatus) -> bool {
+    matches!(
+      (expected, status),
+      (
+        ExpectedAuthorization::Accepted,
+        AuthorizationStatus::Accepted
+      ) | (ExpectedAuthorization::Blocked, AuthorizationStatus::Blocked)
+        | (ExpectedAuthorization::Expired, AuthorizationStatus::Expired)
+        | (ExpectedAuthorization::Invalid, AuthorizationStatus::Invalid)
+        | (
+          ExpectedAuthorization::ConcurrentTx,
+          AuthorizationStatus::ConcurrentTx
+        )
+    )
+  }
+
+  /// Logs a table of outcomes per tag
+  pub fn log_summary(&self) {
+    let tags = self.tags.lock().unwrap();
+
+    if tags.is_empty() {
+      return;
+    }
+
+    info!("{}", "authorization summary".purple());
+    info!(
+      "{:<24} {:<16} {:>8} {:>8} {:>8} {:>8} {:>12} {:>10}",
+      "idTag",
+      "parentIdTag",
+      "Accepted",
+      "Blocked",
+      "Expired",
+      "Invalid",
+      "ConcurrentTx",
+      "Unexpected"
+    );
+
+    for (id_tag, stats) in tags.iter() {
+      let count = |status| stats.outcomes.get(&status).copied().unwrap_or_default();
+      let line = format!(
+        "{:<24} {:<16} {:>8} {:>8} {:>8} {:>8} {:>12} {:>10}",
+        id_tag,
+        stats.parent_id_tag.as_deref().unwrap_or("-"),
+        count(AuthorizationStatus::Accepted),
+        count(AuthorizationStatus::Blocked),
+        count(AuthorizationStatus::Expired),
+        count(AuthorizationStatus::Invalid),
+        count(AuthorizationStatus::ConcurrentTx),
+        stats.unexpected,
+      );
+
+      if stats.unexpected > 0 {
+        info!("{}", line.red());
+      } else {
+        info!("{line}");
+      }
+    }
+  }
+}
+
+/// Where the sessions of one charge point get their tags from
+#[derive(Clone)]
+pub struct IdTags {
+  pool: Option<Arc<IdTagPool>>,
+  stats: Arc<AuthorizationStats>,
+}
+
+impl IdTags {
+  pub fn new(pool: Option<Arc<IdTagPool>>, stats: Arc<AuthorizationStats>) -> Self {
+    Self { pool, stats }
+  }
+
+  /// Draws a tag from the pool, `None` when the charge point uses its configured tag
+  pub fn draw(&self) -> Option<DrawnIdTag> {
+    self.pool.as_ref().and_then(|pool| pool.draw())
+  }
+
+  /// Looks up what the CSMS should answer for a tag of the pool
+  pub fn lookup(&self, id_tag: &str) -> Option<DrawnIdTag> {
+    let group = self.pool.as_ref()?.find(id_tag)?;
+
+    Some(DrawnIdTag {
+      id_tag: id_tag.to_string(),
+      parent_id_tag: group.parent_id_tag.clone(),
+      expected: group.expected,
+    })
+  }
+
+  pub fn record(&self, id_tag: &str, status: AuthorizationStatus, parent_id_tag: Option<&str>) {
+    let drawn = self.lookup(id_tag);
+    self
+      .stats
+      .record(drawn.as_ref(), id_tag, status, parent_id_tag);
+  }
+}
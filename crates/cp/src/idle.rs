@@ -1,77 +1,383 @@
 use anyhow::Result;
-use common::{ChargePointConfig, GeneralConfig};
+use common::{ChargePointConfig, GeneralConfig, ScenarioAction, frame_info};
 use futures::SinkExt;
 use ocpp::{
   OcppSession,
   handler::{MessageFrame, MessageFrameType},
-  types::{AuthorizationStatus, CommonConnectorStatusType, CommonOcppResponse},
-  v1_6::{handler::V16MessageHandler, types::OcppAction},
+  types::{
+    AuthorizationStatus, CommonConfigurationStatus, CommonConnectorStatusType,
+    CommonFirmwareStatus, CommonOcppResponse, CommonSecurityEvent, CommonSettingChange,
+    CommonStopReason, CommonUpdateFirmwareStatus, CommonUploadLogStatus, ConnectorFault,
+  },
+  v1_6::{handler::V16MessageHandler, security::SignedUpdateFirmwareRequest, types::OcppAction},
+  v2_0_1::{handler::V201MessageHandler, types::OcppAction as V201OcppAction},
+};
+use rust_ocpp::{
+  v1_6::{
+    messages::{reset::ResetRequest, update_firmware::UpdateFirmwareRequest},
+    types::ResetRequestStatus,
+  },
+  v2_0_1::{
+    enumerations::reset_enum_type::ResetEnumType,
+    messages::{
+      reset::ResetRequest as V201ResetRequest,
+      update_firmware::UpdateFirmwareRequest as V201UpdateFirmwareRequest,
+    },
+  },
 };
 
 use std::{sync::Arc, time::Duration};
-use tokio::{
-  select,
-  time::{interval, sleep},
-};
+use tokio::{select, time::Instant};
 use tungstenite::Message;
 
 use futures_util::StreamExt;
 use tracing::{error, info, warn};
 
 use crate::{
-  core::{connect, send},
+  certificates::handle_certificate_call,
+  connector::{Connector, ConnectorAction, EV_UNPLUG_DELAY, EvEvent, handle_ev_event},
+  control::{ControlCommand, ControlRegistry, next_command, status_event},
+  core::{
+    WsStream, back_online, connect, reboot, reconnect, send, send_queued_transaction_events,
+    send_security_events, sleep_until,
+  },
+  fault::FaultInjector,
+  firmware::{FirmwareUpdate, SignedFirmwareRequest},
+  id_tags::IdTags,
+  logs::{Logs, MessageTaps},
+  scenario::Scenario,
+  security::Security,
+  security_events::SecurityEventInjector,
   session::TxnSession,
+  timers::TimerWheel,
 };
 
 pub struct ChargePointIdle {
   general_config: Arc<GeneralConfig>,
   config: ChargePointConfig,
+  id_tags: IdTags,
+  /// Drives the EV and the driver, the charge point stops once it is finished
+  scenario: Option<Scenario>,
+  /// Takes commands from outside the simulator while running
+  control: Option<Arc<ControlRegistry>>,
+  taps: MessageTaps,
+  /// Periodic timers, shared with the other charge points of the simulator
+  timers: Option<Arc<TimerWheel>>,
 }
 
 impl ChargePointIdle {
-  pub fn new(general_config: Arc<GeneralConfig>, config: ChargePointConfig) -> Self {
+  pub fn new(
+    general_config: Arc<GeneralConfig>,
+    config: ChargePointConfig,
+    id_tags: IdTags,
+  ) -> Self {
     Self {
       general_config,
       config,
+      id_tags,
+      scenario: None,
+      control: None,
+      taps: MessageTaps::default(),
+      timers: None,
     }
   }
 
+  pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+    self.scenario = Some(scenario);
+    self
+  }
+
+  pub fn with_control(mut self, registry: Arc<ControlRegistry>) -> Self {
+    self.control = Some(registry);
+    self
+  }
+
+  /// Passes the message traffic to the recording and the metrics
+  pub fn with_taps(mut self, taps: MessageTaps) -> Self {
+    self.taps = taps;
+    self
+  }
+
+  pub fn with_timers(mut self, timers: Arc<TimerWheel>) -> Self {
+    self.timers = Some(timers);
+    self
+  }
+
   pub async fn run(&mut self) -> Result<()> {
     let ocpp_version = &self.general_config.ocpp_version;
 
-    let ws_stream = connect(self.general_config.clone(), &self.config).await?;
+    let mut security = Security::new(&self.config)?;
+    let mut logs = Logs::new(
+      &self.config.id,
+      self.general_config.message_log_size,
+      self.taps.clone(),
+    );
+    let ws_stream = connect(
+      self.general_config.clone(),
+      &self.config,
+      &security,
+      logs.messages(),
+    )
+    .await?;
     let (mut ws_tx, mut ws_rx) = ws_stream.split();
 
     let OcppSession { generator, handler } =
-      OcppSession::new(ocpp_version, self.config.clone()).await;
+      OcppSession::new(ocpp_version, self.config.clone()).await?;
 
+    let timers = self.timers.clone().unwrap_or_else(TimerWheel::spawn);
     let mut txn_session = TxnSession::new(
+      &timers,
       self.config.txn_meter_values_interval,
       self.config.txn_meter_values_max_count,
     );
 
-    let mut heartbeat_interval = interval(Duration::from_secs(self.config.heartbeat_interval));
-    let mut ws_ping_interval = interval(Duration::from_secs(30));
+    // Idle mode has no EV driver: the cable is plugged in once a transaction is
+    // authorized and unplugged shortly after it has finished
+    let mut connector = Connector::new(&generator.settings().await);
+    let mut unplug_deadline: Option<Instant> = None;
+    // Network lost by a disconnect, until the deadline or a connect command without one
+    let mut offline = false;
+    let mut reconnect_deadline: Option<Instant> = None;
+    let mut faults = FaultInjector::new(&self.config);
+    let mut firmware = FirmwareUpdate::default();
+    let mut security_events = SecurityEventInjector::new(&self.config);
+
+    let mut heartbeat_interval =
+      timers.interval(Duration::from_secs(self.config.heartbeat_interval));
+    let mut ws_ping_interval = timers.interval(Duration::from_secs(30));
+    // Variable monitors are evaluated against values sampled every second, only while
+    // there are monitors (OCPP 2.0.1)
+    let mut monitoring_interval = timers.interval(Duration::from_secs(1));
+    let mut monitoring = generator.has_monitors().await;
+    // Transaction state and energy register last taken by the metrics
+    let mut metered_session = None;
 
     send(&mut ws_tx, generator.boot_notification().await).await?;
 
+    if let Some(csr) = security.boot_certificate_signing_request()? {
+      send(&mut ws_tx, generator.sign_certificate(csr).await).await?;
+    }
+
+    let mut scenario = self.scenario.take();
+    if let Some(scenario) = &mut scenario {
+      scenario.start();
+    }
+    let mut control = self
+      .control
+      .as_ref()
+      .map(|registry| registry.register(&self.config.id, logs.messages().clone()));
+
     loop {
       select! {
         _ = heartbeat_interval.tick() => send(&mut ws_tx, generator.heartbeat().await).await?,
         _ = ws_ping_interval.tick() => {
-            info!("➡️  ping");
+            frame_info!("➡️  ping");
             ws_tx.send(Message::Ping(vec![].into())).await?
           }
+        // 📡 Monitoring
+        _ = monitoring_interval.tick(), if monitoring => {
+          for message in generator.monitoring_events().await {
+            send(&mut ws_tx, message).await?;
+          }
+        },
         _ = txn_session.tick(), if txn_session.is_running() => {
           send(&mut ws_tx, generator.meter_values().await).await?;
           txn_session.increment();
           if !txn_session.is_running() {
-            send(&mut ws_tx, generator.stop_transaction().await).await?;
+            handle_ev_event(
+              &mut connector,
+              generator.as_ref(),
+              &mut ws_tx,
+              EvEvent::Stop(CommonStopReason::Local),
+            ).await?;
+            unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+          }
+        },
+        // The driver of a scenario unplugs the cable when the scenario says so
+        _ = sleep_until(unplug_deadline), if scenario.is_none() => {
+          unplug_deadline = None;
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Unplug).await?;
+        },
+        _ = sleep_until(connector.connection_timeout_deadline()) => {
+          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::ConnectionTimeout).await?;
+        },
+        _ = sleep_until(faults.next_deadline()) => {
+          if let Some(event) = faults.pop() {
+            let action = handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+
+            if let Some(ConnectorAction::StopTransaction(_)) = action {
+              txn_session.stop();
+            }
+
+            if connector.status() == CommonConnectorStatusType::Finishing {
+              unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+            }
+          }
+        },
+
+        _ = sleep_until(firmware.next_deadline()) => {
+          if let Some(status) = firmware.pop() {
+            // The new firmware is running once the charge point has rebooted
+            if status == CommonFirmwareStatus::Installed {
+              if txn_session.is_running() {
+                txn_session.stop();
+                handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Stop(CommonStopReason::Reboot)).await?;
+                unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+              }
+
+              ws_tx.close().await?;
+              (ws_tx, ws_rx) = reboot(self.general_config.clone(), &self.config, &mut security, logs.messages(), generator.as_ref()).await?.split();
+              security.record_event(CommonSecurityEvent::FirmwareUpdated, None);
+            }
+
+            send(&mut ws_tx, firmware.status_notification(generator.as_ref(), status).await).await?;
+
+            if status == CommonFirmwareStatus::Downloading {
+              firmware.download();
+            }
+            send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+          }
+        },
+        fetched = firmware.fetched(), if firmware.is_downloading() => {
+          firmware.downloaded(fetched, &mut security);
+          send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+        },
+        _ = sleep_until(security_events.next_deadline()) => {
+          if let Some((event, tech_info)) = security_events.pop() {
+            security.record_event(event, tech_info);
+            send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+          }
+        },
+        _ = sleep_until(logs.next_deadline()) => {
+          if let Some((status, request_id)) = logs.pop() {
+            send(&mut ws_tx, generator.log_status_notification(status, request_id).await).await?;
+
+            if status == CommonUploadLogStatus::Uploading {
+              logs.upload();
+            }
+          }
+        },
+        sent = logs.sent(), if logs.is_uploading() => {
+          logs.uploaded(sent);
+        },
+
+        // 🎬 Scenario and 🕹️ control commands
+        command = next_command(&mut scenario, &mut control) => {
+          let event = match command {
+            Some(ControlCommand::Step(ScenarioAction::PlugIn)) => Some(EvEvent::PlugIn),
+            Some(ControlCommand::Step(ScenarioAction::Unplug)) => {
+              unplug_deadline = None;
+              Some(EvEvent::Unplug)
+            },
+            Some(ControlCommand::Step(ScenarioAction::StopTransaction)) => Some(EvEvent::Stop(CommonStopReason::Local)),
+            Some(ControlCommand::Step(ScenarioAction::SuspendEv)) => Some(EvEvent::EvSuspend),
+            Some(ControlCommand::Step(ScenarioAction::ResumeEv)) => Some(EvEvent::EvResume),
+            Some(ControlCommand::Step(ScenarioAction::Fault { error_code, vendor_error_code, info })) => Some(EvEvent::Fault(ConnectorFault {
+              error_code,
+              vendor_error_code,
+              info,
+            })),
+            Some(ControlCommand::Step(ScenarioAction::Recover)) => Some(EvEvent::Recover),
+            Some(ControlCommand::Step(ScenarioAction::PresentTag { id_tag })) => {
+              let id_tag = id_tag.or_else(|| self.id_tags.draw().map(|drawn| drawn.id_tag));
+              send(&mut ws_tx, generator.authorize(id_tag.as_deref()).await).await?;
+              None
+            },
+            Some(ControlCommand::Step(ScenarioAction::DataTransfer { vendor_id, message_id, data })) => {
+              send(&mut ws_tx, generator.data_transfer(vendor_id.as_deref(), message_id.as_deref(), data.as_deref()).await).await?;
+              None
+            },
+            Some(ControlCommand::Step(ScenarioAction::SecurityEvent { event_type, tech_info })) => {
+              security.record_event(CommonSecurityEvent::from(event_type.as_str()), tech_info);
+              send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
+              None
+            },
+            Some(ControlCommand::StartTransaction { id_tag }) => {
+              handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::PlugIn).await?;
+              let id_tag = id_tag.or_else(|| self.id_tags.draw().map(|drawn| drawn.id_tag));
+              send(&mut ws_tx, generator.authorize(id_tag.as_deref()).await).await?;
+              None
+            },
+            Some(ControlCommand::SetStatus { status }) => {
+              let event = status_event(&connector, &status);
+              if event.is_none() {
+                warn!("No EV event leads to status {status}");
+              }
+              event
+            },
+            Some(ControlCommand::SendCall { call, payload }) => {
+              match generator.custom_call(&call, payload).await {
+                Some(message) => send(&mut ws_tx, message).await?,
+                None => warn!("Unknown action {call}"),
+              }
+              None
+            },
+            Some(ControlCommand::Reset) => {
+              if txn_session.is_running() {
+                txn_session.stop();
+                handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Stop(CommonStopReason::HardReset)).await?;
+                unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+              }
 
-            // Sets a connector to an `Available` status
-            send(&mut ws_tx, generator.status_notification(
-              CommonConnectorStatusType::Available
-            ).await).await?;
+              ws_tx.close().await?;
+              (ws_tx, ws_rx) = reboot(self.general_config.clone(), &self.config, &mut security, logs.messages(), generator.as_ref()).await?.split();
+              security.record_event(CommonSecurityEvent::ResetOrReboot, Some(format!("{:?}", CommonStopReason::HardReset)));
+              None
+            },
+            Some(ControlCommand::Connect) if offline => {
+              offline = false;
+              reconnect_deadline = None;
+              (ws_tx, ws_rx) = back_online(self.general_config.clone(), &self.config, &security, logs.messages(), generator.as_ref()).await?.split();
+              if let Some(control) = &control {
+                control.set_connected(true);
+              }
+              None
+            },
+            Some(ControlCommand::Connect) => {
+              warn!("Already connected");
+              None
+            },
+            Some(ControlCommand::Disconnect { duration }) => {
+              // The connection drops without a close handshake, the charge point carries on
+              // and nothing is sent while offline
+              match duration {
+                Some(duration) => warn!("Network lost for {duration}s"),
+                None => warn!("Network lost until connect"),
+              }
+              if !offline {
+                offline = true;
+                (ws_tx, ws_rx) = WsStream::offline(logs.messages()).split();
+                generator.set_offline(true).await;
+                if let Some(control) = &control {
+                  control.set_connected(false);
+                }
+              }
+              reconnect_deadline = duration.map(|duration| Instant::now() + Duration::from_secs(duration));
+              None
+            },
+            Some(ControlCommand::Step(
+              ScenarioAction::Disconnect { .. }
+              | ScenarioAction::WaitForStatus { .. }
+              | ScenarioAction::ExpectCall { .. }
+              | ScenarioAction::ExpectResponse { .. },
+            )) | None => None,
+          };
+
+          if let Some(event) = event {
+            let action = handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+            if let Some(ConnectorAction::StopTransaction(_)) = action {
+              txn_session.stop();
+            }
+          }
+        },
+
+        // 📴 Network back after a disconnect
+        _ = sleep_until(reconnect_deadline) => {
+          offline = false;
+          reconnect_deadline = None;
+          (ws_tx, ws_rx) = back_online(self.general_config.clone(), &self.config, &security, logs.messages(), generator.as_ref()).await?.split();
+          if let Some(control) = &control {
+            control.set_connected(true);
           }
         },
 
@@ -79,214 +385,431 @@ impl ChargePointIdle {
         msg = ws_rx.next() => {
           match msg {
             Some(Ok(Message::Text(text_msg))) => {
-              match handler.parse_raw_ocpp_msg(&text_msg).await? {
-                MessageFrameType::V1_6(ocpp_msg_frame) => {
-                  match ocpp_msg_frame {
-                    MessageFrame::Call {
-                      action,
-                      payload,
-                      ..
-                    } => {
-                      match action {
-                        OcppAction::ChangeConfiguration => {
-                          let change_configuration_payload = V16MessageHandler::parse_change_configuration_payload(payload)?;
-                          match change_configuration_payload.key.as_str() {
-                            // 🔌 Core / Timing
-
-                            "HeartbeatInterval" => {
-                              let value = change_configuration_payload.value.parse::<u32>()?;
-                              heartbeat_interval = interval(Duration::from_secs(value as u64));
-                              generator.heartbeat_interval(value).await
-                            },
-                            "MeterValueSampleInterval" => {
-                              let value = change_configuration_payload.value.parse::<u32>()?;
-                              txn_session.interval(value as u64);
-                              generator.meter_value_sample_interval(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "ClockAlignedDataInterval" => {
-                              generator.clock_aligned_data_interval(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "ConnectionTimeOut" => {
-                              // Not used: assume always plugged in
-                              generator.connection_timeout(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "ResetRetries" => {
-                              // Not used: used for HW side
-                              generator.reset_retries(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "WebSocketPingInterval" => {
-                              let value = change_configuration_payload.value.parse::<u32>()?;
-                              ws_ping_interval = interval(Duration::from_secs(value as u64));
-                              generator.websocket_ping_interval(change_configuration_payload.value.parse::<u32>()?).await
-                            },
+              let frame = handler.parse_raw_ocpp_msg(&text_msg).await?;
+              let mut reset: Option<CommonStopReason> = None;
 
-                            // ⚡ Metering
+              if let Some(scenario) = &mut scenario {
+                scenario.observe_frame(&frame, logs.messages());
+              }
+              if let Some(control) = &control {
+                control.observe_frame(&frame);
+              }
 
-                            "MeterValuesSampledData" => {
-                              generator.meter_values_sampled_data(change_configuration_payload.value.clone()).await
-                            },
-                            "MeterValuesAlignedData" => {
-                              generator.meter_values_aligned_data(change_configuration_payload.value.clone()).await
-                            },
-                            "StopTxnSampledData" => {
-                              generator.stop_txn_sampled_data(change_configuration_payload.value.clone()).await
-                            },
-                            "StopTxnAlignedData" => {
-                              generator.stop_txn_aligned_data(change_configuration_payload.value.clone()).await
-                            },
+              // 🔏 Certificate management
+              if let Some(responses) = handle_certificate_call(&frame, &mut security, generator.as_ref()).await? {
+                for response in responses {
+                  send(&mut ws_tx, response).await?;
+                }
+              } else if let Some(responses) = logs.handle_call(&frame, &mut security, generator.as_ref()).await? {
+                // 🗂️ Logs and customer information
+                for response in responses {
+                  send(&mut ws_tx, response).await?;
+                }
+              } else {
+                match frame {
+                  MessageFrameType::V1_6(MessageFrame::Call {
+                    msg_id,
+                    action,
+                    payload,
+                  }) => {
+                    match action {
+                      OcppAction::ChangeConfiguration => {
+                        let change_configuration_payload = V16MessageHandler::parse_change_configuration_payload(payload)?;
+                        let mut status = CommonConfigurationStatus::Accepted;
+                        match change_configuration_payload.key.as_str() {
+                          // 🔌 Core / Timing
+
+                          "HeartbeatInterval" => {
+                            let value = change_configuration_payload.value.parse::<u32>()?;
+                            heartbeat_interval.set_period(Duration::from_secs(value as u64));
+                            generator.heartbeat_interval(value).await
+                          },
+                          "MeterValueSampleInterval" => {
+                            let value = change_configuration_payload.value.parse::<u32>()?;
+                            txn_session.interval(value as u64);
+                            generator.meter_value_sample_interval(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "ClockAlignedDataInterval" => {
+                            generator.clock_aligned_data_interval(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "ConnectionTimeOut" => {
+                            let value = change_configuration_payload.value.parse::<u32>()?;
+                            connector.set_connection_timeout(value);
+                            generator.connection_timeout(value).await
+                          },
+                          "ResetRetries" => {
+                            // Not used: used for HW side
+                            generator.reset_retries(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "WebSocketPingInterval" => {
+                            let value = change_configuration_payload.value.parse::<u32>()?;
+                            ws_ping_interval.set_period(Duration::from_secs(value as u64));
+                            generator.websocket_ping_interval(change_configuration_payload.value.parse::<u32>()?).await
+                          },
 
-                            // 🔄 Transaction behavior
+                          // ⚡ Metering
 
-                            "TransactionMessageAttempts" => {
-                              generator.transaction_message_attempts(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "TransactionMessageRetryInterval" => {
-                              generator.transaction_message_retry_interval(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "MaxEnergyOnInvalidId" => {
-                              generator.max_energy_on_invalid_id(change_configuration_payload.value.parse::<u32>()?).await
-                            },
+                          "MeterValuesSampledData" => {
+                            generator.meter_values_sampled_data(change_configuration_payload.value.clone()).await
+                          },
+                          "MeterValuesAlignedData" => {
+                            generator.meter_values_aligned_data(change_configuration_payload.value.clone()).await
+                          },
+                          "StopTxnSampledData" => {
+                            generator.stop_txn_sampled_data(change_configuration_payload.value.clone()).await
+                          },
+                          "StopTxnAlignedData" => {
+                            generator.stop_txn_aligned_data(change_configuration_payload.value.clone()).await
+                          },
 
-                            // 🔐 Authorization
+                          // 🔄 Transaction behavior
 
-                            "AuthorizeRemoteTxRequests" => {
-                              generator.authorize_remote_tx_requests(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "StopTransactionOnEVSideDisconnect" => {
-                              generator.stop_transaction_on_ev_side_disconnect(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "StopTransactionOnInvalidId" => {
-                              generator.stop_transaction_on_invalid_id(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "AllowOfflineTxForUnknownId" => {
-                              generator.allow_offline_tx_for_unknown_id(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "LocalAuthorizeOffline" => {
-                              generator.local_authorize_offline(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "LocalPreAuthorize" => {
-                              generator.local_pre_authorize(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "AuthorizationCacheEnabled" => {
-                              generator.authorization_cache_enabled(change_configuration_payload.value.parse::<bool>()?).await
-                            },
+                          "TransactionMessageAttempts" => {
+                            generator.transaction_message_attempts(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "TransactionMessageRetryInterval" => {
+                            generator.transaction_message_retry_interval(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "MaxEnergyOnInvalidId" => {
+                            generator.max_energy_on_invalid_id(change_configuration_payload.value.parse::<u32>()?).await
+                          },
 
-                            // 💳 Local Authorization List
+                          // 🔐 Authorization
 
-                            "LocalAuthListEnabled" => {
-                              generator.local_auth_list_enabled(change_configuration_payload.value.parse::<bool>()?).await
-                            },
-                            "LocalAuthListVersion" => {
-                              generator.local_auth_list_version(change_configuration_payload.value.parse::<i32>()?).await
-                            },
-                            "SendLocalListMaxLength" => {
-                              generator.send_local_list_max_length(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "LocalAuthListMaxLength" => {
-                              generator.local_auth_list_max_length(change_configuration_payload.value.parse::<u32>()?).await
-                            },
+                          "AuthorizeRemoteTxRequests" => {
+                            generator.authorize_remote_tx_requests(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "StopTransactionOnEVSideDisconnect" => {
+                            let value = change_configuration_payload.value.parse::<bool>()?;
+                            connector.set_stop_transaction_on_ev_side_disconnect(value);
+                            generator.stop_transaction_on_ev_side_disconnect(value).await
+                          },
+                          "StopTransactionOnInvalidId" => {
+                            generator.stop_transaction_on_invalid_id(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "AllowOfflineTxForUnknownId" => {
+                            generator.allow_offline_tx_for_unknown_id(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "LocalAuthorizeOffline" => {
+                            generator.local_authorize_offline(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "LocalPreAuthorize" => {
+                            generator.local_pre_authorize(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "AuthorizationCacheEnabled" => {
+                            generator.authorization_cache_enabled(change_configuration_payload.value.parse::<bool>()?).await
+                          },
 
-                            // 🔌 Connector / Hardware
+                          // 💳 Local Authorization List
 
-                            "NumberOfConnectors" => {
-                              generator.number_of_connectors(change_configuration_payload.value.parse::<u32>()?).await
-                            },
-                            "ConnectorPhaseRotation" => {
-                              generator.connector_phase_rotation(change_configuration_payload.value.clone()).await
-                            },
+                          "LocalAuthListEnabled" => {
+                            generator.local_auth_list_enabled(change_configuration_payload.value.parse::<bool>()?).await
+                          },
+                          "LocalAuthListVersion" => {
+                            generator.local_auth_list_version(change_configuration_payload.value.parse::<i32>()?).await
+                          },
+                          "SendLocalListMaxLength" => {
+                            generator.send_local_list_max_length(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "LocalAuthListMaxLength" => {
+                            generator.local_auth_list_max_length(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+
+                          // 🔌 Connector / Hardware
+
+                          "NumberOfConnectors" => {
+                            generator.number_of_connectors(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "ConnectorPhaseRotation" => {
+                            generator.connector_phase_rotation(change_configuration_payload.value.clone()).await
+                          },
+
+                          // ⚡ Smart Charging
+
+                          "ChargeProfileMaxStackLevel" => {
+                            generator.charge_profile_max_stack_level(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+                          "ChargingScheduleAllowedChargingRateUnit" => {
+                            generator.charging_schedule_allowed_charging_rate_unit(change_configuration_payload.value.clone()).await
+                          },
+                          "ChargingScheduleMaxPeriods" => {
+                            generator.charging_schedule_max_periods(change_configuration_payload.value.parse::<u32>()?).await
+                          },
+
+                          // 📊 Limits / Misc
+
+                          "GetConfigurationMaxKeys" => {
+                            generator.get_configuration_max_keys(change_configuration_payload.value.parse::<u32>()?).await
+                          },
 
-                            // ⚡ Smart Charging
+                          // 🛡️ Security
 
-                            "ChargeProfileMaxStackLevel" => {
-                              generator.charge_profile_max_stack_level(change_configuration_payload.value.parse::<u32>()?).await
+                          key @ ("SecurityProfile" | "AuthorizationKey") => {
+                            status = security.change_configuration(key, &change_configuration_payload.value);
+                          },
+                          key => {
+                            error!("ChangeConfiguration unknown key {key}");
+                            status = CommonConfigurationStatus::NotSupported;
+                          },
+                        }
+                        send(&mut ws_tx, generator.change_configuration(&msg_id, status).await).await?;
+                      },
+                      OcppAction::RemoteStartTransaction => {
+                        let action_payload = V16MessageHandler::parse_remote_start_transaction_payload(payload)?;
+                        send(&mut ws_tx, generator.authorize(Some(&action_payload.id_tag)).await).await?;
+                      },
+                      OcppAction::RemoteStopTransaction => {
+                        txn_session.stop();
+                        let action = handle_ev_event(
+                          &mut connector,
+                          generator.as_ref(),
+                          &mut ws_tx,
+                          EvEvent::Stop(CommonStopReason::Remote),
+                        ).await?;
+
+                        if let Some(ConnectorAction::StopTransaction(_)) = action {
+                          unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+                        }
+                      },
+                      OcppAction::Reset => {
+                        let request: ResetRequest = V16MessageHandler::parse_payload(payload)?;
+                        send(&mut ws_tx, generator.reset(&msg_id).await).await?;
+                        reset = Some(match request.kind {
+                          ResetRequestStatus::Hard => CommonStopReason::HardReset,
+                          ResetRequestStatus::Soft => CommonStopReason::SoftReset,
+                        });
+                      },
+                      OcppAction::UpdateFirmware => {
+                        let request: UpdateFirmwareRequest = V16MessageHandler::parse_payload(payload)?;
+                        send(&mut ws_tx, generator.update_firmware(&msg_id, CommonUpdateFirmwareStatus::Accepted).await).await?;
+                        firmware.schedule(request.retrieve_date, None);
+                      },
+                      OcppAction::SignedUpdateFirmware => {
+                        let request: SignedUpdateFirmwareRequest = V16MessageHandler::parse_payload(payload)?;
+                        let status = firmware.schedule_signed(request.into(), &mut security);
+                        send(&mut ws_tx, generator.signed_update_firmware(&msg_id, status).await).await?;
+                      },
+                      OcppAction::SetChargingProfile => {
+                        let (response, limit) = generator.set_charging_profile(&msg_id, payload).await;
+                        send(&mut ws_tx, response).await?;
+                        if let Some(event) = connector.set_charging_limit(limit) {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                        }
+                      },
+                      OcppAction::ClearChargingProfile => {
+                        send(&mut ws_tx, generator.clear_charging_profile(&msg_id, payload).await).await?;
+                        if let Some(event) = connector.set_charging_limit(None) {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                        }
+                      },
+                      _ => warn!("Unknown action {}", action)
+                    }
+                  },
+                  MessageFrameType::V2_0_1(MessageFrame::Call {
+                    msg_id,
+                    action,
+                    payload,
+                  }) => {
+                    match action {
+                      V201OcppAction::RequestStartTransaction => {
+                        let action_payload = V201MessageHandler::parse_request_start_transaction_payload(payload)?;
+                        send(&mut ws_tx, V201MessageHandler::request_start_stop_response(&msg_id, &action, true)).await?;
+                        send(&mut ws_tx, generator.authorize(Some(&action_payload.id_token.id_token)).await).await?;
+                      },
+                      V201OcppAction::RequestStopTransaction => {
+                        let accepted = txn_session.is_running();
+                        send(&mut ws_tx, V201MessageHandler::request_start_stop_response(&msg_id, &action, accepted)).await?;
+
+                        if accepted {
+                          txn_session.stop();
+                          let action = handle_ev_event(
+                            &mut connector,
+                            generator.as_ref(),
+                            &mut ws_tx,
+                            EvEvent::Stop(CommonStopReason::Remote),
+                          ).await?;
+
+                          if let Some(ConnectorAction::StopTransaction(_)) = action {
+                            unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
+                          }
+                        }
+                      },
+                      V201OcppAction::Reset => {
+                        let request: V201ResetRequest = V201MessageHandler::parse_payload(payload)?;
+                        send(&mut ws_tx, generator.reset(&msg_id).await).await?;
+                        reset = Some(match request.request_type {
+                          ResetEnumType::Immediate => CommonStopReason::HardReset,
+                          ResetEnumType::OnIdle => CommonStopReason::SoftReset,
+                        });
+                      },
+                      V201OcppAction::UpdateFirmware => {
+                        let request: V201UpdateFirmwareRequest = V201MessageHandler::parse_payload(payload)?;
+                        let status = match SignedFirmwareRequest::from_v2_0_1(&request) {
+                          Some(signed) => firmware.schedule_signed(signed, &mut security),
+                          None => {
+                            firmware.schedule(request.firmware.retrieve_date_time, Some(request.request_id));
+                            CommonUpdateFirmwareStatus::Accepted
+                          }
+                        };
+                        send(&mut ws_tx, generator.update_firmware(&msg_id, status).await).await?;
+                      },
+
+                      V201OcppAction::GetTransactionStatus => {
+                        send(&mut ws_tx, generator.get_transaction_status(&msg_id, payload).await).await?;
+                      },
+
+                      // 🧩 Device model
+
+                      V201OcppAction::GetVariables => {
+                        send(&mut ws_tx, generator.get_variables(&msg_id, payload).await).await?;
+                      },
+                      V201OcppAction::SetVariables => {
+                        let (response, changes) = generator.set_variables(&msg_id, payload).await;
+                        send(&mut ws_tx, response).await?;
+
+                        for change in changes {
+                          match change {
+                            CommonSettingChange::HeartbeatInterval(value) => {
+                              heartbeat_interval.set_period(Duration::from_secs(value as u64));
                             },
-                            "ChargingScheduleAllowedChargingRateUnit" => {
-                              generator.charging_schedule_allowed_charging_rate_unit(change_configuration_payload.value.clone()).await
+                            CommonSettingChange::MeterValueSampleInterval(value) => txn_session.interval(value as u64),
+                            CommonSettingChange::ConnectionTimeOut(value) => connector.set_connection_timeout(value),
+                            CommonSettingChange::WebSocketPingInterval(value) => {
+                              ws_ping_interval.set_period(Duration::from_secs(value as u64));
                             },
-                            "ChargingScheduleMaxPeriods" => {
-                              generator.charging_schedule_max_periods(change_configuration_payload.value.parse::<u32>()?).await
+                            CommonSettingChange::StopTransactionOnEVSideDisconnect(value) => {
+                              connector.set_stop_transaction_on_ev_side_disconnect(value);
                             },
+                          }
+                        }
+                      },
+                      V201OcppAction::GetBaseReport => {
+                        for message in generator.get_base_report(&msg_id, payload).await {
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
+                      V201OcppAction::GetReport => {
+                        for message in generator.get_report(&msg_id, payload).await {
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
+
+                      // 📡 Monitoring
+
+                      V201OcppAction::SetVariableMonitoring => {
+                        send(&mut ws_tx, generator.set_variable_monitoring(&msg_id, payload).await).await?;
+                        monitoring = generator.has_monitors().await;
+                      },
+                      V201OcppAction::ClearVariableMonitoring => {
+                        send(&mut ws_tx, generator.clear_variable_monitoring(&msg_id, payload).await).await?;
+                        monitoring = generator.has_monitors().await;
+                      },
+                      V201OcppAction::SetMonitoringBase => {
+                        send(&mut ws_tx, generator.set_monitoring_base(&msg_id, payload).await).await?;
+                        monitoring = generator.has_monitors().await;
+                      },
+                      V201OcppAction::SetMonitoringLevel => {
+                        send(&mut ws_tx, generator.set_monitoring_level(&msg_id, payload).await).await?;
+                      },
+                      V201OcppAction::GetMonitoringReport => {
+                        for message in generator.get_monitoring_report(&msg_id, payload).await {
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
 
-                            // 📊 Limits / Misc
+                      // ⚡ Smart charging
 
-                            "GetConfigurationMaxKeys" => {
-                              generator.get_configuration_max_keys(change_configuration_payload.value.parse::<u32>()?).await
+                      V201OcppAction::SetChargingProfile => {
+                        let (response, limit) = generator.set_charging_profile(&msg_id, payload).await;
+                        send(&mut ws_tx, response).await?;
+                        if let Some(event) = connector.set_charging_limit(limit) {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                        }
+                      },
+                      V201OcppAction::ClearChargingProfile => {
+                        send(&mut ws_tx, generator.clear_charging_profile(&msg_id, payload).await).await?;
+                        if let Some(event) = connector.set_charging_limit(None) {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, event).await?;
+                        }
+                      },
+
+                      // 🖥️ Display messages
+
+                      V201OcppAction::SetDisplayMessage => {
+                        send(&mut ws_tx, generator.set_display_message(&msg_id, payload).await).await?;
+                      },
+                      V201OcppAction::GetDisplayMessages => {
+                        for message in generator.get_display_messages(&msg_id, payload).await {
+                          send(&mut ws_tx, message).await?;
+                        }
+                      },
+                      V201OcppAction::ClearDisplayMessage => {
+                        send(&mut ws_tx, generator.clear_display_message(&msg_id, payload).await).await?;
+                      },
+                      V201OcppAction::CostUpdated => {
+                        send(&mut ws_tx, generator.cost_updated(&msg_id, payload).await).await?;
+                      },
+                      _ => send(&mut ws_tx, V201MessageHandler::not_implemented(&msg_id, &action)).await?,
+                    }
+                  },
+                  MessageFrameType::V1_6(MessageFrame::CallResult { msg_id, payload })
+                  | MessageFrameType::V2_0_1(MessageFrame::CallResult { msg_id, payload }) => {
+                    if let Some(common_ocpp_msg) = handler.handle_call_result(&msg_id, &payload).await? {
+                      match common_ocpp_msg {
+                        CommonOcppResponse::Authorize { id_tag, status, parent_id_tag } => {
+                          self.id_tags.record(&id_tag, status, parent_id_tag.as_deref());
+
+                          match status {
+                            AuthorizationStatus::Accepted => {
+                              handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Authorize).await?;
+                              // Without a scenario there is no driver, the cable is plugged in right away
+                              if scenario.is_none() && !connector.is_plugged_in() {
+                                handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::PlugIn).await?;
+                              }
+                            },
+                            AuthorizationStatus::Blocked |
+                            AuthorizationStatus::Expired |
+                            AuthorizationStatus::Invalid => {
+                              warn!("Authorization rejected: {:?}", status);
+                            },
+                            AuthorizationStatus::ConcurrentTx => {
+                              warn!("Concurrent transaction in progress");
                             },
-                            key => error!("ChangeConfiguration unknown key {key}"),
                           }
-                          send(&mut ws_tx, generator.change_configuration().await).await?;
                         },
-                        OcppAction::RemoteStartTransaction => {
-                          let action_payload = V16MessageHandler::parse_remote_start_transaction_payload(payload)?;
-                          send(&mut ws_tx, generator.authorize(Some(&action_payload.id_tag)).await).await?;
+                        CommonOcppResponse::StartTransaction { .. } => {
+                          handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::TransactionStarted).await?;
+                          txn_session.start();
                         },
-                        OcppAction::RemoteStopTransaction => {
-                          txn_session.stop();
-                          send(&mut ws_tx, generator.stop_transaction().await).await?;
-
-                          // Sets a connector to an `Available` status
-                          send(&mut ws_tx, generator.status_notification(
-                            CommonConnectorStatusType::Available
-                          ).await).await?;
-                        },
-                        _ => warn!("Unknown action {}", action)
-                      }
-                    },
-                    MessageFrame::CallResult {
-                      msg_id,
-                      payload
-                    } => {
-                      if let Some(common_ocpp_msg) = handler.handle_call_result(&msg_id, &payload).await? {
-                        match common_ocpp_msg {
-                          CommonOcppResponse::Authorize { status } => {
-                            match status {
-                              AuthorizationStatus::Accepted => {
-                                // Sets a connector to an `Preparing` status
-                                send(&mut ws_tx, generator.status_notification(
-                                  CommonConnectorStatusType::Preparing
-                                ).await).await?;
-
-                                // Simulates HW connector delay
-                                sleep(Duration::from_secs(5)).await;
-
-                                send(&mut ws_tx, generator.start_transaction().await).await?;
-                              },
-                              AuthorizationStatus::Blocked |
-                              AuthorizationStatus::Expired |
-                              AuthorizationStatus::Invalid => {
-                                warn!("Authorization rejected: {:?}", status);
-                              },
-                              AuthorizationStatus::ConcurrentTx => {
-                                warn!("Concurrent transaction in progress");
-                              },
-                            }
-                          },
-                          CommonOcppResponse::StartTransaction { .. } => {
-                            // Sets a connector to an `Charging` status
-                            send(&mut ws_tx, generator.status_notification(
-                              CommonConnectorStatusType::Charging
-                            ).await).await?;
-
-                            txn_session.start();
-                          },
-                          _ => {}
-                        }
+                        _ => {}
                       }
-                    },
-                    _ => {}
-                  }
-                },
-                #[allow(unused)]
-                MessageFrameType::V2_0_1(ocpp_msg_frame) => {
-                  error!("Not implemented yet")
+                    }
+                  },
+                  _ => {}
                 }
-                #[allow(unused)]
-                MessageFrameType::V2_1(ocpp_msg_frame) => {
-                  error!("Not implemented yet")
+              }
+
+              if let Some(reason) = reset {
+                if txn_session.is_running() {
+                  txn_session.stop();
+                  handle_ev_event(&mut connector, generator.as_ref(), &mut ws_tx, EvEvent::Stop(reason)).await?;
+                  unplug_deadline = Some(Instant::now() + EV_UNPLUG_DELAY);
                 }
+
+                ws_tx.close().await?;
+                (ws_tx, ws_rx) = reboot(self.general_config.clone(), &self.config, &mut security, logs.messages(), generator.as_ref()).await?.split();
+                security.record_event(CommonSecurityEvent::ResetOrReboot, Some(format!("{:?}", reason)));
+              } else if security.take_reconnect() {
+                ws_tx.close().await?;
+                (ws_tx, ws_rx) = reconnect(self.general_config.clone(), &self.config, &mut security, logs.messages()).await?.split();
+                generator.security_profile(security.profile() as u32).await;
+                send_queued_transaction_events(&mut ws_tx, generator.as_ref()).await?;
               }
+
+              // 🚨 Security events
+              send_security_events(&mut ws_tx, &mut security, generator.as_ref()).await?;
             },
-            Some(Ok(Message::Pong(_))) => info!("⬅️  pong"),
+            Some(Ok(Message::Pong(_))) => frame_info!("⬅️  pong"),
             Some(other_msg) => warn!("Another message {other_msg:?}"),
             None => break
           }
@@ -294,6 +817,23 @@ impl ChargePointIdle {
 
         _ = tokio::signal::ctrl_c() => break
       }
+
+      if let Some(control) = &control {
+        control.update(&connector, generator.session().await);
+      }
+      if let Some(metrics) = &self.taps.metrics {
+        let session = (connector.has_transaction(), generator.session().await.1);
+        if metered_session != Some(session) {
+          metered_session = Some(session);
+          metrics.update_session(&self.config.id, session.0, session.1);
+        }
+      }
+      if let Some(scenario) = &mut scenario {
+        scenario.observe_status(connector.status());
+        if scenario.is_finished() {
+          break;
+        }
+      }
     }
 
     ws_tx.close().await?;
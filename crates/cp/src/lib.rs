@@ -1,5 +1,25 @@
+pub mod certificates;
 pub mod config;
+pub mod connector;
+pub mod control;
 pub mod core;
 pub mod dynamic;
+pub mod fault;
+pub mod firmware;
+pub mod id_tags;
 pub mod idle;
+pub mod load_test;
+pub mod logs;
+pub mod metrics;
+pub mod plug_and_charge;
+pub mod recording;
+pub mod replay;
+pub mod scenario;
+pub mod security;
+pub mod security_events;
 pub mod session;
+#[cfg(test)]
+mod test_certificates;
+#[cfg(test)]
+mod test_config;
+pub mod timers;
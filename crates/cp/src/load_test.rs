@@ -0,0 +1,156 @@
+//! Ramp-up and ramp-down schedule of a load test and its end-of-run report
+
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::Result;
+use colored::Colorize;
+use common::{LoadTestConfig, RampUpProfile};
+use serde::Serialize;
+use tracing::info;
+
+/// Time after the start of the test the charge point with the index is started
+pub fn start_offset(config: &LoadTestConfig, index: usize) -> Duration {
+  match config.ramp_up_profile {
+    RampUpProfile::Linear => Duration::from_secs_f64(index as f64 / config.ramp_up_rate),
+    RampUpProfile::Stepped => {
+      let per_step = (config.ramp_up_rate * config.step_interval as f64).max(1.0) as usize;
+      Duration::from_secs((index / per_step) as u64 * config.step_interval)
+    }
+  }
+}
+
+/// Time after the end of the steady state the charge point with the index is stopped, the
+/// last started first
+pub fn stop_offset(config: &LoadTestConfig, index: usize, count: usize) -> Duration {
+  match config.ramp_down {
+    Some(ramp_down) => {
+      Duration::from_secs_f64((count - 1 - index) as f64 * ramp_down as f64 / count as f64)
+    }
+    None => Duration::ZERO,
+  }
+}
+
+/// Answer times of an action, milliseconds
+#[derive(Debug, Default, Serialize)]
+pub struct ActionReport {
+  pub action: String,
+  pub answered: u64,
+  pub timeouts: u64,
+  pub p50: Option<f64>,
+  pub p95: Option<f64>,
+  pub p99: Option<f64>,
+}
+
+/// Outcome of a load test
+#[derive(Debug, Serialize)]
+pub struct LoadReport {
+  /// Charge points that connected at least once
+  pub charge_points: u64,
+  pub connection_failures: u64,
+  pub reconnects: u64,
+  pub call_errors: u64,
+  pub actions: Vec<ActionReport>,
+}
+
+impl LoadReport {
+  pub fn log_summary(&self) {
+    info!("{}", "load test summary".purple());
+    info!(
+      "charge points connected {}, connection failures {}, reconnects {}, CallErrors {}",
+      self.charge_points,
+      match self.connection_failures {
+        0 => "0".normal(),
+        failures => failures.to_string().red(),
+      },
+      self.reconnects,
+      self.call_errors
+    );
+    info!(
+      "{:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+      "action", "answered", "timeouts", "p50 ms", "p95 ms", "p99 ms"
+    );
+
+    let ms = |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{value:.1}"));
+    for action in &self.actions {
+      let line = format!(
+        "{:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        action.action,
+        action.answered,
+        action.timeouts,
+        ms(action.p50),
+        ms(action.p95),
+        ms(action.p99)
+      );
+      match action.timeouts {
+        0 => info!("{line}"),
+        _ => info!("{}", line.red()),
+      }
+    }
+  }
+
+  pub fn write_json(&self, path: &Path) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(self)?)?;
+    info!("Load test report written to {}", path.display());
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(ramp_up_profile: RampUpProfile, ramp_up_rate: f64) -> LoadTestConfig {
+    LoadTestConfig {
+      ramp_up_rate,
+      ramp_up_profile,
+      step_interval: 10,
+      steady_duration: 60,
+      ramp_down: None,
+      response_timeout: 30,
+      report_path: None,
+    }
+  }
+
+  #[test]
+  fn linear_ramp_up_starts_at_the_rate() {
+    let config = config(RampUpProfile::Linear, 2.0);
+
+    assert_eq!(start_offset(&config, 0), Duration::ZERO);
+    assert_eq!(start_offset(&config, 3), Duration::from_millis(1500));
+  }
+
+  #[test]
+  fn stepped_ramp_up_starts_a_step_every_interval() {
+    let config = config(RampUpProfile::Stepped, 2.0);
+
+    assert_eq!(start_offset(&config, 19), Duration::ZERO);
+    assert_eq!(start_offset(&config, 20), Duration::from_secs(10));
+    assert_eq!(start_offset(&config, 45), Duration::from_secs(20));
+  }
+
+  #[test]
+  fn stepped_ramp_up_starts_one_per_step_at_least() {
+    let config = config(RampUpProfile::Stepped, 0.05);
+
+    assert_eq!(start_offset(&config, 3), Duration::from_secs(30));
+  }
+
+  #[test]
+  fn ramp_down_stops_the_last_started_first() {
+    let config = LoadTestConfig {
+      ramp_down: Some(10),
+      ..config(RampUpProfile::Linear, 1.0)
+    };
+
+    assert_eq!(stop_offset(&config, 4, 5), Duration::ZERO);
+    assert_eq!(stop_offset(&config, 0, 5), Duration::from_secs(8));
+  }
+
+  #[test]
+  fn without_ramp_down_all_stop_at_once() {
+    let config = config(RampUpProfile::Linear, 1.0);
+
+    assert_eq!(stop_offset(&config, 0, 5), Duration::ZERO);
+    assert_eq!(stop_offset(&config, 4, 5), Duration::ZERO);
+  }
+}
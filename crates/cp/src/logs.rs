@@ -0,0 +1,731 @@
+use std::{
+  collections::VecDeque,
+  path::Path,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, SecondsFormat, Utc};
+use http::StatusCode;
+use ocpp::{
+  generator::MessageGenerator,
+  handler::{MessageFrame, MessageFrameType},
+  types::{CommonCustomerInformationStatus, CommonLogStatus, CommonUploadLogStatus},
+  v2_0_1::{handler::V201MessageHandler, types::OcppAction as V201OcppAction},
+};
+use rust_ocpp::v2_0_1::{
+  enumerations::log_enum_type::LogEnumType,
+  messages::{customer_information::CustomerInformationRequest, get_log::GetLogRequest},
+};
+use serde_json::Value;
+use tokio::{task::JoinHandle, time::Instant};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+  metrics::{ConnectionGuard, Metrics},
+  recording::Recorder,
+  security::Security,
+};
+
+/// Time limit for uploading a log
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// Replaces the customer data cleared from the message log
+const REDACTED: &str = "\"<redacted>\"";
+
+struct LogEntry {
+  timestamp: DateTime<Utc>,
+  sent: bool,
+  text: Box<str>,
+}
+
+/// Where the messages of all charge points go besides their own message log
+#[derive(Clone, Default)]
+pub struct MessageTaps {
+  pub recorder: Option<Arc<Recorder>>,
+  pub metrics: Option<Arc<Metrics>>,
+}
+
+/// OCPP messages a charge point exchanged with the CSMS, shared with its WebSocket
+#[derive(Clone, Default)]
+pub struct MessageLog {
+  entries: Arc<Mutex<VecDeque<LogEntry>>>,
+  /// Messages kept, the oldest are dropped first
+  capacity: usize,
+  charge_point_id: Arc<str>,
+  taps: MessageTaps,
+}
+
+impl MessageLog {
+  pub fn record(&self, sent: bool, text: &str) {
+    let timestamp = Utc::now();
+    if let Some(recorder) = &self.taps.recorder {
+      recorder.record(timestamp, &self.charge_point_id, sent, text);
+    }
+    if let Some(metrics) = &self.taps.metrics {
+      metrics.observe_frame(sent, text);
+    }
+
+    if self.capacity == 0 {
+      return;
+    }
+    let mut entries = self.entries.lock().unwrap();
+    if entries.len() == self.capacity {
+      entries.pop_front();
+    }
+    entries.push_back(LogEntry {
+      timestamp,
+      sent,
+      text: text.into(),
+    });
+  }
+
+  /// Counts the connection in the metrics while the guard is kept
+  pub fn connection(&self) -> Option<ConnectionGuard> {
+    Some(
+      self
+        .taps
+        .metrics
+        .as_ref()?
+        .connection(&self.charge_point_id),
+    )
+  }
+
+  pub fn connection_failed(&self) {
+    if let Some(metrics) = &self.taps.metrics {
+      metrics.connection_failed();
+    }
+  }
+
+  /// Lines of the messages between the given times, the security log only holds the
+  /// security events reported to the CSMS
+  pub fn archive(
+    &self,
+    log_type: &LogEnumType,
+    oldest: Option<DateTime<Utc>>,
+    latest: Option<DateTime<Utc>>,
+  ) -> String {
+    self
+      .entries
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|entry| {
+        oldest.is_none_or(|oldest| entry.timestamp >= oldest)
+          && latest.is_none_or(|latest| entry.timestamp <= latest)
+          && match log_type {
+            LogEnumType::DiagnosticsLog => true,
+            LogEnumType::SecurityLog => entry.text.contains("\"SecurityEventNotification\""),
+          }
+      })
+      .map(|entry| {
+        format!(
+          "{} {} {}\n",
+          entry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+          if entry.sent { "->" } else { "<-" },
+          entry.text
+        )
+      })
+      .collect()
+  }
+
+  /// Lines of the latest messages, the oldest first
+  pub fn tail(&self, count: usize) -> Vec<String> {
+    let entries = self.entries.lock().unwrap();
+    entries
+      .iter()
+      .skip(entries.len().saturating_sub(count))
+      .map(|entry| {
+        format!(
+          "{} {} {}",
+          entry.timestamp.format("%H:%M:%S%.3f"),
+          if entry.sent { "->" } else { "<-" },
+          entry.text
+        )
+      })
+      .collect()
+  }
+
+  /// Times of the messages holding the value as a JSON string
+  pub fn mentions(&self, value: &str) -> Vec<DateTime<Utc>> {
+    let quoted = format!("\"{value}\"");
+    self
+      .entries
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|entry| entry.text.contains(&quoted))
+      .map(|entry| entry.timestamp)
+      .collect()
+  }
+
+  /// Action of the call the charge point sent with the message id
+  pub fn sent_action(&self, msg_id: &str) -> Option<String> {
+    let quoted = format!("\"{msg_id}\"");
+    self
+      .entries
+      .lock()
+      .unwrap()
+      .iter()
+      .rev()
+      .filter(|entry| entry.sent && entry.text.contains(&quoted))
+      .find_map(|entry| {
+        let frame: Value = serde_json::from_str(&entry.text).ok()?;
+        (frame[0] == 2 && frame[1] == msg_id).then(|| frame[2].as_str().map(str::to_string))?
+      })
+  }
+
+  /// Masks the value in every message, returns how many messages held it
+  pub fn redact(&self, value: &str) -> usize {
+    let quoted = format!("\"{value}\"");
+    let mut redacted = 0;
+    for entry in self.entries.lock().unwrap().iter_mut() {
+      if entry.text.contains(&quoted) {
+        entry.text = entry.text.replace(&quoted, REDACTED).into();
+        redacted += 1;
+      }
+    }
+    redacted
+  }
+}
+
+/// Log archive waiting to be uploaded
+struct PendingUpload {
+  request_id: i32,
+  location: Arc<str>,
+  filename: Arc<str>,
+  archive: Arc<str>,
+  retries: u32,
+  retry_interval: Duration,
+}
+
+/// Message log of a charge point with the progress of a log upload requested by the CSMS
+pub struct Logs {
+  charge_point_id: String,
+  messages: MessageLog,
+  upload: Option<PendingUpload>,
+  /// Archive being sent in the background
+  sending: Option<JoinHandle<Result<()>>>,
+  /// Pending status changes ordered by their deadline, with their request id
+  steps: VecDeque<(Instant, CommonUploadLogStatus, i32)>,
+}
+
+impl Logs {
+  pub fn new(charge_point_id: &str, capacity: usize, taps: MessageTaps) -> Self {
+    Self {
+      charge_point_id: charge_point_id.to_string(),
+      messages: MessageLog {
+        entries: Default::default(),
+        capacity,
+        charge_point_id: charge_point_id.into(),
+        taps,
+      },
+      upload: None,
+      sending: None,
+      steps: VecDeque::new(),
+    }
+  }
+
+  pub fn messages(&self) -> &MessageLog {
+    &self.messages
+  }
+
+  /// Archives the requested log and schedules its upload, canceling the pending one
+  fn schedule(&mut self, request: GetLogRequest) -> (CommonLogStatus, String) {
+    let now = Instant::now();
+    let log_type = match request.log_type {
+      LogEnumType::DiagnosticsLog => "DiagnosticsLog",
+      LogEnumType::SecurityLog => "SecurityLog",
+    };
+    let filename = format!(
+      "{}-{}-{}.log",
+      self.charge_point_id,
+      log_type,
+      Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+
+    self.steps.clear();
+    if let Some(sending) = self.sending.take() {
+      sending.abort();
+    }
+    let status = match self.upload.take() {
+      Some(canceled) => {
+        info!("Log upload {} canceled", canceled.request_id);
+        self.steps.push_back((
+          now,
+          CommonUploadLogStatus::AcceptedCanceled,
+          canceled.request_id,
+        ));
+        CommonLogStatus::AcceptedCanceled
+      }
+      None => CommonLogStatus::Accepted,
+    };
+
+    self.upload = Some(PendingUpload {
+      request_id: request.request_id,
+      location: request.log.remote_location.into(),
+      filename: filename.as_str().into(),
+      archive: self
+        .messages
+        .archive(
+          &request.log_type,
+          request.log.oldest_timestamp,
+          request.log.latest_timestamp,
+        )
+        .into(),
+      retries: request.retries.unwrap_or_default().max(0) as u32,
+      retry_interval: Duration::from_secs(request.retry_interval.unwrap_or_default().max(0) as u64),
+    });
+    self
+      .steps
+      .push_back((now, CommonUploadLogStatus::Uploading, request.request_id));
+
+    (status, filename)
+  }
+
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.steps.front().map(|(deadline, _, _)| *deadline)
+  }
+
+  /// Takes the next due status with its request id
+  pub fn pop(&mut self) -> Option<(CommonUploadLogStatus, i32)> {
+    if self.next_deadline()? > Instant::now() {
+      return None;
+    }
+
+    self
+      .steps
+      .pop_front()
+      .map(|(_, status, request_id)| (status, request_id))
+  }
+
+  /// Starts sending the archive once `Uploading` has been reported, the outcome comes
+  /// from `sent`
+  pub fn upload(&mut self) {
+    let Some(upload) = &self.upload else {
+      return;
+    };
+
+    let (location, filename, archive) = (
+      upload.location.clone(),
+      upload.filename.clone(),
+      upload.archive.clone(),
+    );
+    self.sending = Some(tokio::spawn(async move {
+      send_archive(&location, &filename, &archive).await
+    }));
+  }
+
+  pub fn is_uploading(&self) -> bool {
+    self.sending.is_some()
+  }
+
+  /// Outcome of the running upload once the archive has been sent, cancel safe
+  pub async fn sent(&mut self) -> Result<()> {
+    let Some(sending) = &mut self.sending else {
+      return std::future::pending().await;
+    };
+
+    let sent = sending.await.map_err(anyhow::Error::from).flatten();
+    self.sending = None;
+    sent
+  }
+
+  /// Schedules the outcome of the upload, a retry while `UploadFailure` retries are left
+  pub fn uploaded(&mut self, sent: Result<()>) {
+    let Some(upload) = &mut self.upload else {
+      return;
+    };
+
+    let now = Instant::now();
+    let status = match sent {
+      Ok(()) => {
+        info!(
+          "Uploaded log {} ({} bytes) to {}",
+          upload.filename,
+          upload.archive.len(),
+          upload.location
+        );
+        CommonUploadLogStatus::Uploaded
+      }
+      Err(err) => match upload_failure(&err) {
+        CommonUploadLogStatus::UploadFailure if upload.retries > 0 => {
+          warn!("Log upload failed, retrying: {err:#}");
+          upload.retries -= 1;
+          self.steps = VecDeque::from([(
+            now + upload.retry_interval,
+            CommonUploadLogStatus::Uploading,
+            upload.request_id,
+          )]);
+          return;
+        }
+        status => {
+          warn!("Log upload failed: {err:#}");
+          status
+        }
+      },
+    };
+
+    self.steps = VecDeque::from([(now, status, upload.request_id)]);
+    self.upload = None;
+  }
+
+  /// Report of what the charge point stores about the customer, the data is cleared
+  /// afterwards when requested, `None` when no customer is identified
+  fn customer_information(
+    &self,
+    request: &CustomerInformationRequest,
+    security: &mut Security,
+  ) -> Option<String> {
+    let identifiers = request
+      .id_token
+      .iter()
+      .map(|id_token| ("idToken", id_token.id_token.clone()))
+      .chain(
+        request
+          .customer_identifier
+          .iter()
+          .map(|identifier| ("customerIdentifier", identifier.clone())),
+      )
+      .collect::<Vec<_>>();
+    if identifiers.is_empty() && request.customer_certificate.is_none() {
+      return None;
+    }
+
+    let mut report = Vec::new();
+    for (kind, value) in &identifiers {
+      let mentions = self.messages.mentions(value);
+      report.push(match (mentions.first(), mentions.last()) {
+        (Some(first), Some(last)) => format!(
+          "{kind} {value}: {} messages from {} to {}",
+          mentions.len(),
+          first.to_rfc3339_opts(SecondsFormat::Secs, true),
+          last.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+        _ => format!("{kind} {value}: no data"),
+      });
+
+      if request.clear {
+        let redacted = self.messages.redact(value);
+        info!("Cleared {kind} {value} from {redacted} messages");
+      }
+    }
+
+    if let Some(certificate) = &request.customer_certificate {
+      let hash_data = certificate.clone().into();
+      let installed = security
+        .certificates()
+        .installed_ids(&[])
+        .into_iter()
+        .find(|installed| installed.hash_data == hash_data);
+      report.push(match &installed {
+        Some(installed) => format!(
+          "certificate {}: installed as {:?}",
+          certificate.serial_number, installed.certificate_type
+        ),
+        None => format!("certificate {}: not installed", certificate.serial_number),
+      });
+
+      if request.clear && installed.is_some() {
+        security.certificates().delete(&hash_data);
+      }
+    }
+
+    Some(report.join("\n"))
+  }
+
+  /// Handles the `GetLog` and `CustomerInformation` calls of the CSMS, returns the messages
+  /// to send or `None` when the frame is not one of them
+  pub async fn handle_call(
+    &mut self,
+    frame: &MessageFrameType,
+    security: &mut Security,
+    generator: &dyn MessageGenerator,
+  ) -> Result<Option<Vec<Value>>> {
+    let MessageFrameType::V2_0_1(MessageFrame::Call {
+      msg_id,
+      action,
+      payload,
+    }) = frame
+    else {
+      return Ok(None);
+    };
+
+    match action {
+      V201OcppAction::GetLog => {
+        let request: GetLogRequest = V201MessageHandler::parse_payload(payload.clone())?;
+        let (status, filename) = self.schedule(request);
+        Ok(Some(vec![
+          generator.get_log(msg_id, status, Some(filename)).await,
+        ]))
+      }
+      V201OcppAction::CustomerInformation => {
+        let request: CustomerInformationRequest =
+          V201MessageHandler::parse_payload(payload.clone())?;
+
+        let Some(report) = self.customer_information(&request, security) else {
+          return Ok(Some(vec![
+            generator
+              .customer_information(msg_id, CommonCustomerInformationStatus::Invalid)
+              .await,
+          ]));
+        };
+
+        let mut messages = vec![
+          generator
+            .customer_information(msg_id, CommonCustomerInformationStatus::Accepted)
+            .await,
+        ];
+        if request.report {
+          messages.extend(
+            generator
+              .notify_customer_information(request.request_id, &report)
+              .await,
+          );
+        }
+        Ok(Some(messages))
+      }
+      _ => Ok(None),
+    }
+  }
+}
+
+/// Writes the archive into a `file://` directory or posts it to an HTTP(S) location
+async fn send_archive(location: &str, filename: &str, archive: &str) -> Result<()> {
+  let url = Url::parse(location)?;
+
+  match url.scheme() {
+    "file" => {
+      tokio::fs::write(Path::new(url.path()).join(filename), archive).await?;
+    }
+    "http" | "https" => {
+      reqwest::Client::builder()
+        .timeout(UPLOAD_TIMEOUT)
+        .build()?
+        .post(url)
+        .header("Content-Type", "text/plain")
+        .header(
+          "Content-Disposition",
+          format!("attachment; filename=\"{filename}\""),
+        )
+        .body(archive.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    }
+    scheme => bail!("Unsupported upload scheme {scheme}"),
+  }
+
+  Ok(())
+}
+
+/// Status reported for a failed upload, only `UploadFailure` is retried
+fn upload_failure(err: &anyhow::Error) -> CommonUploadLogStatus {
+  if err.is::<url::ParseError>() {
+    return CommonUploadLogStatus::BadMessage;
+  }
+
+  match err
+    .downcast_ref::<reqwest::Error>()
+    .and_then(|err| err.status())
+  {
+    Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+      CommonUploadLogStatus::PermissionDenied
+    }
+    _ => CommonUploadLogStatus::UploadFailure,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use common::SharedData;
+  use ocpp::v2_0_1::generator::V201MessageGenerator;
+  use serde_json::json;
+
+  use super::*;
+  use crate::test_config::charge_point_config;
+
+  const BOOT: &str = r#"[2,"1","BootNotification",{}]"#;
+  const SECURITY_EVENT: &str =
+    r#"[2,"2","SecurityEventNotification",{"type":"StartupOfTheDevice"}]"#;
+  const ACCEPTED: &str = r#"[3,"2",{}]"#;
+
+  fn logs() -> Logs {
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    for (sent, text) in [(true, BOOT), (true, SECURITY_EVENT), (false, ACCEPTED)] {
+      logs.messages().record(sent, text);
+    }
+    logs
+  }
+
+  fn get_log(request_id: i32, remote_location: &str, retries: i32) -> GetLogRequest {
+    serde_json::from_value(json!({
+      "requestId": request_id,
+      "logType": "DiagnosticsLog",
+      "retries": retries,
+      "retryInterval": 0,
+      "log": { "remoteLocation": remote_location },
+    }))
+    .unwrap()
+  }
+
+  #[test]
+  fn archive_holds_the_messages_of_the_log_type_and_period() {
+    let logs = logs();
+
+    let archive = logs
+      .messages()
+      .archive(&LogEnumType::DiagnosticsLog, None, None);
+    let lines: Vec<_> = archive.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with(&format!(" -> {BOOT}")));
+    assert!(lines[1].ends_with(&format!(" -> {SECURITY_EVENT}")));
+    assert!(lines[2].ends_with(&format!(" <- {ACCEPTED}")));
+    let timestamp = lines[0].split(' ').next().unwrap();
+    assert!(DateTime::parse_from_rfc3339(timestamp).is_ok());
+
+    let archive = logs
+      .messages()
+      .archive(&LogEnumType::SecurityLog, None, None);
+    assert_eq!(archive.lines().count(), 1);
+    assert!(archive.contains("StartupOfTheDevice"));
+
+    let future = Utc::now() + chrono::Duration::hours(1);
+    let archive = logs
+      .messages()
+      .archive(&LogEnumType::DiagnosticsLog, Some(future), None);
+    assert!(archive.is_empty());
+  }
+
+  #[test]
+  fn oldest_messages_are_dropped_at_capacity() {
+    let logs = Logs::new("CP1", 2, MessageTaps::default());
+    for text in [BOOT, SECURITY_EVENT, ACCEPTED] {
+      logs.messages().record(true, text);
+    }
+
+    let tail = logs.messages().tail(10);
+    assert_eq!(tail.len(), 2);
+    assert!(tail[0].ends_with(SECURITY_EVENT));
+    assert!(tail[1].ends_with(ACCEPTED));
+  }
+
+  #[tokio::test]
+  async fn uploaded_log_reports_uploading_then_uploaded() {
+    let directory = std::env::temp_dir().join(format!("logs-{}", rand::random::<u64>()));
+    std::fs::create_dir(&directory).unwrap();
+    let location = Url::from_directory_path(&directory).unwrap();
+    let mut logs = logs();
+
+    let (status, filename) = logs.schedule(get_log(7, location.as_str(), 0));
+    assert_eq!(status, CommonLogStatus::Accepted);
+    assert!(filename.starts_with("CP1-DiagnosticsLog-"));
+    assert_eq!(logs.pop(), Some((CommonUploadLogStatus::Uploading, 7)));
+    assert_eq!(logs.pop(), None);
+
+    logs.upload();
+    assert!(logs.is_uploading());
+    let sent = logs.sent().await;
+    logs.uploaded(sent);
+    assert_eq!(logs.pop(), Some((CommonUploadLogStatus::Uploaded, 7)));
+    assert_eq!(logs.next_deadline(), None);
+
+    let uploaded = std::fs::read_to_string(directory.join(&filename)).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(
+      uploaded,
+      logs
+        .messages()
+        .archive(&LogEnumType::DiagnosticsLog, None, None)
+    );
+  }
+
+  #[tokio::test]
+  async fn failed_upload_is_retried_then_reported() {
+    let missing = std::env::temp_dir().join(format!("logs-missing-{}", rand::random::<u64>()));
+    let location = Url::from_directory_path(&missing).unwrap();
+    let mut logs = logs();
+
+    logs.schedule(get_log(8, location.as_str(), 1));
+    let mut statuses = Vec::new();
+    while let Some((status, request_id)) = logs.pop() {
+      assert_eq!(request_id, 8);
+      statuses.push(status);
+      if status == CommonUploadLogStatus::Uploading {
+        logs.upload();
+        let sent = logs.sent().await;
+        logs.uploaded(sent);
+      }
+    }
+
+    assert_eq!(
+      statuses,
+      [
+        CommonUploadLogStatus::Uploading,
+        CommonUploadLogStatus::Uploading,
+        CommonUploadLogStatus::UploadFailure,
+      ]
+    );
+  }
+
+  #[test]
+  fn new_request_cancels_the_pending_upload() {
+    let mut logs = logs();
+    logs.schedule(get_log(1, "file:///tmp/", 0));
+
+    let (status, _) = logs.schedule(get_log(2, "file:///tmp/", 0));
+    assert_eq!(status, CommonLogStatus::AcceptedCanceled);
+    assert_eq!(
+      logs.pop(),
+      Some((CommonUploadLogStatus::AcceptedCanceled, 1))
+    );
+    assert_eq!(logs.pop(), Some((CommonUploadLogStatus::Uploading, 2)));
+  }
+
+  #[tokio::test]
+  async fn customer_information_reports_the_messages_of_a_customer_identifier() {
+    let config = charge_point_config(json!({}));
+    let generator = V201MessageGenerator::new(config.clone(), SharedData::default()).unwrap();
+    let mut security = Security::new(&config).unwrap();
+    let mut logs = logs();
+    logs.messages().record(
+      true,
+      r#"[2,"3","DataTransfer",{"vendorId":"test","data":"CUST-42"}]"#,
+    );
+
+    let frame = MessageFrameType::V2_0_1(MessageFrame::Call {
+      msg_id: "9".to_string(),
+      action: V201OcppAction::CustomerInformation,
+      payload: json!({
+        "requestId": 5,
+        "report": true,
+        "clear": true,
+        "customerIdentifier": "CUST-42",
+      }),
+    });
+    let messages = logs
+      .handle_call(&frame, &mut security, &generator)
+      .await
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(messages[0], json!([3, "9", { "status": "Accepted" }]));
+    let report = &messages[1][3];
+    assert_eq!(messages[1][2], "NotifyCustomerInformation");
+    assert_eq!(report["requestId"], 5);
+    let data = report["data"].as_str().unwrap();
+    assert!(data.starts_with("customerIdentifier CUST-42: 1 messages from "));
+
+    // Cleared from the log
+    assert!(logs.messages().mentions("CUST-42").is_empty());
+    assert_eq!(
+      logs
+        .messages()
+        .archive(&LogEnumType::DiagnosticsLog, None, None)
+        .matches(REDACTED)
+        .count(),
+      1
+    );
+  }
+}
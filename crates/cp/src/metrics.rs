@@ -0,0 +1,551 @@
+//! Prometheus metrics of the charge points, taken from their message traffic and their
+//! sessions and rendered in the text exposition format
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt::Write,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::load_test::{ActionReport, LoadReport};
+
+/// Upper bounds of the response latency buckets, seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+  0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Smallest answer time the percentiles tell apart, seconds
+const PERCENTILE_MIN: f64 = 0.0001;
+/// Ratio between the bounds of consecutive percentile buckets, a percentile is off by 1 %
+/// at most
+const PERCENTILE_GROWTH: f64 = 1.01;
+/// Percentile buckets, the last one reaching beyond half an hour
+const PERCENTILE_BUCKETS: usize = 1_700;
+/// Calls kept waiting for their answer, beyond it the timed out ones are dropped
+const MAX_PENDING_CALLS: usize = 100_000;
+/// Time the CSMS has to answer a call before it counts as timed out
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Histogram {
+  /// Observations per bucket, the last one counting those above all bounds
+  buckets: [u64; LATENCY_BUCKETS.len() + 1],
+  sum: f64,
+  count: u64,
+}
+
+impl Histogram {
+  fn observe(&mut self, value: f64) {
+    let bucket = LATENCY_BUCKETS
+      .iter()
+      .position(|bound| value <= *bound)
+      .unwrap_or(LATENCY_BUCKETS.len());
+    self.buckets[bucket] += 1;
+    self.sum += value;
+    self.count += 1;
+  }
+}
+
+/// Answer times in log-scaled buckets, the percentiles take the same memory whatever the
+/// number of answers
+struct Percentiles {
+  /// Answers per bucket, each one up to `PERCENTILE_MIN * PERCENTILE_GROWTH^index` seconds
+  buckets: Vec<u64>,
+  count: u64,
+}
+
+impl Default for Percentiles {
+  fn default() -> Self {
+    Self {
+      buckets: vec![0; PERCENTILE_BUCKETS],
+      count: 0,
+    }
+  }
+}
+
+impl Percentiles {
+  fn observe(&mut self, value: f64) {
+    let bucket = ((value / PERCENTILE_MIN).ln() / PERCENTILE_GROWTH.ln())
+      .ceil()
+      .max(0.0) as usize;
+    self.buckets[bucket.min(PERCENTILE_BUCKETS - 1)] += 1;
+    self.count += 1;
+  }
+
+  /// Nearest-rank percentile as the upper bound of its bucket, in milliseconds
+  fn percentile(&self, percent: f64) -> Option<f64> {
+    let rank = ((percent / 100.0 * self.count as f64).ceil() as u64).max(1);
+    let mut answers = 0;
+    let bucket = self.buckets.iter().position(|count| {
+      answers += count;
+      answers >= rank
+    })?;
+    Some(PERCENTILE_MIN * PERCENTILE_GROWTH.powi(bucket as i32) * 1000.0)
+  }
+}
+
+/// Session of a charge point as last reported
+#[derive(Default)]
+struct Session {
+  transaction: bool,
+  energy_wh: f64,
+}
+
+#[derive(Default)]
+struct State {
+  /// Generation of the latest connection per charge point and whether it is open
+  connections: HashMap<String, (u64, bool)>,
+  reconnects: u64,
+  connection_failures: u64,
+  boot_notifications: BTreeMap<String, u64>,
+  calls_sent: BTreeMap<String, u64>,
+  calls_received: BTreeMap<String, u64>,
+  /// CallErrors by direction and error code
+  call_errors: BTreeMap<(&'static str, String), u64>,
+  latency: BTreeMap<String, Histogram>,
+  /// Answer times by action for the percentiles of the load test report
+  percentiles: Option<BTreeMap<String, Percentiles>>,
+  call_timeouts: BTreeMap<String, u64>,
+  /// Calls sent by message id, with their action
+  pending: HashMap<String, (String, Instant)>,
+  sessions: HashMap<String, Session>,
+  energy_delivered_wh: f64,
+}
+
+impl State {
+  /// Counts the calls left unanswered beyond the response timeout as timed out
+  fn sweep(&mut self, now: Instant, response_timeout: Duration) {
+    let call_timeouts = &mut self.call_timeouts;
+    self.pending.retain(|_, (action, sent_at)| {
+      let timed_out = now.duration_since(*sent_at) > response_timeout;
+      if timed_out {
+        *call_timeouts.entry(action.clone()).or_default() += 1;
+      }
+      !timed_out
+    });
+  }
+}
+
+/// Metrics of all charge points, shared with the `/metrics` endpoint
+pub struct Metrics {
+  state: Mutex<State>,
+  response_timeout: Duration,
+}
+
+impl Default for Metrics {
+  fn default() -> Self {
+    Self {
+      state: Mutex::default(),
+      response_timeout: RESPONSE_TIMEOUT,
+    }
+  }
+}
+
+impl Metrics {
+  /// Metrics keeping the answer time percentiles for the load test report
+  pub fn with_percentiles(response_timeout: Duration) -> Self {
+    Self {
+      state: Mutex::new(State {
+        percentiles: Some(BTreeMap::new()),
+        ..Default::default()
+      }),
+      response_timeout,
+    }
+  }
+
+  /// Counts a connection attempt the CSMS could not be reached with
+  pub fn connection_failed(&self) {
+    self.state.lock().unwrap().connection_failures += 1;
+  }
+
+  /// Counts the connection until the returned guard is dropped
+  pub fn connection(self: &Arc<Self>, charge_point_id: &str) -> ConnectionGuard {
+    let mut state = self.state.lock().unwrap();
+    let generation = match state.connections.get(charge_point_id).copied() {
+      Some((generation, _)) => {
+        state.reconnects += 1;
+        generation + 1
+      }
+      None => 0,
+    };
+    state
+      .connections
+      .insert(charge_point_id.to_string(), (generation, true));
+
+    ConnectionGuard {
+      metrics: Arc::clone(self),
+      charge_point_id: charge_point_id.to_string(),
+      generation,
+    }
+  }
+
+  /// Counts an OCPP-J frame sent or received by the charge point
+  pub fn observe_frame(&self, sent: bool, text: &str) {
+    let Ok(frame) = serde_json::from_str::<Value>(text) else {
+      return;
+    };
+    let Some(msg_id) = frame[1].as_str() else {
+      return;
+    };
+    let now = Instant::now();
+    let mut state = self.state.lock().unwrap();
+
+    match (frame[0].as_u64(), sent) {
+      (Some(2), true) => {
+        let action = frame[2].as_str().unwrap_or_default().to_string();
+        *state.calls_sent.entry(action.clone()).or_default() += 1;
+
+        if state.pending.len() >= MAX_PENDING_CALLS {
+          state.sweep(now, self.response_timeout);
+        }
+        state.pending.insert(msg_id.to_string(), (action, now));
+      }
+      (Some(2), false) => {
+        let action = frame[2].as_str().unwrap_or_default().to_string();
+        *state.calls_received.entry(action).or_default() += 1;
+      }
+      (Some(answer @ (3 | 4)), false) => {
+        if answer == 4 {
+          let code = frame[2].as_str().unwrap_or_default().to_string();
+          *state.call_errors.entry(("received", code)).or_default() += 1;
+        }
+
+        let Some((action, sent_at)) = state.pending.remove(msg_id) else {
+          return;
+        };
+        let latency = now.duration_since(sent_at);
+        if latency > self.response_timeout {
+          *state.call_timeouts.entry(action).or_default() += 1;
+          return;
+        }
+
+        if answer == 3
+          && action == "BootNotification"
+          && let Some(status) = frame[2]["status"].as_str()
+        {
+          *state
+            .boot_notifications
+            .entry(status.to_string())
+            .or_default() += 1;
+        }
+        if let Some(percentiles) = &mut state.percentiles {
+          percentiles
+            .entry(action.clone())
+            .or_default()
+            .observe(latency.as_secs_f64());
+        }
+        state
+          .latency
+          .entry(action)
+          .or_default()
+          .observe(latency.as_secs_f64());
+      }
+      (Some(4), true) => {
+        let code = frame[2].as_str().unwrap_or_default().to_string();
+        *state.call_errors.entry(("sent", code)).or_default() += 1;
+      }
+      _ => {}
+    }
+  }
+
+  /// Takes the transaction state and the energy register of the charge point, the energy
+  /// registered meanwhile counts as delivered
+  pub fn update_session(&self, charge_point_id: &str, transaction: bool, energy_wh: f64) {
+    let state = &mut *self.state.lock().unwrap();
+    let session = state
+      .sessions
+      .entry(charge_point_id.to_string())
+      .or_insert_with(|| Session {
+        transaction,
+        energy_wh,
+      });
+    let delivered = (energy_wh - session.energy_wh).max(0.0);
+    session.transaction = transaction;
+    session.energy_wh = energy_wh;
+    state.energy_delivered_wh += delivered;
+  }
+
+  /// Metrics in the Prometheus text exposition format
+  pub fn render(&self) -> String {
+    let mut state = self.state.lock().unwrap();
+    state.sweep(Instant::now(), self.response_timeout);
+    let mut out = String::new();
+
+    let connected = state
+      .connections
+      .values()
+      .filter(|(_, connected)| *connected)
+      .count();
+    header(
+      &mut out,
+      "ocpp_charge_points_connected",
+      "gauge",
+      "Charge points connected to the CSMS",
+    );
+    let _ = writeln!(out, "ocpp_charge_points_connected {connected}");
+
+    header(
+      &mut out,
+      "ocpp_reconnects_total",
+      "counter",
+      "Connections opened again by a charge point",
+    );
+    let _ = writeln!(out, "ocpp_reconnects_total {}", state.reconnects);
+
+    header(
+      &mut out,
+      "ocpp_connection_failures_total",
+      "counter",
+      "Connection attempts the CSMS could not be reached with",
+    );
+    let _ = writeln!(
+      out,
+      "ocpp_connection_failures_total {}",
+      state.connection_failures
+    );
+
+    header(
+      &mut out,
+      "ocpp_boot_notifications_total",
+      "counter",
+      "BootNotification answers by status",
+    );
+    for (status, count) in &state.boot_notifications {
+      let _ = writeln!(
+        out,
+        "ocpp_boot_notifications_total{{status=\"{}\"}} {count}",
+        escape(status)
+      );
+    }
+
+    for (name, help, calls) in [
+      (
+        "ocpp_calls_sent_total",
+        "Calls sent by the charge points by action",
+        &state.calls_sent,
+      ),
+      (
+        "ocpp_calls_received_total",
+        "Calls received from the CSMS by action",
+        &state.calls_received,
+      ),
+    ] {
+      header(&mut out, name, "counter", help);
+      for (action, count) in calls {
+        let _ = writeln!(out, "{name}{{action=\"{}\"}} {count}", escape(action));
+      }
+    }
+
+    header(
+      &mut out,
+      "ocpp_call_errors_total",
+      "counter",
+      "CallErrors by direction and error code",
+    );
+    for ((direction, code), count) in &state.call_errors {
+      let _ = writeln!(
+        out,
+        "ocpp_call_errors_total{{direction=\"{direction}\",code=\"{}\"}} {count}",
+        escape(code)
+      );
+    }
+
+    header(
+      &mut out,
+      "ocpp_call_timeouts_total",
+      "counter",
+      "Calls the CSMS did not answer in time by action",
+    );
+    for (action, count) in &state.call_timeouts {
+      let _ = writeln!(
+        out,
+        "ocpp_call_timeouts_total{{action=\"{}\"}} {count}",
+        escape(action)
+      );
+    }
+
+    header(
+      &mut out,
+      "ocpp_response_seconds",
+      "histogram",
+      "Time the CSMS took to answer a call by action",
+    );
+    for (action, histogram) in &state.latency {
+      let action = escape(action);
+      let mut cumulative = 0;
+      for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+        cumulative += count;
+        let _ = writeln!(
+          out,
+          "ocpp_response_seconds_bucket{{action=\"{action}\",le=\"{bound}\"}} {cumulative}"
+        );
+      }
+      let _ = writeln!(
+        out,
+        "ocpp_response_seconds_bucket{{action=\"{action}\",le=\"+Inf\"}} {}",
+        histogram.count
+      );
+      let _ = writeln!(
+        out,
+        "ocpp_response_seconds_sum{{action=\"{action}\"}} {}",
+        histogram.sum
+      );
+      let _ = writeln!(
+        out,
+        "ocpp_response_seconds_count{{action=\"{action}\"}} {}",
+        histogram.count
+      );
+    }
+
+    header(
+      &mut out,
+      "ocpp_transactions_active",
+      "gauge",
+      "Transactions running on the charge points",
+    );
+    let active = state
+      .sessions
+      .values()
+      .filter(|session| session.transaction)
+      .count();
+    let _ = writeln!(out, "ocpp_transactions_active {active}");
+
+    header(
+      &mut out,
+      "ocpp_energy_delivered_wh_total",
+      "counter",
+      "Energy delivered by the charge points, Wh",
+    );
+    let _ = writeln!(
+      out,
+      "ocpp_energy_delivered_wh_total {}",
+      state.energy_delivered_wh
+    );
+
+    out
+  }
+
+  /// Answer time percentiles and failures of the load test, the calls still unanswered
+  /// count as timed out
+  pub fn load_report(&self) -> LoadReport {
+    let mut state = self.state.lock().unwrap();
+    state.sweep(
+      Instant::now() + self.response_timeout,
+      self.response_timeout,
+    );
+
+    let mut actions = BTreeMap::<String, ActionReport>::new();
+    for (action, percentiles) in state.percentiles.iter().flatten() {
+      actions.insert(
+        action.clone(),
+        ActionReport {
+          action: action.clone(),
+          answered: percentiles.count,
+          timeouts: 0,
+          p50: percentiles.percentile(50.0),
+          p95: percentiles.percentile(95.0),
+          p99: percentiles.percentile(99.0),
+        },
+      );
+    }
+    for (action, timeouts) in &state.call_timeouts {
+      actions
+        .entry(action.clone())
+        .or_insert_with(|| ActionReport {
+          action: action.clone(),
+          ..Default::default()
+        })
+        .timeouts = *timeouts;
+    }
+
+    LoadReport {
+      charge_points: state.connections.len() as u64,
+      connection_failures: state.connection_failures,
+      reconnects: state.reconnects,
+      call_errors: state.call_errors.values().sum(),
+      actions: actions.into_values().collect(),
+    }
+  }
+}
+
+/// Open connection of a charge point, counted as disconnected once dropped
+pub struct ConnectionGuard {
+  metrics: Arc<Metrics>,
+  charge_point_id: String,
+  generation: u64,
+}
+
+impl Drop for ConnectionGuard {
+  fn drop(&mut self) {
+    let mut state = self.metrics.state.lock().unwrap();
+    // A newer connection of the charge point may already be open
+    if let Some((generation, connected)) = state.connections.get_mut(&self.charge_point_id)
+      && *generation == self.generation
+    {
+      *connected = false;
+    }
+  }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+  let _ = writeln!(out, "# HELP {name} {help}");
+  let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.unwrap();
+    assert!(
+      actual >= expected && actual <= expected * PERCENTILE_GROWTH,
+      "{actual} is not within 1 % above {expected}"
+    );
+  }
+
+  #[test]
+  fn percentiles_take_the_nearest_rank() {
+    let mut percentiles = Percentiles::default();
+    for ms in 1..=100 {
+      percentiles.observe(ms as f64 / 1000.0);
+    }
+
+    assert_close(percentiles.percentile(50.0), 50.0);
+    assert_close(percentiles.percentile(95.0), 95.0);
+    assert_close(percentiles.percentile(99.0), 99.0);
+    assert_close(percentiles.percentile(100.0), 100.0);
+  }
+
+  #[test]
+  fn percentiles_are_none_without_answers() {
+    assert_eq!(Percentiles::default().percentile(50.0), None);
+  }
+
+  #[test]
+  fn percentiles_keep_their_size() {
+    let mut percentiles = Percentiles::default();
+    for _ in 0..10_000 {
+      percentiles.observe(0.02);
+    }
+    percentiles.observe(0.0);
+    percentiles.observe(1e6);
+
+    assert_eq!(percentiles.buckets.len(), PERCENTILE_BUCKETS);
+    assert_eq!(percentiles.count, 10_002);
+    assert_close(percentiles.percentile(50.0), 20.0);
+    assert_eq!(percentiles.percentile(0.0), Some(PERCENTILE_MIN * 1000.0));
+    assert!(percentiles.percentile(100.0).unwrap() > 1_800_000.0);
+  }
+}
@@ -0,0 +1,185 @@
+//! ISO 15118 Plug & Charge of a simulated EV: its contract certificate is requested with
+//! `Get15118EVCertificate`, checked with `GetCertificateStatus` and its eMAID authorized
+
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::{ChargePointConfig, OcppVersion, PlugAndChargeConfig};
+use ocpp::{generator::MessageGenerator, types::CommonOcspRequestData};
+use openssl::{
+  asn1::{Asn1Integer, Asn1Time},
+  bn::BigNum,
+  ec::{EcGroup, EcKey},
+  hash::MessageDigest,
+  nid::Nid,
+  pkey::{PKey, Private},
+  x509::{X509, X509NameBuilder, extension::BasicConstraints},
+};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::certificates::certificate_hash_data;
+
+/// Header of an EXI stream without options, ahead of the encoded body
+const EXI_HEADER: u8 = 0x80;
+/// Validity of the generated certificates
+const CERTIFICATE_DAYS: u32 = 365;
+
+/// Contract certificate of the EV, generated locally and signed by a simulated mobility
+/// operator root
+pub struct PlugAndCharge {
+  config: PlugAndChargeConfig,
+  mo_root: X509,
+  contract: X509,
+  /// OEM provisioning certificate the EV requests its contract with
+  provisioning: X509,
+  /// Whether the CSMS accepted to install the contract certificate
+  installed: bool,
+}
+
+impl PlugAndCharge {
+  /// `None` when Plug & Charge is not configured or the OCPP version lacks it
+  pub fn new(cp_config: &ChargePointConfig, ocpp_version: &OcppVersion) -> Result<Option<Self>> {
+    let Some(config) = &cp_config.plug_and_charge else {
+      return Ok(None);
+    };
+    if !matches!(ocpp_version, OcppVersion::V2_0_1) {
+      warn!("Plug & Charge requires OCPP 2.0.1, ignored for {ocpp_version}");
+      return Ok(None);
+    }
+
+    let mo_root_key = generate_key()?;
+    let mo_root = issue("MO Root CA", &mo_root_key, None, 1, true)?;
+    let contract = issue(
+      &config.emaid,
+      &generate_key()?,
+      Some((&mo_root, &mo_root_key)),
+      2,
+      false,
+    )?;
+    let oem_key = generate_key()?;
+    let provisioning = issue(&format!("{} OEM", cp_config.id), &oem_key, None, 3, false)?;
+
+    info!("Plug & Charge contract {} generated", config.emaid);
+    Ok(Some(Self {
+      config: config.clone(),
+      mo_root,
+      contract,
+      provisioning,
+      installed: false,
+    }))
+  }
+
+  /// OCSP request of the contract certificate
+  fn ocsp_request(&self) -> Result<CommonOcspRequestData> {
+    Ok(CommonOcspRequestData {
+      hash_data: certificate_hash_data(&self.contract, &self.mo_root)?,
+      responder_url: self.config.ocsp_responder_url.clone(),
+    })
+  }
+
+  /// Stand-in for the EXI encoded `CertificateInstallationReq` of the EV: the EXI header
+  /// followed by its provisioning certificate, the body is not schema encoded
+  fn exi_request(&self) -> Result<String> {
+    let mut exi = vec![EXI_HEADER];
+    exi.extend(self.provisioning.to_der()?);
+    Ok(STANDARD.encode(exi))
+  }
+
+  /// First message once the EV is plugged in, the contract certificate is requested until
+  /// the CSMS installed it and only its status is checked afterwards
+  pub async fn plug_in(&self, generator: &dyn MessageGenerator) -> Result<Value> {
+    if self.installed {
+      return Ok(generator.get_certificate_status(self.ocsp_request()?).await);
+    }
+
+    Ok(
+      generator
+        .get_15118_ev_certificate(&self.config.schema_version, self.exi_request()?)
+        .await,
+    )
+  }
+
+  /// Checks the status of the installed contract, `None` when the CSMS failed to install it
+  pub async fn certificate_installed(
+    &mut self,
+    accepted: bool,
+    generator: &dyn MessageGenerator,
+  ) -> Result<Option<Value>> {
+    if !accepted {
+      warn!("Contract certificate {} not installed", self.config.emaid);
+      return Ok(None);
+    }
+
+    info!("Contract certificate {} installed", self.config.emaid);
+    self.installed = true;
+    Ok(Some(
+      generator.get_certificate_status(self.ocsp_request()?).await,
+    ))
+  }
+
+  /// Authorizes the eMAID of a valid contract, `None` when its status is unknown
+  pub async fn certificate_status(
+    &self,
+    accepted: bool,
+    generator: &dyn MessageGenerator,
+  ) -> Result<Option<Value>> {
+    if !accepted {
+      warn!(
+        "No OCSP status for contract certificate {}",
+        self.config.emaid
+      );
+      return Ok(None);
+    }
+
+    Ok(Some(
+      generator
+        .authorize_contract(&self.config.emaid, vec![self.ocsp_request()?])
+        .await,
+    ))
+  }
+}
+
+fn generate_key() -> Result<PKey<Private>> {
+  let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+  Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+/// Certificate for the key signed by the issuer, self-signed without one
+fn issue(
+  common_name: &str,
+  key: &PKey<Private>,
+  issuer: Option<(&X509, &PKey<Private>)>,
+  serial_number: u32,
+  ca: bool,
+) -> Result<X509> {
+  let mut name = X509NameBuilder::new()?;
+  name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
+  let name = name.build();
+
+  let mut builder = X509::builder()?;
+  builder.set_version(2)?;
+  let serial_number = Asn1Integer::from_bn(&*BigNum::from_u32(serial_number)?)?;
+  builder.set_serial_number(&serial_number)?;
+  builder.set_subject_name(&name)?;
+  builder.set_pubkey(key)?;
+  let not_before = Asn1Time::days_from_now(0)?;
+  let not_after = Asn1Time::days_from_now(CERTIFICATE_DAYS)?;
+  builder.set_not_before(&not_before)?;
+  builder.set_not_after(&not_after)?;
+  if ca {
+    builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+  }
+
+  match issuer {
+    Some((issuer, issuer_key)) => {
+      builder.set_issuer_name(issuer.subject_name())?;
+      builder.sign(issuer_key, MessageDigest::sha256())?;
+    }
+    None => {
+      builder.set_issuer_name(&name)?;
+      builder.sign(key, MessageDigest::sha256())?;
+    }
+  }
+
+  Ok(builder.build())
+}
@@ -0,0 +1,93 @@
+//! Recording of the message traffic of the charge points to a JSONL file, one frame per line
+
+use std::{
+  collections::BTreeMap,
+  fs::File,
+  io::{BufRead, BufReader, LineWriter, Write},
+  path::Path,
+  sync::Mutex,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+  /// Sent by the charge point
+  Sent,
+  /// Received from the CSMS
+  Received,
+}
+
+/// Line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+  pub timestamp: DateTime<Utc>,
+  pub charge_point_id: String,
+  pub direction: Direction,
+  /// OCPP-J frame, kept as a string when it is not valid JSON
+  pub frame: Value,
+}
+
+/// JSONL file the frames of all charge points are appended to as they are sent and received
+pub struct Recorder {
+  file: Mutex<LineWriter<File>>,
+}
+
+impl Recorder {
+  pub fn create(path: &Path) -> Result<Self> {
+    let file =
+      File::create(path).with_context(|| format!("Cannot create recording {}", path.display()))?;
+    Ok(Self {
+      file: Mutex::new(LineWriter::new(file)),
+    })
+  }
+
+  pub fn record(&self, timestamp: DateTime<Utc>, charge_point_id: &str, sent: bool, text: &str) {
+    let frame = RecordedFrame {
+      timestamp,
+      charge_point_id: charge_point_id.to_string(),
+      direction: match sent {
+        true => Direction::Sent,
+        false => Direction::Received,
+      },
+      frame: serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())),
+    };
+
+    let written = serde_json::to_string(&frame)
+      .map_err(std::io::Error::from)
+      .and_then(|line| writeln!(self.file.lock().unwrap(), "{line}"));
+    if let Err(e) = written {
+      warn!("Frame not recorded: {e}");
+    }
+  }
+}
+
+/// Frames of a recording per charge point, in the order they were recorded
+pub fn load(path: &Path) -> Result<BTreeMap<String, Vec<RecordedFrame>>> {
+  let file =
+    File::open(path).with_context(|| format!("Cannot open recording {}", path.display()))?;
+
+  let mut charge_points: BTreeMap<String, Vec<RecordedFrame>> = BTreeMap::new();
+  for (number, line) in BufReader::new(file).lines().enumerate() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let frame: RecordedFrame = serde_json::from_str(&line)
+      .with_context(|| format!("{}:{}: invalid frame", path.display(), number + 1))?;
+    charge_points
+      .entry(frame.charge_point_id.clone())
+      .or_default()
+      .push(frame);
+  }
+
+  if charge_points.is_empty() {
+    bail!("Recording {} holds no frames", path.display());
+  }
+  Ok(charge_points)
+}
@@ -0,0 +1,321 @@
+//! Replay of a recording: the frames the charge point sent go to the CSMS again with the
+//! recorded timing, message and transaction ids are taken over from the new answers
+
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Arc,
+  time::Duration,
+};
+
+use anyhow::{Result, bail};
+use common::{ChargePointConfig, GeneralConfig, frame_info};
+use futures::Stream;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::{select, time::Instant};
+use tracing::{info, warn};
+use tungstenite::Message;
+use uuid::Uuid;
+
+use crate::{
+  core::{connect, send},
+  logs::{Logs, MessageTaps},
+  recording::{Direction, RecordedFrame},
+  security::Security,
+};
+
+/// Time the CSMS has to answer a replayed call, or to send the call a recorded answer is for
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Ids of the recording mapped to the ones of the replay
+#[derive(Default)]
+struct IdMap {
+  /// Message ids of the calls the charge point sent
+  calls: HashMap<String, String>,
+  /// Recorded message id of the call waiting for its answer
+  pending: Option<String>,
+  /// Message ids of the calls the CSMS sent
+  csms_calls: HashMap<String, String>,
+  /// Recorded calls of the CSMS that did not come again yet, with their action
+  expected_csms_calls: VecDeque<(String, String)>,
+  /// Recorded answers of the CSMS by message id
+  answers: HashMap<String, Value>,
+  /// Recorded transaction ids, as JSON, mapped to the ones the CSMS assigned this time
+  transaction_ids: HashMap<String, Value>,
+}
+
+impl IdMap {
+  fn new(frames: &[RecordedFrame]) -> Self {
+    let mut ids = Self::default();
+    for recorded in frames
+      .iter()
+      .filter(|recorded| recorded.direction == Direction::Received)
+    {
+      let frame = &recorded.frame;
+      let Some(msg_id) = frame[1].as_str() else {
+        continue;
+      };
+      match frame[0].as_u64() {
+        Some(2) => ids.expected_csms_calls.push_back((
+          msg_id.to_string(),
+          frame[2].as_str().unwrap_or_default().to_string(),
+        )),
+        Some(3) => {
+          ids.answers.insert(msg_id.to_string(), frame[2].clone());
+        }
+        _ => {}
+      }
+    }
+    ids
+  }
+
+  /// Takes the ids from a frame of the CSMS
+  fn receive(&mut self, text: &str) {
+    let Ok(frame) = serde_json::from_str::<Value>(text) else {
+      warn!("Invalid frame from the CSMS: {text}");
+      return;
+    };
+    let Some(msg_id) = frame[1].as_str() else {
+      return;
+    };
+
+    match frame[0].as_u64() {
+      Some(2) => {
+        let action = frame[2].as_str().unwrap_or_default();
+        frame_info!("⬅️  {action}");
+        match self
+          .expected_csms_calls
+          .iter()
+          .position(|(_, expected)| expected == action)
+          .and_then(|position| self.expected_csms_calls.remove(position))
+        {
+          Some((recorded, _)) => {
+            self.csms_calls.insert(recorded, msg_id.to_string());
+          }
+          None => warn!("{action} of the CSMS is not in the recording, left unanswered"),
+        }
+      }
+      Some(answer @ (3 | 4)) => {
+        let calls = &self.calls;
+        let Some(recorded) = self
+          .pending
+          .take_if(|recorded| calls.get(recorded).is_some_and(|id| id == msg_id))
+        else {
+          return;
+        };
+        if answer == 3
+          && let Some(recorded_answer) = self.answers.get(&recorded)
+        {
+          map_transaction_ids(recorded_answer, &frame[2], &mut self.transaction_ids);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Frame to send for a recorded one, `None` when it answers a call the CSMS did not send
+  fn substitute(&mut self, recorded: &Value) -> Option<Value> {
+    let mut frame = recorded.clone();
+    let recorded_id = frame[1].as_str()?.to_string();
+
+    match frame[0].as_u64()? {
+      2 => {
+        let msg_id = Uuid::new_v4().to_string();
+        self.calls.insert(recorded_id.clone(), msg_id.clone());
+        self.pending = Some(recorded_id);
+        frame[1] = msg_id.into();
+      }
+      _ => frame[1] = self.csms_calls.get(&recorded_id)?.clone().into(),
+    }
+
+    if let Some(payload) = frame.as_array_mut().and_then(|frame| frame.last_mut()) {
+      replace_transaction_ids(payload, &self.transaction_ids);
+    }
+    Some(frame)
+  }
+}
+
+/// Recorded charge point sending its side of the recording again
+pub struct ChargePointReplay {
+  general_config: Arc<GeneralConfig>,
+  config: ChargePointConfig,
+  frames: Vec<RecordedFrame>,
+  /// Factor the recorded timing is sped up by
+  speed: f64,
+  taps: MessageTaps,
+}
+
+impl ChargePointReplay {
+  pub fn new(
+    general_config: Arc<GeneralConfig>,
+    config: ChargePointConfig,
+    frames: Vec<RecordedFrame>,
+    speed: f64,
+  ) -> Self {
+    Self {
+      general_config,
+      config,
+      frames,
+      speed,
+      taps: MessageTaps::default(),
+    }
+  }
+
+  /// Passes the message traffic to the recording and the metrics
+  pub fn with_taps(mut self, taps: MessageTaps) -> Self {
+    self.taps = taps;
+    self
+  }
+
+  pub async fn run(&mut self) -> Result<()> {
+    let Some(first) = self.frames.first() else {
+      return Ok(());
+    };
+    let recording_start = first.timestamp;
+
+    let security = Security::new(&self.config)?;
+    let logs = Logs::new(
+      &self.config.id,
+      self.general_config.message_log_size,
+      self.taps.clone(),
+    );
+    let ws_stream = connect(
+      self.general_config.clone(),
+      &self.config,
+      &security,
+      logs.messages(),
+    )
+    .await?;
+    let (mut ws_tx, mut ws_rx) = ws_stream.split();
+
+    let mut ids = IdMap::new(&self.frames);
+    let sent = self
+      .frames
+      .iter()
+      .filter(|recorded| recorded.direction == Direction::Sent)
+      .collect::<Vec<_>>();
+    info!("Replaying {} frames at {}x speed", sent.len(), self.speed);
+
+    let start = Instant::now();
+    for recorded in sent {
+      let offset = (recorded.timestamp - recording_start)
+        .to_std()
+        .unwrap_or_default()
+        .div_f64(self.speed);
+      receive_until(&mut ws_rx, &mut ids, start + offset, |_| false).await?;
+
+      let frame = &recorded.frame;
+      match (frame[0].as_u64(), frame[1].as_str()) {
+        // One call at a time, as the charge point did
+        (Some(2), _) => {
+          if !receive_until(
+            &mut ws_rx,
+            &mut ids,
+            Instant::now() + ANSWER_TIMEOUT,
+            |ids| ids.pending.is_none(),
+          )
+          .await?
+          {
+            warn!("No answer to the previous call, replaying on");
+          }
+          frame_info!("➡️  {}", frame[2].as_str().unwrap_or_default());
+        }
+        (Some(3 | 4), Some(recorded_id)) => {
+          receive_until(
+            &mut ws_rx,
+            &mut ids,
+            Instant::now() + ANSWER_TIMEOUT,
+            |ids| ids.csms_calls.contains_key(recorded_id),
+          )
+          .await?;
+        }
+        _ => {}
+      }
+
+      match ids.substitute(frame) {
+        Some(frame) => send(&mut ws_tx, frame).await?,
+        None => warn!("Skipped {frame}, the CSMS did not send the call it answers"),
+      }
+    }
+
+    receive_until(
+      &mut ws_rx,
+      &mut ids,
+      Instant::now() + ANSWER_TIMEOUT,
+      |ids| ids.pending.is_none(),
+    )
+    .await?;
+    ws_tx.close().await?;
+    info!("Replay finished");
+    Ok(())
+  }
+}
+
+/// Handles the frames of the CSMS until `done` holds or the deadline passed, returns
+/// whether `done` holds
+async fn receive_until<S>(
+  ws_rx: &mut S,
+  ids: &mut IdMap,
+  deadline: Instant,
+  done: impl Fn(&IdMap) -> bool,
+) -> Result<bool>
+where
+  S: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+  while !done(ids) {
+    select! {
+      _ = tokio::time::sleep_until(deadline) => return Ok(false),
+      message = ws_rx.next() => match message {
+        Some(Ok(Message::Text(text))) => ids.receive(&text),
+        Some(Ok(Message::Close(_))) | None => bail!("CSMS closed the connection"),
+        Some(Ok(_)) => {}
+        Some(Err(e)) => return Err(e.into()),
+      },
+    }
+  }
+  Ok(true)
+}
+
+/// Maps the transaction ids of a recorded answer to the ones at the same place in the new one
+fn map_transaction_ids(recorded: &Value, answer: &Value, ids: &mut HashMap<String, Value>) {
+  match (recorded, answer) {
+    (Value::Object(recorded), Value::Object(answer)) => {
+      for (key, value) in recorded {
+        let Some(new_value) = answer.get(key) else {
+          continue;
+        };
+        if key != "transactionId" {
+          map_transaction_ids(value, new_value, ids);
+        } else if value != new_value {
+          info!("Transaction {value} replayed as {new_value}");
+          ids.insert(value.to_string(), new_value.clone());
+        }
+      }
+    }
+    (Value::Array(recorded), Value::Array(answer)) => {
+      for (value, new_value) in recorded.iter().zip(answer) {
+        map_transaction_ids(value, new_value, ids);
+      }
+    }
+    _ => {}
+  }
+}
+
+fn replace_transaction_ids(payload: &mut Value, ids: &HashMap<String, Value>) {
+  match payload {
+    Value::Object(object) => {
+      for (key, value) in object {
+        match ids.get(&value.to_string()) {
+          Some(new_value) if key == "transactionId" => *value = new_value.clone(),
+          _ => replace_transaction_ids(value, ids),
+        }
+      }
+    }
+    Value::Array(array) => {
+      for value in array {
+        replace_transaction_ids(value, ids);
+      }
+    }
+    _ => {}
+  }
+}
@@ -0,0 +1,883 @@
+use std::{
+  collections::{BTreeMap, VecDeque},
+  fmt::Write as _,
+  path::Path,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use colored::Colorize;
+use common::{ScenarioAction, ScenarioFile, ScenarioStep};
+use ocpp::{handler::MessageFrameType, types::CommonConnectorStatusType};
+use serde_json::Value;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::logs::MessageLog;
+
+/// Frames kept for the step after the current one, the oldest are dropped first
+const SEEN_FRAMES: usize = 64;
+
+/// Steps of a scenario run in order, each one once the previous one is done
+pub struct Scenario {
+  name: String,
+  charge_point_id: String,
+  steps: VecDeque<ScenarioStep>,
+  total: usize,
+  /// When the next step is due, or when the step waiting on the CSMS times out
+  deadline: Option<Instant>,
+  /// Whether the current step is waiting for a status, a call or a response
+  waiting: bool,
+  /// When the current step started waiting
+  started: Instant,
+  /// Frames of the CSMS since the previous step was done, with the action they belong to,
+  /// an `expect_call` or `expect_response` step also matches those
+  seen: VecDeque<(Value, String)>,
+  no_call_errors: bool,
+  /// CallErrors the CSMS answered with, as `action: code description`
+  call_errors: Vec<String>,
+  report: Arc<ScenarioReport>,
+  reported: bool,
+}
+
+impl Scenario {
+  pub fn new(
+    file: &ScenarioFile,
+    charge_point_id: &str,
+    report: Arc<ScenarioReport>,
+  ) -> Result<Self> {
+    for step in &file.steps {
+      if let ScenarioAction::WaitForStatus { status, .. } = &step.action
+        && parse_status(status).is_none()
+      {
+        bail!(
+          "Scenario {}: unknown connector status {}",
+          file.name,
+          status
+        );
+      }
+    }
+
+    Ok(Self {
+      name: file.name.clone(),
+      charge_point_id: charge_point_id.to_string(),
+      steps: file.steps.iter().cloned().collect(),
+      total: file.steps.len(),
+      deadline: None,
+      waiting: false,
+      started: Instant::now(),
+      seen: VecDeque::new(),
+      no_call_errors: file.no_call_errors,
+      call_errors: Vec::new(),
+      report,
+      reported: false,
+    })
+  }
+
+  /// Starts the timeline, the first step is due after its delay
+  pub fn start(&mut self) {
+    info!("🎬 Scenario {} started", self.name);
+    self.schedule_next(Instant::now());
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.steps.is_empty()
+  }
+
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.deadline
+  }
+
+  /// Takes the next due action, a step waiting on the CSMS starts waiting instead and
+  /// fails when its timeout expired
+  pub fn pop(&mut self) -> Option<ScenarioAction> {
+    let now = Instant::now();
+    if self.deadline.is_none_or(|deadline| deadline > now) {
+      return None;
+    }
+
+    let step = self.steps.front()?.clone();
+
+    match &step.action {
+      ScenarioAction::WaitForStatus { status, .. } if self.waiting => {
+        self.fail(format!("no {status} status in time"));
+        None
+      }
+      ScenarioAction::ExpectCall { call, .. } if self.waiting => {
+        self.fail(format!("no {call} call in time"));
+        None
+      }
+      ScenarioAction::ExpectResponse { call, .. } if self.waiting => {
+        self.fail(format!("no response to {call} in time"));
+        None
+      }
+      ScenarioAction::WaitForStatus { timeout, .. }
+      | ScenarioAction::ExpectCall { timeout, .. }
+      | ScenarioAction::ExpectResponse { timeout, .. } => {
+        let timeout = *timeout;
+        info!("🎬 {} {}", self.current(), step.action);
+        self.waiting = true;
+        self.started = now;
+        self.deadline = Some(now + Duration::from_secs(timeout));
+        self.match_seen();
+        None
+      }
+      _ => {
+        info!("🎬 {} {}", self.current(), step.action);
+        self.started = now;
+        self.record(Outcome::Passed);
+        self.steps.pop_front();
+        self.schedule_next(now);
+        Some(step.action)
+      }
+    }
+  }
+
+  /// Ends a `wait_for_status` step once the connector is in its status
+  pub fn observe_status(&mut self, status: CommonConnectorStatusType) {
+    let Some(ScenarioStep {
+      action: ScenarioAction::WaitForStatus {
+        status: expected, ..
+      },
+      ..
+    }) = self.steps.front()
+    else {
+      return;
+    };
+
+    if self.waiting && parse_status(expected) == Some(status) {
+      self.done();
+    }
+  }
+
+  /// Ends an `expect_call` step once the CSMS sent its call and an `expect_response` step
+  /// once the CSMS answered its call, the log resolves which call an answer belongs to
+  pub fn observe_frame(&mut self, frame: &MessageFrameType, log: &MessageLog) {
+    let frame = frame.to_frame();
+    let action = match frame[0].as_u64() {
+      Some(2) => frame[2].as_str().map(str::to_string),
+      _ => frame[1].as_str().and_then(|msg_id| log.sent_action(msg_id)),
+    };
+    let Some(action) = action else {
+      return;
+    };
+
+    if frame[0] == 4 {
+      let call_error = format!(
+        "{}: {} {}",
+        action,
+        frame[2].as_str().unwrap_or_default(),
+        frame[3].as_str().unwrap_or_default()
+      );
+      warn!("🎬 CallError {call_error}");
+      self.call_errors.push(call_error);
+    }
+
+    if !self.waiting {
+      if self.seen.len() == SEEN_FRAMES {
+        self.seen.pop_front();
+      }
+      self.seen.push_back((frame, action));
+      return;
+    }
+
+    self.match_frame(&frame, &action);
+  }
+
+  /// Matches the frames seen before the current step started waiting, those after the
+  /// matching one are kept for the next step
+  fn match_seen(&mut self) {
+    let mut seen = std::mem::take(&mut self.seen).into_iter();
+    for (frame, action) in seen.by_ref() {
+      self.match_frame(&frame, &action);
+      if !self.waiting {
+        break;
+      }
+    }
+    self.seen.extend(seen);
+  }
+
+  fn match_frame(&mut self, frame: &Value, action: &str) {
+    match (
+      frame[0].as_u64(),
+      self.steps.front().map(|step| &step.action),
+    ) {
+      (Some(2), Some(ScenarioAction::ExpectCall { call, .. })) if *call == action => self.done(),
+      (
+        Some(3),
+        Some(ScenarioAction::ExpectResponse {
+          call,
+          field,
+          equals,
+          ..
+        }),
+      ) if *call == action => match check_response(&frame[2], field.as_deref(), equals.as_ref()) {
+        Ok(()) => self.done(),
+        Err(message) => self.fail(message),
+      },
+      (Some(4), Some(ScenarioAction::ExpectResponse { call, .. })) if *call == action => {
+        self.fail(format!("CallError {}", self.call_errors.last().unwrap()))
+      }
+      _ => {}
+    }
+  }
+
+  fn done(&mut self) {
+    info!("🎬 {} done", self.current());
+    self.record(Outcome::Passed);
+    self.steps.pop_front();
+    self.schedule_next(Instant::now());
+  }
+
+  /// Fails the current step and skips the rest of the scenario
+  fn fail(&mut self, message: String) {
+    warn!("🎬 {} failed: {}", self.current(), message);
+    self.record(Outcome::Failed(message));
+    self.steps.pop_front();
+    while !self.steps.is_empty() {
+      self.started = Instant::now();
+      self.record(Outcome::Skipped);
+      self.steps.pop_front();
+    }
+    self.schedule_next(Instant::now());
+  }
+
+  fn schedule_next(&mut self, now: Instant) {
+    self.waiting = false;
+    self.seen.clear();
+    self.deadline = self
+      .steps
+      .front()
+      .map(|step| now + Duration::from_secs(step.after));
+
+    if self.is_finished() && !self.reported {
+      self.finish();
+    }
+  }
+
+  /// Records the checks of the whole scenario
+  fn finish(&mut self) {
+    self.reported = true;
+    if self.no_call_errors {
+      let outcome = match self.call_errors.is_empty() {
+        true => Outcome::Passed,
+        false => Outcome::Failed(self.call_errors.join(", ")),
+      };
+      self.report.record(
+        &self.charge_point_id,
+        "no CallError received".to_string(),
+        Duration::ZERO,
+        outcome,
+      );
+    }
+    info!("🎬 Scenario {} finished", self.name);
+  }
+
+  /// Records the outcome of the current step
+  fn record(&self, outcome: Outcome) {
+    let Some(step) = self.steps.front() else {
+      return;
+    };
+    self.report.record(
+      &self.charge_point_id,
+      format!(
+        "step {}: {}",
+        self.total - self.steps.len() + 1,
+        step.action
+      ),
+      self.started.elapsed(),
+      outcome,
+    );
+  }
+
+  /// Scenario name and number of the current step
+  fn current(&self) -> String {
+    format!(
+      "[{}] step {}/{}",
+      self.name,
+      self.total - self.steps.len() + 1,
+      self.total
+    )
+  }
+}
+
+impl Drop for Scenario {
+  /// A charge point stopping before the end fails the current step
+  fn drop(&mut self) {
+    if !self.is_finished() {
+      self.fail("charge point stopped before the step was done".to_string());
+    }
+  }
+}
+
+/// Checks the field of a response, the whole payload without one
+fn check_response(
+  payload: &Value,
+  field: Option<&str>,
+  equals: Option<&Value>,
+) -> Result<(), String> {
+  let value = match field {
+    Some(field) => payload
+      .pointer(&format!("/{}", field.replace('.', "/")))
+      .ok_or_else(|| format!("no {field} in the response"))?,
+    None => payload,
+  };
+
+  match equals {
+    Some(expected) if value != expected => Err(format!(
+      "{} is {}, expected {}",
+      field.unwrap_or("response"),
+      value,
+      expected
+    )),
+    _ => Ok(()),
+  }
+}
+
+pub(crate) fn parse_status(status: &str) -> Option<CommonConnectorStatusType> {
+  use CommonConnectorStatusType::*;
+
+  match status {
+    "Available" => Some(Available),
+    "Preparing" => Some(Preparing),
+    "Charging" => Some(Charging),
+    "SuspendedEVSE" => Some(SuspendedEVSE),
+    "SuspendedEV" => Some(SuspendedEV),
+    "Finishing" => Some(Finishing),
+    "Reserved" => Some(Reserved),
+    "Unavailable" => Some(Unavailable),
+    "Faulted" => Some(Faulted),
+    _ => None,
+  }
+}
+
+enum Outcome {
+  Passed,
+  Failed(String),
+  Skipped,
+}
+
+struct CheckResult {
+  name: String,
+  time: Duration,
+  outcome: Outcome,
+}
+
+/// Outcome of the scenario checks of every charge point
+pub struct ScenarioReport {
+  name: String,
+  charge_points: Mutex<BTreeMap<String, Vec<CheckResult>>>,
+}
+
+impl ScenarioReport {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      charge_points: Mutex::default(),
+    }
+  }
+
+  fn record(&self, charge_point_id: &str, name: String, time: Duration, outcome: Outcome) {
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .entry(charge_point_id.to_string())
+      .or_default()
+      .push(CheckResult {
+        name,
+        time,
+        outcome,
+      });
+  }
+
+  pub fn failures(&self) -> usize {
+    self
+      .charge_points
+      .lock()
+      .unwrap()
+      .values()
+      .flatten()
+      .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+      .count()
+  }
+
+  /// Logs the checks per charge point with the reason of each failure
+  pub fn log_summary(&self) {
+    let charge_points = self.charge_points.lock().unwrap();
+
+    info!("{}", format!("scenario {} summary", self.name).purple());
+    info!(
+      "{:<24} {:>8} {:>8} {:>8}",
+      "Charge point", "Passed", "Failed", "Skipped"
+    );
+
+    for (charge_point_id, checks) in charge_points.iter() {
+      let count = |matches: fn(&Outcome) -> bool| {
+        checks
+          .iter()
+          .filter(|check| matches(&check.outcome))
+          .count()
+      };
+      let failed = count(|outcome| matches!(outcome, Outcome::Failed(_)));
+      let line = format!(
+        "{:<24} {:>8} {:>8} {:>8}",
+        charge_point_id,
+        count(|outcome| matches!(outcome, Outcome::Passed)),
+        failed,
+        count(|outcome| matches!(outcome, Outcome::Skipped)),
+      );
+
+      if failed > 0 {
+        info!("{}", line.red());
+      } else {
+        info!("{line}");
+      }
+
+      for check in checks {
+        if let Outcome::Failed(message) = &check.outcome {
+          info!("{}", format!("  ✗ {}: {}", check.name, message).red());
+        }
+      }
+    }
+  }
+
+  /// Writes the checks as JUnit XML, a test suite per charge point
+  pub fn write_junit(&self, path: &Path) -> Result<()> {
+    let charge_points = self.charge_points.lock().unwrap();
+    let all = charge_points.values().flatten();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    writeln!(
+      xml,
+      "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">",
+      escape(&self.name),
+      all.clone().count(),
+      all
+        .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+        .count()
+    )?;
+
+    for (charge_point_id, checks) in charge_points.iter() {
+      let failures = checks
+        .iter()
+        .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+        .count();
+      let skipped = checks
+        .iter()
+        .filter(|check| matches!(check.outcome, Outcome::Skipped))
+        .count();
+      let time: Duration = checks.iter().map(|check| check.time).sum();
+      writeln!(
+        xml,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        escape(charge_point_id),
+        checks.len(),
+        failures,
+        skipped,
+        time.as_secs_f64()
+      )?;
+
+      for check in checks {
+        let testcase = format!(
+          "    <testcase classname=\"{}.{}\" name=\"{}\" time=\"{:.3}\"",
+          escape(&self.name),
+          escape(charge_point_id),
+          escape(&check.name),
+          check.time.as_secs_f64()
+        );
+        match &check.outcome {
+          Outcome::Passed => writeln!(xml, "{testcase}/>")?,
+          Outcome::Failed(message) => writeln!(
+            xml,
+            "{testcase}>\n      <failure message=\"{}\"/>\n    </testcase>",
+            escape(message)
+          )?,
+          Outcome::Skipped => writeln!(xml, "{testcase}>\n      <skipped/>\n    </testcase>")?,
+        }
+      }
+
+      writeln!(xml, "  </testsuite>")?;
+    }
+    writeln!(xml, "</testsuites>")?;
+
+    std::fs::write(path, xml).with_context(|| format!("Cannot write {}", path.display()))?;
+    info!("JUnit report written to {}", path.display());
+    Ok(())
+  }
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+  use ocpp::{handler::MessageFrame, v1_6::types::OcppAction as V16OcppAction};
+  use serde_json::json;
+
+  use super::*;
+  use crate::logs::{Logs, MessageTaps};
+
+  const YAML: &str = "
+name: Remote start with network loss
+charge_points: [CP1]
+steps:
+  - action: expect_call
+    call: RemoteStartTransaction
+    timeout: 30
+  - action: plug_in
+    after: 2
+  - action: wait_for_status
+    status: Charging
+    timeout: 10
+  - action: fault
+    error_code: GroundFailure
+  - action: disconnect
+    duration: 20
+  - action: present_tag
+    after: 5
+    id_tag: TAG1
+";
+
+  const TOML: &str = r#"
+name = "Remote start with network loss"
+charge_points = ["CP1"]
+
+[[steps]]
+action = "expect_call"
+call = "RemoteStartTransaction"
+timeout = 30
+
+[[steps]]
+action = "plug_in"
+after = 2
+
+[[steps]]
+action = "wait_for_status"
+status = "Charging"
+timeout = 10
+
+[[steps]]
+action = "fault"
+error_code = "GroundFailure"
+
+[[steps]]
+action = "disconnect"
+duration = 20
+
+[[steps]]
+action = "present_tag"
+after = 5
+id_tag = "TAG1"
+"#;
+
+  /// Scenario file written with the extension, removed once loaded
+  fn load(extension: &str, content: &str) -> Result<ScenarioFile> {
+    let path = std::env::temp_dir().join(format!("scenario-{}.{extension}", rand::random::<u64>()));
+    std::fs::write(&path, content).unwrap();
+    let scenario = ScenarioFile::try_load(&path);
+    std::fs::remove_file(&path).unwrap();
+    scenario
+  }
+
+  fn steps(scenario: &ScenarioFile) -> Vec<(u64, String)> {
+    scenario
+      .steps
+      .iter()
+      .map(|step| (step.after, step.action.to_string()))
+      .collect()
+  }
+
+  fn scenario(steps: Value) -> (Scenario, Arc<ScenarioReport>) {
+    let file: ScenarioFile =
+      serde_json::from_value(json!({ "name": "test", "steps": steps })).unwrap();
+    let report = Arc::new(ScenarioReport::new(&file.name));
+    let scenario = Scenario::new(&file, "CP1", Arc::clone(&report)).unwrap();
+    (scenario, report)
+  }
+
+  #[test]
+  fn yaml_and_toml_scenarios_hold_the_same_steps() {
+    let yaml = load("yaml", YAML).unwrap();
+    let toml = load("toml", TOML).unwrap();
+
+    assert_eq!(yaml.name, "Remote start with network loss");
+    assert_eq!(yaml.charge_points, ["CP1"]);
+    assert_eq!(
+      steps(&yaml),
+      [
+        (
+          0,
+          "expect_call RemoteStartTransaction within 30s".to_string()
+        ),
+        (2, "plug_in".to_string()),
+        (0, "wait_for_status Charging within 10s".to_string()),
+        (0, "fault GroundFailure".to_string()),
+        (0, "disconnect 20s".to_string()),
+        (5, "present_tag TAG1".to_string()),
+      ]
+    );
+    assert_eq!(steps(&toml), steps(&yaml));
+    assert_eq!(toml.charge_points, yaml.charge_points);
+  }
+
+  #[test]
+  fn invalid_scenarios_are_refused() {
+    let error = load("yml", "name: empty\nsteps: []\n").unwrap_err();
+    assert!(error.to_string().contains("has no steps"));
+
+    assert!(load("yaml", "name: unknown\nsteps:\n  - action: fly\n").is_err());
+
+    let file: ScenarioFile = serde_json::from_value(json!({
+      "name": "typo",
+      "steps": [{ "action": "wait_for_status", "status": "Chraging", "timeout": 10 }],
+    }))
+    .unwrap();
+    let report = Arc::new(ScenarioReport::new(&file.name));
+    assert!(Scenario::new(&file, "CP1", report).is_err());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn steps_are_due_after_their_delay_once_the_previous_one_is_done() {
+    let (mut scenario, report) = scenario(json!([
+      { "action": "plug_in", "after": 2 },
+      { "action": "present_tag", "after": 3 },
+      { "action": "unplug" },
+    ]));
+    scenario.start();
+
+    assert!(scenario.pop().is_none());
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert!(matches!(scenario.pop(), Some(ScenarioAction::PlugIn)));
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert!(scenario.pop().is_none());
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(matches!(
+      scenario.pop(),
+      Some(ScenarioAction::PresentTag { id_tag: None })
+    ));
+
+    // Due right away
+    assert!(matches!(scenario.pop(), Some(ScenarioAction::Unplug)));
+    assert!(scenario.is_finished());
+    assert_eq!(scenario.next_deadline(), None);
+    assert_eq!(report.failures(), 0);
+  }
+
+  /// Name and outcome of the checks of the charge point
+  fn outcomes(report: &ScenarioReport) -> Vec<(String, String)> {
+    report.charge_points.lock().unwrap()["CP1"]
+      .iter()
+      .map(|check| {
+        let outcome = match &check.outcome {
+          Outcome::Passed => "passed".to_string(),
+          Outcome::Failed(message) => format!("failed: {message}"),
+          Outcome::Skipped => "skipped".to_string(),
+        };
+        (check.name.clone(), outcome)
+      })
+      .collect()
+  }
+
+  /// Answer of the CSMS to a call the charge point logged as sent
+  fn answer(log: &MessageLog, msg_id: &str, action: &str, payload: Value) -> MessageFrameType {
+    log.record(true, &json!([2, msg_id, action, {}]).to_string());
+    MessageFrameType::V1_6(MessageFrame::CallResult {
+      msg_id: msg_id.to_string(),
+      payload,
+    })
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn response_fields_are_compared_and_a_failure_skips_the_rest() {
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    let (mut scenario, report) = scenario(json!([
+      {
+        "action": "expect_response",
+        "call": "Authorize",
+        "field": "idTagInfo.status",
+        "equals": "Accepted",
+        "timeout": 10,
+      },
+      {
+        "action": "expect_response",
+        "call": "StartTransaction",
+        "field": "idTagInfo.status",
+        "equals": "Accepted",
+        "timeout": 10,
+      },
+      { "action": "unplug" },
+    ]));
+    scenario.start();
+    assert!(scenario.pop().is_none());
+
+    let accepted = json!({ "idTagInfo": { "status": "Accepted" } });
+    scenario.observe_frame(
+      &answer(logs.messages(), "1", "Authorize", accepted),
+      logs.messages(),
+    );
+    assert!(scenario.pop().is_none());
+    let blocked = json!({ "idTagInfo": { "status": "Blocked" }, "transactionId": 1 });
+    scenario.observe_frame(
+      &answer(logs.messages(), "2", "StartTransaction", blocked),
+      logs.messages(),
+    );
+
+    assert!(scenario.is_finished());
+    assert_eq!(report.failures(), 1);
+    assert_eq!(
+      outcomes(&report),
+      [
+        (
+          "step 1: expect_response Authorize idTagInfo.status == \"Accepted\" within 10s"
+            .to_string(),
+          "passed".to_string()
+        ),
+        (
+          "step 2: expect_response StartTransaction idTagInfo.status == \"Accepted\" within 10s"
+            .to_string(),
+          "failed: idTagInfo.status is \"Blocked\", expected \"Accepted\"".to_string()
+        ),
+        ("step 3: unplug".to_string(), "skipped".to_string()),
+      ]
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn waiting_step_fails_once_its_timeout_expired() {
+    let (mut scenario, report) = scenario(json!([
+      { "action": "expect_call", "call": "RemoteStartTransaction", "timeout": 30 },
+      { "action": "plug_in" },
+    ]));
+    scenario.start();
+    assert!(scenario.pop().is_none());
+
+    tokio::time::advance(Duration::from_secs(29)).await;
+    assert!(scenario.pop().is_none());
+    assert_eq!(report.failures(), 0);
+
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(scenario.pop().is_none());
+    assert!(scenario.is_finished());
+    assert_eq!(
+      outcomes(&report),
+      [
+        (
+          "step 1: expect_call RemoteStartTransaction within 30s".to_string(),
+          "failed: no RemoteStartTransaction call in time".to_string()
+        ),
+        ("step 2: plug_in".to_string(), "skipped".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn call_errors_fail_the_scenario_when_not_allowed() {
+    let logs = Logs::new("CP1", 10, MessageTaps::default());
+    let file: ScenarioFile = serde_json::from_value(json!({
+      "name": "test",
+      "no_call_errors": true,
+      "steps": [{ "action": "expect_call", "call": "Reset", "timeout": 10 }],
+    }))
+    .unwrap();
+    let report = Arc::new(ScenarioReport::new(&file.name));
+    let mut scenario = Scenario::new(&file, "CP1", Arc::clone(&report)).unwrap();
+    scenario.start();
+
+    logs.messages().record(true, r#"[2,"1","DataTransfer",{}]"#);
+    let call_error = MessageFrameType::V1_6(MessageFrame::CallError {
+      msg_id: "1".to_string(),
+      error_code: "NotImplemented".to_string(),
+      description: "Unknown vendor".to_string(),
+    });
+    scenario.observe_frame(&call_error, logs.messages());
+    let reset = MessageFrameType::V1_6(MessageFrame::Call {
+      msg_id: "2".to_string(),
+      action: V16OcppAction::Reset,
+      payload: json!({ "type": "Soft" }),
+    });
+    assert!(scenario.pop().is_none());
+    scenario.observe_frame(&reset, logs.messages());
+
+    assert!(scenario.is_finished());
+    assert_eq!(
+      outcomes(&report)[1],
+      (
+        "no CallError received".to_string(),
+        "failed: DataTransfer: NotImplemented Unknown vendor".to_string()
+      )
+    );
+  }
+
+  #[test]
+  fn junit_report_is_valid_xml_with_escaped_names() {
+    let name = r#"Tag "A" & <B>'s"#;
+    let report = ScenarioReport::new(name);
+    let checks = [
+      ("step 1: plug_in", Outcome::Passed),
+      (
+        "step 2: expect_call <Reset>",
+        Outcome::Failed("a & b < c".to_string()),
+      ),
+      ("step 3: unplug", Outcome::Skipped),
+    ];
+    for (check, outcome) in checks {
+      report.record(
+        "CP1",
+        check.to_string(),
+        Duration::from_millis(1500),
+        outcome,
+      );
+    }
+
+    let path = std::env::temp_dir().join(format!("junit-{}.xml", rand::random::<u64>()));
+    report.write_junit(&path).unwrap();
+    let xml = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let testsuites = document.root_element();
+    assert_eq!(testsuites.attribute("name"), Some(name));
+    assert_eq!(testsuites.attribute("tests"), Some("3"));
+    assert_eq!(testsuites.attribute("failures"), Some("1"));
+
+    let testsuite = testsuites
+      .children()
+      .find(|node| node.has_tag_name("testsuite"))
+      .unwrap();
+    assert_eq!(testsuite.attribute("name"), Some("CP1"));
+    assert_eq!(testsuite.attribute("skipped"), Some("1"));
+    assert_eq!(testsuite.attribute("time"), Some("4.500"));
+
+    let testcases: Vec<_> = testsuite
+      .children()
+      .filter(|node| node.has_tag_name("testcase"))
+      .collect();
+    assert_eq!(testcases.len(), 3);
+    assert_eq!(
+      testcases[1].attribute("classname"),
+      Some(format!("{name}.CP1").as_str())
+    );
+    assert_eq!(
+      testcases[1].attribute("name"),
+      Some("step 2: expect_call <Reset>")
+    );
+    let failure = testcases[1].first_element_child().unwrap();
+    assert!(failure.has_tag_name("failure"));
+    assert_eq!(failure.attribute("message"), Some("a & b < c"));
+    assert!(
+      testcases[2]
+        .first_element_child()
+        .unwrap()
+        .has_tag_name("skipped")
+    );
+  }
+}
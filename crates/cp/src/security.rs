@@ -0,0 +1,369 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::{ChargePointConfig, GeneralConfig, SecurityConfig};
+use native_tls::{Certificate, Identity, Protocol, TlsConnector};
+use ocpp::types::{
+  CommonCertificateStatus, CommonCertificateUse, CommonConfigurationStatus, CommonSecurityEvent,
+};
+use tracing::{info, warn};
+
+use crate::certificates::CertificateStore;
+
+/// `AuthorizationKey` length limits in bytes of the OCPP 1.6 Security Extension, the key is
+/// hex-encoded
+const AUTHORIZATION_KEY_BYTES: std::ops::RangeInclusive<usize> = 16..=20;
+
+#[derive(Debug, Clone)]
+struct Profile {
+  profile: u8,
+  authorization_key: Option<String>,
+}
+
+/// Security profile of a charge point, changed at runtime by the `SecurityProfile` and
+/// `AuthorizationKey` configuration keys
+pub struct Security {
+  config: SecurityConfig,
+  current: Profile,
+  /// Profile to fall back to when the connection with the current one fails
+  previous: Option<Profile>,
+  reconnect: bool,
+  certificates: CertificateStore,
+  /// Security events waiting for a `SecurityEventNotification`
+  events: Vec<(CommonSecurityEvent, Option<String>)>,
+}
+
+impl Security {
+  pub fn new(cp_config: &ChargePointConfig) -> Result<Self> {
+    Ok(Self {
+      config: cp_config.security.clone(),
+      current: Profile {
+        profile: cp_config.security.profile,
+        authorization_key: cp_config.security.authorization_key.clone(),
+      },
+      previous: None,
+      reconnect: false,
+      certificates: CertificateStore::new(cp_config)?,
+      events: Vec::new(),
+    })
+  }
+
+  pub fn profile(&self) -> u8 {
+    self.current.profile
+  }
+
+  /// Url of the CSMS for the current profile
+  pub fn server_url(&self, general_config: &GeneralConfig) -> String {
+    if self.current.profile < 2 {
+      return general_config.server_url.clone();
+    }
+
+    self
+      .config
+      .tls_server_url
+      .clone()
+      .unwrap_or_else(|| general_config.server_url.replacen("ws://", "wss://", 1))
+  }
+
+  /// Value of the `Authorization` header, if any
+  pub fn authorization_header(&self, cp_config: &ChargePointConfig) -> Option<String> {
+    match (self.current.profile, &self.current.authorization_key) {
+      (0, _) if !cp_config.auth_header.is_empty() => Some(cp_config.auth_header.clone()),
+      (1 | 2, Some(key)) => Some(format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", cp_config.id, key))
+      )),
+      _ => None,
+    }
+  }
+
+  /// TLS settings for profiles 2 and 3
+  pub fn tls_connector(&self) -> Result<Option<TlsConnector>> {
+    if self.current.profile < 2 {
+      return Ok(None);
+    }
+
+    let mut builder = TlsConnector::builder();
+    builder.min_protocol_version(Some(Protocol::Tlsv12));
+
+    if let Some(ca_cert_path) = &self.config.ca_cert_path {
+      let pem = fs::read(ca_cert_path)
+        .with_context(|| format!("Failed to read CA certificate {}", ca_cert_path.display()))?;
+      builder.add_root_certificate(Certificate::from_pem(&pem)?);
+      builder.disable_built_in_roots(true);
+    }
+
+    // Roots installed by the CSMS are trusted as well
+    for der in self
+      .certificates
+      .certificates(CommonCertificateUse::CentralSystemRoot)?
+    {
+      builder.add_root_certificate(Certificate::from_der(&der)?);
+    }
+
+    // A certificate signed by the CSMS replaces the configured one
+    if self.current.profile == 3
+      && let Some((cert, key)) = self.certificates.client_identity()
+    {
+      builder.identity(Identity::from_pkcs8(cert, key)?);
+    } else if self.current.profile == 3 {
+      let (Some(cert_path), Some(key_path)) =
+        (&self.config.client_cert_path, &self.config.client_key_path)
+      else {
+        anyhow::bail!("Security profile 3 needs a client certificate and key");
+      };
+
+      let cert = fs::read(cert_path)
+        .with_context(|| format!("Failed to read client certificate {}", cert_path.display()))?;
+      let key = fs::read(key_path)
+        .with_context(|| format!("Failed to read client key {}", key_path.display()))?;
+      builder.identity(Identity::from_pkcs8(&cert, &key)?);
+    }
+
+    Ok(Some(builder.build()?))
+  }
+
+  /// Applies a change of a security configuration key. An accepted change takes effect
+  /// on the next connection, see [`Security::take_reconnect`].
+  pub fn change_configuration(&mut self, key: &str, value: &str) -> CommonConfigurationStatus {
+    match key {
+      "SecurityProfile" => {
+        let Ok(profile) = value.parse::<u8>() else {
+          return CommonConfigurationStatus::Rejected;
+        };
+
+        if profile == self.current.profile {
+          return CommonConfigurationStatus::Accepted;
+        }
+
+        // Downgrading is not allowed
+        if profile < self.current.profile || profile > 3 {
+          warn!(
+            "SecurityProfile {} rejected, current profile is {}",
+            profile, self.current.profile
+          );
+          return CommonConfigurationStatus::Rejected;
+        }
+
+        let prerequisites = match profile {
+          1 | 2 => self.current.authorization_key.is_some(),
+          _ => {
+            self.certificates.has_client_certificate()
+              || (self.config.client_cert_path.is_some() && self.config.client_key_path.is_some())
+          }
+        };
+
+        if !prerequisites {
+          warn!("SecurityProfile {} rejected, missing credentials", profile);
+          return CommonConfigurationStatus::Rejected;
+        }
+
+        self.switch(Profile {
+          profile,
+          authorization_key: self.current.authorization_key.clone(),
+        });
+        self.record_event(
+          CommonSecurityEvent::ReconfigurationOfSecurityParameters,
+          Some(format!("SecurityProfile {}", profile)),
+        );
+        CommonConfigurationStatus::Accepted
+      }
+      "AuthorizationKey" => {
+        match hex::decode(value) {
+          Ok(key) if AUTHORIZATION_KEY_BYTES.contains(&key.len()) => {}
+          Ok(key) => {
+            warn!(
+              "AuthorizationKey rejected, invalid length of {} bytes",
+              key.len()
+            );
+            return CommonConfigurationStatus::Rejected;
+          }
+          Err(err) => {
+            warn!("AuthorizationKey rejected, not hex-encoded: {err}");
+            return CommonConfigurationStatus::Rejected;
+          }
+        }
+
+        self.switch(Profile {
+          profile: self.current.profile,
+          authorization_key: Some(value.to_string()),
+        });
+        self.record_event(
+          CommonSecurityEvent::ReconfigurationOfSecurityParameters,
+          Some("AuthorizationKey".to_string()),
+        );
+        CommonConfigurationStatus::Accepted
+      }
+      _ => CommonConfigurationStatus::NotSupported,
+    }
+  }
+
+  pub fn certificates(&mut self) -> &mut CertificateStore {
+    &mut self.certificates
+  }
+
+  /// Signing request sent at boot when a certificate store is configured and has no client
+  /// certificate yet
+  pub fn boot_certificate_signing_request(&mut self) -> Result<Option<String>> {
+    if self.config.certificate_store.is_none() || self.certificates.has_client_certificate() {
+      return Ok(None);
+    }
+
+    self.certificates.certificate_signing_request().map(Some)
+  }
+
+  /// Accepts a client certificate signed by the CSMS, used from the next connection on
+  pub fn certificate_signed(&mut self, certificate_chain: &str) -> CommonCertificateStatus {
+    let status = self.certificates.certificate_signed(certificate_chain);
+    if status == CommonCertificateStatus::Accepted && self.current.profile == 3 {
+      self.reconnect = true;
+    }
+    status
+  }
+
+  pub fn record_event(&mut self, event: CommonSecurityEvent, tech_info: Option<String>) {
+    self.events.push((event, tech_info));
+  }
+
+  /// Security events to report, empties the queue
+  pub fn take_events(&mut self) -> Vec<(CommonSecurityEvent, Option<String>)> {
+    std::mem::take(&mut self.events)
+  }
+
+  /// Whether an accepted change requires a new connection, resets the flag
+  pub fn take_reconnect(&mut self) -> bool {
+    std::mem::take(&mut self.reconnect)
+  }
+
+  /// Falls back to the profile used before the last change
+  pub fn revert(&mut self) -> bool {
+    match self.previous.take() {
+      Some(previous) => {
+        warn!(
+          "Reverting to security profile {} from {}",
+          previous.profile, self.current.profile
+        );
+        self.current = previous;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Keeps the current profile for good once a connection with it succeeded
+  pub fn confirm(&mut self) {
+    if self.previous.take().is_some() {
+      info!("Connected with security profile {}", self.current.profile);
+    }
+  }
+
+  fn switch(&mut self, profile: Profile) {
+    // The key is only sent with profiles 1 and 2
+    self.reconnect = profile.profile != self.current.profile || matches!(profile.profile, 1 | 2);
+    let current = std::mem::replace(&mut self.current, profile);
+    self.previous = self.previous.take().or(Some(current));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::test_config::charge_point_config;
+
+  const KEY: &str = "0123456789abcdef0123456789abcdef";
+
+  fn security(profile: u8, authorization_key: Option<&str>) -> Security {
+    Security::new(&charge_point_config(json!({
+      "security": { "profile": profile, "authorization_key": authorization_key },
+    })))
+    .unwrap()
+  }
+
+  #[test]
+  fn basic_auth_header_carries_the_id_and_the_key() {
+    let cp_config = charge_point_config(json!({ "auth_header": "Bearer token" }));
+
+    // "CP1:0123456789abcdef0123456789abcdef"
+    assert_eq!(
+      security(1, Some(KEY)).authorization_header(&cp_config),
+      Some("Basic Q1AxOjAxMjM0NTY3ODlhYmNkZWYwMTIzNDU2Nzg5YWJjZGVm".to_string())
+    );
+    assert_eq!(
+      security(0, Some(KEY)).authorization_header(&cp_config),
+      Some("Bearer token".to_string())
+    );
+    assert_eq!(
+      security(3, Some(KEY)).authorization_header(&cp_config),
+      None
+    );
+  }
+
+  #[test]
+  fn authorization_key_must_be_hex_of_16_to_20_bytes() {
+    let mut security = security(1, Some(KEY));
+
+    for rejected in [
+      "0123456789abcdef0123",
+      "0123456789abcdef0123456789abcdef0",
+      "0123456789abcdefghij0123456789abcdef",
+      "0123456789abcdef0123456789abcdef0123456789",
+    ] {
+      assert_eq!(
+        security.change_configuration("AuthorizationKey", rejected),
+        CommonConfigurationStatus::Rejected,
+        "{rejected}"
+      );
+    }
+    assert!(security.take_events().is_empty());
+
+    for accepted in [
+      "00112233445566778899AABBCCDDEEFF",
+      "0123456789abcdef0123456789abcdef01234567",
+    ] {
+      assert_eq!(
+        security.change_configuration("AuthorizationKey", accepted),
+        CommonConfigurationStatus::Accepted,
+        "{accepted}"
+      );
+    }
+  }
+
+  #[test]
+  fn security_profile_is_never_lowered() {
+    let mut security = security(2, Some(KEY));
+
+    for profile in ["0", "1", "4", "x"] {
+      assert_eq!(
+        security.change_configuration("SecurityProfile", profile),
+        CommonConfigurationStatus::Rejected,
+        "{profile}"
+      );
+    }
+    assert_eq!(security.profile(), 2);
+    assert!(!security.take_reconnect());
+    assert!(security.take_events().is_empty());
+  }
+
+  #[test]
+  fn raised_profile_reconnects_and_falls_back_on_failure() {
+    let mut security = security(1, Some(KEY));
+
+    assert_eq!(
+      security.change_configuration("SecurityProfile", "2"),
+      CommonConfigurationStatus::Accepted
+    );
+    assert_eq!(security.profile(), 2);
+    assert!(security.take_reconnect());
+    assert_eq!(
+      security.take_events()[0].0,
+      CommonSecurityEvent::ReconfigurationOfSecurityParameters
+    );
+
+    assert!(security.revert());
+    assert_eq!(security.profile(), 1);
+    assert!(!security.revert());
+  }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use common::ChargePointConfig;
+use ocpp::types::CommonSecurityEvent;
+use tokio::time::Instant;
+
+struct ScheduledSecurityEvent {
+  deadline: Instant,
+  event: CommonSecurityEvent,
+  tech_info: Option<String>,
+}
+
+/// Schedules the configured security events of a charge point
+pub struct SecurityEventInjector {
+  /// Pending events ordered by their deadline
+  scheduled: Vec<ScheduledSecurityEvent>,
+}
+
+impl SecurityEventInjector {
+  pub fn new(cp_config: &ChargePointConfig) -> Self {
+    let now = Instant::now();
+
+    let mut injector = Self {
+      scheduled: Vec::new(),
+    };
+
+    for event_config in &cp_config.security_events {
+      injector.schedule(
+        now + Duration::from_secs(event_config.after),
+        CommonSecurityEvent::from(event_config.kind.as_str()),
+        event_config.tech_info.clone(),
+      );
+    }
+
+    injector
+  }
+
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.scheduled.first().map(|scheduled| scheduled.deadline)
+  }
+
+  /// Takes the next due event
+  pub fn pop(&mut self) -> Option<(CommonSecurityEvent, Option<String>)> {
+    if self.next_deadline()? > Instant::now() {
+      return None;
+    }
+
+    let scheduled = self.scheduled.remove(0);
+    Some((scheduled.event, scheduled.tech_info))
+  }
+
+  pub fn schedule(
+    &mut self,
+    deadline: Instant,
+    event: CommonSecurityEvent,
+    tech_info: Option<String>,
+  ) {
+    let index = self
+      .scheduled
+      .partition_point(|scheduled| scheduled.deadline <= deadline);
+    self.scheduled.insert(
+      index,
+      ScheduledSecurityEvent {
+        deadline,
+        event,
+        tech_info,
+      },
+    );
+  }
+}
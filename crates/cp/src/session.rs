@@ -1,38 +1,39 @@
-use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 
-use tokio::time::{Instant, Interval, MissedTickBehavior, interval};
+use crate::timers::{Timer, TimerWheel};
 
 pub struct TxnSession {
   is_running: bool,
   max_count: u64,
   count: u64,
-  interval: Interval,
+  interval: Timer,
 }
 
 impl TxnSession {
-  pub fn new(meter_values_interval: u64, meter_values_max_cnt: u64) -> Self {
-    let mut interval = interval(Duration::from_secs(meter_values_interval));
-    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
-
+  pub fn new(
+    timers: &Arc<TimerWheel>,
+    meter_values_interval: u64,
+    meter_values_max_cnt: u64,
+  ) -> Self {
     Self {
       is_running: false,
       max_count: meter_values_max_cnt,
       count: 0,
-      interval,
+      interval: timers.interval(Duration::from_secs(meter_values_interval)),
     }
   }
 
   pub fn interval(&mut self, value: u64) {
-    self.interval = interval(Duration::from_secs(value));
+    self.interval.set_period(Duration::from_secs(value));
   }
 
   pub fn is_running(&self) -> bool {
     self.is_running
   }
 
-  pub fn tick(&mut self) -> impl Future<Output = Instant> {
-    self.interval.tick()
+  pub async fn tick(&mut self) {
+    self.interval.tick().await
   }
 
   /// Simulates `meter_values` internal counter
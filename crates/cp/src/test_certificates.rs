@@ -0,0 +1,93 @@
+//! Throwaway CA and certificates for the unit tests
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use openssl::{
+  asn1::Asn1Time,
+  bn::BigNum,
+  ec::{EcGroup, EcKey},
+  hash::MessageDigest,
+  nid::Nid,
+  pkey::{HasPublic, PKey, PKeyRef, Private},
+  x509::{
+    X509, X509Builder, X509NameBuilder,
+    extension::{BasicConstraints, KeyUsage},
+  },
+};
+
+/// New P-256 key
+pub fn key() -> PKey<Private> {
+  let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+  PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+/// Self-signed CA certificate with its key
+pub fn ca(name: &str) -> (X509, PKey<Private>) {
+  let key = key();
+  let certificate = build(name, &key, None, &key, true, 30);
+  (certificate, key)
+}
+
+/// Certificate for the public key, signed by the CA and valid for the days from now, an
+/// expired one when negative
+pub fn issue<T: HasPublic>(
+  name: &str,
+  public_key: &PKeyRef<T>,
+  (ca, ca_key): &(X509, PKey<Private>),
+  valid_days: i64,
+) -> X509 {
+  build(name, public_key, Some(ca), ca_key, false, valid_days)
+}
+
+pub fn pem(certificate: &X509) -> String {
+  String::from_utf8(certificate.to_pem().unwrap()).unwrap()
+}
+
+/// Certificate signed with the key of the issuer, self-signed without one
+fn build<T: HasPublic>(
+  name: &str,
+  public_key: &PKeyRef<T>,
+  issuer: Option<&X509>,
+  signing_key: &PKey<Private>,
+  is_ca: bool,
+  valid_days: i64,
+) -> X509 {
+  let mut subject = X509NameBuilder::new().unwrap();
+  subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+  let subject = subject.build();
+
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_secs() as i64;
+  let mut builder = X509Builder::new().unwrap();
+  builder.set_version(2).unwrap();
+  let serial_number = BigNum::from_u32(rand::random::<u32>() >> 1)
+    .unwrap()
+    .to_asn1_integer()
+    .unwrap();
+  builder.set_serial_number(&serial_number).unwrap();
+  builder.set_subject_name(&subject).unwrap();
+  builder.set_pubkey(public_key).unwrap();
+  builder
+    .set_not_before(&Asn1Time::from_unix(now - 2 * 86_400).unwrap())
+    .unwrap();
+  builder
+    .set_not_after(&Asn1Time::from_unix(now + valid_days * 86_400).unwrap())
+    .unwrap();
+
+  if is_ca {
+    let constraints = BasicConstraints::new().critical().ca().build().unwrap();
+    builder.append_extension(constraints).unwrap();
+    let usage = KeyUsage::new().key_cert_sign().crl_sign().build().unwrap();
+    builder.append_extension(usage).unwrap();
+  } else {
+    let usage = KeyUsage::new().digital_signature().build().unwrap();
+    builder.append_extension(usage).unwrap();
+  }
+
+  let issuer_name = issuer.map_or(subject.as_ref(), |issuer| issuer.subject_name());
+  builder.set_issuer_name(issuer_name).unwrap();
+  builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+  builder.build()
+}
@@ -0,0 +1,240 @@
+//! Timer wheel shared by all charge points of the simulator: one task turns the wheel and
+//! wakes the charge points whose periodic timers are due, instead of a tokio interval per
+//! timer and charge point
+
+use std::{
+  mem,
+  sync::{Arc, Mutex, Weak},
+  time::Duration,
+};
+
+use tokio::{
+  sync::Notify,
+  time::{MissedTickBehavior, interval},
+};
+
+/// Time a slot of the wheel covers
+const RESOLUTION: Duration = Duration::from_millis(100);
+/// Slots of the wheel, timers further away stay in their slot for more than one turn
+const SLOTS: u64 = 1024;
+
+struct Wheel {
+  /// Ticks since the wheel started turning
+  tick: u64,
+  /// Due tick of the timers per slot, dropped timers are skipped
+  slots: Vec<Vec<(u64, Weak<Notify>)>>,
+}
+
+pub struct TimerWheel {
+  wheel: Mutex<Wheel>,
+}
+
+impl TimerWheel {
+  /// Starts turning the wheel on the current runtime until it is dropped
+  pub fn spawn() -> Arc<Self> {
+    let timers = Arc::new(Self {
+      wheel: Mutex::new(Wheel {
+        tick: 0,
+        slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+      }),
+    });
+
+    let weak = Arc::downgrade(&timers);
+    tokio::spawn(async move {
+      let mut ticks = interval(RESOLUTION);
+      ticks.set_missed_tick_behavior(MissedTickBehavior::Burst);
+      ticks.tick().await;
+      loop {
+        ticks.tick().await;
+        match weak.upgrade() {
+          Some(timers) => timers.turn(),
+          None => break,
+        }
+      }
+    });
+    timers
+  }
+
+  /// Periodic timer, its first tick completes immediately, a zero period never ticks
+  pub fn interval(self: &Arc<Self>, period: Duration) -> Timer {
+    Timer {
+      timers: Arc::clone(self),
+      notify: Arc::new(Notify::new()),
+      period: ticks(period),
+      due: self.wheel.lock().unwrap().tick,
+      scheduled: false,
+    }
+  }
+
+  fn now(&self) -> u64 {
+    self.wheel.lock().unwrap().tick
+  }
+
+  fn schedule(&self, due: u64, notify: &Arc<Notify>) {
+    let mut wheel = self.wheel.lock().unwrap();
+    if due <= wheel.tick {
+      notify.notify_one();
+    } else {
+      wheel.slots[(due % SLOTS) as usize].push((due, Arc::downgrade(notify)));
+    }
+  }
+
+  /// Advances the wheel by a slot and wakes the timers due
+  fn turn(&self) {
+    let mut due = Vec::new();
+    {
+      let wheel = &mut *self.wheel.lock().unwrap();
+      wheel.tick += 1;
+      let slot = &mut wheel.slots[(wheel.tick % SLOTS) as usize];
+      for (tick, notify) in mem::take(slot) {
+        if tick > wheel.tick {
+          slot.push((tick, notify));
+        } else if let Some(notify) = notify.upgrade() {
+          due.push(notify);
+        }
+      }
+    }
+    for notify in due {
+      notify.notify_one();
+    }
+  }
+}
+
+/// Periodic timer on the wheel, ticks missed while the charge point was busy are skipped
+pub struct Timer {
+  timers: Arc<TimerWheel>,
+  /// Replaced on reset, so the timer scheduled before does not wake it
+  notify: Arc<Notify>,
+  period: u64,
+  /// Tick of the wheel the next tick is due at
+  due: u64,
+  /// Whether the next tick is on the wheel, it stays there when a tick future is dropped
+  scheduled: bool,
+}
+
+impl Timer {
+  /// Next tick, never with a zero period, as OCPP turns an interval of 0 off
+  pub async fn tick(&mut self) {
+    if self.period == 0 {
+      return std::future::pending().await;
+    }
+    if !self.scheduled {
+      self.timers.schedule(self.due, &self.notify);
+      self.scheduled = true;
+    }
+    self.notify.notified().await;
+
+    self.scheduled = false;
+    self.due = (self.due + self.period).max(self.timers.now() + 1);
+  }
+
+  /// Next tick a full period from now
+  pub fn reset(&mut self) {
+    self.notify = Arc::new(Notify::new());
+    self.scheduled = false;
+    self.due = self.timers.now() + self.period;
+  }
+
+  /// Changes the period, the next tick completes immediately as for a new timer
+  pub fn set_period(&mut self, period: Duration) {
+    self.notify = Arc::new(Notify::new());
+    self.scheduled = false;
+    self.period = ticks(period);
+    self.due = self.timers.now();
+  }
+}
+
+/// Slots a duration covers, at least one unless it is zero
+fn ticks(duration: Duration) -> u64 {
+  if duration.is_zero() {
+    return 0;
+  }
+  duration.as_millis().div_ceil(RESOLUTION.as_millis()).max(1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::time::Instant;
+
+  use super::*;
+
+  #[test]
+  fn ticks_round_up_to_the_resolution() {
+    assert_eq!(ticks(Duration::from_micros(1)), 1);
+    assert_eq!(ticks(Duration::from_millis(150)), 2);
+    assert_eq!(ticks(Duration::from_secs(1)), 10);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn interval_ticks_at_once_then_every_period() {
+    let timers = TimerWheel::spawn();
+    let mut timer = timers.interval(Duration::from_secs(1));
+    let start = Instant::now();
+
+    timer.tick().await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    timer.tick().await;
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+    timer.tick().await;
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn periods_beyond_a_turn_of_the_wheel() {
+    let timers = TimerWheel::spawn();
+    let period = RESOLUTION * SLOTS as u32 + Duration::from_secs(5);
+    let mut timer = timers.interval(period);
+    let start = Instant::now();
+
+    timer.tick().await;
+    timer.tick().await;
+    assert_eq!(start.elapsed(), period);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn reset_waits_a_full_period() {
+    let timers = TimerWheel::spawn();
+    let mut timer = timers.interval(Duration::from_secs(1));
+    timer.tick().await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let reset_at = Instant::now();
+    timer.reset();
+    timer.tick().await;
+    // Counted from the slot the wheel is in
+    let elapsed = reset_at.elapsed();
+    assert!(
+      elapsed >= Duration::from_secs(1) - RESOLUTION && elapsed <= Duration::from_secs(1),
+      "{elapsed:?}"
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn a_zero_period_never_ticks() {
+    let timers = TimerWheel::spawn();
+    let mut timer = timers.interval(Duration::from_secs(1));
+    timer.tick().await;
+
+    timer.set_period(Duration::ZERO);
+    let ticked = tokio::time::timeout(Duration::from_secs(60), timer.tick()).await;
+    assert!(ticked.is_err());
+
+    let mut timer = timers.interval(Duration::ZERO);
+    let ticked = tokio::time::timeout(Duration::from_secs(60), timer.tick()).await;
+    assert!(ticked.is_err());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn set_period_ticks_at_once() {
+    let timers = TimerWheel::spawn();
+    let mut timer = timers.interval(Duration::from_secs(60));
+    timer.tick().await;
+
+    timer.set_period(Duration::from_secs(2));
+    let start = Instant::now();
+    timer.tick().await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    timer.tick().await;
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+  }
+}
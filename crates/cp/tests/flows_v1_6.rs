@@ -0,0 +1,237 @@
+//! OCPP 1.6 message flows of the charge points, in virtual time
+
+mod support;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::json;
+use support::{TestCsms, assert_elapsed, charge_point_config, now};
+
+const VERSION: &str = "ocpp1.6";
+
+#[tokio::test(start_paused = true)]
+async fn boot_then_heartbeats_at_the_interval() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(
+    VERSION,
+    charge_point_config("CP1", json!({ "heartbeat_interval": 30 }))?,
+  )?;
+  let mut cp = csms.accept().await?;
+  assert_eq!(cp.charge_point_id, "CP1");
+
+  let boot = cp.expect_call("BootNotification").await?;
+  assert_eq!(boot.payload["chargePointModel"], "test-model");
+  assert_eq!(boot.payload["chargePointVendor"], "test-vendor");
+  cp.respond(
+    &boot,
+    json!({ "status": "Accepted", "currentTime": now(), "interval": 30 }),
+  )
+  .await?;
+
+  let heartbeat = cp.expect_call("Heartbeat").await?;
+  cp.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+  for _ in 0..3 {
+    let (heartbeat, elapsed) = cp.expect_call_timed("Heartbeat").await?;
+    assert_elapsed(elapsed, Duration::from_secs(30));
+    cp.respond(&heartbeat, json!({ "currentTime": now() }))
+      .await?;
+  }
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn session_is_authorized_started_metered_and_stopped() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(VERSION, charge_point_config("CP1", json!({}))?)?;
+  let mut cp = csms.accept().await?;
+  cp.boot().await?;
+  cp.answer_always("StatusNotification", json!({}));
+
+  // Plugged in after `start_tx_after`, the driver presents the tag
+  let (authorize, elapsed) = cp.expect_call_timed("Authorize").await?;
+  assert_elapsed(elapsed, Duration::from_secs(5));
+  assert_eq!(authorize.payload["idTag"], "TESTTAG");
+  cp.respond(&authorize, json!({ "idTagInfo": { "status": "Accepted" } }))
+    .await?;
+
+  let start = cp.expect_call("StartTransaction").await?;
+  assert_eq!(start.payload["connectorId"], 1);
+  assert_eq!(start.payload["idTag"], "TESTTAG");
+  cp.respond(
+    &start,
+    json!({ "idTagInfo": { "status": "Accepted" }, "transactionId": 42 }),
+  )
+  .await?;
+
+  let mut energy = 0.0;
+  for _ in 0..3 {
+    let (meter_values, elapsed) = cp.expect_call_timed("MeterValues").await?;
+    assert_elapsed(elapsed, Duration::from_secs(10));
+    assert_eq!(meter_values.payload["transactionId"], 42);
+    let register = meter_values.payload["meterValue"][0]["sampledValue"]
+      .as_array()
+      .and_then(|values| {
+        values
+          .iter()
+          .find(|value| value["measurand"] == "Energy.Active.Import.Register")
+      })
+      .and_then(|value| value["value"].as_str()?.parse::<f64>().ok())
+      .expect("energy register sampled");
+    assert!(register > energy, "{register} Wh after {energy} Wh");
+    energy = register;
+    cp.respond(&meter_values, json!({})).await?;
+  }
+
+  // Stopped `stop_tx_after` after the start
+  let (stop, elapsed) = cp.expect_call_timed("StopTransaction").await?;
+  assert_elapsed(elapsed, Duration::from_secs(5));
+  assert_eq!(stop.payload["transactionId"], 42);
+  assert_eq!(stop.payload["reason"], "Local");
+  assert!(stop.payload["meterStop"].as_f64() >= Some(energy.floor()));
+  cp.respond(&stop, json!({ "idTagInfo": { "status": "Accepted" } }))
+    .await?;
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn connector_follows_the_session() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(VERSION, charge_point_config("CP1", json!({}))?)?;
+  let mut cp = csms.accept().await?;
+  cp.boot().await?;
+  cp.answer_always(
+    "Authorize",
+    json!({ "idTagInfo": { "status": "Accepted" } }),
+  );
+  cp.answer_always(
+    "StartTransaction",
+    json!({ "idTagInfo": { "status": "Accepted" }, "transactionId": 1 }),
+  );
+  cp.answer_always("MeterValues", json!({}));
+  cp.answer_always("StopTransaction", json!({}));
+
+  for status in ["Preparing", "Charging", "Finishing", "Available"] {
+    let notification = cp.expect_call("StatusNotification").await?;
+    assert_eq!(notification.payload["status"], status);
+    assert_eq!(notification.payload["errorCode"], "NoError");
+    cp.respond(&notification, json!({})).await?;
+  }
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn rejected_tag_starts_no_transaction() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(VERSION, charge_point_config("CP1", json!({}))?)?;
+  let mut cp = csms.accept().await?;
+  cp.boot().await?;
+
+  let preparing = cp.expect_call("StatusNotification").await?;
+  cp.respond(&preparing, json!({})).await?;
+  let authorize = cp.expect_call("Authorize").await?;
+  cp.respond(&authorize, json!({ "idTagInfo": { "status": "Blocked" } }))
+    .await?;
+
+  // The driver leaves with the EV
+  let available = cp.expect_call("StatusNotification").await?;
+  assert_eq!(available.payload["status"], "Available");
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn change_configuration_sets_the_heartbeat_interval() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(VERSION, charge_point_config("CP1", json!({}))?)?;
+  let mut cp = csms.accept().await?;
+  let boot = cp.expect_call("BootNotification").await?;
+  cp.respond(
+    &boot,
+    json!({ "status": "Accepted", "currentTime": now(), "interval": 60 }),
+  )
+  .await?;
+  let heartbeat = cp.expect_call("Heartbeat").await?;
+  cp.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+
+  let result = cp
+    .call(
+      "ChangeConfiguration",
+      json!({ "key": "HeartbeatInterval", "value": "10" }),
+    )
+    .await?;
+  assert_eq!(result["status"], "Accepted");
+
+  // The new interval starts with a heartbeat
+  let heartbeat = cp.expect_call("Heartbeat").await?;
+  cp.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+  let (_, elapsed) = cp.expect_call_timed("Heartbeat").await?;
+  assert_elapsed(elapsed, Duration::from_secs(10));
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn change_configuration_of_an_unknown_key_is_not_supported() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(VERSION, charge_point_config("CP1", json!({}))?)?;
+  let mut cp = csms.accept().await?;
+  cp.boot().await?;
+
+  let result = cp
+    .call(
+      "ChangeConfiguration",
+      json!({ "key": "UnknownKey", "value": "1" }),
+    )
+    .await?;
+  assert_eq!(result["status"], "NotSupported");
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn transaction_messages_unanswered_at_a_disconnect_are_sent_again() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_scenario(
+    VERSION,
+    charge_point_config("CP1", json!({}))?,
+    json!([
+      { "action": "present_tag" },
+      { "action": "plug_in", "after": 1 },
+      { "action": "disconnect", "after": 5, "duration": 30 },
+      { "action": "stop_transaction", "after": 60 },
+      { "action": "unplug", "after": 10 },
+    ]),
+  )?;
+  let mut cp = csms.accept().await?;
+  cp.boot().await?;
+  cp.answer_always("StatusNotification", json!({}));
+  cp.answer_always(
+    "Authorize",
+    json!({ "idTagInfo": { "status": "Accepted" } }),
+  );
+
+  // The connection drops before the CSMS answers the start
+  let start = cp.expect_call("StartTransaction").await?;
+  while cp.next_call().await?.is_some() {}
+
+  let mut cp = csms.accept().await?;
+  cp.answer_always("StatusNotification", json!({}));
+  cp.answer_always("Heartbeat", json!({ "currentTime": now() }));
+  let queued = cp.expect_call("StartTransaction").await?;
+  assert_ne!(queued.msg_id, start.msg_id);
+  assert_eq!(queued.payload, start.payload);
+  cp.respond(
+    &queued,
+    json!({ "idTagInfo": { "status": "Accepted" }, "transactionId": 7 }),
+  )
+  .await?;
+
+  let meter_values = cp.expect_call("MeterValues").await?;
+  assert_eq!(meter_values.payload["transactionId"], 7);
+  cp.respond(&meter_values, json!({})).await?;
+  cp.answer_always("MeterValues", json!({}));
+  let stop = cp.expect_call("StopTransaction").await?;
+  assert_eq!(stop.payload["transactionId"], 7);
+  Ok(())
+}
@@ -0,0 +1,438 @@
+//! OCPP 2.0.1 message flows of the charge points, in virtual time
+
+mod support;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::{Value, json};
+use support::{Connection, TestCsms, assert_elapsed, charge_point_config, now};
+
+const VERSION: &str = "ocpp2.0.1";
+
+#[tokio::test(start_paused = true)]
+async fn boot_then_heartbeats_at_the_interval() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(
+    VERSION,
+    charge_point_config("CS1", json!({ "heartbeat_interval": 30 }))?,
+  )?;
+  let mut cs = csms.accept().await?;
+  assert_eq!(cs.charge_point_id, "CS1");
+
+  let boot = cs.expect_call("BootNotification").await?;
+  assert_eq!(boot.payload["reason"], "PowerUp");
+  assert_eq!(boot.payload["chargingStation"]["model"], "test-model");
+  assert_eq!(boot.payload["chargingStation"]["vendorName"], "test-vendor");
+  cs.respond(
+    &boot,
+    json!({ "status": "Accepted", "currentTime": now(), "interval": 30 }),
+  )
+  .await?;
+
+  let heartbeat = cs.expect_call("Heartbeat").await?;
+  cs.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+  for _ in 0..3 {
+    let (heartbeat, elapsed) = cs.expect_call_timed("Heartbeat").await?;
+    assert_elapsed(elapsed, Duration::from_secs(30));
+    cs.respond(&heartbeat, json!({ "currentTime": now() }))
+      .await?;
+  }
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn transaction_events_carry_one_transaction_in_sequence() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(VERSION, charge_point_config("CS1", json!({}))?)?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+
+  // Plugged in after `start_tx_after`, the transaction starts with the cable
+  let (started, elapsed) = cs.expect_call_timed("TransactionEvent").await?;
+  assert_elapsed(elapsed, Duration::from_secs(5));
+  assert_eq!(started.payload["eventType"], "Started");
+  assert_eq!(started.payload["seqNo"], 0);
+  let transaction_id = started.payload["transactionInfo"]["transactionId"].clone();
+  assert!(transaction_id.is_string());
+  cs.respond(&started, json!({})).await?;
+
+  let authorize = cs.expect_call("Authorize").await?;
+  assert_eq!(authorize.payload["idToken"]["idToken"], "TESTTAG");
+  cs.respond(
+    &authorize,
+    json!({ "idTokenInfo": { "status": "Accepted" } }),
+  )
+  .await?;
+
+  // Authorized, then charging until `stop_tx_after`
+  let mut events = Vec::new();
+  loop {
+    let event = cs.expect_call("TransactionEvent").await?;
+    cs.respond(&event, json!({})).await?;
+    let ended = event.payload["eventType"] == "Ended";
+    events.push(event);
+    if ended {
+      break;
+    }
+  }
+
+  assert_eq!(events[0].payload["idToken"]["idToken"], "TESTTAG");
+  for (seq_no, event) in events.iter().enumerate() {
+    assert_eq!(event.payload["seqNo"], seq_no + 1);
+    assert_eq!(
+      event.payload["transactionInfo"]["transactionId"],
+      transaction_id
+    );
+  }
+  let periodic = events
+    .iter()
+    .filter(|event| {
+      event.payload["meterValue"][0]["sampledValue"][0]["context"] == "Sample.Periodic"
+    })
+    .count();
+  assert_eq!(periodic, 3);
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn set_variables_sets_the_heartbeat_interval() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(VERSION, charge_point_config("CS1", json!({}))?)?;
+  let mut cs = csms.accept().await?;
+  let boot = cs.expect_call("BootNotification").await?;
+  cs.respond(
+    &boot,
+    json!({ "status": "Accepted", "currentTime": now(), "interval": 60 }),
+  )
+  .await?;
+  let heartbeat = cs.expect_call("Heartbeat").await?;
+  cs.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+
+  let result = cs
+    .call(
+      "SetVariables",
+      json!({ "setVariableData": [{
+        "attributeValue": "10",
+        "component": { "name": "OCPPCommCtrlr" },
+        "variable": { "name": "HeartbeatInterval" },
+      }] }),
+    )
+    .await?;
+  assert_eq!(
+    result["setVariableResult"][0]["attributeStatus"],
+    "Accepted"
+  );
+
+  // The new interval starts with a heartbeat
+  let heartbeat = cs.expect_call("Heartbeat").await?;
+  cs.respond(&heartbeat, json!({ "currentTime": now() }))
+    .await?;
+  let (_, elapsed) = cs.expect_call_timed("Heartbeat").await?;
+  assert_elapsed(elapsed, Duration::from_secs(10));
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn get_variables_reads_the_device_model() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(
+    VERSION,
+    charge_point_config("CS1", json!({ "heartbeat_interval": 45 }))?,
+  )?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+
+  let result = cs
+    .call(
+      "GetVariables",
+      json!({ "getVariableData": [
+        { "component": { "name": "OCPPCommCtrlr" }, "variable": { "name": "HeartbeatInterval" } },
+        { "component": { "name": "OCPPCommCtrlr" }, "variable": { "name": "UnknownVariable" } },
+      ] }),
+    )
+    .await?;
+  let results = &result["getVariableResult"];
+  assert_eq!(results[0]["attributeStatus"], "Accepted");
+  assert_eq!(results[0]["attributeValue"], "45");
+  assert_eq!(results[1]["attributeStatus"], "UnknownVariable");
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn events_of_a_disconnect_are_queued_as_offline() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_scenario(
+    VERSION,
+    charge_point_config("CS1", json!({}))?,
+    json!([
+      { "action": "present_tag" },
+      { "action": "plug_in", "after": 1 },
+      { "action": "wait_for_status", "status": "Charging", "timeout": 30 },
+      { "action": "disconnect", "after": 15, "duration": 60 },
+      { "action": "stop_transaction", "after": 20 },
+      { "action": "unplug", "after": 100 },
+    ]),
+  )?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+  cs.answer_always(
+    "Authorize",
+    json!({ "idTokenInfo": { "status": "Accepted" } }),
+  );
+
+  // Online until the connection drops, every event is confirmed
+  let mut last_seq_no = None;
+  while let Some(call) = cs.next_call().await? {
+    assert_eq!(call.action, "TransactionEvent");
+    assert_eq!(call.payload.get("offline"), None);
+    last_seq_no = call.payload["seqNo"].as_u64();
+    cs.respond(&call, json!({})).await?;
+  }
+  let last_seq_no = last_seq_no.expect("No transaction event before the disconnect");
+
+  // The transaction is stopped while offline, its events follow the reconnect in order
+  let (mut cs, elapsed) = {
+    let start = tokio::time::Instant::now();
+    (csms.accept().await?, start.elapsed())
+  };
+  assert_elapsed(elapsed, Duration::from_secs(60));
+  cs.answer_always("StatusNotification", json!({}));
+
+  let mut queued = Vec::new();
+  loop {
+    let event = cs.expect_call("TransactionEvent").await?;
+    cs.respond(&event, json!({})).await?;
+    let ended = event.payload["eventType"] == "Ended";
+    queued.push(event);
+    if ended {
+      break;
+    }
+  }
+
+  for (offset, event) in queued.iter().enumerate() {
+    assert_eq!(event.payload["offline"], true);
+    assert_eq!(event.payload["seqNo"], last_seq_no + 1 + offset as u64);
+  }
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scenario_matches_responses_before_the_step_and_reports_security_events() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  let (charge_point, report) = csms.spawn_scenario(
+    VERSION,
+    charge_point_config("CS1", json!({}))?,
+    json!([
+      { "action": "present_tag" },
+      // The answer to Authorize arrives while this step is still due
+      {
+        "action": "expect_response",
+        "after": 5,
+        "call": "Authorize",
+        "field": "idTokenInfo.status",
+        "equals": "Accepted",
+        "timeout": 10,
+      },
+      { "action": "security_event", "type": "SettingSystemTime", "tech_info": "manual" },
+    ]),
+  )?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+  cs.answer_always("TransactionEvent", json!({}));
+
+  let authorize = cs.expect_call("Authorize").await?;
+  cs.respond(
+    &authorize,
+    json!({ "idTokenInfo": { "status": "Accepted" } }),
+  )
+  .await?;
+
+  let notification = cs.expect_call("SecurityEventNotification").await?;
+  assert_eq!(notification.payload["type"], "SettingSystemTime");
+  assert_eq!(notification.payload["techInfo"], "manual");
+  cs.respond(&notification, json!({})).await?;
+
+  charge_point.await??;
+  assert_eq!(report.failures(), 0);
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn display_messages_are_set_filtered_and_cleared() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_idle(VERSION, charge_point_config("CS1", json!({}))?)?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+
+  let messages = [
+    (1, "NormalCycle", None),
+    (2, "InFront", None),
+    (3, "NormalCycle", Some("Charging")),
+  ];
+  for (id, priority, state) in messages {
+    let result = cs
+      .call(
+        "SetDisplayMessage",
+        json!({ "message": {
+          "id": id,
+          "priority": priority,
+          "state": state,
+          "message": { "format": "UTF8", "content": format!("Message {id}") },
+        } }),
+      )
+      .await?;
+    assert_eq!(result["status"], "Accepted");
+  }
+
+  assert_eq!(display_message_ids(&mut cs, 1, json!({})).await?, [1, 2, 3]);
+  assert_eq!(
+    display_message_ids(&mut cs, 2, json!({ "priority": "NormalCycle" })).await?,
+    [1, 3]
+  );
+  assert_eq!(
+    display_message_ids(&mut cs, 3, json!({ "state": "Charging" })).await?,
+    [3]
+  );
+  assert_eq!(
+    display_message_ids(&mut cs, 4, json!({ "id": [2, 3], "priority": "InFront" })).await?,
+    [2]
+  );
+  assert!(
+    display_message_ids(&mut cs, 5, json!({ "state": "Faulted" }))
+      .await?
+      .is_empty()
+  );
+
+  let result = cs.call("ClearDisplayMessage", json!({ "id": 1 })).await?;
+  assert_eq!(result["status"], "Accepted");
+  let result = cs.call("ClearDisplayMessage", json!({ "id": 1 })).await?;
+  assert_eq!(result["status"], "Unknown");
+  assert_eq!(
+    display_message_ids(&mut cs, 6, json!({ "priority": "NormalCycle" })).await?,
+    [3]
+  );
+  Ok(())
+}
+
+/// Ids of the messages the charge point reports for the filter
+async fn display_message_ids(
+  cs: &mut Connection,
+  request_id: i32,
+  filter: Value,
+) -> Result<Vec<i64>> {
+  let mut request = json!({ "requestId": request_id });
+  request
+    .as_object_mut()
+    .unwrap()
+    .extend(filter.as_object().unwrap().clone());
+  let result = cs.call("GetDisplayMessages", request).await?;
+  if result["status"] == "Unknown" {
+    return Ok(Vec::new());
+  }
+  assert_eq!(result["status"], "Accepted");
+
+  let notify = cs.expect_call("NotifyDisplayMessages").await?;
+  assert_eq!(notify.payload["requestId"], request_id);
+  assert_eq!(notify.payload["tbc"], false);
+  cs.respond(&notify, json!({})).await?;
+  Ok(
+    notify.payload["messageInfo"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .filter_map(|message| message["id"].as_i64())
+      .collect(),
+  )
+}
+
+#[tokio::test(start_paused = true)]
+async fn plug_and_charge_installs_checks_and_authorizes_the_contract() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(
+    VERSION,
+    charge_point_config(
+      "CS1",
+      json!({ "plug_and_charge": { "emaid": "DEABCC1234567X" } }),
+    )?,
+  )?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+  cs.answer_always("TransactionEvent", json!({}));
+
+  let request = cs.expect_call("Get15118EVCertificate").await?;
+  assert_eq!(request.payload["action"], "Install");
+  assert_eq!(
+    request.payload["iso15118SchemaVersion"],
+    "urn:iso:15118:2:2013:MsgDef"
+  );
+  let exi = STANDARD.decode(request.payload["exiRequest"].as_str().unwrap())?;
+  assert_eq!(exi[0], 0x80);
+  cs.respond(
+    &request,
+    json!({ "status": "Accepted", "exiResponse": STANDARD.encode([0x80]) }),
+  )
+  .await?;
+
+  let status = cs.expect_call("GetCertificateStatus").await?;
+  let ocsp_request = &status.payload["ocspRequestData"];
+  assert_eq!(ocsp_request["hashAlgorithm"], "SHA256");
+  assert_eq!(ocsp_request["responderURL"], "http://localhost/ocsp");
+  cs.respond(
+    &status,
+    json!({ "status": "Accepted", "ocspResult": STANDARD.encode("good") }),
+  )
+  .await?;
+
+  let authorize = cs.expect_call("Authorize").await?;
+  assert_eq!(
+    authorize.payload["idToken"],
+    json!({ "idToken": "DEABCC1234567X", "type": "eMAID" })
+  );
+  assert_eq!(
+    authorize.payload["iso15118CertificateHashData"][0],
+    *ocsp_request
+  );
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_contract_installation_falls_back_to_the_tag() -> Result<()> {
+  let csms = TestCsms::bind().await?;
+  csms.spawn_dynamic(
+    VERSION,
+    charge_point_config(
+      "CS1",
+      json!({ "plug_and_charge": { "emaid": "DEABCC1234567X" } }),
+    )?,
+  )?;
+  let mut cs = csms.accept().await?;
+  cs.boot().await?;
+  cs.answer_always("StatusNotification", json!({}));
+  cs.answer_always("TransactionEvent", json!({}));
+
+  let request = cs.expect_call("Get15118EVCertificate").await?;
+  cs.respond(&request, json!({ "status": "Failed", "exiResponse": "" }))
+    .await?;
+
+  let authorize = cs.expect_call("Authorize").await?;
+  assert_eq!(
+    authorize.payload["idToken"],
+    json!({ "idToken": "TESTTAG", "type": "ISO14443" })
+  );
+  assert!(
+    authorize
+      .payload
+      .get("iso15118CertificateHashData")
+      .is_none()
+  );
+  Ok(())
+}
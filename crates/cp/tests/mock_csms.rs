@@ -0,0 +1,243 @@
+//! Message flows of the charge points against the mock CSMS
+
+mod support;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::{ChargePointConfig, MockCsmsConfig};
+use serde_json::json;
+use support::{EXPECT_TIMEOUT, TestCsms, charge_point_config};
+
+#[tokio::test(start_paused = true)]
+async fn idle_charge_point_boots() -> Result<()> {
+  let server = TestCsms::mock(MockCsmsConfig::default()).await?;
+  server.spawn_idle("ocpp1.6", charge_point_config("CP16", json!({}))?)?;
+  let csms = server.csms();
+
+  let boot = csms
+    .wait_for_call("CP16", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(boot.payload()["chargePointModel"], "test-model");
+  csms
+    .wait_for_call("CP16", "Heartbeat", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(csms.connected(), ["CP16"]);
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_charge_point_boots_with_ocpp_2_0_1() -> Result<()> {
+  let server = TestCsms::mock(MockCsmsConfig::default()).await?;
+  server.spawn_idle("ocpp2.0.1", charge_point_config("CP201", json!({}))?)?;
+  let csms = server.csms();
+
+  let boot = csms
+    .wait_for_call("CP201", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(boot.payload()["chargingStation"]["model"], "test-model");
+  csms
+    .wait_for_call("CP201", "Heartbeat", EXPECT_TIMEOUT)
+    .await?;
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn dynamic_session_uses_the_transaction_id_of_the_csms() -> Result<()> {
+  let server = TestCsms::mock(MockCsmsConfig::default()).await?;
+  server.spawn_dynamic("ocpp1.6", charge_point_config("CP16", json!({}))?)?;
+  let csms = server.csms();
+
+  let authorize = csms
+    .wait_for_call("CP16", "Authorize", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(authorize.payload()["idTag"], "TESTTAG");
+  let start = csms
+    .wait_for_call("CP16", "StartTransaction", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(start.payload()["idTag"], "TESTTAG");
+
+  let meter_values = csms
+    .wait_for_call("CP16", "MeterValues", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(meter_values.payload()["transactionId"], 1);
+  let stop = csms
+    .wait_for_call("CP16", "StopTransaction", EXPECT_TIMEOUT)
+    .await?;
+  assert_eq!(stop.payload()["transactionId"], 1);
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn charge_point_answers_a_call_of_the_csms() -> Result<()> {
+  let server = TestCsms::mock(MockCsmsConfig::default()).await?;
+  server.spawn_idle("ocpp1.6", charge_point_config("CP16", json!({}))?)?;
+  let csms = server.csms();
+  csms
+    .wait_for_call("CP16", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+
+  let msg_id = csms.send_call(
+    "CP16",
+    "ChangeConfiguration",
+    json!({ "key": "UnknownKey", "value": "1" }),
+  )?;
+  let answer = csms.wait_for_answer(&msg_id, EXPECT_TIMEOUT).await?;
+  assert_eq!(answer.payload()["status"], "NotSupported");
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduled_command_reaches_the_charge_points_it_names() -> Result<()> {
+  let config = serde_json::from_value(json!({
+    "commands": [{
+      "call": "ChangeConfiguration",
+      "payload": { "key": "HeartbeatInterval", "value": "30" },
+      "charge_points": ["CP1"],
+    }],
+  }))?;
+  let server = TestCsms::mock(config).await?;
+  server.spawn_idle("ocpp1.6", charge_point_config("CP1", json!({}))?)?;
+  server.spawn_idle("ocpp1.6", charge_point_config("CP2", json!({}))?)?;
+  let csms = server.csms();
+  csms
+    .wait_for_call("CP2", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+
+  let answered = |id: &str| {
+    csms.received().iter().any(|frame| {
+      frame.charge_point_id == id
+        && frame.payload()["status"] == "Accepted"
+        && frame.action().is_none()
+    })
+  };
+  csms
+    .wait_until(EXPECT_TIMEOUT, || answered("CP1").then_some(()))
+    .await
+    .expect("CP1 did not answer");
+  assert!(!answered("CP2"));
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn configured_call_error_is_sent_to_the_charge_point() -> Result<()> {
+  let config = serde_json::from_value(json!({
+    "call_errors": { "Authorize": "NotSupported" },
+  }))?;
+  let server = TestCsms::mock(config).await?;
+  server.spawn_dynamic("ocpp1.6", charge_point_config("CP16", json!({}))?)?;
+  let csms = server.csms();
+
+  // No transaction is started for a tag the CSMS did not accept, the driver tries again
+  // once the connection timeout unplugged the EV
+  csms
+    .wait_for_calls("CP16", "Authorize", 2, Duration::from_secs(120))
+    .await?;
+  assert!(csms.calls("CP16", "StartTransaction").is_empty());
+  Ok(())
+}
+
+const AUTHORIZATION_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+/// Charge point on security profile 1 with the key the mock CSMS accepts
+fn basic_auth_config() -> Result<ChargePointConfig> {
+  charge_point_config(
+    "CP16",
+    json!({ "security": { "profile": 1, "authorization_key": AUTHORIZATION_KEY } }),
+  )
+}
+
+/// Types of the `SecurityEventNotification`s received once `count` of them arrived
+async fn security_events(server: &TestCsms, count: usize) -> Result<Vec<String>> {
+  let events = server
+    .csms()
+    .wait_for_calls("CP16", "SecurityEventNotification", count, EXPECT_TIMEOUT)
+    .await?;
+  Ok(
+    events
+      .iter()
+      .map(|event| {
+        event.payload()["type"]
+          .as_str()
+          .unwrap_or_default()
+          .to_string()
+      })
+      .collect(),
+  )
+}
+
+#[tokio::test(start_paused = true)]
+async fn rejected_credentials_fall_back_to_the_previous_key_and_are_reported() -> Result<()> {
+  let server = TestCsms::mock(serde_json::from_value(json!({
+    "authorization_keys": [AUTHORIZATION_KEY],
+  }))?)
+  .await?;
+  server.spawn_idle("ocpp1.6", basic_auth_config()?)?;
+  let csms = server.csms();
+  csms
+    .wait_for_call("CP16", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+
+  // The CSMS does not know the new key, the charge point reconnects with the old one
+  let msg_id = csms.send_call(
+    "CP16",
+    "ChangeConfiguration",
+    json!({ "key": "AuthorizationKey", "value": "ffffffffffffffffffffffffffffffff" }),
+  )?;
+  let answer = csms.wait_for_answer(&msg_id, EXPECT_TIMEOUT).await?;
+  assert_eq!(answer.payload()["status"], "Accepted");
+
+  assert_eq!(
+    security_events(&server, 2).await?,
+    [
+      "ReconfigurationOfSecurityParameters",
+      "FailedToAuthenticateAtCentralSystem"
+    ]
+  );
+  assert_eq!(csms.connected(), ["CP16"]);
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_tls_handshake_falls_back_to_the_previous_profile_and_is_reported() -> Result<()> {
+  let server = TestCsms::mock(MockCsmsConfig::default()).await?;
+  server.spawn_idle("ocpp1.6", basic_auth_config()?)?;
+  let csms = server.csms();
+  csms
+    .wait_for_call("CP16", "BootNotification", EXPECT_TIMEOUT)
+    .await?;
+
+  // The mock CSMS serves ws:// only, the TLS handshake of profile 2 fails
+  let msg_id = csms.send_call(
+    "CP16",
+    "ChangeConfiguration",
+    json!({ "key": "SecurityProfile", "value": "2" }),
+  )?;
+  let answer = csms.wait_for_answer(&msg_id, EXPECT_TIMEOUT).await?;
+  assert_eq!(answer.payload()["status"], "Accepted");
+
+  assert_eq!(
+    security_events(&server, 2).await?,
+    [
+      "ReconfigurationOfSecurityParameters",
+      "InvalidCentralSystemCertificate"
+    ]
+  );
+  Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn charge_point_with_unknown_credentials_is_not_connected() -> Result<()> {
+  let server = TestCsms::mock(serde_json::from_value(json!({
+    "authorization_keys": ["ffffffffffffffffffffffffffffffff"],
+  }))?)
+  .await?;
+  let charge_point = server.spawn_idle("ocpp1.6", basic_auth_config()?)?;
+
+  let err = charge_point
+    .await?
+    .expect_err("Connected with unknown credentials");
+  assert!(err.to_string().contains("401"), "{err}");
+  assert!(server.csms().connected().is_empty());
+  Ok(())
+}
@@ -0,0 +1,202 @@
+//! Recordings of charge point sessions replayed against the CSMS
+
+mod support;
+
+use std::{
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use common::MockCsmsConfig;
+use cp::{
+  logs::MessageTaps,
+  recording::{self, Direction, RecordedFrame, Recorder},
+  replay::ChargePointReplay,
+};
+use serde_json::{Value, json};
+use support::{EXPECT_TIMEOUT, TestCsms, assert_elapsed, charge_point_config};
+
+fn recording_path() -> PathBuf {
+  std::env::temp_dir().join(format!("recording-{}.jsonl", rand::random::<u64>()))
+}
+
+fn taps(path: &Path) -> Result<MessageTaps> {
+  Ok(MessageTaps {
+    recorder: Some(Arc::new(Recorder::create(path)?)),
+    metrics: None,
+  })
+}
+
+/// Message id, action and payload of the calls sent in the direction
+fn calls(frames: &[RecordedFrame], direction: Direction) -> Vec<(String, String, Value)> {
+  frames
+    .iter()
+    .filter(|recorded| recorded.direction == direction && recorded.frame[0] == 2)
+    .map(|recorded| {
+      let frame = &recorded.frame;
+      (
+        frame[1].as_str().unwrap_or_default().to_string(),
+        frame[2].as_str().unwrap_or_default().to_string(),
+        frame[3].clone(),
+      )
+    })
+    .collect()
+}
+
+/// Answer of the CSMS to the call with the message id
+fn answer<'a>(frames: &'a [RecordedFrame], msg_id: &str) -> Option<&'a Value> {
+  frames
+    .iter()
+    .find(|recorded| {
+      recorded.direction == Direction::Received
+        && recorded.frame[0] == 3
+        && recorded.frame[1] == msg_id
+    })
+    .map(|recorded| &recorded.frame[2])
+}
+
+#[tokio::test(start_paused = true)]
+async fn recorded_session_is_replayed_with_new_message_and_transaction_ids() -> Result<()> {
+  let recorded_path = recording_path();
+  let server = TestCsms::mock(MockCsmsConfig::default())
+    .await?
+    .with_taps(taps(&recorded_path)?);
+  let config = charge_point_config("CP16", json!({}))?;
+  let charge_point = server.spawn_dynamic("ocpp1.6", config.clone())?;
+  server
+    .csms()
+    .wait_for_call("CP16", "StopTransaction", EXPECT_TIMEOUT)
+    .await?;
+  tokio::time::sleep(Duration::from_secs(1)).await;
+  charge_point.abort();
+
+  let recorded = recording::load(&recorded_path)?.remove("CP16").unwrap();
+  let replayed_path = recording_path();
+  // The same mock CSMS, which assigns the next transaction id to the replay
+  ChargePointReplay::new(
+    server.general_config("ocpp1.6")?,
+    config,
+    recorded.clone(),
+    1.0,
+  )
+  .with_taps(taps(&replayed_path)?)
+  .run()
+  .await?;
+  let replayed = recording::load(&replayed_path)?.remove("CP16").unwrap();
+
+  let recorded_calls = calls(&recorded, Direction::Sent);
+  let replayed_calls = calls(&replayed, Direction::Sent);
+  let actions = |calls: &[(String, String, Value)]| {
+    calls
+      .iter()
+      .map(|(_, action, _)| action.clone())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(actions(&replayed_calls), actions(&recorded_calls));
+  assert!(actions(&recorded_calls).contains(&"MeterValues".to_string()));
+
+  let transaction_id = |frames, calls: &[(String, String, Value)]| {
+    let (msg_id, _, _) = calls
+      .iter()
+      .find(|(_, action, _)| action == "StartTransaction")
+      .unwrap();
+    answer(frames, msg_id).unwrap()["transactionId"].clone()
+  };
+  let recorded_transaction = transaction_id(&recorded, &recorded_calls);
+  let replayed_transaction = transaction_id(&replayed, &replayed_calls);
+  assert_eq!(recorded_transaction, 1);
+  assert_eq!(replayed_transaction, 2);
+
+  for ((recorded_id, action, recorded_payload), (replayed_id, _, replayed_payload)) in
+    recorded_calls.iter().zip(&replayed_calls)
+  {
+    assert_ne!(recorded_id, replayed_id, "{action} kept its message id");
+    assert!(
+      answer(&replayed, replayed_id).is_some(),
+      "{action} was not answered"
+    );
+    if recorded_payload.get("transactionId").is_some() {
+      assert_eq!(recorded_payload["transactionId"], recorded_transaction);
+      assert_eq!(
+        replayed_payload["transactionId"], replayed_transaction,
+        "{action} kept the recorded transaction"
+      );
+    }
+  }
+  Ok(())
+}
+
+/// Frame of the recording `seconds` after its start
+fn recorded(seconds: i64, direction: Direction, frame: Value) -> RecordedFrame {
+  RecordedFrame {
+    timestamp: DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::seconds(seconds),
+    charge_point_id: "CP16".to_string(),
+    direction,
+    frame,
+  }
+}
+
+#[tokio::test(start_paused = true)]
+async fn replay_keeps_the_recorded_timing_at_its_speed() -> Result<()> {
+  use Direction::{Received, Sent};
+  let frames = vec![
+    recorded(0, Sent, json!([2, "boot", "BootNotification", {}])),
+    recorded(0, Received, json!([3, "boot", { "status": "Accepted" }])),
+    recorded(
+      10,
+      Sent,
+      json!([2, "start", "StartTransaction", { "connectorId": 1 }]),
+    ),
+    recorded(10, Received, json!([3, "start", { "transactionId": 7 }])),
+    recorded(
+      30,
+      Sent,
+      json!([2, "meter", "MeterValues", { "transactionId": 7 }]),
+    ),
+    recorded(30, Received, json!([3, "meter", {}])),
+    recorded(35, Received, json!([2, "trigger", "TriggerMessage", {}])),
+    recorded(36, Sent, json!([3, "trigger", { "status": "Accepted" }])),
+    recorded(
+      50,
+      Sent,
+      json!([2, "stop", "StopTransaction", { "transactionId": 7 }]),
+    ),
+  ];
+
+  let server = TestCsms::bind().await?;
+  let mut replay = ChargePointReplay::new(
+    server.general_config("ocpp1.6")?,
+    charge_point_config("CP16", json!({}))?,
+    frames,
+    2.0,
+  );
+  let replay = tokio::spawn(async move { replay.run().await });
+  let mut cs = server.accept().await?;
+
+  let (boot, _) = cs.expect_call_timed("BootNotification").await?;
+  assert_ne!(boot.msg_id, "boot");
+  cs.respond(&boot, json!({ "status": "Accepted" })).await?;
+
+  let (start, elapsed) = cs.expect_call_timed("StartTransaction").await?;
+  assert_elapsed(elapsed, Duration::from_secs(5));
+  cs.respond(&start, json!({ "transactionId": 42 })).await?;
+
+  let (meter_values, elapsed) = cs.expect_call_timed("MeterValues").await?;
+  assert_elapsed(elapsed, Duration::from_secs(10));
+  assert_eq!(meter_values.payload["transactionId"], 42);
+  cs.respond(&meter_values, json!({})).await?;
+
+  let msg_id = cs.send_call("TriggerMessage", json!({})).await?;
+  assert_eq!(cs.expect_result(&msg_id).await?["status"], "Accepted");
+
+  let (stop, elapsed) = cs.expect_call_timed("StopTransaction").await?;
+  assert_elapsed(elapsed, Duration::from_secs(7));
+  assert_eq!(stop.payload["transactionId"], 42);
+  cs.respond(&stop, json!({})).await?;
+
+  replay.await??;
+  Ok(())
+}
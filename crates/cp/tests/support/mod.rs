@@ -0,0 +1,331 @@
+//! Test support: the mock CSMS the charge points connect to, either answering them itself or
+//! scripted, with the test answering each call. Run the tests with
+//! `#[tokio::test(start_paused = true)]`, so the intervals of the charge points pass in
+//! virtual time.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use common::{ChargePointConfig, GeneralConfig, MockCsmsConfig, ScenarioFile};
+use cp::{
+  dynamic::ChargePointDynamic,
+  id_tags::{AuthorizationStats, IdTags},
+  idle::ChargePointIdle,
+  logs::MessageTaps,
+  scenario::{Scenario, ScenarioReport},
+  timers::TimerWheel,
+};
+use csms::MockCsms;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::{
+  net::TcpStream,
+  task::JoinHandle,
+  time::{Instant, timeout, timeout_at},
+};
+use tokio_tungstenite::{WebSocketStream, tungstenite::Message};
+use uuid::Uuid;
+
+/// Virtual time a charge point has to send what the test expects
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Charge point with short intervals, `overrides` replaces fields of it
+pub fn charge_point_config(id: &str, overrides: Value) -> Result<ChargePointConfig> {
+  let mut config = json!({
+    "id": id,
+    "model": "test-model",
+    "vendor": "test-vendor",
+    "auth_header": "",
+    "boot_delay_interval": 0,
+    "heartbeat_interval": 60,
+    "txn_meter_values_interval": 10,
+    "txn_meter_values_max_count": 100,
+    "status_interval": 60,
+    "start_tx_after": 5,
+    "stop_tx_after": 35,
+    "id_tag": "TESTTAG",
+  });
+  if let (Some(config), Value::Object(overrides)) = (config.as_object_mut(), overrides) {
+    config.extend(overrides);
+  }
+  Ok(serde_json::from_value(config)?)
+}
+
+/// Mock CSMS on a free port for the charge points of a test
+pub struct TestCsms {
+  csms: MockCsms,
+  /// Turns from the start, so the paused clock advances in steps of the wheel while a
+  /// charge point waits on the loopback connection
+  timers: Arc<TimerWheel>,
+  /// Passed to the charge points spawned
+  taps: MessageTaps,
+}
+
+impl TestCsms {
+  /// Scripted CSMS, the test accepts the charge points and answers their calls
+  pub async fn bind() -> Result<Self> {
+    Ok(Self {
+      csms: MockCsms::start_scripted("127.0.0.1:0".parse()?).await?,
+      timers: TimerWheel::spawn(),
+      taps: MessageTaps::default(),
+    })
+  }
+
+  /// Mock CSMS answering the charge points as configured
+  pub async fn mock(config: MockCsmsConfig) -> Result<Self> {
+    Ok(Self {
+      csms: MockCsms::start("127.0.0.1:0".parse()?, config).await?,
+      timers: TimerWheel::spawn(),
+      taps: MessageTaps::default(),
+    })
+  }
+
+  /// Records the charge points spawned from now on, or passes their traffic to the metrics
+  pub fn with_taps(mut self, taps: MessageTaps) -> Self {
+    self.taps = taps;
+    self
+  }
+
+  /// Frames the charge points sent, unless scripted
+  pub fn csms(&self) -> &MockCsms {
+    &self.csms
+  }
+
+  pub fn general_config(&self, ocpp_version: &str) -> Result<Arc<GeneralConfig>> {
+    Ok(Arc::new(serde_json::from_value(json!({
+      "debug_mode": true,
+      "server_url": self.csms.url(),
+      "ocpp_version": ocpp_version,
+    }))?))
+  }
+
+  pub fn spawn_idle(
+    &self,
+    ocpp_version: &str,
+    config: ChargePointConfig,
+  ) -> Result<JoinHandle<Result<()>>> {
+    let mut charge_point =
+      ChargePointIdle::new(self.general_config(ocpp_version)?, config, id_tags())
+        .with_taps(self.taps.clone())
+        .with_timers(Arc::clone(&self.timers));
+    Ok(tokio::spawn(async move { charge_point.run().await }))
+  }
+
+  /// Idle charge point running the scenario steps, with the report of its checks
+  pub fn spawn_scenario(
+    &self,
+    ocpp_version: &str,
+    config: ChargePointConfig,
+    steps: Value,
+  ) -> Result<(JoinHandle<Result<()>>, Arc<ScenarioReport>)> {
+    let file: ScenarioFile = serde_json::from_value(json!({ "name": "test", "steps": steps }))?;
+    let report = Arc::new(ScenarioReport::new(&file.name));
+    let scenario = Scenario::new(&file, &config.id, Arc::clone(&report))?;
+    let mut charge_point =
+      ChargePointIdle::new(self.general_config(ocpp_version)?, config, id_tags())
+        .with_scenario(scenario)
+        .with_taps(self.taps.clone())
+        .with_timers(Arc::clone(&self.timers));
+    Ok((
+      tokio::spawn(async move { charge_point.run().await }),
+      report,
+    ))
+  }
+
+  pub fn spawn_dynamic(
+    &self,
+    ocpp_version: &str,
+    config: ChargePointConfig,
+  ) -> Result<JoinHandle<Result<()>>> {
+    let mut charge_point =
+      ChargePointDynamic::new(self.general_config(ocpp_version)?, config, id_tags())
+        .with_taps(self.taps.clone())
+        .with_timers(Arc::clone(&self.timers));
+    Ok(tokio::spawn(async move { charge_point.run().await }))
+  }
+
+  /// Next charge point connecting to the scripted CSMS
+  pub async fn accept(&self) -> Result<Connection> {
+    let connection = self.csms.accept(EXPECT_TIMEOUT).await?;
+    Ok(Connection {
+      ws: connection.ws,
+      charge_point_id: connection.charge_point_id,
+      answered: HashMap::new(),
+    })
+  }
+}
+
+/// Current time of an answer
+pub fn now() -> String {
+  chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Fails unless the elapsed virtual time is the expected one, give or take the 100 ms ticks
+/// of the timer wheel the frames are scheduled on
+pub fn assert_elapsed(elapsed: Duration, expected: Duration) {
+  let difference = elapsed.abs_diff(expected);
+  assert!(
+    difference <= Duration::from_millis(500),
+    "{elapsed:?} elapsed, expected {expected:?}"
+  );
+}
+
+fn id_tags() -> IdTags {
+  IdTags::new(None, Arc::new(AuthorizationStats::default()))
+}
+
+/// Call of the charge point, answered with `Connection::respond`
+#[derive(Debug)]
+pub struct Call {
+  pub msg_id: String,
+  pub action: String,
+  pub payload: Value,
+}
+
+/// WebSocket connection of a charge point, seen from the CSMS
+pub struct Connection {
+  ws: WebSocketStream<TcpStream>,
+  pub charge_point_id: String,
+  /// Calls answered without the test seeing them
+  answered: HashMap<String, Value>,
+}
+
+impl Connection {
+  /// Answers calls of the action with the payload from now on, `expect_call` skips them
+  pub fn answer_always(&mut self, action: &str, payload: Value) {
+    self.answered.insert(action.to_string(), payload);
+  }
+
+  /// Accepts the BootNotification and answers the heartbeats from now on
+  pub async fn boot(&mut self) -> Result<Call> {
+    let boot = self.expect_call("BootNotification").await?;
+    self
+      .respond(
+        &boot,
+        json!({ "status": "Accepted", "currentTime": now(), "interval": 60 }),
+      )
+      .await?;
+    self.answer_always("Heartbeat", json!({ "currentTime": now() }));
+    Ok(boot)
+  }
+
+  /// Next call of the charge point, failing on any other frame
+  pub async fn expect_call(&mut self, action: &str) -> Result<Call> {
+    let frame = self.next_frame().await?;
+    match frame[0].as_u64() {
+      Some(2) if frame[2] == action => Ok(Call {
+        msg_id: frame[1].as_str().unwrap_or_default().to_string(),
+        action: action.to_string(),
+        payload: frame[3].clone(),
+      }),
+      _ => bail!("Expected a {action} call, got {frame}"),
+    }
+  }
+
+  /// Next call of the charge point, `None` once it dropped the connection
+  pub async fn next_call(&mut self) -> Result<Option<Call>> {
+    let Some(frame) = self.try_next_frame().await? else {
+      return Ok(None);
+    };
+    match frame[0].as_u64() {
+      Some(2) => Ok(Some(Call {
+        msg_id: frame[1].as_str().unwrap_or_default().to_string(),
+        action: frame[2].as_str().unwrap_or_default().to_string(),
+        payload: frame[3].clone(),
+      })),
+      _ => bail!("Expected a call, got {frame}"),
+    }
+  }
+
+  /// `expect_call` and the virtual time since the previous frame
+  pub async fn expect_call_timed(&mut self, action: &str) -> Result<(Call, Duration)> {
+    let start = Instant::now();
+    let call = self.expect_call(action).await?;
+    Ok((call, start.elapsed()))
+  }
+
+  /// Fails when the charge point sends anything not answered always within the time
+  pub async fn expect_silence(&mut self, duration: Duration) -> Result<()> {
+    match timeout(duration, self.next_frame()).await {
+      Ok(Ok(frame)) => bail!("Expected no frame within {duration:?}, got {frame}"),
+      _ => Ok(()),
+    }
+  }
+
+  /// Answers the call with a CallResult
+  pub async fn respond(&mut self, call: &Call, payload: Value) -> Result<()> {
+    self.send(json!([3, call.msg_id, payload])).await
+  }
+
+  /// Answers the call with a CallError
+  pub async fn respond_error(&mut self, call: &Call, error_code: &str) -> Result<()> {
+    self.send(json!([4, call.msg_id, error_code, "", {}])).await
+  }
+
+  /// Sends a call to the charge point, returns its message id
+  pub async fn send_call(&mut self, action: &str, payload: Value) -> Result<String> {
+    let msg_id = Uuid::new_v4().to_string();
+    self.send(json!([2, msg_id, action, payload])).await?;
+    Ok(msg_id)
+  }
+
+  /// Payload of the CallResult the charge point answers the call with
+  pub async fn expect_result(&mut self, msg_id: &str) -> Result<Value> {
+    let frame = self.next_frame().await?;
+    match frame[0].as_u64() {
+      Some(3) if frame[1] == msg_id => Ok(frame[2].clone()),
+      _ => bail!("Expected the CallResult to {msg_id}, got {frame}"),
+    }
+  }
+
+  /// `send_call` and `expect_result`
+  pub async fn call(&mut self, action: &str, payload: Value) -> Result<Value> {
+    let msg_id = self.send_call(action, payload).await?;
+    self.expect_result(&msg_id).await
+  }
+
+  async fn send(&mut self, frame: Value) -> Result<()> {
+    self
+      .ws
+      .send(Message::Text(frame.to_string().into()))
+      .await?;
+    Ok(())
+  }
+
+  /// Next OCPP frame, after answering the calls answered always
+  async fn next_frame(&mut self) -> Result<Value> {
+    self
+      .try_next_frame()
+      .await?
+      .context("The charge point closed the connection")
+  }
+
+  /// `next_frame`, `None` once the charge point closed or dropped the connection
+  async fn try_next_frame(&mut self) -> Result<Option<Value>> {
+    let deadline = Instant::now() + EXPECT_TIMEOUT;
+    loop {
+      let message = match timeout_at(deadline, self.ws.next())
+        .await
+        .context("The charge point sent nothing")?
+      {
+        Some(Ok(message)) => message,
+        None | Some(Err(_)) => return Ok(None),
+      };
+      let Message::Text(text) = message else {
+        continue;
+      };
+
+      let frame: Value = serde_json::from_str(&text)?;
+      let answer = match (frame[0].as_u64(), frame[2].as_str()) {
+        (Some(2), Some(action)) => self.answered.get(action).cloned(),
+        _ => None,
+      };
+      match answer {
+        Some(payload) => self.send(json!([3, frame[1], payload])).await?,
+        None => return Ok(Some(frame)),
+      }
+    }
+  }
+}
@@ -0,0 +1,548 @@
+//! Mock CSMS for demos and tests: accepts OCPP 1.6 and 2.0.1 charge points, answers their
+//! calls, sends calls to them on a schedule and keeps the frames they sent. Scripted, it hands
+//! the connections over to a test answering each call itself
+
+mod responses;
+
+use std::{
+  collections::{BTreeMap, HashMap, VecDeque},
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use common::{MockCsmsCommand, MockCsmsConfig, OcppVersion, frame_info};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::{
+  net::{TcpListener, TcpStream, lookup_host},
+  select,
+  sync::{Mutex as AsyncMutex, Notify, mpsc},
+  task::{JoinHandle, JoinSet},
+  time::{Instant, sleep_until, timeout_at},
+};
+use tokio_tungstenite::{
+  WebSocketStream,
+  tungstenite::{
+    Message,
+    handshake::server::{Callback, ErrorResponse, Request, Response},
+    http::{StatusCode, Uri},
+  },
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Frames kept for inspection, the oldest are dropped first
+const MAX_FRAMES: usize = 100_000;
+
+static NULL: Value = Value::Null;
+
+/// Frame a charge point sent to the mock CSMS
+#[derive(Debug, Clone)]
+pub struct ReceivedFrame {
+  pub timestamp: DateTime<Utc>,
+  pub charge_point_id: String,
+  pub frame: Value,
+}
+
+impl ReceivedFrame {
+  /// Action of a call, `None` for answers
+  pub fn action(&self) -> Option<&str> {
+    match self.frame[0].as_u64() {
+      Some(2) => self.frame[2].as_str(),
+      _ => None,
+    }
+  }
+
+  pub fn msg_id(&self) -> Option<&str> {
+    self.frame[1].as_str()
+  }
+
+  /// Payload of a call or a CallResult, `Null` for a CallError
+  pub fn payload(&self) -> &Value {
+    match self.frame[0].as_u64() {
+      Some(2) => &self.frame[3],
+      Some(3) => &self.frame[2],
+      _ => &NULL,
+    }
+  }
+}
+
+#[derive(Default)]
+struct State {
+  frames: VecDeque<ReceivedFrame>,
+  /// Calls received per action
+  calls: BTreeMap<String, u64>,
+  /// Frames to send per connected charge point
+  connections: HashMap<String, mpsc::UnboundedSender<String>>,
+  last_transaction_id: i32,
+}
+
+/// Connection of a charge point handed over to the test by a scripted mock CSMS
+pub struct ScriptedConnection {
+  pub charge_point_id: String,
+  pub version: OcppVersion,
+  pub ws: WebSocketStream<TcpStream>,
+}
+
+struct Shared {
+  config: MockCsmsConfig,
+  state: Mutex<State>,
+  /// Woken on every frame received
+  received: Notify,
+  /// Where the connections go when scripted, nothing is answered then
+  scripted: Option<mpsc::UnboundedSender<ScriptedConnection>>,
+}
+
+/// Mock CSMS serving until it is dropped
+pub struct MockCsms {
+  addr: SocketAddr,
+  shared: Arc<Shared>,
+  server: JoinHandle<()>,
+  scripted: Option<AsyncMutex<mpsc::UnboundedReceiver<ScriptedConnection>>>,
+}
+
+impl Drop for MockCsms {
+  fn drop(&mut self) {
+    self.server.abort();
+  }
+}
+
+impl MockCsms {
+  /// Listens on the address, port 0 picks a free one
+  pub async fn start(addr: SocketAddr, config: MockCsmsConfig) -> Result<Self> {
+    Self::listen(addr, config, false).await
+  }
+
+  /// Listens on the address and hands every connection over with `accept`
+  pub async fn start_scripted(addr: SocketAddr) -> Result<Self> {
+    Self::listen(addr, MockCsmsConfig::default(), true).await
+  }
+
+  async fn listen(addr: SocketAddr, config: MockCsmsConfig, scripted: bool) -> Result<Self> {
+    let listener = TcpListener::bind(addr)
+      .await
+      .with_context(|| format!("Mock CSMS cannot listen on {addr}"))?;
+    let addr = listener.local_addr()?;
+    let (connections, accepted) = match scripted {
+      true => {
+        let (connections, accepted) = mpsc::unbounded_channel();
+        (Some(connections), Some(AsyncMutex::new(accepted)))
+      }
+      false => (None, None),
+    };
+    let shared = Arc::new(Shared {
+      config,
+      state: Mutex::new(State::default()),
+      received: Notify::new(),
+      scripted: connections,
+    });
+    let server = tokio::spawn(serve(listener, Arc::clone(&shared)));
+
+    info!("mock CSMS listening on {}", format!("ws://{addr}").cyan());
+    Ok(Self {
+      addr,
+      shared,
+      server,
+      scripted: accepted,
+    })
+  }
+
+  /// Next connection of a charge point once the WebSocket handshake is done, scripted only
+  pub async fn accept(&self, timeout: Duration) -> Result<ScriptedConnection> {
+    let accepted = self
+      .scripted
+      .as_ref()
+      .context("The mock CSMS answers the charge points itself")?;
+    timeout_at(Instant::now() + timeout, accepted.lock().await.recv())
+      .await
+      .ok()
+      .flatten()
+      .with_context(|| format!("No charge point connected within {timeout:?}"))
+  }
+
+  /// Listens where the charge points connect to with the server URL, `ws://` only
+  pub async fn start_at_url(server_url: &str, config: MockCsmsConfig) -> Result<Self> {
+    let uri = server_url.parse::<Uri>()?;
+    if uri.scheme_str() != Some("ws") {
+      bail!("The mock CSMS serves ws:// only, not {server_url}");
+    }
+    let host = uri.host().context("No host in the server URL")?;
+    let addr = lookup_host((host, uri.port_u16().unwrap_or(80)))
+      .await?
+      .next()
+      .with_context(|| format!("Cannot resolve {host}"))?;
+    Self::start(addr, config).await
+  }
+
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Server URL of the charge points, their id is appended to it
+  pub fn url(&self) -> String {
+    format!("ws://{}", self.addr)
+  }
+
+  /// Frames of all charge points, the oldest first
+  pub fn received(&self) -> Vec<ReceivedFrame> {
+    self.state().frames.iter().cloned().collect()
+  }
+
+  /// Calls of the action the charge point sent, the oldest first
+  pub fn calls(&self, charge_point_id: &str, action: &str) -> Vec<ReceivedFrame> {
+    self
+      .state()
+      .frames
+      .iter()
+      .filter(|frame| frame.charge_point_id == charge_point_id && frame.action() == Some(action))
+      .cloned()
+      .collect()
+  }
+
+  /// Charge points with an open connection
+  pub fn connected(&self) -> Vec<String> {
+    let mut connected = self.state().connections.keys().cloned().collect::<Vec<_>>();
+    connected.sort();
+    connected
+  }
+
+  /// Sends a call to the charge point, returns its message id
+  pub fn send_call(&self, charge_point_id: &str, action: &str, payload: Value) -> Result<String> {
+    let msg_id = Uuid::new_v4().to_string();
+    let state = self.state();
+    let Some(connection) = state.connections.get(charge_point_id) else {
+      bail!("Charge point {charge_point_id} is not connected");
+    };
+    frame_info!("➡️  [mock CSMS] {charge_point_id} {action}");
+    connection.send(json!([2, msg_id, action, payload]).to_string())?;
+    Ok(msg_id)
+  }
+
+  /// Waits for the first call of the action from the charge point
+  pub async fn wait_for_call(
+    &self,
+    charge_point_id: &str,
+    action: &str,
+    timeout: Duration,
+  ) -> Result<ReceivedFrame> {
+    let mut calls = self
+      .wait_for_calls(charge_point_id, action, 1, timeout)
+      .await?;
+    Ok(calls.remove(0))
+  }
+
+  /// Waits until the charge point sent the action `count` times, returns these calls
+  pub async fn wait_for_calls(
+    &self,
+    charge_point_id: &str,
+    action: &str,
+    count: usize,
+    timeout: Duration,
+  ) -> Result<Vec<ReceivedFrame>> {
+    self
+      .wait_until(timeout, || {
+        let calls = self.calls(charge_point_id, action);
+        (calls.len() >= count).then(|| calls.into_iter().take(count).collect())
+      })
+      .await
+      .with_context(|| {
+        format!("{charge_point_id} did not send {action} {count} time(s) within {timeout:?}")
+      })
+  }
+
+  /// Waits for the answer of a charge point to a call sent with `send_call`
+  pub async fn wait_for_answer(&self, msg_id: &str, timeout: Duration) -> Result<ReceivedFrame> {
+    self
+      .wait_until(timeout, || {
+        self
+          .state()
+          .frames
+          .iter()
+          .find(|frame| frame.action().is_none() && frame.msg_id() == Some(msg_id))
+          .cloned()
+      })
+      .await
+      .with_context(|| format!("No answer to {msg_id} within {timeout:?}"))
+  }
+
+  /// Calls received per action
+  pub fn log_summary(&self) {
+    info!("{}", "mock CSMS summary".purple());
+    info!("{:<32} {:>10}", "action", "received");
+    for (action, count) in &self.state().calls {
+      info!("{action:<32} {count:>10}");
+    }
+  }
+
+  /// Checks again on every frame received until the check returns a value, `None` once the
+  /// timeout expired
+  pub async fn wait_until<R>(&self, timeout: Duration, check: impl Fn() -> Option<R>) -> Option<R> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let received = self.shared.received.notified();
+      tokio::pin!(received);
+      received.as_mut().enable();
+
+      if let Some(result) = check() {
+        return Some(result);
+      }
+      timeout_at(deadline, received).await.ok()?;
+    }
+  }
+
+  fn state(&self) -> std::sync::MutexGuard<'_, State> {
+    self.shared.state.lock().unwrap()
+  }
+}
+
+impl Shared {
+  /// Keeps the frame, returns the answer when it is a call
+  fn receive(&self, charge_point_id: &str, version: &OcppVersion, text: &str) -> Option<String> {
+    let Ok(frame) = serde_json::from_str::<Value>(text) else {
+      warn!("Mock CSMS: invalid frame from {charge_point_id}: {text}");
+      return None;
+    };
+
+    let answer = {
+      let state = &mut *self.state.lock().unwrap();
+      let answer = match (frame[0].as_u64(), frame[1].as_str(), frame[2].as_str()) {
+        (Some(2), Some(msg_id), Some(action)) => {
+          frame_info!("⬅️  [mock CSMS] {charge_point_id} {action}");
+          *state.calls.entry(action.to_string()).or_default() += 1;
+          let answer = responses::answer(&self.config, version, msg_id, action, &frame[3], || {
+            state.last_transaction_id += 1;
+            state.last_transaction_id
+          });
+          Some(answer.to_string())
+        }
+        _ => None,
+      };
+
+      if state.frames.len() == MAX_FRAMES {
+        state.frames.pop_front();
+      }
+      state.frames.push_back(ReceivedFrame {
+        timestamp: Utc::now(),
+        charge_point_id: charge_point_id.to_string(),
+        frame,
+      });
+      answer
+    };
+    self.received.notify_waiters();
+    answer
+  }
+}
+
+async fn serve(listener: TcpListener, shared: Arc<Shared>) {
+  // Dropped with the server, which closes the connections
+  let mut connections = JoinSet::new();
+  loop {
+    select! {
+      accepted = listener.accept() => match accepted {
+        Ok((stream, _)) => {
+          // Answers sent back to back would wait for an ACK
+          let _ = stream.set_nodelay(true);
+          connections.spawn(connection(stream, Arc::clone(&shared)));
+        }
+        Err(e) => warn!("Mock CSMS cannot accept a connection: {e}"),
+      },
+      Some(_) = connections.join_next() => {},
+    }
+  }
+}
+
+/// Charge point id and OCPP version taken from the WebSocket handshake
+struct Handshake<'a> {
+  /// Basic auth passwords accepted, any when empty
+  authorization_keys: &'a [String],
+  charge_point_id: String,
+  version: Option<OcppVersion>,
+}
+
+impl Handshake<'_> {
+  /// Whether the request carries `<charge point id>:<accepted key>` as Basic auth
+  fn authorized(&self, request: &Request) -> bool {
+    if self.authorization_keys.is_empty() {
+      return true;
+    }
+
+    let credentials = request
+      .headers()
+      .get("Authorization")
+      .and_then(|header| header.to_str().ok())
+      .and_then(|header| header.strip_prefix("Basic "))
+      .and_then(|encoded| STANDARD.decode(encoded).ok())
+      .and_then(|decoded| String::from_utf8(decoded).ok());
+    credentials
+      .as_deref()
+      .and_then(|credentials| credentials.split_once(':'))
+      .is_some_and(|(user, password)| {
+        user == self.charge_point_id && self.authorization_keys.iter().any(|key| key == password)
+      })
+  }
+}
+
+impl Callback for &mut Handshake<'_> {
+  fn on_request(
+    self,
+    request: &Request,
+    mut response: Response,
+  ) -> Result<Response, ErrorResponse> {
+    self.charge_point_id = request
+      .uri()
+      .path()
+      .rsplit('/')
+      .next()
+      .unwrap_or_default()
+      .to_string();
+
+    if !self.authorized(request) {
+      let mut error = ErrorResponse::new(Some("Invalid credentials".to_string()));
+      *error.status_mut() = StatusCode::UNAUTHORIZED;
+      return Err(error);
+    }
+
+    let offered = request
+      .headers()
+      .get("Sec-WebSocket-Protocol")
+      .and_then(|protocols| protocols.to_str().ok())
+      .unwrap_or_default();
+    let Some((protocol, version)) = offered
+      .split(',')
+      .map(str::trim)
+      .find_map(|protocol| Some((protocol, OcppVersion::from_header(protocol)?)))
+    else {
+      let mut error = ErrorResponse::new(Some(format!("No supported OCPP version in {offered:?}")));
+      *error.status_mut() = StatusCode::BAD_REQUEST;
+      return Err(error);
+    };
+
+    if let Ok(protocol) = protocol.parse() {
+      response
+        .headers_mut()
+        .insert("Sec-WebSocket-Protocol", protocol);
+    }
+    self.version = Some(version);
+    Ok(response)
+  }
+}
+
+async fn connection(stream: TcpStream, shared: Arc<Shared>) {
+  let mut handshake = Handshake {
+    authorization_keys: &shared.config.authorization_keys,
+    charge_point_id: String::new(),
+    version: None,
+  };
+  let ws = match tokio_tungstenite::accept_hdr_async(stream, &mut handshake).await {
+    Ok(ws) => ws,
+    Err(e) => {
+      warn!("Mock CSMS handshake failed: {e}");
+      return;
+    }
+  };
+  let Handshake {
+    charge_point_id,
+    version: Some(version),
+    ..
+  } = handshake
+  else {
+    return;
+  };
+
+  if let Some(scripted) = &shared.scripted {
+    let _ = scripted.send(ScriptedConnection {
+      charge_point_id,
+      version,
+      ws,
+    });
+    return;
+  }
+
+  let (mut ws_tx, mut ws_rx) = ws.split();
+  let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+  shared
+    .state
+    .lock()
+    .unwrap()
+    .connections
+    .insert(charge_point_id.clone(), outgoing.clone());
+  info!("mock CSMS: {charge_point_id} connected with {version}");
+
+  let mut schedule = Schedule::new(&shared.config.commands, &charge_point_id);
+  loop {
+    select! {
+      message = ws_rx.next() => match message {
+        Some(Ok(Message::Text(text))) => {
+          if let Some(answer) = shared.receive(&charge_point_id, &version, &text) {
+            let _ = outgoing.send(answer);
+          }
+        }
+        Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+        Some(Ok(_)) => {}
+      },
+      Some(text) = outgoing_rx.recv() => {
+        if ws_tx.send(Message::Text(text.into())).await.is_err() {
+          break;
+        }
+      },
+      command = schedule.next() => {
+        frame_info!("➡️  [mock CSMS] {charge_point_id} {}", command.call);
+        let call = json!([2, Uuid::new_v4().to_string(), command.call, command.payload]);
+        let _ = outgoing.send(call.to_string());
+      },
+    }
+  }
+
+  // A reconnect of the charge point may have replaced the connection already
+  let connections = &mut shared.state.lock().unwrap().connections;
+  if connections
+    .get(&charge_point_id)
+    .is_some_and(|current| current.same_channel(&outgoing))
+  {
+    connections.remove(&charge_point_id);
+  }
+  info!("mock CSMS: {charge_point_id} disconnected");
+}
+
+/// Scheduled calls for a charge point, with the time each is due next
+struct Schedule<'a> {
+  commands: Vec<(Instant, &'a MockCsmsCommand)>,
+}
+
+impl<'a> Schedule<'a> {
+  fn new(commands: &'a [MockCsmsCommand], charge_point_id: &str) -> Self {
+    let now = Instant::now();
+    Self {
+      commands: commands
+        .iter()
+        .filter(|command| {
+          command.charge_points.is_empty()
+            || command.charge_points.iter().any(|id| id == charge_point_id)
+        })
+        .map(|command| (now + Duration::from_secs(command.after), command))
+        .collect(),
+    }
+  }
+
+  /// Waits for the next call that is due, forever when none is left
+  async fn next(&mut self) -> &'a MockCsmsCommand {
+    let Some(index) = (0..self.commands.len()).min_by_key(|&index| self.commands[index].0) else {
+      return std::future::pending().await;
+    };
+    let (due, command) = self.commands[index];
+    sleep_until(due).await;
+
+    match command.every {
+      Some(every) => self.commands[index].0 = due + Duration::from_secs(every),
+      None => {
+        self.commands.remove(index);
+      }
+    }
+    command
+  }
+}
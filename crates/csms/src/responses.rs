@@ -0,0 +1,192 @@
+//! Answers of the mock CSMS to the calls of the charge points
+
+use chrono::{SecondsFormat, Utc};
+use common::{MockCsmsConfig, OcppVersion};
+use serde_json::{Value, json};
+
+/// Answer frame to a call, the configured one or one accepting the call
+pub fn answer(
+  config: &MockCsmsConfig,
+  version: &OcppVersion,
+  msg_id: &str,
+  action: &str,
+  payload: &Value,
+  next_transaction_id: impl FnOnce() -> i32,
+) -> Value {
+  if let Some(error_code) = config.call_errors.get(action) {
+    return json!([
+      4,
+      msg_id,
+      error_code,
+      format!("{action} refused by the mock CSMS"),
+      {}
+    ]);
+  }
+
+  let payload = match config.responses.get(action) {
+    Some(response) => response.clone(),
+    None => accepting(config, version, action, payload, next_transaction_id),
+  };
+  json!([3, msg_id, payload])
+}
+
+fn accepting(
+  config: &MockCsmsConfig,
+  version: &OcppVersion,
+  action: &str,
+  payload: &Value,
+  next_transaction_id: impl FnOnce() -> i32,
+) -> Value {
+  let accepted = json!({ "status": "Accepted" });
+  let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+  match (version, action) {
+    (_, "BootNotification") => json!({
+      "status": "Accepted",
+      "currentTime": now,
+      "interval": config.heartbeat_interval,
+    }),
+    (_, "Heartbeat") => json!({ "currentTime": now }),
+    (OcppVersion::V1_6, "Authorize" | "StopTransaction") => json!({ "idTagInfo": accepted }),
+    (OcppVersion::V1_6, "StartTransaction") => json!({
+      "idTagInfo": accepted,
+      "transactionId": next_transaction_id(),
+    }),
+    (_, "Authorize") => json!({ "idTokenInfo": accepted }),
+    // The id token of a transaction is authorized by the event carrying it
+    (_, "TransactionEvent") if payload.get("idToken").is_some() => {
+      json!({ "idTokenInfo": accepted })
+    }
+    (_, "DataTransfer" | "SignCertificate" | "GetCertificateStatus") => accepted,
+    _ => json!({}),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn boot_notification_is_accepted_with_the_heartbeat_interval() {
+    let config = MockCsmsConfig {
+      heartbeat_interval: 30,
+      ..Default::default()
+    };
+    let frame = answer(
+      &config,
+      &OcppVersion::V1_6,
+      "1",
+      "BootNotification",
+      &json!({}),
+      || 1,
+    );
+
+    assert_eq!(frame[0], 3);
+    assert_eq!(frame[1], "1");
+    assert_eq!(frame[2]["status"], "Accepted");
+    assert_eq!(frame[2]["interval"], 30);
+  }
+
+  #[test]
+  fn start_transaction_gets_the_next_transaction_id() {
+    let config = MockCsmsConfig::default();
+    let frame = answer(
+      &config,
+      &OcppVersion::V1_6,
+      "1",
+      "StartTransaction",
+      &json!({}),
+      || 7,
+    );
+
+    assert_eq!(frame[2]["transactionId"], 7);
+    assert_eq!(frame[2]["idTagInfo"]["status"], "Accepted");
+  }
+
+  #[test]
+  fn authorize_answer_follows_the_ocpp_version() {
+    let config = MockCsmsConfig::default();
+    let v16 = answer(
+      &config,
+      &OcppVersion::V1_6,
+      "1",
+      "Authorize",
+      &json!({}),
+      || 1,
+    );
+    let v201 = answer(
+      &config,
+      &OcppVersion::V2_0_1,
+      "1",
+      "Authorize",
+      &json!({}),
+      || 1,
+    );
+
+    assert_eq!(v16[2], json!({ "idTagInfo": { "status": "Accepted" } }));
+    assert_eq!(v201[2], json!({ "idTokenInfo": { "status": "Accepted" } }));
+  }
+
+  #[test]
+  fn transaction_event_authorizes_its_id_token() {
+    let config = MockCsmsConfig::default();
+    let with_token = json!({ "idToken": { "idToken": "aabbcc", "type": "ISO14443" } });
+    let started = answer(
+      &config,
+      &OcppVersion::V2_0_1,
+      "1",
+      "TransactionEvent",
+      &with_token,
+      || 1,
+    );
+    let updated = answer(
+      &config,
+      &OcppVersion::V2_0_1,
+      "2",
+      "TransactionEvent",
+      &json!({}),
+      || 1,
+    );
+
+    assert_eq!(started[2]["idTokenInfo"]["status"], "Accepted");
+    assert_eq!(updated[2], json!({}));
+  }
+
+  #[test]
+  fn configured_response_replaces_the_default() {
+    let mut config = MockCsmsConfig::default();
+    config.responses.insert(
+      "Authorize".to_string(),
+      json!({ "idTagInfo": { "status": "Blocked" } }),
+    );
+    let frame = answer(
+      &config,
+      &OcppVersion::V1_6,
+      "1",
+      "Authorize",
+      &json!({}),
+      || 1,
+    );
+
+    assert_eq!(frame[2]["idTagInfo"]["status"], "Blocked");
+  }
+
+  #[test]
+  fn configured_call_error_refuses_the_call() {
+    let mut config = MockCsmsConfig::default();
+    config
+      .call_errors
+      .insert("DataTransfer".to_string(), "NotImplemented".to_string());
+    let frame = answer(
+      &config,
+      &OcppVersion::V1_6,
+      "1",
+      "DataTransfer",
+      &json!({}),
+      || 1,
+    );
+
+    assert_eq!(frame[0], 4);
+    assert_eq!(frame[2], "NotImplemented");
+  }
+}
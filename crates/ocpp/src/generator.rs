@@ -1,14 +1,21 @@
 use async_trait::async_trait;
+use common::shared_data::ChargePointSettings;
 use serde_json::Value;
 
-use crate::types::CommonConnectorStatusType;
+use crate::types::{
+  CommonCertificateStatus, CommonConfigurationStatus, CommonConnectorStatusType,
+  CommonCustomerInformationStatus, CommonFirmwareStatus, CommonLogStatus, CommonOcspRequestData,
+  CommonSecurityEvent, CommonSettingChange, CommonStopReason, CommonTransactionTrigger,
+  CommonTriggerMessageStatus, CommonUpdateFirmwareStatus, CommonUploadLogStatus, ConnectorFault,
+  InstalledCertificate,
+};
 
 pub trait MessageBuilder {
   fn to_call_frame(&self) -> Value;
 }
 
 #[async_trait]
-pub trait MessageGenerator: Send {
+pub trait MessageGenerator: Send + Sync {
   // 🔌 Core / Timing
   async fn heartbeat_interval(&self, value: u32);
   async fn connection_timeout(&self, value: u32);
@@ -55,16 +62,139 @@ pub trait MessageGenerator: Send {
   // 📊 Limits / Misc
   async fn get_configuration_max_keys(&self, value: u32);
 
+  // 🛡️ Security
+  async fn security_profile(&self, value: u32);
+
+  /// Current settings, with the values of the device model for OCPP 2.0.1
+  async fn settings(&self) -> ChargePointSettings;
+
   async fn boot_notification(&self) -> Value;
-  async fn heartbeat(&self) -> Value;
+  /// Frame of the `Heartbeat`, from a template
+  async fn heartbeat(&self) -> String;
   async fn authorize(&self, tag_id: Option<&str>) -> Value;
   async fn start_transaction(&self) -> Value;
-  async fn stop_transaction(&self) -> Value;
-  async fn status_notification(&self, status: CommonConnectorStatusType) -> Value;
+  async fn stop_transaction(&self, reason: CommonStopReason) -> Value;
+  async fn status_notification(
+    &self,
+    status: CommonConnectorStatusType,
+    fault: Option<&ConnectorFault>,
+  ) -> Value;
   async fn meter_values(&self) -> Value;
+  /// Transaction id and energy register (Wh) of the current session
+  async fn session(&self) -> (Option<i32>, f64);
   async fn diagnostics_status_notification(&self) -> Value;
-  async fn firmware_status_notification(&self) -> Value;
-  async fn data_transfer(&self) -> Value;
-  async fn change_configuration(&self) -> Value;
+  async fn firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    request_id: Option<i32>,
+  ) -> Value;
+  /// `DataTransfer` from the vendor of the charge point unless another one is given
+  async fn data_transfer(
+    &self,
+    vendor_id: Option<&str>,
+    message_id: Option<&str>,
+    data: Option<&str>,
+  ) -> Value;
+  /// Call of any charge point action with the payload sent as given, `None` for an unknown
+  /// action
+  async fn custom_call(&self, action: &str, payload: Value) -> Option<Value>;
+  async fn change_configuration(&self, msg_id: &str, status: CommonConfigurationStatus) -> Value;
+  async fn reset(&self, msg_id: &str) -> Value;
+  async fn update_firmware(&self, msg_id: &str, status: CommonUpdateFirmwareStatus) -> Value;
+
+  // 🧾 Transaction events
+  /// `TransactionEvent` for a connector change, `Null` when the transaction is not affected
+  async fn transaction_trigger(
+    &self,
+    trigger: CommonTransactionTrigger,
+    status: CommonConnectorStatusType,
+  ) -> Value;
+  /// While offline the transaction events are queued, those generated meanwhile are marked
+  /// as `offline`
+  async fn set_offline(&self, offline: bool);
+  /// Transaction messages left unconfirmed when the connection was lost, sent again
+  async fn queued_transaction_events(&self) -> Vec<Value>;
+  async fn get_transaction_status(&self, msg_id: &str, payload: Value) -> Value;
+
+  // 🔏 Signed firmware update
+  async fn signed_update_firmware(&self, msg_id: &str, status: CommonUpdateFirmwareStatus)
+  -> Value;
+  async fn signed_firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    request_id: i32,
+  ) -> Value;
+
+  // 🚨 Security events
+  async fn security_event_notification(
+    &self,
+    event: &CommonSecurityEvent,
+    tech_info: Option<&str>,
+  ) -> Value;
+
+  // 🔏 Certificate management
+  async fn sign_certificate(&self, csr: String) -> Value;
+  async fn certificate_signed(&self, msg_id: &str, status: CommonCertificateStatus) -> Value;
+  async fn install_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value;
+  async fn delete_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value;
+  async fn get_installed_certificate_ids(
+    &self,
+    msg_id: &str,
+    certificates: Vec<InstalledCertificate>,
+  ) -> Value;
+  async fn trigger_message(&self, msg_id: &str, status: CommonTriggerMessageStatus) -> Value;
+
+  // 🧩 Device model
+  async fn get_variables(&self, msg_id: &str, payload: Value) -> Value;
+  async fn set_variables(&self, msg_id: &str, payload: Value) -> (Value, Vec<CommonSettingChange>);
+  async fn get_base_report(&self, msg_id: &str, payload: Value) -> Vec<Value>;
+  async fn get_report(&self, msg_id: &str, payload: Value) -> Vec<Value>;
+
+  // 📡 Monitoring
+  async fn set_variable_monitoring(&self, msg_id: &str, payload: Value) -> Value;
+  async fn clear_variable_monitoring(&self, msg_id: &str, payload: Value) -> Value;
+  async fn set_monitoring_base(&self, msg_id: &str, payload: Value) -> Value;
+  async fn set_monitoring_level(&self, msg_id: &str, payload: Value) -> Value;
+  async fn get_monitoring_report(&self, msg_id: &str, payload: Value) -> Vec<Value>;
+  /// Whether variable monitors are set, the simulated values are only sampled then
+  async fn has_monitors(&self) -> bool;
+  /// `NotifyEvent` for the monitors tripped by the current simulated values
+  async fn monitoring_events(&self) -> Vec<Value>;
+
+  // ⚡ Smart charging
+  /// Response and the limit the charging schedule starts with, a limit of 0 suspends the EVSE
+  async fn set_charging_profile(&self, msg_id: &str, payload: Value) -> (Value, Option<f64>);
+  async fn clear_charging_profile(&self, msg_id: &str, payload: Value) -> Value;
+
+  // 🖥️ Display messages
+  async fn set_display_message(&self, msg_id: &str, payload: Value) -> Value;
+  /// Response followed by `NotifyDisplayMessages` with the matching messages
+  async fn get_display_messages(&self, msg_id: &str, payload: Value) -> Vec<Value>;
+  async fn clear_display_message(&self, msg_id: &str, payload: Value) -> Value;
+  async fn cost_updated(&self, msg_id: &str, payload: Value) -> Value;
+
+  // 🗂️ Logs and customer information
+  async fn get_log(&self, msg_id: &str, status: CommonLogStatus, filename: Option<String>)
+  -> Value;
+  async fn log_status_notification(&self, status: CommonUploadLogStatus, request_id: i32) -> Value;
+  async fn customer_information(
+    &self,
+    msg_id: &str,
+    status: CommonCustomerInformationStatus,
+  ) -> Value;
+  /// `NotifyCustomerInformation` parts of the report, the data of a part is limited in length
+  async fn notify_customer_information(&self, request_id: i32, data: &str) -> Vec<Value>;
+
+  // 🔌 ISO 15118 Plug & Charge
+  /// `Get15118EVCertificate` installing the contract certificate of the EV
+  async fn get_15118_ev_certificate(&self, schema_version: &str, exi_request: String) -> Value;
+  async fn get_certificate_status(&self, ocsp_request: CommonOcspRequestData) -> Value;
+  /// `Authorize` with the eMAID of the contract certificate, which identifies the session
+  async fn authorize_contract(
+    &self,
+    emaid: &str,
+    certificate_hash_data: Vec<CommonOcspRequestData>,
+  ) -> Value;
+
   fn next_id(&self) -> String;
 }
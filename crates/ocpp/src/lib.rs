@@ -1,11 +1,17 @@
 pub mod generator;
 pub mod handler;
 pub mod mock_data;
+pub mod signed_meter;
+pub mod template;
+#[cfg(test)]
+mod test_config;
 pub mod types;
 pub mod v1_6;
 pub mod v2_0_1;
 pub mod v2_1;
 
+use anyhow::Result;
+
 use crate::{generator::MessageGenerator, handler::MessageHandler};
 use common::{ChargePointConfig, OcppVersion, SharedData};
 
@@ -15,7 +21,7 @@ pub struct OcppSession {
 }
 
 impl OcppSession {
-  pub async fn new(ocpp_version: &OcppVersion, config: ChargePointConfig) -> Self {
+  pub async fn new(ocpp_version: &OcppVersion, config: ChargePointConfig) -> Result<Self> {
     match ocpp_version {
       #[cfg(feature = "ocpp1_6")]
       OcppVersion::V1_6 => {
@@ -25,11 +31,28 @@ impl OcppSession {
 
         let shared_data = SharedData::<OcppAction>::from_cp_config(&config).await;
 
-        Self {
-          generator: Box::new(V16MessageGenerator::new(config, shared_data.clone())),
+        Ok(Self {
+          generator: Box::new(V16MessageGenerator::new(config, shared_data.clone())?),
           handler: Box::new(V16MessageHandler::new(shared_data)),
-        }
+        })
+      }
+      #[cfg(feature = "ocpp2_0_1")]
+      OcppVersion::V2_0_1 => {
+        use crate::v2_0_1::{
+          generator::V201MessageGenerator, handler::V201MessageHandler, types::OcppAction,
+        };
+
+        let shared_data = SharedData::<OcppAction>::from_cp_config(&config).await;
+
+        let generator = V201MessageGenerator::new(config, shared_data.clone())?;
+        let display = generator.display();
+
+        Ok(Self {
+          generator: Box::new(generator),
+          handler: Box::new(V201MessageHandler::new(shared_data, display)),
+        })
       }
+      #[allow(unreachable_patterns)]
       _ => panic!("OCPP version not supported in this build"),
     }
   }
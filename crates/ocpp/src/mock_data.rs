@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
 use chrono::Utc;
+use common::{CurrentType, MeterConfig};
 use rand::Rng;
 use rand::seq::IndexedRandom;
 use rust_ocpp::v1_6::types::{
@@ -8,7 +9,12 @@ use rust_ocpp::v1_6::types::{
 };
 
 pub trait MeterValueMockData: Sized {
-  fn mock_data(meter_values_sampled_data: HashSet<String>) -> (Self, f64);
+  fn mock_data(
+    meter_config: &MeterConfig,
+    meter_values_sampled_data: &HashSet<String>,
+    reading: &MeterReading,
+    context: ReadingContext,
+  ) -> Self;
 }
 
 pub trait SampledValueMockData {
@@ -65,148 +71,314 @@ impl SampledValueMockData for SampledValue {
   }
 }
 
-impl MeterValueMockData for MeterValue {
-  fn mock_data(meter_values_sampled_data: HashSet<String>) -> (Self, f64) {
+/// All measurands in the order they are reported
+const MEASURANDS: [Measurand; 22] = [
+  Measurand::EnergyActiveImportRegister,
+  Measurand::EnergyActiveExportRegister,
+  Measurand::EnergyReactiveImportRegister,
+  Measurand::EnergyReactiveExportRegister,
+  Measurand::EnergyActiveImportInterval,
+  Measurand::EnergyActiveExportInterval,
+  Measurand::EnergyReactiveImportInterval,
+  Measurand::EnergyReactiveExportInterval,
+  Measurand::PowerActiveImport,
+  Measurand::PowerActiveExport,
+  Measurand::PowerReactiveImport,
+  Measurand::PowerReactiveExport,
+  Measurand::PowerOffered,
+  Measurand::PowerFactor,
+  Measurand::CurrentImport,
+  Measurand::CurrentExport,
+  Measurand::CurrentOffered,
+  Measurand::Voltage,
+  Measurand::Frequency,
+  Measurand::Temperature,
+  Measurand::SoC,
+  Measurand::Rpm,
+];
+
+/// Instantaneous values of the simulated meter, one entry per phase for AC
+pub struct MeterReading {
+  pub voltage: Vec<f64>,
+  pub current: Vec<f64>,
+  pub power_factor: f64,
+  pub frequency: f64,
+  pub temperature: f64,
+  pub fan_rpm: f64,
+  pub energy_wh: f64,
+  pub interval_wh: f64,
+  pub soc: f64,
+}
+
+impl MeterReading {
+  /// Samples the electrical values while energy is being delivered
+  pub fn sample(meter_config: &MeterConfig, soc: f64) -> Self {
     let mut rng = rand::rng();
-    let mut sampled_values = Vec::new();
-
-    // Realistic voltage ranges for each phase (in Volts)
-    let voltage_l1 = rng.random_range(220.0..240.0);
-    let voltage_l2 = rng.random_range(220.0..240.0);
-    let voltage_l3 = rng.random_range(220.0..240.0);
-
-    let current_l1 = rng.random_range(5.0..32.0);
-    let current_l2 = rng.random_range(5.0..32.0);
-    let current_l3 = rng.random_range(5.0..32.0);
-
-    // Calculate power for each phase (P = V * I) in kW
-    let power_l1 = (voltage_l1 * current_l1) / 1000.0;
-    let power_l2 = (voltage_l2 * current_l2) / 1000.0;
-    let power_l3 = (voltage_l3 * current_l3) / 1000.0;
-
-    let total_power_kw = power_l1 + power_l2 + power_l3;
-
-    // Energy register (cumulative, in Wh)
-    let energy_register = rng.random_range(1000.0..50000.0);
-
-    let context = ReadingContext::InterruptionBegin;
-    let format = ValueFormat::Raw;
-    let location = Location::Outlet;
-
-    if meter_values_sampled_data.contains("Current.Import") {
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::CurrentImport),
-        phase: Some(Phase::L1),
-        unit: Some(UnitOfMeasure::A),
-        value: format!("{:.3}", current_l1),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::CurrentImport),
-        phase: Some(Phase::L2),
-        unit: Some(UnitOfMeasure::A),
-        value: format!("{:.3}", current_l2),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::CurrentImport),
-        phase: Some(Phase::L3),
-        unit: Some(UnitOfMeasure::A),
-        value: format!("{:.3}", current_l3),
-      });
-    }
+    let phases = meter_config.phases() as usize;
 
-    if meter_values_sampled_data.contains("Energy.Active.Import.Register") {
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::EnergyActiveImportRegister),
-        phase: None,
-        unit: Some(UnitOfMeasure::Wh),
-        value: format!("{:.3}", energy_register),
-      });
-    }
+    let voltage = match meter_config.current_type {
+      CurrentType::Ac => (0..phases)
+        .map(|_| meter_config.voltage * rng.random_range(0.98..1.02))
+        .collect::<Vec<f64>>(),
+      // Battery voltage rises with the state of charge
+      CurrentType::Dc => vec![meter_config.voltage * (0.9 + soc / 500.0)],
+    };
+
+    // EVs taper the current above 80 % SoC
+    let taper = match meter_config.current_type {
+      CurrentType::Dc if soc > 80.0 => ((100.0 - soc) / 20.0).max(0.05),
+      _ => 1.0,
+    };
+
+    let max_current_by_power =
+      meter_config.max_power() / voltage.iter().sum::<f64>().max(f64::EPSILON);
+    let current = (0..phases)
+      .map(|_| {
+        meter_config.max_current.min(max_current_by_power) * taper * rng.random_range(0.9..1.0)
+      })
+      .collect::<Vec<f64>>();
+
+    let power_factor = match meter_config.current_type {
+      CurrentType::Ac => rng.random_range(0.95..0.99),
+      CurrentType::Dc => 1.0,
+    };
+
+    let mut reading = Self {
+      voltage,
+      current,
+      power_factor,
+      frequency: meter_config.frequency + rng.random_range(-0.05..0.05),
+      temperature: 0.0,
+      fan_rpm: 0.0,
+      energy_wh: 0.0,
+      interval_wh: 0.0,
+      soc,
+    };
+
+    let load = reading.power_active_w() / meter_config.max_power().max(f64::EPSILON);
+    reading.temperature = meter_config.temperature + load * 15.0 + rng.random_range(-0.5..0.5);
+    reading.fan_rpm = load * 3000.0;
+    reading
+  }
+
+  pub fn power_active_w(&self) -> f64 {
+    self.phase_power_active_w().iter().sum()
+  }
+
+  pub fn phase_power_active_w(&self) -> Vec<f64> {
+    self
+      .voltage
+      .iter()
+      .zip(&self.current)
+      .map(|(voltage, current)| voltage * current * self.power_factor)
+      .collect()
+  }
 
-    if meter_values_sampled_data.contains("Power.Active.Import") {
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::PowerActiveImport),
-        phase: Some(Phase::L1N),
-        unit: Some(UnitOfMeasure::Kw),
-        value: format!("{:.3}", power_l1),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::PowerActiveImport),
-        phase: Some(Phase::L2N),
-        unit: Some(UnitOfMeasure::Kw),
-        value: format!("{:.3}", power_l2),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::PowerActiveImport),
-        phase: Some(Phase::L3N),
-        unit: Some(UnitOfMeasure::Kw),
-        value: format!("{:.3}", power_l3),
-      });
+  pub fn power_reactive_var(&self) -> f64 {
+    let apparent: f64 = self
+      .voltage
+      .iter()
+      .zip(&self.current)
+      .map(|(voltage, current)| voltage * current)
+      .sum();
+    (apparent.powi(2) - self.power_active_w().powi(2))
+      .max(0.0)
+      .sqrt()
+  }
+}
+
+struct SampledValueBuilder<'a> {
+  meter_config: &'a MeterConfig,
+  context: ReadingContext,
+  sampled_values: Vec<SampledValue>,
+}
+
+impl SampledValueBuilder<'_> {
+  fn push(
+    &mut self,
+    measurand: Measurand,
+    location: Location,
+    phase: Option<Phase>,
+    unit: Option<UnitOfMeasure>,
+    value: f64,
+  ) {
+    self.sampled_values.push(SampledValue {
+      context: Some(self.context.clone()),
+      format: Some(ValueFormat::Raw),
+      location: Some(location),
+      measurand: Some(measurand),
+      phase,
+      unit,
+      value: format!("{:.3}", value),
+    });
+  }
+
+  /// Pushes one value per phase for AC or a single value without a phase for DC
+  fn push_phases(
+    &mut self,
+    measurand: Measurand,
+    phases: [Phase; 3],
+    unit: UnitOfMeasure,
+    values: &[f64],
+  ) {
+    match self.meter_config.current_type {
+      CurrentType::Ac => {
+        for (phase, value) in phases.into_iter().zip(values) {
+          self.push(
+            measurand.clone(),
+            Location::Outlet,
+            Some(phase),
+            Some(unit.clone()),
+            *value,
+          );
+        }
+      }
+      CurrentType::Dc => self.push(
+        measurand,
+        Location::Outlet,
+        None,
+        Some(unit),
+        values.iter().sum(),
+      ),
     }
+  }
+}
+
+impl MeterValueMockData for MeterValue {
+  fn mock_data(
+    meter_config: &MeterConfig,
+    meter_values_sampled_data: &HashSet<String>,
+    reading: &MeterReading,
+    context: ReadingContext,
+  ) -> Self {
+    let mut builder = SampledValueBuilder {
+      meter_config,
+      context,
+      sampled_values: Vec::new(),
+    };
+
+    let current_phases = [Phase::L1, Phase::L2, Phase::L3];
+    let voltage_phases = [Phase::L1N, Phase::L2N, Phase::L3N];
 
-    if meter_values_sampled_data.contains("Voltage") {
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::Voltage),
-        phase: Some(Phase::L1N),
-        unit: Some(UnitOfMeasure::V),
-        value: format!("{:.3}", voltage_l1),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::Voltage),
-        phase: Some(Phase::L2N),
-        unit: Some(UnitOfMeasure::V),
-        value: format!("{:.3}", voltage_l2),
-      });
-
-      sampled_values.push(SampledValue {
-        context: Some(context.clone()),
-        format: Some(format.clone()),
-        location: Some(location.clone()),
-        measurand: Some(Measurand::Voltage),
-        phase: Some(Phase::L3N),
-        unit: Some(UnitOfMeasure::V),
-        value: format!("{:.3}", voltage_l3),
-      });
+    for measurand in MEASURANDS {
+      // Measurands are configured by their OCPP names, e.g. `Energy.Active.Import.Register`
+      let enabled = serde_json::to_value(&measurand)
+        .ok()
+        .and_then(|name| {
+          name
+            .as_str()
+            .map(|name| meter_values_sampled_data.contains(name))
+        })
+        .unwrap_or(false);
+
+      if !enabled {
+        continue;
+      }
+
+      let outlet = Location::Outlet;
+
+      match measurand {
+        Measurand::EnergyActiveImportRegister => builder.push(
+          measurand,
+          outlet,
+          None,
+          Some(UnitOfMeasure::Wh),
+          reading.energy_wh,
+        ),
+        Measurand::EnergyActiveImportInterval => builder.push(
+          measurand,
+          outlet,
+          None,
+          Some(UnitOfMeasure::Wh),
+          reading.interval_wh,
+        ),
+        Measurand::EnergyActiveExportRegister | Measurand::EnergyActiveExportInterval => {
+          builder.push(measurand, outlet, None, Some(UnitOfMeasure::Wh), 0.0)
+        }
+        Measurand::EnergyReactiveImportRegister
+        | Measurand::EnergyReactiveImportInterval
+        | Measurand::EnergyReactiveExportRegister
+        | Measurand::EnergyReactiveExportInterval => {
+          builder.push(measurand, outlet, None, Some(UnitOfMeasure::Varh), 0.0)
+        }
+        Measurand::PowerActiveImport => builder.push_phases(
+          measurand,
+          current_phases.clone(),
+          UnitOfMeasure::W,
+          &reading.phase_power_active_w(),
+        ),
+        Measurand::PowerActiveExport => {
+          builder.push(measurand, outlet, None, Some(UnitOfMeasure::W), 0.0)
+        }
+        Measurand::PowerReactiveImport => builder.push(
+          measurand,
+          outlet,
+          None,
+          Some(UnitOfMeasure::Var),
+          reading.power_reactive_var(),
+        ),
+        Measurand::PowerReactiveExport => {
+          builder.push(measurand, outlet, None, Some(UnitOfMeasure::Var), 0.0)
+        }
+        Measurand::PowerOffered => builder.push(
+          measurand,
+          outlet,
+          None,
+          Some(UnitOfMeasure::W),
+          meter_config.max_power(),
+        ),
+        // Power factor and frequency only exist on AC and have no unit of measure in OCPP 1.6
+        Measurand::PowerFactor if meter_config.current_type == CurrentType::Ac => {
+          builder.push(measurand, outlet, None, None, reading.power_factor)
+        }
+        Measurand::Frequency if meter_config.current_type == CurrentType::Ac => {
+          builder.push(measurand, outlet, None, None, reading.frequency)
+        }
+        Measurand::CurrentImport => builder.push_phases(
+          measurand,
+          current_phases.clone(),
+          UnitOfMeasure::A,
+          &reading.current,
+        ),
+        Measurand::CurrentExport => {
+          builder.push(measurand, outlet, None, Some(UnitOfMeasure::A), 0.0)
+        }
+        Measurand::CurrentOffered => builder.push(
+          measurand,
+          outlet,
+          None,
+          Some(UnitOfMeasure::A),
+          meter_config.max_current,
+        ),
+        Measurand::Voltage => builder.push_phases(
+          measurand,
+          voltage_phases.clone(),
+          UnitOfMeasure::V,
+          &reading.voltage,
+        ),
+        Measurand::Temperature => builder.push(
+          measurand,
+          Location::Body,
+          None,
+          Some(UnitOfMeasure::Celsius),
+          reading.temperature,
+        ),
+        // Only a DC charger communicates with the EV battery management
+        Measurand::SoC if meter_config.current_type == CurrentType::Dc => builder.push(
+          measurand,
+          Location::Ev,
+          None,
+          Some(UnitOfMeasure::Percent),
+          reading.soc,
+        ),
+        Measurand::Rpm => builder.push(measurand, Location::Body, None, None, reading.fan_rpm),
+        _ => {}
+      }
     }
 
-    (
-      MeterValue {
-        timestamp: Utc::now(),
-        sampled_value: sampled_values,
-      },
-      total_power_kw,
-    )
+    MeterValue {
+      timestamp: Utc::now(),
+      sampled_value: builder.sampled_values,
+    }
   }
 }
@@ -0,0 +1,351 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{fs, io::Write, path::Path, sync::Mutex};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use common::{ChargePointConfig, CurrentType, SignedMeterConfig, SignedMeterFormat};
+use p256::{
+  ecdsa::{Signature, SigningKey, signature::Signer},
+  pkcs8::{DecodePrivateKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+};
+use serde_json::json;
+use tracing::info;
+
+const OCMF_VERSION: &str = "1.0";
+pub const OCMF_SIGNATURE_ALGORITHM: &str = "ECDSA-secp256r1-SHA256";
+
+/// Position of a signed reading within a transaction
+#[derive(Debug, Clone, Copy)]
+pub enum SignedReadingType {
+  Begin,
+  Charging,
+  End,
+}
+
+impl SignedReadingType {
+  fn ocmf_code(&self) -> &'static str {
+    match self {
+      Self::Begin => "B",
+      Self::Charging => "C",
+      Self::End => "E",
+    }
+  }
+}
+
+#[derive(Default)]
+struct SignedTransaction {
+  id_tag: String,
+  begin: Option<String>,
+}
+
+/// Produces signed meter readings (OCMF) with a per charge point ECDSA key
+pub struct MeterSigner {
+  format: SignedMeterFormat,
+  signing_key: SigningKey,
+  gateway_id: String,
+  meter_vendor: String,
+  meter_model: String,
+  meter_serial: String,
+  current_type: CurrentType,
+  pagination: Mutex<u32>,
+  transaction: Mutex<SignedTransaction>,
+}
+
+impl MeterSigner {
+  pub fn from_config(cp_config: &ChargePointConfig) -> Result<Option<Self>> {
+    let Some(signed_config) = &cp_config.signed_meter_values else {
+      return Ok(None);
+    };
+
+    let signer = Self {
+      format: signed_config.format.clone(),
+      signing_key: Self::load_signing_key(signed_config)?,
+      gateway_id: cp_config.id.clone(),
+      meter_vendor: cp_config.vendor.clone(),
+      meter_model: cp_config.model.clone(),
+      meter_serial: signed_config
+        .meter_serial
+        .clone()
+        .unwrap_or_else(|| format!("{}-meter", cp_config.id)),
+      current_type: cp_config.meter.current_type,
+      pagination: Mutex::new(0),
+      transaction: Mutex::new(SignedTransaction::default()),
+    };
+
+    if let Some(path) = &signed_config.public_key_path {
+      fs::write(path, signer.public_key_pem()?)
+        .with_context(|| format!("Failed to export public key: {}", path.display()))?;
+    }
+
+    info!(
+      target: "simulator",
+      "{} [{}] signing meter values, public key {}",
+      cp_config.id,
+      signer.format,
+      signer.public_key_hex()?
+    );
+
+    Ok(Some(signer))
+  }
+
+  fn load_signing_key(signed_config: &SignedMeterConfig) -> Result<SigningKey> {
+    match &signed_config.private_key_path {
+      Some(path) if path.exists() => {
+        let pem = fs::read_to_string(path)
+          .with_context(|| format!("Failed to read private key: {}", path.display()))?;
+        SigningKey::from_pkcs8_pem(&pem)
+          .with_context(|| format!("Invalid secp256r1 private key: {}", path.display()))
+      }
+      Some(path) => {
+        let signing_key = Self::generate_signing_key();
+        let pem = signing_key.to_pkcs8_pem(LineEnding::LF)?;
+        Self::store_private_key(path, pem.as_bytes())
+          .with_context(|| format!("Failed to store private key: {}", path.display()))?;
+        Ok(signing_key)
+      }
+      None => Ok(Self::generate_signing_key()),
+    }
+  }
+
+  /// Creates the key file readable by its owner only
+  fn store_private_key(path: &Path, pem: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)?.write_all(pem)
+  }
+
+  fn generate_signing_key() -> SigningKey {
+    loop {
+      // A random scalar is out of range with negligible probability
+      if let Ok(signing_key) = SigningKey::from_slice(&rand::random::<[u8; 32]>()) {
+        return signing_key;
+      }
+    }
+  }
+
+  pub fn public_key_pem(&self) -> Result<String> {
+    Ok(
+      self
+        .signing_key
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)?,
+    )
+  }
+
+  /// DER encoded public key as hex, the representation OCMF verifiers expect
+  pub fn public_key_hex(&self) -> Result<String> {
+    let der = self.signing_key.verifying_key().to_public_key_der()?;
+    Ok(hex::encode_upper(der.as_bytes()))
+  }
+
+  /// Signs the meter reading at the start of a transaction
+  pub fn begin(&self, id_tag: &str, energy_wh: f64) -> String {
+    let signed = self.sign_reading(id_tag, SignedReadingType::Begin, energy_wh);
+    *self.transaction.lock().unwrap() = SignedTransaction {
+      id_tag: id_tag.to_string(),
+      begin: Some(signed.clone()),
+    };
+    signed
+  }
+
+  /// Signs an intermediate reading of the running transaction
+  pub fn sample(&self, energy_wh: f64) -> String {
+    let id_tag = self.transaction.lock().unwrap().id_tag.clone();
+    self.sign_reading(&id_tag, SignedReadingType::Charging, energy_wh)
+  }
+
+  /// Signs the final reading and returns it together with the begin reading
+  pub fn end(&self, energy_wh: f64) -> (Option<String>, String) {
+    let transaction = std::mem::take(&mut *self.transaction.lock().unwrap());
+    let end = self.sign_reading(&transaction.id_tag, SignedReadingType::End, energy_wh);
+    (transaction.begin, end)
+  }
+
+  fn sign_reading(&self, id_tag: &str, reading_type: SignedReadingType, energy_wh: f64) -> String {
+    match self.format {
+      SignedMeterFormat::Ocmf => self.ocmf(id_tag, reading_type, energy_wh),
+    }
+  }
+
+  fn next_pagination(&self) -> u32 {
+    let mut pagination = self.pagination.lock().unwrap();
+    *pagination += 1;
+    *pagination
+  }
+
+  /// `OCMF|<payload>|<signature>` as specified by the S.A.F.E. e.V. Open Charge Metering Format
+  fn ocmf(&self, id_tag: &str, reading_type: SignedReadingType, energy_wh: f64) -> String {
+    let payload = json!({
+      "FV": OCMF_VERSION,
+      "GI": self.gateway_id,
+      "GS": self.gateway_id,
+      "PG": format!("T{}", self.next_pagination()),
+      "MV": self.meter_vendor,
+      "MM": self.meter_model,
+      "MS": self.meter_serial,
+      "IS": !id_tag.is_empty(),
+      "IL": "NONE",
+      "IT": "ISO14443",
+      "ID": id_tag,
+      "RD": [{
+        "TM": Utc::now().format("%Y-%m-%dT%H:%M:%S,%3f%z S").to_string(),
+        "TX": reading_type.ocmf_code(),
+        // Wh, as the unsigned Energy.Active.Import.Register next to it
+        "RV": energy_wh.round(),
+        "RI": "1-b:1.8.0",
+        "RU": "Wh",
+        "RT": self.current_type.to_string(),
+        "ST": "G",
+      }],
+    })
+    .to_string();
+
+    let signature: Signature = self.signing_key.sign(payload.as_bytes());
+    let signature = json!({
+      "SA": OCMF_SIGNATURE_ALGORITHM,
+      "SD": hex::encode_upper(signature.to_der().as_bytes()),
+    });
+
+    format!("OCMF|{payload}|{signature}")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use p256::{
+    ecdsa::{VerifyingKey, signature::Verifier},
+    pkcs8::DecodePublicKey,
+  };
+  use serde_json::Value;
+
+  use super::*;
+
+  fn signer(public_key_path: &Path) -> MeterSigner {
+    let config = config(json!({ "format": "OCMF", "public_key_path": public_key_path }));
+    MeterSigner::from_config(&config).unwrap().unwrap()
+  }
+
+  fn config(signed_meter_values: Value) -> ChargePointConfig {
+    serde_json::from_value(json!({
+      "id": "CP1",
+      "model": "test-model",
+      "vendor": "test-vendor",
+      "auth_header": "",
+      "boot_delay_interval": 0,
+      "heartbeat_interval": 60,
+      "txn_meter_values_interval": 10,
+      "txn_meter_values_max_count": 10,
+      "status_interval": 60,
+      "start_tx_after": 5,
+      "stop_tx_after": 35,
+      "id_tag": "TAG1",
+      "signed_meter_values": signed_meter_values,
+    }))
+    .unwrap()
+  }
+
+  /// Checks the `OCMF|<payload>|<signature>` signature with the key, returns the payload
+  fn verify(verifying_key: &VerifyingKey, signed_data: &str) -> Value {
+    let (payload, signature) = signed_data
+      .strip_prefix("OCMF|")
+      .and_then(|data| data.rsplit_once('|'))
+      .expect("OCMF|<payload>|<signature>");
+    let signature: Value = serde_json::from_str(signature).unwrap();
+    assert_eq!(signature["SA"], OCMF_SIGNATURE_ALGORITHM);
+
+    let der = hex::decode(signature["SD"].as_str().unwrap()).unwrap();
+    verifying_key
+      .verify(payload.as_bytes(), &Signature::from_der(&der).unwrap())
+      .expect("signature verifies with the exported public key");
+    serde_json::from_str(payload).unwrap()
+  }
+
+  #[test]
+  fn readings_verify_with_the_exported_public_key() {
+    let public_key_path =
+      std::env::temp_dir().join(format!("signed-meter-{}.pem", rand::random::<u64>()));
+    let signer = signer(&public_key_path);
+    let pem = fs::read_to_string(&public_key_path).unwrap();
+    fs::remove_file(&public_key_path).unwrap();
+
+    let verifying_key = VerifyingKey::from_public_key_pem(&pem).unwrap();
+    let der = hex::decode(signer.public_key_hex().unwrap()).unwrap();
+    assert_eq!(
+      VerifyingKey::from_public_key_der(&der).unwrap(),
+      verifying_key
+    );
+
+    let begin = verify(&verifying_key, &signer.begin("TAG1", 1_000.0));
+    let sample = verify(&verifying_key, &signer.sample(1_500.0));
+    let (begin_again, end) = signer.end(2_000.0);
+    let end = verify(&verifying_key, &end);
+    verify(&verifying_key, &begin_again.unwrap());
+
+    for (payload, code, wh) in [
+      (&begin, "B", 1_000.0),
+      (&sample, "C", 1_500.0),
+      (&end, "E", 2_000.0),
+    ] {
+      assert_eq!(payload["ID"], "TAG1");
+      assert_eq!(payload["RD"][0]["TX"], code);
+      assert_eq!(payload["RD"][0]["RV"], wh);
+      assert_eq!(payload["RD"][0]["RU"], "Wh");
+    }
+    assert_eq!(begin["PG"], "T1");
+    assert_eq!(end["PG"], "T3");
+  }
+
+  #[test]
+  fn tampered_reading_does_not_verify() {
+    let public_key_path =
+      std::env::temp_dir().join(format!("signed-meter-{}.pem", rand::random::<u64>()));
+    let signer = signer(&public_key_path);
+    fs::remove_file(&public_key_path).unwrap();
+
+    let signed_data = signer
+      .begin("TAG1", 1_000.0)
+      .replace("\"RV\":1000.0", "\"RV\":9000.0");
+    let (payload, signature) = signed_data
+      .strip_prefix("OCMF|")
+      .and_then(|data| data.rsplit_once('|'))
+      .unwrap();
+    let signature: Value = serde_json::from_str(signature).unwrap();
+    let der = hex::decode(signature["SD"].as_str().unwrap()).unwrap();
+
+    assert!(payload.contains("\"RV\":9000.0"));
+    assert!(
+      signer
+        .signing_key
+        .verifying_key()
+        .verify(payload.as_bytes(), &Signature::from_der(&der).unwrap())
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn generated_private_key_is_stored_for_the_owner_and_reused() {
+    let private_key_path =
+      std::env::temp_dir().join(format!("signed-meter-{}.key.pem", rand::random::<u64>()));
+    let config = config(json!({ "format": "OCMF", "private_key_path": private_key_path }));
+    let generated = MeterSigner::from_config(&config).unwrap().unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(&private_key_path)
+        .unwrap()
+        .permissions()
+        .mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let loaded = MeterSigner::from_config(&config).unwrap().unwrap();
+    fs::remove_file(&private_key_path).unwrap();
+    assert_eq!(
+      loaded.public_key_hex().unwrap(),
+      generated.public_key_hex().unwrap()
+    );
+  }
+}
@@ -0,0 +1,56 @@
+//! Calls serialized once for all charge points, only the message id is filled in per call
+
+use serde::Serialize;
+
+/// Call of an action whose payload never changes, e.g. `Heartbeat`
+pub struct CallTemplate {
+  /// Frame after the message id
+  tail: String,
+}
+
+impl CallTemplate {
+  pub fn new(action: impl Serialize, payload: impl Serialize) -> Self {
+    Self {
+      tail: format!(
+        ",{},{}]",
+        serde_json::to_string(&action).unwrap_or_default(),
+        serde_json::to_string(&payload).unwrap_or_default()
+      ),
+    }
+  }
+
+  /// Frame of the call, the message id is a generated UUID and needs no escaping
+  pub fn render(&self, msg_id: &str) -> String {
+    format!("[2,\"{msg_id}\"{}", self.tail)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::{Value, json};
+
+  use super::*;
+
+  #[test]
+  fn render_fills_in_the_message_id() {
+    let template = CallTemplate::new("Heartbeat", json!({}));
+
+    let frame: Value = serde_json::from_str(&template.render("1234")).unwrap();
+
+    assert_eq!(frame, json!([2, "1234", "Heartbeat", {}]));
+  }
+
+  #[test]
+  fn render_keeps_the_payload() {
+    let payload =
+      json!({ "reason": "PowerUp", "chargingStation": { "model": "a \"quoted\" model" } });
+    let template = CallTemplate::new("BootNotification", &payload);
+
+    let first: Value = serde_json::from_str(&template.render("a")).unwrap();
+    let second: Value = serde_json::from_str(&template.render("b")).unwrap();
+
+    assert_eq!(first, json!([2, "a", "BootNotification", payload]));
+    assert_eq!(second[1], "b");
+    assert_eq!(second[3], payload);
+  }
+}
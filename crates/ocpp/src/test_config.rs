@@ -0,0 +1,26 @@
+//! Configuration of the charge points in the unit tests
+
+use common::ChargePointConfig;
+use serde_json::{Value, json};
+
+/// Charge point `CP1` with the fields of `overrides` replaced
+pub fn charge_point_config(overrides: Value) -> ChargePointConfig {
+  let mut config = json!({
+    "id": "CP1",
+    "model": "test-model",
+    "vendor": "test-vendor",
+    "auth_header": "",
+    "boot_delay_interval": 0,
+    "heartbeat_interval": 60,
+    "txn_meter_values_interval": 10,
+    "txn_meter_values_max_count": 0,
+    "status_interval": 0,
+    "start_tx_after": 5,
+    "stop_tx_after": 35,
+    "id_tag": "TESTTAG",
+  });
+  if let (Some(config), Some(overrides)) = (config.as_object_mut(), overrides.as_object()) {
+    config.extend(overrides.clone());
+  }
+  serde_json::from_value(config).expect("Invalid charge point config")
+}
@@ -1,9 +1,14 @@
+use common::{ChargePointErrorCode, FaultConfig};
 use rust_ocpp::{
-  v1_6::types::ChargePointStatus,
-  v2_0_1::enumerations::connector_status_enum_type::ConnectorStatusEnumType,
+  v1_6::types::{ChargePointStatus, ConfigurationStatus, FirmwareStatus, Reason},
+  v2_0_1::enumerations::{
+    connector_status_enum_type::ConnectorStatusEnumType,
+    firmware_status_enum_type::FirmwareStatusEnumType,
+    update_firmware_status_enum_type::UpdateFirmwareStatusEnumType,
+  },
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AuthorizationStatus {
   Accepted,
   Blocked,
@@ -18,13 +23,22 @@ pub enum CommonOcppResponse {
   // BootNotification,
   // Heartbeat,
   Authorize {
-    // id_tag: String,
+    id_tag: String,
     status: AuthorizationStatus,
+    parent_id_tag: Option<String>,
   },
   StartTransaction {
     transaction_id: i32,
   },
   StopTransaction,
+  /// OCPP 2.0.1: answer to `Get15118EVCertificate`, the EXI response is not decoded
+  Iso15118EVCertificate {
+    accepted: bool,
+  },
+  /// OCPP 2.0.1: answer to `GetCertificateStatus`
+  CertificateStatus {
+    accepted: bool,
+  },
   // StatusNotification,
   // MeterValues,
   // DiagnosticsStatusNotification,
@@ -51,7 +65,7 @@ pub enum CommonOcppResponse {
   // SendLocalList,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CommonConnectorStatusType {
   Available,
   /// When a Connector becomes no longer available for a new user but there is no ongoing Transaction (yet). Typically a Connector is in preparing state when a user presents a tag, inserts a cable or a vehicle occupies the parking bay 6 (Operative)
@@ -72,6 +86,151 @@ pub enum CommonConnectorStatusType {
   Faulted,
 }
 
+/// Error reported with a connector status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectorFault {
+  pub error_code: ChargePointErrorCode,
+  pub vendor_error_code: Option<String>,
+  pub info: Option<String>,
+}
+
+impl From<&FaultConfig> for ConnectorFault {
+  fn from(value: &FaultConfig) -> Self {
+    Self {
+      error_code: value.error_code,
+      vendor_error_code: value.vendor_error_code.clone(),
+      info: value.info.clone(),
+    }
+  }
+}
+
+/// Why a transaction was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonStopReason {
+  /// Stopped locally, e.g. by presenting the tag again or by reaching a limit
+  Local,
+  /// Stopped by a remote stop command from the CSMS
+  Remote,
+  /// The cable was unplugged from the EV
+  EVDisconnected,
+  /// The charge point detected an error
+  Other,
+  /// Hard reset requested by the CSMS
+  HardReset,
+  /// Soft reset requested by the CSMS
+  SoftReset,
+  /// The charge point reboots on its own, e.g. to install a firmware
+  Reboot,
+}
+
+impl From<CommonStopReason> for Reason {
+  fn from(value: CommonStopReason) -> Self {
+    match value {
+      CommonStopReason::Local => Reason::Local,
+      CommonStopReason::Remote => Reason::Remote,
+      CommonStopReason::EVDisconnected => Reason::EVDisconnected,
+      CommonStopReason::Other => Reason::Other,
+      CommonStopReason::HardReset => Reason::HardReset,
+      CommonStopReason::SoftReset => Reason::SoftReset,
+      CommonStopReason::Reboot => Reason::Reboot,
+    }
+  }
+}
+
+/// Answer to a configuration change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonConfigurationStatus {
+  Accepted,
+  Rejected,
+  RebootRequired,
+  NotSupported,
+}
+
+impl From<CommonConfigurationStatus> for ConfigurationStatus {
+  fn from(value: CommonConfigurationStatus) -> Self {
+    match value {
+      CommonConfigurationStatus::Accepted => ConfigurationStatus::Accepted,
+      CommonConfigurationStatus::Rejected => ConfigurationStatus::Rejected,
+      CommonConfigurationStatus::RebootRequired => ConfigurationStatus::RebootRequired,
+      CommonConfigurationStatus::NotSupported => ConfigurationStatus::NotSupported,
+    }
+  }
+}
+
+/// Setting changed through the device model that the charge point applies at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonSettingChange {
+  HeartbeatInterval(u32),
+  MeterValueSampleInterval(u32),
+  ConnectionTimeOut(u32),
+  WebSocketPingInterval(u32),
+  StopTransactionOnEVSideDisconnect(bool),
+}
+
+/// Connector change that may start, update or end an OCPP 2.0.1 transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonTransactionTrigger {
+  /// The cable is plugged into the EV
+  CablePluggedIn,
+  /// The driver presented an accepted tag
+  Authorized,
+  /// The EV or the EVSE suspended or resumed charging
+  ChargingStateChanged,
+  /// The cable is unplugged from the EV
+  EVDeparted,
+  /// `EVConnectionTimeOut` expired before charging started
+  EVConnectTimeout,
+}
+
+/// Kind of an installed certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommonCertificateUse {
+  /// `CentralSystemRootCertificate` in 1.6, `CSMSRootCertificate` in 2.0.1
+  CentralSystemRoot,
+  ManufacturerRoot,
+  V2GRoot,
+  MORoot,
+  /// The V2G certificate of the charge point with its sub CAs (2.0.1)
+  V2GCertificateChain,
+}
+
+/// Identifies a certificate, the hashes are SHA-256
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommonCertificateHashData {
+  pub issuer_name_hash: String,
+  pub issuer_key_hash: String,
+  pub serial_number: String,
+}
+
+/// Certificate whose revocation status is checked with its OCSP responder
+#[derive(Debug, Clone)]
+pub struct CommonOcspRequestData {
+  pub hash_data: CommonCertificateHashData,
+  pub responder_url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstalledCertificate {
+  pub certificate_type: CommonCertificateUse,
+  pub hash_data: CommonCertificateHashData,
+}
+
+/// Answer to a certificate management request, mapped to the closest status of each message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonCertificateStatus {
+  Accepted,
+  Rejected,
+  Failed,
+  NotFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonTriggerMessageStatus {
+  Accepted,
+  Rejected,
+  NotImplemented,
+}
+
 impl From<CommonConnectorStatusType> for ChargePointStatus {
   fn from(value: CommonConnectorStatusType) -> Self {
     match value {
@@ -103,3 +262,164 @@ impl From<CommonConnectorStatusType> for ConnectorStatusEnumType {
     }
   }
 }
+
+/// Progress of a firmware update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonFirmwareStatus {
+  Downloading,
+  Downloaded,
+  DownloadFailed,
+  Installing,
+  Installed,
+  InstallationFailed,
+  /// Only reported for signed firmware updates
+  SignatureVerified,
+  InvalidSignature,
+}
+
+/// The plain 1.6 `FirmwareStatusNotification` knows no signature states
+impl From<CommonFirmwareStatus> for FirmwareStatus {
+  fn from(value: CommonFirmwareStatus) -> Self {
+    match value {
+      CommonFirmwareStatus::SignatureVerified => FirmwareStatus::Downloaded,
+      CommonFirmwareStatus::InvalidSignature => FirmwareStatus::InstallationFailed,
+      CommonFirmwareStatus::Downloading => FirmwareStatus::Downloading,
+      CommonFirmwareStatus::Downloaded => FirmwareStatus::Downloaded,
+      CommonFirmwareStatus::DownloadFailed => FirmwareStatus::DownloadFailed,
+      CommonFirmwareStatus::Installing => FirmwareStatus::Installing,
+      CommonFirmwareStatus::Installed => FirmwareStatus::Installed,
+      CommonFirmwareStatus::InstallationFailed => FirmwareStatus::InstallationFailed,
+    }
+  }
+}
+
+impl From<CommonFirmwareStatus> for FirmwareStatusEnumType {
+  fn from(value: CommonFirmwareStatus) -> Self {
+    match value {
+      CommonFirmwareStatus::Downloading => FirmwareStatusEnumType::Downloading,
+      CommonFirmwareStatus::Downloaded => FirmwareStatusEnumType::Downloaded,
+      CommonFirmwareStatus::DownloadFailed => FirmwareStatusEnumType::DownloadFailed,
+      CommonFirmwareStatus::Installing => FirmwareStatusEnumType::Installing,
+      CommonFirmwareStatus::Installed => FirmwareStatusEnumType::Installed,
+      CommonFirmwareStatus::InstallationFailed => FirmwareStatusEnumType::InstallationFailed,
+      CommonFirmwareStatus::SignatureVerified => FirmwareStatusEnumType::SignatureVerified,
+      CommonFirmwareStatus::InvalidSignature => FirmwareStatusEnumType::InvalidSignature,
+    }
+  }
+}
+
+/// Answer to a firmware update request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonUpdateFirmwareStatus {
+  Accepted,
+  Rejected,
+  InvalidCertificate,
+}
+
+impl From<CommonUpdateFirmwareStatus> for UpdateFirmwareStatusEnumType {
+  fn from(value: CommonUpdateFirmwareStatus) -> Self {
+    match value {
+      CommonUpdateFirmwareStatus::Accepted => UpdateFirmwareStatusEnumType::Accepted,
+      CommonUpdateFirmwareStatus::Rejected => UpdateFirmwareStatusEnumType::Rejected,
+      CommonUpdateFirmwareStatus::InvalidCertificate => {
+        UpdateFirmwareStatusEnumType::InvalidCertificate
+      }
+    }
+  }
+}
+
+/// Answer to a log upload request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonLogStatus {
+  Accepted,
+  Rejected,
+  /// Accepted, canceling the upload in progress
+  AcceptedCanceled,
+}
+
+/// Progress of a log upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonUploadLogStatus {
+  Uploading,
+  Uploaded,
+  UploadFailure,
+  /// The remote location is not a valid URL
+  BadMessage,
+  /// The server refused the upload
+  PermissionDenied,
+  /// Canceled by a newer request
+  AcceptedCanceled,
+}
+
+/// Answer to a customer information request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonCustomerInformationStatus {
+  Accepted,
+  Rejected,
+  /// No customer is identified
+  Invalid,
+}
+
+/// Event reported with a `SecurityEventNotification`, named after the 1.6 Security Extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommonSecurityEvent {
+  FirmwareUpdated,
+  FailedToAuthenticateAtCentralSystem,
+  StartupOfTheDevice,
+  ResetOrReboot,
+  ReconfigurationOfSecurityParameters,
+  InvalidFirmwareSignature,
+  InvalidFirmwareSigningCertificate,
+  InvalidCentralSystemCertificate,
+  InvalidTLSVersion,
+  /// Any other event, sent with its name as is
+  Other(String),
+}
+
+impl CommonSecurityEvent {
+  /// Name in the OCPP 1.6 Security Extension
+  pub fn v1_6_name(&self) -> &str {
+    match self {
+      Self::FirmwareUpdated => "FirmwareUpdated",
+      Self::FailedToAuthenticateAtCentralSystem => "FailedToAuthenticateAtCentralSystem",
+      Self::StartupOfTheDevice => "StartupOfTheDevice",
+      Self::ResetOrReboot => "ResetOrReboot",
+      Self::ReconfigurationOfSecurityParameters => "ReconfigurationOfSecurityParameters",
+      Self::InvalidFirmwareSignature => "InvalidFirmwareSignature",
+      Self::InvalidFirmwareSigningCertificate => "InvalidFirmwareSigningCertificate",
+      Self::InvalidCentralSystemCertificate => "InvalidCentralSystemCertificate",
+      Self::InvalidTLSVersion => "InvalidTLSVersion",
+      Self::Other(name) => name,
+    }
+  }
+
+  /// Name in OCPP 2.0.1, where the central system is the CSMS
+  pub fn v2_0_1_name(&self) -> &str {
+    match self {
+      Self::FailedToAuthenticateAtCentralSystem => "FailedToAuthenticateAtCsms",
+      Self::InvalidCentralSystemCertificate => "InvalidCsmsCertificate",
+      _ => self.v1_6_name(),
+    }
+  }
+}
+
+impl From<&str> for CommonSecurityEvent {
+  fn from(value: &str) -> Self {
+    match value {
+      "FirmwareUpdated" => Self::FirmwareUpdated,
+      "FailedToAuthenticateAtCentralSystem" | "FailedToAuthenticateAtCsms" => {
+        Self::FailedToAuthenticateAtCentralSystem
+      }
+      "StartupOfTheDevice" => Self::StartupOfTheDevice,
+      "ResetOrReboot" => Self::ResetOrReboot,
+      "ReconfigurationOfSecurityParameters" => Self::ReconfigurationOfSecurityParameters,
+      "InvalidFirmwareSignature" => Self::InvalidFirmwareSignature,
+      "InvalidFirmwareSigningCertificate" => Self::InvalidFirmwareSigningCertificate,
+      "InvalidCentralSystemCertificate" | "InvalidCsmsCertificate" => {
+        Self::InvalidCentralSystemCertificate
+      }
+      "InvalidTLSVersion" => Self::InvalidTLSVersion,
+      other => Self::Other(other.to_string()),
+    }
+  }
+}
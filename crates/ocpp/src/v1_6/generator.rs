@@ -1,11 +1,19 @@
 use std::collections::HashSet;
 use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::LazyLock;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use anyhow::Result;
 use async_trait::async_trait;
-use common::shared_data::ChargingSessionState;
-use common::{ChargePointConfig, SharedData};
+use common::{ChargePointConfig, SharedData, frame_info, shared_data::ChargePointSettings};
 use rust_ocpp::v1_6::messages::change_configuration::ChangeConfigurationResponse;
+use rust_ocpp::v1_6::messages::clear_charging_profile::ClearChargingProfileResponse;
+use rust_ocpp::v1_6::messages::reset::ResetResponse;
+use rust_ocpp::v1_6::messages::set_charging_profile::{
+  SetChargingProfileRequest, SetChargingProfileResponse,
+};
+use rust_ocpp::v1_6::messages::update_firmware::UpdateFirmwareResponse;
 use rust_ocpp::v1_6::messages::{
   authorize::AuthorizeRequest, boot_notification::BootNotificationRequest,
   data_transfer::DataTransferRequest,
@@ -15,22 +23,46 @@ use rust_ocpp::v1_6::messages::{
   status_notification::StatusNotificationRequest, stop_transaction::StopTransactionRequest,
 };
 
-use rust_ocpp::v1_6::types::FirmwareStatus;
-use rust_ocpp::v1_6::types::{ChargePointErrorCode, MeterValue};
-use rust_ocpp::v1_6::types::{ConfigurationStatus, DiagnosticsStatus};
+use rust_decimal::prelude::ToPrimitive;
+use rust_ocpp::v1_6::types::DiagnosticsStatus;
+use rust_ocpp::v1_6::types::ResetResponseStatus;
+use rust_ocpp::v1_6::types::{
+  ChargePointErrorCode, Measurand, MeterValue, ReadingContext, SampledValue, UnitOfMeasure,
+  ValueFormat,
+};
+use rust_ocpp::v1_6::types::{ChargingProfileStatus, ClearChargingProfileStatus};
 use serde::Serialize;
 use serde_json::{Value, json};
 
 use tokio::time::Instant;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::generator::MessageGenerator;
-use crate::mock_data::MeterValueMockData;
-use crate::types::CommonConnectorStatusType;
+use crate::mock_data::{MeterReading, MeterValueMockData};
+use crate::signed_meter::MeterSigner;
+use crate::template::CallTemplate;
+use crate::types::{
+  CommonCertificateStatus, CommonConfigurationStatus, CommonConnectorStatusType,
+  CommonCustomerInformationStatus, CommonFirmwareStatus, CommonLogStatus, CommonOcspRequestData,
+  CommonSecurityEvent, CommonSettingChange, CommonStopReason, CommonTransactionTrigger,
+  CommonTriggerMessageStatus, CommonUpdateFirmwareStatus, CommonUploadLogStatus, ConnectorFault,
+  InstalledCertificate,
+};
+use crate::v1_6::security::{
+  CertificateSignedResponse, CertificateStatus, DeleteCertificateResponse, DeleteCertificateStatus,
+  ExtendedTriggerMessageResponse, GenericStatus, GetInstalledCertificateIdsResponse,
+  GetInstalledCertificateStatus, InstallCertificateResponse, SecurityEventNotificationRequest,
+  SignCertificateRequest, SignedFirmwareStatusNotificationRequest, SignedUpdateFirmwareResponse,
+  TriggerMessageStatus,
+};
+use crate::v1_6::types::IntoV16;
 
 use super::types::OcppAction;
 
+static HEARTBEAT: LazyLock<CallTemplate> =
+  LazyLock::new(|| CallTemplate::new(OcppAction::Heartbeat, HeartbeatRequest {}));
+
 async fn build_call<T>(
   shared_data: &SharedData<OcppAction>,
   ocpp_action: OcppAction,
@@ -39,23 +71,47 @@ async fn build_call<T>(
 where
   T: Debug + Serialize,
 {
-  info!("➡️  [🔵 Call] {}", ocpp_action);
+  build_tagged_call(shared_data, ocpp_action, payload, None).await
+}
+
+/// Call made for a tag, its answer is matched to the tag by the message id
+async fn build_tagged_call<T>(
+  shared_data: &SharedData<OcppAction>,
+  ocpp_action: OcppAction,
+  payload: T,
+  tag_id: Option<String>,
+) -> Value
+where
+  T: Debug + Serialize,
+{
+  frame_info!("➡️  [🔵 Call] {}", ocpp_action);
   debug!(action = %ocpp_action, ?payload);
 
   let msg_id = Uuid::new_v4();
   shared_data
-    .insert_msg(&msg_id.to_string(), ocpp_action.clone())
+    .insert_tagged_msg(&msg_id.to_string(), ocpp_action.clone(), tag_id)
     .await;
 
   json!([2, msg_id, ocpp_action, payload])
 }
 
-#[allow(unused)]
+async fn build_template_call(
+  shared_data: &SharedData<OcppAction>,
+  ocpp_action: OcppAction,
+  template: &CallTemplate,
+) -> String {
+  frame_info!("➡️  [🔵 Call] {}", ocpp_action);
+
+  let msg_id = Uuid::new_v4().to_string();
+  let call = template.render(&msg_id);
+  shared_data.insert_msg(&msg_id, ocpp_action).await;
+  call
+}
+
 pub fn build_call_result<T: Serialize>(message_id: &str, payload: T) -> Value {
   json!([3, message_id, payload])
 }
 
-#[allow(unused)]
 pub fn build_call_error(
   message_id: &str,
   error_code: &str,
@@ -74,6 +130,7 @@ pub fn build_call_error(
 pub struct V16MessageGenerator {
   config: ChargePointConfig,
   shared_data: SharedData<OcppAction>,
+  meter_signer: Option<MeterSigner>,
   id_counter: AtomicUsize,
 }
 
@@ -128,7 +185,7 @@ impl MessageGenerator for V16MessageGenerator {
   async fn meter_values_sampled_data(&self, value: String) {
     let values = value
       .split(',')
-      .map(|item| item.to_string())
+      .map(|item| item.trim().to_string())
       .collect::<HashSet<String>>();
 
     self
@@ -310,6 +367,19 @@ impl MessageGenerator for V16MessageGenerator {
       .await;
   }
 
+  // 🛡️ Security
+
+  async fn security_profile(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.security_profile = value)
+      .await;
+  }
+
+  async fn settings(&self) -> ChargePointSettings {
+    self.shared_data.read(|data| data.settings.clone()).await
+  }
+
   async fn boot_notification(&self) -> Value {
     self
       .build_call(
@@ -323,10 +393,8 @@ impl MessageGenerator for V16MessageGenerator {
       .await
   }
 
-  async fn heartbeat(&self) -> Value {
-    self
-      .build_call(OcppAction::Heartbeat, HeartbeatRequest {})
-      .await
+  async fn heartbeat(&self) -> String {
+    build_template_call(&self.shared_data, OcppAction::Heartbeat, &HEARTBEAT).await
   }
 
   async fn authorize(&self, tag_id: Option<&str>) -> Value {
@@ -339,22 +407,41 @@ impl MessageGenerator for V16MessageGenerator {
       .write(|data| data.tag_id = Some(id_tag.clone()))
       .await;
 
-    self
-      .build_call(OcppAction::Authorize, AuthorizeRequest { id_tag })
-      .await
+    build_tagged_call(
+      &self.shared_data,
+      OcppAction::Authorize,
+      AuthorizeRequest {
+        id_tag: id_tag.clone(),
+      },
+      Some(id_tag),
+    )
+    .await
   }
 
   async fn start_transaction(&self) -> Value {
+    let (id_tag, energy_wh) = self
+      .shared_data
+      .write(|data| {
+        let state = &mut data.charging_session_state;
+        state.last_update = Instant::now();
+        state.last_sample_energy_wh = state.energy_wh;
+        state.soc = self.config.meter.initial_soc;
+
+        (data.tag_id.clone().unwrap(), state.energy_wh)
+      })
+      .await;
+
+    if let Some(meter_signer) = &self.meter_signer {
+      meter_signer.begin(&id_tag, energy_wh);
+    }
+
     self
-      .build_call(
+      .transaction_message(
         OcppAction::StartTransaction,
         StartTransactionRequest {
           connector_id: 1,
-          id_tag: self
-            .shared_data
-            .read(|data| data.tag_id.clone().unwrap())
-            .await,
-          meter_start: 0,
+          id_tag,
+          meter_start: energy_wh as i32,
           timestamp: chrono::Utc::now(),
           ..Default::default()
         },
@@ -362,36 +449,65 @@ impl MessageGenerator for V16MessageGenerator {
       .await
   }
 
-  async fn stop_transaction(&self) -> Value {
+  async fn stop_transaction(&self, reason: CommonStopReason) -> Value {
+    let (energy_wh, id_tag, transaction_id) = self
+      .shared_data
+      .read(|data| {
+        (
+          data.charging_session_state.energy_wh,
+          data.tag_id.clone(),
+          data.transaction_id.unwrap_or(1),
+        )
+      })
+      .await;
+
+    // Signed begin/end readings let the CSMS verify the whole transaction
+    let transaction_data = self.meter_signer.as_ref().map(|meter_signer| {
+      let (begin, end) = meter_signer.end(energy_wh);
+
+      begin
+        .map(|begin| (begin, ReadingContext::TransactionBegin))
+        .into_iter()
+        .chain([(end, ReadingContext::TransactionEnd)])
+        .map(|(signed_data, context)| MeterValue {
+          timestamp: chrono::Utc::now(),
+          sampled_value: vec![Self::signed_sampled_value(signed_data, context)],
+        })
+        .collect::<Vec<MeterValue>>()
+    });
+
     self
-      .build_call(
+      .transaction_message(
         OcppAction::StopTransaction,
         StopTransactionRequest {
-          meter_stop: self
-            .shared_data
-            .read(|data| data.charging_session_state.energy_wh as i32)
-            .await,
+          meter_stop: energy_wh as i32,
           timestamp: chrono::Utc::now(),
-          id_tag: self.shared_data.read(|data| data.tag_id.clone()).await,
-          transaction_id: self
-            .shared_data
-            .read(|data| data.transaction_id.unwrap_or(1))
-            .await,
-          ..Default::default()
+          id_tag,
+          transaction_id,
+          reason: Some(reason.into()),
+          transaction_data,
         },
       )
       .await
   }
 
-  async fn status_notification(&self, status: CommonConnectorStatusType) -> Value {
+  async fn status_notification(
+    &self,
+    status: CommonConnectorStatusType,
+    fault: Option<&ConnectorFault>,
+  ) -> Value {
     self
       .build_call(
         OcppAction::StatusNotification,
         StatusNotificationRequest {
           connector_id: 1,
-          error_code: ChargePointErrorCode::NoError,
+          error_code: fault
+            .map(|fault| fault.error_code.into_v16())
+            .unwrap_or(ChargePointErrorCode::NoError),
+          info: fault.and_then(|fault| fault.info.clone()),
           status: status.into(),
           timestamp: Some(chrono::Utc::now()),
+          vendor_error_code: fault.and_then(|fault| fault.vendor_error_code.clone()),
           ..Default::default()
         },
       )
@@ -399,28 +515,50 @@ impl MessageGenerator for V16MessageGenerator {
   }
 
   async fn meter_values(&self) -> Value {
-    let (transaction_id, meter_values_sampled_data) = self
+    let (transaction_id, meter_values_sampled_data, soc) = self
       .shared_data
       .read(|data| {
         (
           data.transaction_id,
           data.settings.meter_values_sampled_data.clone(),
+          data.charging_session_state.soc,
         )
       })
       .await;
 
     if let Some(transaction_id) = transaction_id {
-      let (meter_value, total_power_kw) = MeterValue::mock_data(meter_values_sampled_data);
+      let mut reading = MeterReading::sample(&self.config.meter, soc);
 
       self
         .shared_data
         .write(|data| {
-          V16MessageGenerator::update_energy(&mut data.charging_session_state, total_power_kw)
+          let state = &mut data.charging_session_state;
+          state.update_energy(&self.config.meter, reading.power_active_w());
+
+          reading.energy_wh = state.energy_wh;
+          reading.interval_wh = state.energy_wh - state.last_sample_energy_wh;
+          reading.soc = state.soc;
+          state.last_sample_energy_wh = state.energy_wh;
         })
         .await;
 
+      let energy_wh = reading.energy_wh;
+      let mut meter_value = MeterValue::mock_data(
+        &self.config.meter,
+        &meter_values_sampled_data,
+        &reading,
+        ReadingContext::SamplePeriodic,
+      );
+
+      if let Some(meter_signer) = &self.meter_signer {
+        meter_value.sampled_value.push(Self::signed_sampled_value(
+          meter_signer.sample(energy_wh),
+          ReadingContext::SamplePeriodic,
+        ));
+      }
+
       self
-        .build_call(
+        .transaction_message(
           OcppAction::MeterValues,
           MeterValuesRequest {
             connector_id: 1,
@@ -434,6 +572,13 @@ impl MessageGenerator for V16MessageGenerator {
     }
   }
 
+  async fn session(&self) -> (Option<i32>, f64) {
+    self
+      .shared_data
+      .read(|data| (data.transaction_id, data.charging_session_state.energy_wh))
+      .await
+  }
+
   async fn diagnostics_status_notification(&self) -> Value {
     self
       .build_call(
@@ -445,59 +590,445 @@ impl MessageGenerator for V16MessageGenerator {
       .await
   }
 
-  async fn firmware_status_notification(&self) -> Value {
+  async fn firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    _request_id: Option<i32>,
+  ) -> Value {
     self
       .build_call(
         OcppAction::FirmwareStatusNotification,
         FirmwareStatusNotificationRequest {
-          status: FirmwareStatus::Installed,
+          status: status.into(),
         },
       )
       .await
   }
 
-  async fn data_transfer(&self) -> Value {
+  async fn data_transfer(
+    &self,
+    vendor_id: Option<&str>,
+    message_id: Option<&str>,
+    data: Option<&str>,
+  ) -> Value {
     self
       .build_call(
-        OcppAction::StatusNotification,
+        OcppAction::DataTransfer,
         DataTransferRequest {
-          vendor_string: self.config.vendor.clone(),
-          ..Default::default()
+          vendor_string: vendor_id.unwrap_or(&self.config.vendor).to_string(),
+          message_id: message_id.map(String::from),
+          data: data.map(String::from),
         },
       )
       .await
   }
 
-  async fn change_configuration(&self) -> Value {
+  async fn custom_call(&self, action: &str, payload: Value) -> Option<Value> {
+    let action = OcppAction::from_str(action).ok()?;
+    Some(self.build_call(action, payload).await)
+  }
+
+  async fn change_configuration(&self, msg_id: &str, status: CommonConfigurationStatus) -> Value {
+    Self::call_result(
+      OcppAction::ChangeConfiguration,
+      msg_id,
+      ChangeConfigurationResponse {
+        status: status.into(),
+      },
+    )
+  }
+
+  async fn reset(&self, msg_id: &str) -> Value {
+    Self::call_result(
+      OcppAction::Reset,
+      msg_id,
+      ResetResponse {
+        status: ResetResponseStatus::Accepted,
+      },
+    )
+  }
+
+  async fn update_firmware(&self, msg_id: &str, _status: CommonUpdateFirmwareStatus) -> Value {
+    // The plain 1.6 UpdateFirmware has no status
+    Self::call_result(
+      OcppAction::UpdateFirmware,
+      msg_id,
+      UpdateFirmwareResponse {},
+    )
+  }
+
+  // 🧾 Transaction events
+
+  async fn transaction_trigger(
+    &self,
+    _trigger: CommonTransactionTrigger,
+    _status: CommonConnectorStatusType,
+  ) -> Value {
+    // 1.6 transactions only know StartTransaction and StopTransaction
+    Value::Null
+  }
+
+  async fn set_offline(&self, _offline: bool) {
+    // 1.6 transaction messages carry no offline flag
+  }
+
+  async fn queued_transaction_events(&self) -> Vec<Value> {
+    let queued = self
+      .shared_data
+      .write(|data| std::mem::take(&mut data.transaction_events))
+      .await;
+
+    if !queued.is_empty() {
+      info!("Sending {} queued transaction messages", queued.len());
+    }
+
+    let mut messages = Vec::with_capacity(queued.len());
+    for (msg_id, call) in queued {
+      // A late answer of the lost call no longer counts
+      self.shared_data.take_msg(&msg_id).await;
+      let Some(ocpp_action) = call[2]
+        .as_str()
+        .and_then(|action| OcppAction::from_str(action).ok())
+      else {
+        continue;
+      };
+
+      messages.push(self.transaction_message(ocpp_action, call[3].clone()).await);
+    }
+    messages
+  }
+
+  async fn get_transaction_status(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "GetTransactionStatus")
+  }
+
+  // 🔏 Signed firmware update
+
+  async fn signed_update_firmware(
+    &self,
+    msg_id: &str,
+    status: CommonUpdateFirmwareStatus,
+  ) -> Value {
+    Self::call_result(
+      OcppAction::SignedUpdateFirmware,
+      msg_id,
+      SignedUpdateFirmwareResponse {
+        status: status.into(),
+      },
+    )
+  }
+
+  async fn signed_firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    request_id: i32,
+  ) -> Value {
     self
       .build_call(
-        OcppAction::StatusNotification,
-        ChangeConfigurationResponse {
-          status: ConfigurationStatus::Accepted,
+        OcppAction::SignedFirmwareStatusNotification,
+        SignedFirmwareStatusNotificationRequest {
+          status: status.into(),
+          request_id: Some(request_id),
+        },
+      )
+      .await
+  }
+
+  // 🚨 Security events
+
+  async fn security_event_notification(
+    &self,
+    event: &CommonSecurityEvent,
+    tech_info: Option<&str>,
+  ) -> Value {
+    self
+      .build_call(
+        OcppAction::SecurityEventNotification,
+        SecurityEventNotificationRequest {
+          kind: event.v1_6_name().to_string(),
+          timestamp: chrono::Utc::now(),
+          tech_info: tech_info.map(String::from),
         },
       )
       .await
   }
 
+  // 🔏 Certificate management
+
+  async fn sign_certificate(&self, csr: String) -> Value {
+    self
+      .build_call(OcppAction::SignCertificate, SignCertificateRequest { csr })
+      .await
+  }
+
+  async fn certificate_signed(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => GenericStatus::Accepted,
+      _ => GenericStatus::Rejected,
+    };
+
+    Self::call_result(
+      OcppAction::CertificateSigned,
+      msg_id,
+      CertificateSignedResponse { status },
+    )
+  }
+
+  async fn install_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => CertificateStatus::Accepted,
+      CommonCertificateStatus::Rejected => CertificateStatus::Rejected,
+      CommonCertificateStatus::Failed | CommonCertificateStatus::NotFound => {
+        CertificateStatus::Failed
+      }
+    };
+
+    Self::call_result(
+      OcppAction::InstallCertificate,
+      msg_id,
+      InstallCertificateResponse { status },
+    )
+  }
+
+  async fn delete_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => DeleteCertificateStatus::Accepted,
+      CommonCertificateStatus::NotFound => DeleteCertificateStatus::NotFound,
+      CommonCertificateStatus::Rejected | CommonCertificateStatus::Failed => {
+        DeleteCertificateStatus::Failed
+      }
+    };
+
+    Self::call_result(
+      OcppAction::DeleteCertificate,
+      msg_id,
+      DeleteCertificateResponse { status },
+    )
+  }
+
+  async fn get_installed_certificate_ids(
+    &self,
+    msg_id: &str,
+    certificates: Vec<InstalledCertificate>,
+  ) -> Value {
+    let response = if certificates.is_empty() {
+      GetInstalledCertificateIdsResponse {
+        status: GetInstalledCertificateStatus::NotFound,
+        certificate_hash_data: None,
+      }
+    } else {
+      GetInstalledCertificateIdsResponse {
+        status: GetInstalledCertificateStatus::Accepted,
+        certificate_hash_data: Some(
+          certificates
+            .into_iter()
+            .map(|certificate| certificate.hash_data.into())
+            .collect(),
+        ),
+      }
+    };
+
+    Self::call_result(OcppAction::GetInstalledCertificateIds, msg_id, response)
+  }
+
+  async fn trigger_message(&self, msg_id: &str, status: CommonTriggerMessageStatus) -> Value {
+    let status = match status {
+      CommonTriggerMessageStatus::Accepted => TriggerMessageStatus::Accepted,
+      CommonTriggerMessageStatus::Rejected => TriggerMessageStatus::Rejected,
+      CommonTriggerMessageStatus::NotImplemented => TriggerMessageStatus::NotImplemented,
+    };
+
+    Self::call_result(
+      OcppAction::ExtendedTriggerMessage,
+      msg_id,
+      ExtendedTriggerMessageResponse { status },
+    )
+  }
+
+  // 🧩 Device model
+
+  async fn get_variables(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "GetVariables")
+  }
+
+  async fn set_variables(
+    &self,
+    msg_id: &str,
+    _payload: Value,
+  ) -> (Value, Vec<CommonSettingChange>) {
+    (Self::not_supported(msg_id, "SetVariables"), Vec::new())
+  }
+
+  async fn get_base_report(&self, msg_id: &str, _payload: Value) -> Vec<Value> {
+    vec![Self::not_supported(msg_id, "GetBaseReport")]
+  }
+
+  async fn get_report(&self, msg_id: &str, _payload: Value) -> Vec<Value> {
+    vec![Self::not_supported(msg_id, "GetReport")]
+  }
+
+  // 📡 Monitoring
+
+  async fn set_variable_monitoring(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "SetVariableMonitoring")
+  }
+
+  async fn clear_variable_monitoring(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "ClearVariableMonitoring")
+  }
+
+  async fn set_monitoring_base(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "SetMonitoringBase")
+  }
+
+  async fn set_monitoring_level(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "SetMonitoringLevel")
+  }
+
+  async fn get_monitoring_report(&self, msg_id: &str, _payload: Value) -> Vec<Value> {
+    vec![Self::not_supported(msg_id, "GetMonitoringReport")]
+  }
+
+  async fn has_monitors(&self) -> bool {
+    false
+  }
+
+  async fn monitoring_events(&self) -> Vec<Value> {
+    Vec::new()
+  }
+
+  // ⚡ Smart charging
+
+  async fn set_charging_profile(&self, msg_id: &str, payload: Value) -> (Value, Option<f64>) {
+    let request: SetChargingProfileRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return (
+          build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+          None,
+        );
+      }
+    };
+
+    let limit = request
+      .cs_charging_profiles
+      .charging_schedule
+      .charging_schedule_period
+      .iter()
+      .min_by_key(|period| period.start_period)
+      .and_then(|period| period.limit.to_f64());
+
+    let response = Self::call_result(
+      OcppAction::SetChargingProfile,
+      msg_id,
+      SetChargingProfileResponse {
+        status: ChargingProfileStatus::Accepted,
+      },
+    );
+    (response, limit)
+  }
+
+  async fn clear_charging_profile(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::call_result(
+      OcppAction::ClearChargingProfile,
+      msg_id,
+      ClearChargingProfileResponse {
+        status: ClearChargingProfileStatus::Accepted,
+      },
+    )
+  }
+
+  // 🖥️ Display messages
+
+  async fn set_display_message(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "SetDisplayMessage")
+  }
+
+  async fn get_display_messages(&self, msg_id: &str, _payload: Value) -> Vec<Value> {
+    vec![Self::not_supported(msg_id, "GetDisplayMessages")]
+  }
+
+  async fn clear_display_message(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "ClearDisplayMessage")
+  }
+
+  async fn cost_updated(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::not_supported(msg_id, "CostUpdated")
+  }
+
+  // 🗂️ Logs and customer information
+
+  async fn get_log(
+    &self,
+    msg_id: &str,
+    _status: CommonLogStatus,
+    _filename: Option<String>,
+  ) -> Value {
+    Self::not_supported(msg_id, "GetLog")
+  }
+
+  async fn log_status_notification(
+    &self,
+    _status: CommonUploadLogStatus,
+    _request_id: i32,
+  ) -> Value {
+    Value::Null
+  }
+
+  async fn customer_information(
+    &self,
+    msg_id: &str,
+    _status: CommonCustomerInformationStatus,
+  ) -> Value {
+    Self::not_supported(msg_id, "CustomerInformation")
+  }
+
+  async fn notify_customer_information(&self, _request_id: i32, _data: &str) -> Vec<Value> {
+    Vec::new()
+  }
+
+  // 🔌 ISO 15118 Plug & Charge
+
+  async fn get_15118_ev_certificate(&self, _schema_version: &str, _exi_request: String) -> Value {
+    Value::Null
+  }
+
+  async fn get_certificate_status(&self, _ocsp_request: CommonOcspRequestData) -> Value {
+    Value::Null
+  }
+
+  async fn authorize_contract(
+    &self,
+    _emaid: &str,
+    _certificate_hash_data: Vec<CommonOcspRequestData>,
+  ) -> Value {
+    Value::Null
+  }
+
   fn next_id(&self) -> String {
     self.id_counter.fetch_add(1, Ordering::Relaxed).to_string()
   }
 }
 
 impl V16MessageGenerator {
-  pub fn new(config: ChargePointConfig, shared_data: SharedData<OcppAction>) -> Self {
-    Self {
+  pub fn new(config: ChargePointConfig, shared_data: SharedData<OcppAction>) -> Result<Self> {
+    Ok(Self {
+      meter_signer: MeterSigner::from_config(&config)?,
       config,
       shared_data,
       id_counter: AtomicUsize::new(1),
-    }
+    })
   }
 
-  fn update_energy(state: &mut ChargingSessionState, power_kw: f64) {
-    let now = Instant::now();
-    let delta_hours = now.duration_since(state.last_update).as_secs_f64() / 3600.0;
-    state.energy_wh += power_kw * 1000.0 * delta_hours;
-    state.last_update = now;
+  fn signed_sampled_value(signed_data: String, context: ReadingContext) -> SampledValue {
+    SampledValue {
+      value: signed_data,
+      context: Some(context),
+      format: Some(ValueFormat::SignedData),
+      measurand: Some(Measurand::EnergyActiveImportRegister),
+      unit: Some(UnitOfMeasure::Wh),
+      ..Default::default()
+    }
   }
 
   async fn build_call<T>(&self, ocpp_action: OcppAction, payload: T) -> Value
@@ -506,4 +1037,42 @@ impl V16MessageGenerator {
   {
     build_call(&self.shared_data, ocpp_action, payload).await
   }
+
+  /// `StartTransaction`, `StopTransaction` or transaction `MeterValues` call, queued until
+  /// the CSMS confirms it
+  async fn transaction_message<T>(&self, ocpp_action: OcppAction, payload: T) -> Value
+  where
+    T: Debug + Serialize,
+  {
+    let call = self.build_call(ocpp_action, payload).await;
+    let msg_id = call[1].as_str().unwrap_or_default().to_string();
+
+    self
+      .shared_data
+      .write(|data| data.transaction_events.push((msg_id, call.clone())))
+      .await;
+
+    call
+  }
+
+  fn call_result<T>(ocpp_action: OcppAction, msg_id: &str, payload: T) -> Value
+  where
+    T: Debug + Serialize,
+  {
+    frame_info!("➡️  [🟢 CallResult] {}", ocpp_action);
+    debug!(action = %ocpp_action, ?payload);
+
+    build_call_result(msg_id, payload)
+  }
+
+  /// The device model and its monitoring are only part of OCPP 2.0.1
+  fn not_supported(msg_id: &str, action: &str) -> Value {
+    warn!("{action} is not part of OCPP 1.6");
+    build_call_error(
+      msg_id,
+      "NotSupported",
+      &format!("{action} is not part of OCPP 1.6"),
+      None,
+    )
+  }
 }
@@ -7,19 +7,18 @@ use crate::{
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use common::SharedData;
+use common::{SharedData, frame_info};
 use rust_ocpp::v1_6::messages::{
   authorize::AuthorizeResponse,
   change_configuration::ChangeConfigurationRequest,
   get_configuration::{GetConfigurationRequest, GetConfigurationResponse},
-  heart_beat::HeartbeatRequest,
   remote_start_transaction::RemoteStartTransactionRequest,
   start_transaction::StartTransactionResponse,
 };
 
 use serde::{Serialize, de::DeserializeOwned};
 use serde_json::Value;
-use tracing::{debug, info};
+use tracing::{debug, warn};
 
 pub struct V16MessageHandler {
   shared_data: SharedData<OcppAction>,
@@ -45,7 +44,7 @@ impl MessageHandler for V16MessageHandler {
         let action = OcppAction::from_str(action_string.as_str())
           .map_err(|err| anyhow::anyhow!("Invalid OCPP action: {}", err))?;
 
-        info!("⬅️  [🔵 Call] {}", action);
+        frame_info!("⬅️  [🔵 Call] {}", action);
 
         Ok(MessageFrameType::V1_6(MessageFrame::Call {
           msg_id,
@@ -57,7 +56,7 @@ impl MessageHandler for V16MessageHandler {
         let msg_id = arr[1].as_str().unwrap_or("").to_string();
         let payload = arr[2].clone();
 
-        info!(
+        frame_info!(
           "⬅️  [🟢 CallResult] {:?}",
           self.shared_data.get_msg(&msg_id).await
         );
@@ -72,7 +71,9 @@ impl MessageHandler for V16MessageHandler {
         let error_code = arr[2].as_str().unwrap_or("").to_string();
         let description = arr[3].as_str().unwrap_or("").to_string();
 
-        info!("⬅️  [🔴 CallError] {}", error_code);
+        // The failed call no longer waits for an answer
+        let ocpp_action = self.shared_data.take_msg(&msg_id).await;
+        frame_info!("⬅️  [🔴 CallError] {} {:?}", error_code, ocpp_action);
 
         Ok(MessageFrameType::V1_6(MessageFrame::CallError {
           msg_id,
@@ -122,9 +123,24 @@ impl MessageHandler for V16MessageHandler {
     msg_id: &str,
     payload: &Value,
   ) -> Result<Option<CommonOcppResponse>> {
-    let ocpp_action = self.shared_data.get_msg(msg_id).await;
-    match ocpp_action {
-      Some(ocpp_action) => match ocpp_action {
+    let call = self.shared_data.take_tagged_msg(msg_id).await;
+    if let Some((
+      OcppAction::StartTransaction | OcppAction::StopTransaction | OcppAction::MeterValues,
+      _,
+    )) = &call
+    {
+      self
+        .shared_data
+        .write(|data| {
+          data
+            .transaction_events
+            .retain(|(message_msg_id, _)| message_msg_id != msg_id)
+        })
+        .await;
+    }
+
+    match call {
+      Some((ocpp_action, tag_id)) => match ocpp_action {
         OcppAction::StartTransaction => {
           let res: StartTransactionResponse = serde_json::from_value(payload.clone())?;
 
@@ -140,12 +156,17 @@ impl MessageHandler for V16MessageHandler {
         OcppAction::Authorize => {
           let res: AuthorizeResponse = serde_json::from_value(payload.clone())?;
           Ok(Some(CommonOcppResponse::Authorize {
+            id_tag: tag_id.unwrap_or_default(),
             status: res.id_tag_info.status.into(),
+            parent_id_tag: res.id_tag_info.parent_id_tag,
           }))
         }
         _ => Ok(None),
       },
-      None => anyhow::bail!("msg_id not found"),
+      None => {
+        warn!("No call waiting for the answer {msg_id}");
+        Ok(None)
+      }
     }
   }
 }
@@ -1,3 +1,4 @@
 pub mod generator;
 pub mod handler;
+pub mod security;
 pub mod types;
@@ -0,0 +1,281 @@
+//! Messages of the OCPP 1.6 Security Extension (Improved security for OCPP 1.6-J),
+//! which `rust_ocpp` does not provide
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+  CommonCertificateHashData, CommonCertificateUse, CommonFirmwareStatus, CommonUpdateFirmwareStatus,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CertificateUse {
+  CentralSystemRootCertificate,
+  ManufacturerRootCertificate,
+}
+
+impl From<CertificateUse> for CommonCertificateUse {
+  fn from(value: CertificateUse) -> Self {
+    match value {
+      CertificateUse::CentralSystemRootCertificate => CommonCertificateUse::CentralSystemRoot,
+      CertificateUse::ManufacturerRootCertificate => CommonCertificateUse::ManufacturerRoot,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+  SHA256,
+  SHA384,
+  SHA512,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateHashData {
+  pub hash_algorithm: HashAlgorithm,
+  pub issuer_name_hash: String,
+  pub issuer_key_hash: String,
+  pub serial_number: String,
+}
+
+impl From<CertificateHashData> for CommonCertificateHashData {
+  fn from(value: CertificateHashData) -> Self {
+    Self {
+      issuer_name_hash: value.issuer_name_hash,
+      issuer_key_hash: value.issuer_key_hash,
+      serial_number: value.serial_number,
+    }
+  }
+}
+
+impl From<CommonCertificateHashData> for CertificateHashData {
+  fn from(value: CommonCertificateHashData) -> Self {
+    Self {
+      hash_algorithm: HashAlgorithm::SHA256,
+      issuer_name_hash: value.issuer_name_hash,
+      issuer_key_hash: value.issuer_key_hash,
+      serial_number: value.serial_number,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GenericStatus {
+  Accepted,
+  Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignCertificateRequest {
+  pub csr: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignCertificateResponse {
+  pub status: GenericStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityEventNotificationRequest {
+  #[serde(rename = "type")]
+  pub kind: String,
+  pub timestamp: DateTime<Utc>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tech_info: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSignedRequest {
+  pub certificate_chain: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSignedResponse {
+  pub status: GenericStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallCertificateRequest {
+  pub certificate_type: CertificateUse,
+  pub certificate: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CertificateStatus {
+  Accepted,
+  Failed,
+  Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallCertificateResponse {
+  pub status: CertificateStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteCertificateRequest {
+  pub certificate_hash_data: CertificateHashData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeleteCertificateStatus {
+  Accepted,
+  Failed,
+  NotFound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteCertificateResponse {
+  pub status: DeleteCertificateStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetInstalledCertificateIdsRequest {
+  pub certificate_type: CertificateUse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GetInstalledCertificateStatus {
+  Accepted,
+  NotFound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetInstalledCertificateIdsResponse {
+  pub status: GetInstalledCertificateStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub certificate_hash_data: Option<Vec<CertificateHashData>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageTrigger {
+  BootNotification,
+  LogStatusNotification,
+  FirmwareStatusNotification,
+  Heartbeat,
+  MeterValues,
+  SignChargePointCertificate,
+  StatusNotification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendedTriggerMessageRequest {
+  pub requested_message: MessageTrigger,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub connector_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerMessageStatus {
+  Accepted,
+  Rejected,
+  NotImplemented,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendedTriggerMessageResponse {
+  pub status: TriggerMessageStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Firmware {
+  pub location: String,
+  pub retrieve_date_time: DateTime<Utc>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub install_date_time: Option<DateTime<Utc>>,
+  pub signing_certificate: String,
+  pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedUpdateFirmwareRequest {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retries: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retry_interval: Option<u32>,
+  pub request_id: i32,
+  pub firmware: Firmware,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateFirmwareStatus {
+  Accepted,
+  Rejected,
+  AcceptedCanceled,
+  InvalidCertificate,
+  RevokedCertificate,
+}
+
+impl From<CommonUpdateFirmwareStatus> for UpdateFirmwareStatus {
+  fn from(value: CommonUpdateFirmwareStatus) -> Self {
+    match value {
+      CommonUpdateFirmwareStatus::Accepted => UpdateFirmwareStatus::Accepted,
+      CommonUpdateFirmwareStatus::Rejected => UpdateFirmwareStatus::Rejected,
+      CommonUpdateFirmwareStatus::InvalidCertificate => UpdateFirmwareStatus::InvalidCertificate,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedUpdateFirmwareResponse {
+  pub status: UpdateFirmwareStatus,
+}
+
+/// `FirmwareStatusEnumType` of the Security Extension, with the signature states
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignedFirmwareStatus {
+  Downloaded,
+  DownloadFailed,
+  Downloading,
+  DownloadScheduled,
+  DownloadPaused,
+  Idle,
+  InstallationFailed,
+  Installing,
+  Installed,
+  InstallRebooting,
+  InstallScheduled,
+  InstallVerificationFailed,
+  InvalidSignature,
+  SignatureVerified,
+}
+
+impl From<CommonFirmwareStatus> for SignedFirmwareStatus {
+  fn from(value: CommonFirmwareStatus) -> Self {
+    match value {
+      CommonFirmwareStatus::Downloading => SignedFirmwareStatus::Downloading,
+      CommonFirmwareStatus::Downloaded => SignedFirmwareStatus::Downloaded,
+      CommonFirmwareStatus::DownloadFailed => SignedFirmwareStatus::DownloadFailed,
+      CommonFirmwareStatus::Installing => SignedFirmwareStatus::Installing,
+      CommonFirmwareStatus::Installed => SignedFirmwareStatus::Installed,
+      CommonFirmwareStatus::InstallationFailed => SignedFirmwareStatus::InstallationFailed,
+      CommonFirmwareStatus::SignatureVerified => SignedFirmwareStatus::SignatureVerified,
+      CommonFirmwareStatus::InvalidSignature => SignedFirmwareStatus::InvalidSignature,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedFirmwareStatusNotificationRequest {
+  pub status: SignedFirmwareStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub request_id: Option<i32>,
+}
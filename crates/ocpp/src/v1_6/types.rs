@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use anyhow::Result;
-use rust_ocpp::v1_6::types::AuthorizationStatus;
+use rust_ocpp::v1_6::types::{AuthorizationStatus, ChargePointErrorCode};
 use serde::Serialize;
 
 #[derive(Debug, Serialize, Clone)]
@@ -36,6 +36,19 @@ pub enum OcppAction {
   GetCompositeSchedule,
   GetLocalListVersion,
   SendLocalList,
+
+  // Security Extension, CP → CSMS
+  SignCertificate,
+  SecurityEventNotification,
+  SignedFirmwareStatusNotification,
+
+  // Security Extension, CSMS → CP
+  CertificateSigned,
+  InstallCertificate,
+  DeleteCertificate,
+  GetInstalledCertificateIds,
+  ExtendedTriggerMessage,
+  SignedUpdateFirmware,
 }
 
 impl FromStr for OcppAction {
@@ -74,6 +87,16 @@ impl FromStr for OcppAction {
       "GetLocalListVersion" => Ok(GetLocalListVersion),
       "SendLocalList" => Ok(SendLocalList),
 
+      "SignCertificate" => Ok(SignCertificate),
+      "SecurityEventNotification" => Ok(SecurityEventNotification),
+      "CertificateSigned" => Ok(CertificateSigned),
+      "InstallCertificate" => Ok(InstallCertificate),
+      "DeleteCertificate" => Ok(DeleteCertificate),
+      "GetInstalledCertificateIds" => Ok(GetInstalledCertificateIds),
+      "ExtendedTriggerMessage" => Ok(ExtendedTriggerMessage),
+      "SignedFirmwareStatusNotification" => Ok(SignedFirmwareStatusNotification),
+      "SignedUpdateFirmware" => Ok(SignedUpdateFirmware),
+
       _ => Err("Unknown OCPP v1.6 action"),
     }
   }
@@ -111,6 +134,15 @@ impl std::fmt::Display for OcppAction {
       GetCompositeSchedule => "GetCompositeSchedule",
       GetLocalListVersion => "GetLocalListVersion",
       SendLocalList => "SendLocalList",
+      SignCertificate => "SignCertificate",
+      SecurityEventNotification => "SecurityEventNotification",
+      CertificateSigned => "CertificateSigned",
+      InstallCertificate => "InstallCertificate",
+      DeleteCertificate => "DeleteCertificate",
+      GetInstalledCertificateIds => "GetInstalledCertificateIds",
+      ExtendedTriggerMessage => "ExtendedTriggerMessage",
+      SignedFirmwareStatusNotification => "SignedFirmwareStatusNotification",
+      SignedUpdateFirmware => "SignedUpdateFirmware",
     };
 
     write!(f, "{s}")
@@ -128,3 +160,32 @@ impl From<AuthorizationStatus> for crate::types::AuthorizationStatus {
     }
   }
 }
+
+/// Conversion of configuration types defined in `common` into OCPP 1.6 types
+pub trait IntoV16<T> {
+  fn into_v16(self) -> T;
+}
+
+impl IntoV16<ChargePointErrorCode> for common::ChargePointErrorCode {
+  fn into_v16(self) -> ChargePointErrorCode {
+    use common::ChargePointErrorCode::*;
+
+    match self {
+      ConnectorLockFailure => ChargePointErrorCode::ConnectorLockFailure,
+      EVCommunicationError => ChargePointErrorCode::EVCommunicationError,
+      GroundFailure => ChargePointErrorCode::GroundFailure,
+      HighTemperature => ChargePointErrorCode::HighTemperature,
+      InternalError => ChargePointErrorCode::InternalError,
+      LocalListConflict => ChargePointErrorCode::LocalListConflict,
+      OtherError => ChargePointErrorCode::OtherError,
+      OverCurrentFailure => ChargePointErrorCode::OverCurrentFailure,
+      OverVoltage => ChargePointErrorCode::OverVoltage,
+      PowerMeterFailure => ChargePointErrorCode::PowerMeterFailure,
+      PowerSwitchFailure => ChargePointErrorCode::PowerSwitchFailure,
+      ReaderFailure => ChargePointErrorCode::ReaderFailure,
+      ResetFailure => ChargePointErrorCode::ResetFailure,
+      UnderVoltage => ChargePointErrorCode::UnderVoltage,
+      WeakSignal => ChargePointErrorCode::WeakSignal,
+    }
+  }
+}
@@ -0,0 +1,1208 @@
+//! OCPP 2.0.1 device model: the components and variables of a charging station, built in
+//! or loaded from a `device_model` file, with the overrides of the charge point applied,
+//! and the monitors of its variables
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use common::{
+  ChargePointConfig, CurrentType, DeviceModelFile, VariableConfig, shared_data::ChargePointSettings,
+};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use rust_ocpp::v2_0_1::{
+  datatypes::{
+    clear_monitoring_result_type::ClearMonitoringResultType, component_type::ComponentType,
+    component_variable_type::ComponentVariableType, event_data_type::EventDataType,
+    evse_type::EVSEType, get_variable_data_type::GetVariableDataType,
+    get_variable_result_type::GetVariableResultType, monitoring_data_type::MonitoringDataType,
+    report_data_type::ReportDataType, set_monitoring_data_type::SetMonitoringDataType,
+    set_monitoring_result_type::SetMonitoringResultType,
+    set_variable_data_type::SetVariableDataType, set_variable_result_type::SetVariableResultType,
+    variable_attribute_type::VariableAttributeType,
+    variable_characteristics_type::VariableCharacteristicsType, variable_type::VariableType,
+  },
+  enumerations::{
+    attribute_enum_type::AttributeEnumType,
+    component_criterion_enum_type::ComponentCriterionEnumType, data_enum_type::DataEnumType,
+    get_variable_status_enum_type::GetVariableStatusEnumType, monitor_enum_type::MonitorEnumType,
+    monitoring_base_enum_type::MonitoringBaseEnumType,
+    monitoring_criterion_enum_type::MonitoringCriterionEnumType,
+    mutability_enum_type::MutabilityEnumType, report_base_enum_type::ReportBaseEnumType,
+    set_monitoring_status_enum_type::SetMonitoringStatusEnumType,
+    set_variable_status_enum_type::SetVariableStatusEnumType,
+  },
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::display::MAX_DISPLAY_MESSAGES;
+use super::monitoring::{DEBUG_SEVERITY, Monitor, Monitoring};
+
+/// Measurands the simulated meter can sample
+const MEASURANDS: &str = "Energy.Active.Import.Register,Energy.Active.Import.Interval,Power.Active.Import,Current.Import,Voltage,Power.Factor,SoC,Frequency,Temperature";
+const TX_POINTS: &str =
+  "ParkingBayOccupancy,EVConnected,Authorized,DataSigned,PowerPathClosed,EnergyTransfer";
+const AVAILABILITY_STATES: &str = "Available,Occupied,Reserved,Unavailable,Faulted";
+const MESSAGE_FORMATS: &str = "ASCII,HTML,URI,UTF8";
+const MESSAGE_PRIORITIES: &str = "AlwaysFront,InFront,NormalCycle";
+/// Temperature of the preconfigured `UpperThreshold` monitor in °C
+const OVERHEAT_TEMPERATURE: i64 = 60;
+/// Severity `Alert`
+const OVERHEAT_SEVERITY: u8 = 5;
+
+struct Variable {
+  component: ComponentType,
+  variable: VariableType,
+  attributes: Vec<VariableAttributeType>,
+  characteristics: VariableCharacteristicsType,
+}
+
+impl Variable {
+  fn attribute(&self, kind: &AttributeEnumType) -> Option<&VariableAttributeType> {
+    self
+      .attributes
+      .iter()
+      .find(|attribute| attribute.kind.as_ref() == Some(kind))
+  }
+
+  fn is_writable(&self) -> bool {
+    self
+      .attributes
+      .iter()
+      .any(|attribute| attribute.mutability != Some(MutabilityEnumType::ReadOnly))
+  }
+
+  fn report_data(&self) -> ReportDataType {
+    // Values of write-only attributes are never reported
+    let variable_attribute = self
+      .attributes
+      .iter()
+      .map(|attribute| VariableAttributeType {
+        value: attribute
+          .value
+          .clone()
+          .filter(|_| attribute.mutability != Some(MutabilityEnumType::WriteOnly)),
+        ..attribute.clone()
+      })
+      .collect();
+
+    ReportDataType {
+      component: self.component.clone(),
+      variable: self.variable.clone(),
+      variable_attribute,
+      variable_characteristics: Some(self.characteristics.clone()),
+    }
+  }
+
+  /// Whether a value fits the data type and limits of the variable
+  fn accepts(&self, value: &str) -> bool {
+    let characteristics = &self.characteristics;
+    let within_limits = |number: f64| {
+      let min = characteristics.min_limit.and_then(|limit| limit.to_f64());
+      let max = characteristics.max_limit.and_then(|limit| limit.to_f64());
+      min.is_none_or(|min| number >= min) && max.is_none_or(|max| number <= max)
+    };
+    let allowed = |member: &str| {
+      characteristics
+        .values_list
+        .as_deref()
+        .is_none_or(|values_list| values_list.split(',').any(|allowed| allowed == member))
+    };
+
+    match characteristics.data_type {
+      DataEnumType::Integer => value.parse::<i64>().is_ok_and(|n| within_limits(n as f64)),
+      DataEnumType::Decimal => value.parse::<f64>().is_ok_and(within_limits),
+      DataEnumType::Boolean => value == "true" || value == "false",
+      DataEnumType::Datetime => DateTime::parse_from_rfc3339(value).is_ok(),
+      DataEnumType::OptionList => allowed(value),
+      DataEnumType::MemberList | DataEnumType::SequenceList => {
+        value.is_empty() || value.split(',').all(allowed)
+      }
+      DataEnumType::String => within_limits(value.len() as f64),
+    }
+  }
+}
+
+pub struct DeviceModel {
+  variables: Vec<Variable>,
+  monitoring: Monitoring,
+}
+
+impl DeviceModel {
+  pub fn new(config: &ChargePointConfig) -> Result<Self> {
+    let definitions = match &config.device_model {
+      Some(path) => DeviceModelFile::try_load(path)?.variables,
+      None => Self::built_in(config),
+    };
+
+    let mut device_model = Self {
+      variables: Vec::new(),
+      monitoring: Monitoring::new(Vec::new()),
+    };
+
+    for variable in definitions.iter().chain(&config.variables) {
+      device_model.apply(variable).with_context(|| {
+        format!(
+          "Charge point {}: invalid variable {}.{}",
+          config.id, variable.component, variable.variable
+        )
+      })?;
+    }
+
+    // The charging station warns about overheating unless its model has no temperature
+    let (component, variable) = temperature_sensor();
+    if device_model.find(&component, &variable).is_ok() {
+      device_model.monitoring = Monitoring::new(vec![Monitor::preconfigured(
+        1,
+        component,
+        variable,
+        MonitorEnumType::UpperThreshold,
+        Decimal::from(OVERHEAT_TEMPERATURE),
+        OVERHEAT_SEVERITY,
+      )]);
+    }
+
+    Ok(device_model)
+  }
+
+  /// Value of the `Actual` attribute, e.g. an `ItemsPerMessage` limit
+  pub fn actual(&self, component: &str, variable: &str, instance: Option<&str>) -> Option<&str> {
+    self
+      .variables
+      .iter()
+      .find(|entry| {
+        entry.component.name == component
+          && entry.variable.name == variable
+          && entry.variable.instance.as_deref() == instance
+      })?
+      .attribute(&AttributeEnumType::Actual)?
+      .value
+      .as_deref()
+  }
+
+  pub fn get(&self, data: &GetVariableDataType) -> GetVariableResultType {
+    let attribute_type = data.attribute_type.clone().unwrap_or_default();
+    let mut result = GetVariableResultType {
+      attribute_status: GetVariableStatusEnumType::Accepted,
+      attribute_type: data.attribute_type.clone(),
+      component: data.component.clone(),
+      variable: data.variable.clone(),
+      ..Default::default()
+    };
+
+    result.attribute_status = match self.find(&data.component, &data.variable) {
+      Err(status) => status.into(),
+      Ok(index) => match self.variables[index].attribute(&attribute_type) {
+        None => GetVariableStatusEnumType::NotSupportedAttributeType,
+        Some(attribute) if attribute.mutability == Some(MutabilityEnumType::WriteOnly) => {
+          GetVariableStatusEnumType::Rejected
+        }
+        Some(attribute) => {
+          result.attribute_value = Some(attribute.value.clone().unwrap_or_default());
+          GetVariableStatusEnumType::Accepted
+        }
+      },
+    };
+
+    result
+  }
+
+  /// Sets an attribute, returns the result and whether the value was accepted
+  pub fn set(&mut self, data: &SetVariableDataType) -> (SetVariableResultType, bool) {
+    let attribute_type = data.attribute_type.clone().unwrap_or_default();
+
+    let status = match self.find(&data.component, &data.variable) {
+      Err(status) => status.into(),
+      Ok(index) => {
+        let variable = &self.variables[index];
+        match variable.attribute(&attribute_type) {
+          None => SetVariableStatusEnumType::NotSupportedAttributeType,
+          Some(attribute) if attribute.mutability == Some(MutabilityEnumType::ReadOnly) => {
+            SetVariableStatusEnumType::Rejected
+          }
+          Some(_) if !variable.accepts(&data.attribute_value) => {
+            SetVariableStatusEnumType::Rejected
+          }
+          Some(_) => {
+            let attribute = self.variables[index]
+              .attributes
+              .iter_mut()
+              .find(|attribute| attribute.kind.as_ref() == Some(&attribute_type));
+            if let Some(attribute) = attribute {
+              attribute.value = Some(data.attribute_value.clone());
+            }
+            SetVariableStatusEnumType::Accepted
+          }
+        }
+      }
+    };
+
+    let accepted = status == SetVariableStatusEnumType::Accepted;
+    let result = SetVariableResultType {
+      attribute_type: data.attribute_type.clone(),
+      attribute_status: status,
+      component: data.component.clone(),
+      variable: data.variable.clone(),
+      attribute_status_info: None,
+    };
+
+    (result, accepted)
+  }
+
+  /// Variables of a `GetBaseReport`
+  pub fn base_report(&self, report_base: &ReportBaseEnumType) -> Vec<ReportDataType> {
+    self
+      .variables
+      .iter()
+      .filter(|variable| match report_base {
+        ReportBaseEnumType::FullInventory => true,
+        ReportBaseEnumType::ConfigurationInventory => variable.is_writable(),
+        ReportBaseEnumType::SummaryInventory => variable.variable.name == "AvailabilityState",
+      })
+      .map(Variable::report_data)
+      .collect()
+  }
+
+  /// Variables of a `GetReport`, of the components meeting all criteria and matching
+  /// one of the requested component variables
+  pub fn report(
+    &self,
+    criteria: &[ComponentCriterionEnumType],
+    component_variables: &[ComponentVariableType],
+  ) -> Vec<ReportDataType> {
+    self
+      .variables
+      .iter()
+      .filter(|variable| {
+        criteria
+          .iter()
+          .all(|criterion| self.meets(&variable.component, criterion))
+      })
+      .filter(|variable| {
+        component_variables.is_empty()
+          || component_variables.iter().any(|requested| {
+            matches_component(&requested.component, &variable.component)
+              && requested
+                .variable
+                .as_ref()
+                .is_none_or(|requested| *requested == variable.variable)
+          })
+      })
+      .map(Variable::report_data)
+      .collect()
+  }
+
+  /// Adds or replaces a monitor of a variable that supports monitoring
+  pub fn set_monitor(&mut self, data: &SetMonitoringDataType) -> SetMonitoringResultType {
+    let result = match self.find(&data.component, &data.variable) {
+      Err(UnknownStatus::Component) => Err(SetMonitoringStatusEnumType::UnknownComponent),
+      Err(UnknownStatus::Variable) => Err(SetMonitoringStatusEnumType::UnknownVariable),
+      Ok(index) if !self.variables[index].characteristics.supports_monitoring => {
+        Err(SetMonitoringStatusEnumType::UnsupportedMonitorType)
+      }
+      Ok(_) => self.monitoring.set(data),
+    };
+
+    let (id, status) = match result {
+      Ok(id) => (Some(id), SetMonitoringStatusEnumType::Accepted),
+      Err(status) => (data.id, status),
+    };
+
+    SetMonitoringResultType {
+      id,
+      status,
+      kind: data.kind.clone(),
+      severity: data.severity,
+      component: data.component.clone(),
+      variable: data.variable.clone(),
+      status_info: None,
+    }
+  }
+
+  pub fn has_monitors(&self) -> bool {
+    !self.monitoring.is_empty()
+  }
+
+  pub fn clear_monitor(&mut self, id: i32) -> ClearMonitoringResultType {
+    self.monitoring.clear(id)
+  }
+
+  pub fn set_monitoring_base(&mut self, base: &MonitoringBaseEnumType) {
+    self.monitoring.set_base(base);
+
+    let base = serde_json::to_value(base).unwrap_or_default();
+    self.set_actual(
+      "MonitoringCtrlr",
+      "ActiveMonitoringBase",
+      base.as_str().unwrap_or_default(),
+    );
+  }
+
+  /// Only monitors at least as severe as the level report events, `false` for an
+  /// unknown severity
+  pub fn set_monitoring_level(&mut self, severity: u8) -> bool {
+    if severity > DEBUG_SEVERITY {
+      return false;
+    }
+
+    self.monitoring.level = severity;
+    self.set_actual(
+      "MonitoringCtrlr",
+      "ActiveMonitoringLevel",
+      &severity.to_string(),
+    );
+    true
+  }
+
+  /// Monitors of a `GetMonitoringReport` meeting one of the criteria and matching one of
+  /// the requested component variables
+  pub fn monitoring_report(
+    &self,
+    criteria: &[MonitoringCriterionEnumType],
+    component_variables: &[ComponentVariableType],
+  ) -> Vec<MonitoringDataType> {
+    self.monitoring.report(criteria, |monitor| {
+      component_variables.is_empty()
+        || component_variables.iter().any(|requested| {
+          matches_component(&requested.component, &monitor.component)
+            && requested
+              .variable
+              .as_ref()
+              .is_none_or(|requested| *requested == monitor.variable)
+        })
+    })
+  }
+
+  /// Records the sampled power and temperature, returns the events of the tripped monitors
+  pub fn sample(
+    &mut self,
+    power: f64,
+    temperature: f64,
+    transaction_id: Option<&str>,
+  ) -> Vec<EventDataType> {
+    if self.actual("MonitoringCtrlr", "Enabled", None) == Some("false") {
+      return Vec::new();
+    }
+
+    let power_component = ComponentType {
+      name: "EVSE".to_string(),
+      instance: None,
+      evse: Some(EVSEType {
+        id: 1,
+        connector_id: None,
+      }),
+    };
+    let power_variable = VariableType {
+      name: "Power".to_string(),
+      instance: None,
+    };
+    let (temperature_component, temperature_variable) = temperature_sensor();
+
+    let samples = [
+      (power_component, power_variable, power),
+      (temperature_component, temperature_variable, temperature),
+    ];
+
+    let mut events = Vec::new();
+    for (component, variable, value) in samples {
+      let Ok(index) = self.find(&component, &variable) else {
+        continue;
+      };
+
+      let actual = self.variables[index]
+        .attributes
+        .iter_mut()
+        .find(|attribute| attribute.kind == Some(AttributeEnumType::Actual));
+      if let Some(actual) = actual {
+        actual.value = Some(format!("{value:.1}"));
+      }
+
+      events.extend(
+        self
+          .monitoring
+          .evaluate(&component, &variable, value, transaction_id),
+      );
+    }
+
+    events
+  }
+
+  pub fn set_actual(&mut self, component: &str, variable: &str, value: &str) {
+    let actual = self
+      .variables
+      .iter_mut()
+      .filter(|entry| entry.component.name == component && entry.variable.name == variable)
+      .flat_map(|entry| entry.attributes.iter_mut())
+      .find(|attribute| attribute.kind == Some(AttributeEnumType::Actual));
+
+    if let Some(actual) = actual {
+      actual.value = Some(value.to_string());
+    }
+  }
+
+  fn find(
+    &self,
+    component: &ComponentType,
+    variable: &VariableType,
+  ) -> Result<usize, UnknownStatus> {
+    if !self
+      .variables
+      .iter()
+      .any(|entry| entry.component == *component)
+    {
+      return Err(UnknownStatus::Component);
+    }
+
+    self
+      .variables
+      .iter()
+      .position(|entry| entry.component == *component && entry.variable == *variable)
+      .ok_or(UnknownStatus::Variable)
+  }
+
+  /// Criteria are met by components whose `Active`, `Available`, `Enabled` or `Problem`
+  /// variable is `true`
+  fn meets(&self, component: &ComponentType, criterion: &ComponentCriterionEnumType) -> bool {
+    let name = match criterion {
+      ComponentCriterionEnumType::Active => "Active",
+      ComponentCriterionEnumType::Available => "Available",
+      ComponentCriterionEnumType::Enabled => "Enabled",
+      ComponentCriterionEnumType::Problem => "Problem",
+    };
+
+    self.variables.iter().any(|entry| {
+      entry.component == *component
+        && entry.variable.name == name
+        && entry
+          .attribute(&AttributeEnumType::Actual)
+          .and_then(|attribute| attribute.value.as_deref())
+          == Some("true")
+    })
+  }
+
+  /// Defines a variable or overrides the given fields of an existing one
+  fn apply(&mut self, config: &VariableConfig) -> Result<()> {
+    let component = ComponentType {
+      name: config.component.clone(),
+      instance: config.component_instance.clone(),
+      evse: config.evse.map(|id| EVSEType {
+        id,
+        connector_id: config.connector,
+      }),
+    };
+    let variable = VariableType {
+      name: config.variable.clone(),
+      instance: config.variable_instance.clone(),
+    };
+
+    let index = match self
+      .variables
+      .iter()
+      .position(|entry| entry.component == component && entry.variable == variable)
+    {
+      Some(index) => index,
+      None => {
+        self.variables.push(Variable {
+          component,
+          variable,
+          attributes: Vec::new(),
+          characteristics: VariableCharacteristicsType::default(),
+        });
+        self.variables.len() - 1
+      }
+    };
+    let entry = &mut self.variables[index];
+
+    let values = [
+      (AttributeEnumType::Actual, &config.actual),
+      (AttributeEnumType::Target, &config.target),
+      (AttributeEnumType::MinSet, &config.min_set),
+      (AttributeEnumType::MaxSet, &config.max_set),
+    ];
+    for (kind, value) in values {
+      let Some(value) = value else {
+        continue;
+      };
+
+      match entry
+        .attributes
+        .iter_mut()
+        .find(|attribute| attribute.kind.as_ref() == Some(&kind))
+      {
+        Some(attribute) => attribute.value = Some(value.clone()),
+        None => entry.attributes.push(VariableAttributeType {
+          kind: Some(kind),
+          value: Some(value.clone()),
+          ..Default::default()
+        }),
+      }
+    }
+
+    if entry.attributes.is_empty() {
+      entry.attributes.push(VariableAttributeType {
+        kind: Some(AttributeEnumType::Actual),
+        ..Default::default()
+      });
+    }
+
+    let mutability = config
+      .mutability
+      .as_deref()
+      .map(parse_enum::<MutabilityEnumType>)
+      .transpose()?;
+    for attribute in &mut entry.attributes {
+      if let Some(mutability) = &mutability {
+        attribute.mutability = Some(mutability.clone());
+      }
+      attribute
+        .mutability
+        .get_or_insert(MutabilityEnumType::ReadWrite);
+      attribute.persistent = config.persistent.or(attribute.persistent);
+      attribute.constant = config.constant.or(attribute.constant);
+    }
+
+    let characteristics = &mut entry.characteristics;
+    if let Some(data_type) = &config.data_type {
+      characteristics.data_type = parse_enum(data_type)?;
+    }
+    if config.unit.is_some() {
+      characteristics.unit = config.unit.clone();
+    }
+    if let Some(min_limit) = config.min_limit {
+      characteristics.min_limit = Decimal::from_f64_retain(min_limit);
+    }
+    if let Some(max_limit) = config.max_limit {
+      characteristics.max_limit = Decimal::from_f64_retain(max_limit);
+    }
+    if config.values_list.is_some() {
+      characteristics.values_list = config.values_list.clone();
+    }
+    if let Some(supports_monitoring) = config.supports_monitoring {
+      characteristics.supports_monitoring = supports_monitoring;
+    }
+
+    Ok(())
+  }
+
+  /// Controllers of the simulated charging station with one EVSE and one connector
+  fn built_in(config: &ChargePointConfig) -> Vec<VariableConfig> {
+    let settings = ChargePointSettings::default();
+    let meter = &config.meter;
+    let connector_type = match meter.current_type {
+      CurrentType::Ac => "cType2",
+      CurrentType::Dc => "cCCS2",
+    };
+
+    let read_only =
+      |component: &str, variable: &str, data_type: &str, actual: String| VariableConfig {
+        component: component.to_string(),
+        variable: variable.to_string(),
+        actual: Some(actual),
+        mutability: Some("ReadOnly".to_string()),
+        data_type: Some(data_type.to_string()),
+        ..Default::default()
+      };
+    let read_write =
+      |component: &str, variable: &str, data_type: &str, actual: String| VariableConfig {
+        mutability: Some("ReadWrite".to_string()),
+        ..read_only(component, variable, data_type, actual)
+      };
+    let seconds = |variable: VariableConfig, min_limit: f64| VariableConfig {
+      unit: Some("s".to_string()),
+      min_limit: Some(min_limit),
+      ..variable
+    };
+    let instance = |variable: VariableConfig, instance: &str| VariableConfig {
+      variable_instance: Some(instance.to_string()),
+      ..variable
+    };
+    let list = |variable: VariableConfig, values_list: &str| VariableConfig {
+      values_list: Some(values_list.to_string()),
+      ..variable
+    };
+    let at = |variable: VariableConfig, connector: Option<i32>| VariableConfig {
+      evse: Some(1),
+      connector,
+      ..variable
+    };
+
+    vec![
+      // 🏭 Charging station
+      read_only("ChargingStation", "Model", "string", config.model.clone()),
+      read_only(
+        "ChargingStation",
+        "VendorName",
+        "string",
+        config.vendor.clone(),
+      ),
+      list(
+        read_only(
+          "ChargingStation",
+          "AvailabilityState",
+          "OptionList",
+          "Available".to_string(),
+        ),
+        AVAILABILITY_STATES,
+      ),
+      read_only(
+        "ChargingStation",
+        "Available",
+        "boolean",
+        "true".to_string(),
+      ),
+      // 📊 Device data
+      instance(
+        read_only(
+          "DeviceDataCtrlr",
+          "ItemsPerMessage",
+          "integer",
+          "20".to_string(),
+        ),
+        "GetReport",
+      ),
+      instance(
+        read_only(
+          "DeviceDataCtrlr",
+          "ItemsPerMessage",
+          "integer",
+          "20".to_string(),
+        ),
+        "GetVariables",
+      ),
+      instance(
+        read_only(
+          "DeviceDataCtrlr",
+          "ItemsPerMessage",
+          "integer",
+          "20".to_string(),
+        ),
+        "SetVariables",
+      ),
+      // 📡 Monitoring
+      read_write("MonitoringCtrlr", "Enabled", "boolean", "true".to_string()),
+      instance(
+        read_only(
+          "MonitoringCtrlr",
+          "ItemsPerMessage",
+          "integer",
+          "20".to_string(),
+        ),
+        "SetVariableMonitoring",
+      ),
+      instance(
+        read_only(
+          "MonitoringCtrlr",
+          "ItemsPerMessage",
+          "integer",
+          "20".to_string(),
+        ),
+        "ClearVariableMonitoring",
+      ),
+      list(
+        read_only(
+          "MonitoringCtrlr",
+          "ActiveMonitoringBase",
+          "OptionList",
+          "All".to_string(),
+        ),
+        "All,FactoryDefault,HardWiredOnly",
+      ),
+      read_only(
+        "MonitoringCtrlr",
+        "ActiveMonitoringLevel",
+        "integer",
+        DEBUG_SEVERITY.to_string(),
+      ),
+      // 🌡️ Temperature
+      VariableConfig {
+        unit: Some("Celsius".to_string()),
+        supports_monitoring: Some(true),
+        ..read_only(
+          "TemperatureSensor",
+          "Temperature",
+          "decimal",
+          format!("{:.1}", meter.temperature),
+        )
+      },
+      // 🌐 Communication
+      seconds(
+        read_write(
+          "OCPPCommCtrlr",
+          "HeartbeatInterval",
+          "integer",
+          config.heartbeat_interval.to_string(),
+        ),
+        1.0,
+      ),
+      seconds(
+        read_write(
+          "OCPPCommCtrlr",
+          "WebSocketPingInterval",
+          "integer",
+          "30".to_string(),
+        ),
+        1.0,
+      ),
+      instance(
+        seconds(
+          read_only(
+            "OCPPCommCtrlr",
+            "MessageTimeout",
+            "integer",
+            "30".to_string(),
+          ),
+          1.0,
+        ),
+        "Default",
+      ),
+      seconds(
+        read_write(
+          "OCPPCommCtrlr",
+          "OfflineThreshold",
+          "integer",
+          "60".to_string(),
+        ),
+        0.0,
+      ),
+      read_write(
+        "OCPPCommCtrlr",
+        "ResetRetries",
+        "integer",
+        settings.reset_retries.to_string(),
+      ),
+      instance(
+        read_write(
+          "OCPPCommCtrlr",
+          "MessageAttempts",
+          "integer",
+          settings.transaction_message_attempts.to_string(),
+        ),
+        "TransactionEvent",
+      ),
+      instance(
+        seconds(
+          read_write(
+            "OCPPCommCtrlr",
+            "MessageAttemptInterval",
+            "integer",
+            settings.transaction_message_retry_interval.to_string(),
+          ),
+          0.0,
+        ),
+        "TransactionEvent",
+      ),
+      // 🔄 Transactions
+      seconds(
+        read_write(
+          "TxCtrlr",
+          "EVConnectionTimeOut",
+          "integer",
+          settings.connection_timeout.to_string(),
+        ),
+        0.0,
+      ),
+      read_write(
+        "TxCtrlr",
+        "StopTxOnEVSideDisconnect",
+        "boolean",
+        settings.stop_transaction_on_ev_side_disconnect.to_string(),
+      ),
+      read_write(
+        "TxCtrlr",
+        "StopTxOnInvalidId",
+        "boolean",
+        settings.stop_transaction_on_invalid_id.to_string(),
+      ),
+      VariableConfig {
+        unit: Some("Wh".to_string()),
+        ..read_write(
+          "TxCtrlr",
+          "MaxEnergyOnInvalidId",
+          "integer",
+          settings.max_energy_on_invalid_id.to_string(),
+        )
+      },
+      list(
+        read_write(
+          "TxCtrlr",
+          "TxStartPoint",
+          "MemberList",
+          "Authorized,EVConnected".to_string(),
+        ),
+        TX_POINTS,
+      ),
+      list(
+        read_write(
+          "TxCtrlr",
+          "TxStopPoint",
+          "MemberList",
+          "Authorized,EVConnected".to_string(),
+        ),
+        TX_POINTS,
+      ),
+      // ⚡ Sampled data
+      seconds(
+        read_write(
+          "SampledDataCtrlr",
+          "TxUpdatedInterval",
+          "integer",
+          config.txn_meter_values_interval.to_string(),
+        ),
+        1.0,
+      ),
+      list(
+        read_write(
+          "SampledDataCtrlr",
+          "TxUpdatedMeasurands",
+          "MemberList",
+          "Energy.Active.Import.Register,Power.Active.Import,Current.Import,Voltage,SoC"
+            .to_string(),
+        ),
+        MEASURANDS,
+      ),
+      list(
+        read_write(
+          "SampledDataCtrlr",
+          "TxEndedMeasurands",
+          "MemberList",
+          settings.stop_txn_sampled_data.clone(),
+        ),
+        MEASURANDS,
+      ),
+      seconds(
+        read_write(
+          "SampledDataCtrlr",
+          "TxEndedInterval",
+          "integer",
+          "0".to_string(),
+        ),
+        0.0,
+      ),
+      // 🔐 Authorization
+      read_write("AuthCtrlr", "Enabled", "boolean", "true".to_string()),
+      read_write(
+        "AuthCtrlr",
+        "AuthorizeRemoteStart",
+        "boolean",
+        settings.authorize_remote_tx_requests.to_string(),
+      ),
+      read_write(
+        "AuthCtrlr",
+        "LocalAuthorizeOffline",
+        "boolean",
+        settings.local_authorize_offline.to_string(),
+      ),
+      read_write(
+        "AuthCtrlr",
+        "LocalPreAuthorize",
+        "boolean",
+        settings.local_pre_authorize.to_string(),
+      ),
+      read_write(
+        "AuthCtrlr",
+        "OfflineTxForUnknownIdEnabled",
+        "boolean",
+        settings.allow_offline_tx_for_unknown_id.to_string(),
+      ),
+      // 🖥️ Display messages and tariff
+      read_write(
+        "DisplayMessageCtrlr",
+        "Enabled",
+        "boolean",
+        "true".to_string(),
+      ),
+      read_only(
+        "DisplayMessageCtrlr",
+        "Available",
+        "boolean",
+        "true".to_string(),
+      ),
+      VariableConfig {
+        max_limit: Some(MAX_DISPLAY_MESSAGES as f64),
+        ..read_only(
+          "DisplayMessageCtrlr",
+          "DisplayMessages",
+          "integer",
+          "0".to_string(),
+        )
+      },
+      list(
+        read_only(
+          "DisplayMessageCtrlr",
+          "SupportedFormats",
+          "MemberList",
+          MESSAGE_FORMATS.to_string(),
+        ),
+        MESSAGE_FORMATS,
+      ),
+      list(
+        read_only(
+          "DisplayMessageCtrlr",
+          "SupportedPriorities",
+          "MemberList",
+          MESSAGE_PRIORITIES.to_string(),
+        ),
+        MESSAGE_PRIORITIES,
+      ),
+      read_only("TariffCostCtrlr", "Currency", "string", "EUR".to_string()),
+      // 🛡️ Security
+      read_only(
+        "SecurityCtrlr",
+        "SecurityProfile",
+        "integer",
+        config.security.profile.to_string(),
+      ),
+      read_only("SecurityCtrlr", "Identity", "string", config.id.clone()),
+      // 🔌 EVSE and connector
+      at(
+        list(
+          read_only(
+            "EVSE",
+            "AvailabilityState",
+            "OptionList",
+            "Available".to_string(),
+          ),
+          AVAILABILITY_STATES,
+        ),
+        None,
+      ),
+      at(
+        read_only("EVSE", "Available", "boolean", "true".to_string()),
+        None,
+      ),
+      at(
+        VariableConfig {
+          unit: Some("W".to_string()),
+          max_set: Some(meter.max_power().to_string()),
+          supports_monitoring: Some(true),
+          ..read_only("EVSE", "Power", "decimal", "0".to_string())
+        },
+        None,
+      ),
+      at(
+        read_only(
+          "EVSE",
+          "SupplyPhases",
+          "integer",
+          meter.phases().to_string(),
+        ),
+        None,
+      ),
+      at(
+        list(
+          read_only(
+            "Connector",
+            "AvailabilityState",
+            "OptionList",
+            "Available".to_string(),
+          ),
+          AVAILABILITY_STATES,
+        ),
+        Some(1),
+      ),
+      at(
+        read_only("Connector", "Available", "boolean", "true".to_string()),
+        Some(1),
+      ),
+      at(
+        read_only(
+          "Connector",
+          "ConnectorType",
+          "string",
+          connector_type.to_string(),
+        ),
+        Some(1),
+      ),
+      at(
+        read_only(
+          "Connector",
+          "SupplyPhases",
+          "integer",
+          meter.phases().to_string(),
+        ),
+        Some(1),
+      ),
+    ]
+  }
+}
+
+/// Lookup failure shared by `GetVariables` and `SetVariables`
+enum UnknownStatus {
+  Component,
+  Variable,
+}
+
+impl From<UnknownStatus> for GetVariableStatusEnumType {
+  fn from(value: UnknownStatus) -> Self {
+    match value {
+      UnknownStatus::Component => GetVariableStatusEnumType::UnknownComponent,
+      UnknownStatus::Variable => GetVariableStatusEnumType::UnknownVariable,
+    }
+  }
+}
+
+impl From<UnknownStatus> for SetVariableStatusEnumType {
+  fn from(value: UnknownStatus) -> Self {
+    match value {
+      UnknownStatus::Component => SetVariableStatusEnumType::UnknownComponent,
+      UnknownStatus::Variable => SetVariableStatusEnumType::UnknownVariable,
+    }
+  }
+}
+
+fn temperature_sensor() -> (ComponentType, VariableType) {
+  let component = ComponentType {
+    name: "TemperatureSensor".to_string(),
+    ..Default::default()
+  };
+  let variable = VariableType {
+    name: "Temperature".to_string(),
+    instance: None,
+  };
+  (component, variable)
+}
+
+/// A requested component without EVSE or instance matches all of them
+fn matches_component(requested: &ComponentType, component: &ComponentType) -> bool {
+  requested.name == component.name
+    && (requested.instance.is_none() || requested.instance == component.instance)
+    && requested.evse.as_ref().is_none_or(|evse| {
+      component.evse.as_ref().is_some_and(|component_evse| {
+        component_evse.id == evse.id
+          && (evse.connector_id.is_none() || evse.connector_id == component_evse.connector_id)
+      })
+    })
+}
+
+fn parse_enum<T: DeserializeOwned>(value: &str) -> Result<T> {
+  serde_json::from_value(Value::String(value.to_string()))
+    .with_context(|| format!("Unknown value {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::test_config::charge_point_config;
+
+  fn component(name: &str) -> ComponentType {
+    ComponentType {
+      name: name.to_string(),
+      ..Default::default()
+    }
+  }
+
+  fn variable(name: &str) -> VariableType {
+    VariableType {
+      name: name.to_string(),
+      instance: None,
+    }
+  }
+
+  fn get(
+    device_model: &DeviceModel,
+    component_name: &str,
+    variable_name: &str,
+  ) -> GetVariableResultType {
+    device_model.get(&GetVariableDataType {
+      attribute_type: None,
+      component: component(component_name),
+      variable: variable(variable_name),
+    })
+  }
+
+  fn set(
+    device_model: &mut DeviceModel,
+    component_name: &str,
+    variable_name: &str,
+    value: &str,
+  ) -> (SetVariableResultType, bool) {
+    device_model.set(&SetVariableDataType {
+      attribute_type: None,
+      attribute_value: value.to_string(),
+      component: component(component_name),
+      variable: variable(variable_name),
+    })
+  }
+
+  #[test]
+  fn unknown_component_or_variable_is_reported() {
+    let device_model = DeviceModel::new(&charge_point_config(json!({}))).unwrap();
+
+    let result = get(&device_model, "Dishwasher", "HeartbeatInterval");
+    assert_eq!(
+      result.attribute_status,
+      GetVariableStatusEnumType::UnknownComponent
+    );
+    assert_eq!(result.attribute_value, None);
+
+    let result = get(&device_model, "OCPPCommCtrlr", "Colour");
+    assert_eq!(
+      result.attribute_status,
+      GetVariableStatusEnumType::UnknownVariable
+    );
+
+    let result = get(&device_model, "ChargingStation", "VendorName");
+    assert_eq!(result.attribute_status, GetVariableStatusEnumType::Accepted);
+    assert_eq!(result.attribute_value.as_deref(), Some("test-vendor"));
+  }
+
+  #[test]
+  fn read_only_variable_is_not_set() {
+    let mut device_model = DeviceModel::new(&charge_point_config(json!({}))).unwrap();
+
+    let (result, accepted) = set(&mut device_model, "ChargingStation", "Model", "other-model");
+    assert_eq!(result.attribute_status, SetVariableStatusEnumType::Rejected);
+    assert!(!accepted);
+    assert_eq!(
+      device_model.actual("ChargingStation", "Model", None),
+      Some("test-model")
+    );
+
+    let (result, accepted) = set(&mut device_model, "Dishwasher", "Model", "other-model");
+    assert_eq!(
+      result.attribute_status,
+      SetVariableStatusEnumType::UnknownComponent
+    );
+    assert!(!accepted);
+  }
+
+  #[test]
+  fn writable_variable_is_set_within_its_limits() {
+    let mut device_model = DeviceModel::new(&charge_point_config(json!({}))).unwrap();
+
+    let (result, accepted) = set(&mut device_model, "TxCtrlr", "StopTxOnInvalidId", "maybe");
+    assert_eq!(result.attribute_status, SetVariableStatusEnumType::Rejected);
+    assert!(!accepted);
+
+    let (result, accepted) = set(&mut device_model, "TxCtrlr", "StopTxOnInvalidId", "false");
+    assert_eq!(result.attribute_status, SetVariableStatusEnumType::Accepted);
+    assert!(accepted);
+    assert_eq!(
+      device_model.actual("TxCtrlr", "StopTxOnInvalidId", None),
+      Some("false")
+    );
+  }
+
+  #[test]
+  fn charge_point_variables_override_the_built_in_ones() {
+    let config = charge_point_config(json!({
+      "variables": [{
+        "component": "DeviceDataCtrlr",
+        "variable": "ItemsPerMessage",
+        "variable_instance": "GetVariables",
+        "actual": "2",
+      }],
+    }));
+    let device_model = DeviceModel::new(&config).unwrap();
+
+    assert_eq!(
+      device_model.actual("DeviceDataCtrlr", "ItemsPerMessage", Some("GetVariables")),
+      Some("2")
+    );
+    assert_eq!(
+      device_model.actual("DeviceDataCtrlr", "ItemsPerMessage", Some("SetVariables")),
+      Some("20")
+    );
+  }
+}
@@ -0,0 +1,222 @@
+//! OCPP 2.0.1 display messages set by the CSMS, with the personal message and the cost of
+//! the running transaction, rendered as the text the screen of the charging station shows
+
+use chrono::Utc;
+use rust_decimal::Decimal;
+use rust_ocpp::v2_0_1::{
+  datatypes::{message_content_type::MessageContentType, message_info_type::MessageInfoType},
+  enumerations::{
+    clear_message_status_enum_type::ClearMessageStatusEnumType,
+    display_message_status_enum_type::DisplayMessageStatusEnumType,
+    message_priority_enum_type::MessagePriorityEnumType,
+    message_state_enum_type::MessageStateEnumType,
+  },
+  messages::get_display_message::GetDisplayMessagesRequest,
+};
+use tracing::info;
+
+use crate::types::CommonConnectorStatusType;
+
+/// Messages the charging station can store
+pub const MAX_DISPLAY_MESSAGES: usize = 20;
+
+pub struct DisplayMessages {
+  messages: Vec<MessageInfoType>,
+  currency: String,
+  state: MessageStateEnumType,
+  transaction_id: Option<String>,
+  /// Tariff or other text for the driver of the running transaction
+  personal_message: Option<MessageContentType>,
+  total_cost: Option<Decimal>,
+  /// Last logged screen, only changes are logged
+  screen: String,
+}
+
+impl DisplayMessages {
+  pub fn new(currency: &str) -> Self {
+    Self {
+      messages: Vec::new(),
+      currency: currency.to_string(),
+      state: MessageStateEnumType::Idle,
+      transaction_id: None,
+      personal_message: None,
+      total_cost: None,
+      screen: String::new(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.messages.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.messages.is_empty()
+  }
+
+  /// Adds a message or replaces the one with the same id
+  pub fn set(&mut self, message: MessageInfoType) -> DisplayMessageStatusEnumType {
+    if message
+      .transaction_id
+      .as_ref()
+      .is_some_and(|transaction_id| self.transaction_id.as_ref() != Some(transaction_id))
+    {
+      return DisplayMessageStatusEnumType::UnknownTransaction;
+    }
+
+    let count = self.messages.len();
+    match self
+      .messages
+      .iter_mut()
+      .find(|existing| existing.id == message.id)
+    {
+      Some(existing) => *existing = message,
+      None if count >= MAX_DISPLAY_MESSAGES => return DisplayMessageStatusEnumType::Rejected,
+      None => self.messages.push(message),
+    }
+
+    self.refresh();
+    DisplayMessageStatusEnumType::Accepted
+  }
+
+  /// Messages matching all the given criteria
+  pub fn get(&self, request: &GetDisplayMessagesRequest) -> Vec<MessageInfoType> {
+    self
+      .messages
+      .iter()
+      .filter(|message| {
+        request
+          .id
+          .as_ref()
+          .is_none_or(|ids| ids.contains(&message.id))
+          && request
+            .priority
+            .as_ref()
+            .is_none_or(|priority| *priority == message.priority)
+          && request
+            .state
+            .as_ref()
+            .is_none_or(|state| message.state.as_ref() == Some(state))
+      })
+      .cloned()
+      .collect()
+  }
+
+  pub fn clear(&mut self, id: i32) -> ClearMessageStatusEnumType {
+    let count = self.messages.len();
+    self.messages.retain(|message| message.id != id);
+    if self.messages.len() == count {
+      return ClearMessageStatusEnumType::Unknown;
+    }
+
+    self.refresh();
+    ClearMessageStatusEnumType::Accepted
+  }
+
+  pub fn set_status(&mut self, status: CommonConnectorStatusType) {
+    self.state = match status {
+      CommonConnectorStatusType::Charging
+      | CommonConnectorStatusType::SuspendedEV
+      | CommonConnectorStatusType::SuspendedEVSE => MessageStateEnumType::Charging,
+      CommonConnectorStatusType::Faulted => MessageStateEnumType::Faulted,
+      CommonConnectorStatusType::Unavailable => MessageStateEnumType::Unavailable,
+      _ => MessageStateEnumType::Idle,
+    };
+    self.refresh();
+  }
+
+  /// A new transaction clears the personal message and cost of the previous one, messages
+  /// bound to a transaction are removed once it ends
+  pub fn set_transaction(&mut self, transaction_id: Option<String>) {
+    match &transaction_id {
+      Some(_) => {
+        self.personal_message = None;
+        self.total_cost = None;
+      }
+      None => self
+        .messages
+        .retain(|message| message.transaction_id.is_none()),
+    }
+
+    self.transaction_id = transaction_id;
+    self.refresh();
+  }
+
+  pub fn set_personal_message(&mut self, message: MessageContentType) {
+    self.personal_message = Some(message);
+    self.refresh();
+  }
+
+  /// Running or final cost, ignored when it is for another transaction
+  pub fn set_total_cost(&mut self, total_cost: Decimal, transaction_id: Option<&str>) {
+    if transaction_id
+      .is_some_and(|transaction_id| self.transaction_id.as_deref() != Some(transaction_id))
+    {
+      return;
+    }
+
+    self.total_cost = Some(total_cost);
+    self.refresh();
+  }
+
+  /// Text shown on the screen: `AlwaysFront` messages hide all others, `InFront` messages
+  /// hide the `NormalCycle` ones which are shown in turn
+  pub fn screen(&self) -> Vec<String> {
+    let now = Utc::now();
+    let shown: Vec<&MessageInfoType> = self
+      .messages
+      .iter()
+      .filter(|message| {
+        message.start_date_time.is_none_or(|start| start <= now)
+          && message.end_date_time.is_none_or(|end| now < end)
+          && message
+            .state
+            .as_ref()
+            .is_none_or(|state| *state == self.state)
+          && message
+            .transaction_id
+            .as_ref()
+            .is_none_or(|transaction_id| self.transaction_id.as_ref() == Some(transaction_id))
+      })
+      .collect();
+
+    let priority = [
+      MessagePriorityEnumType::AlwaysFront,
+      MessagePriorityEnumType::InFront,
+      MessagePriorityEnumType::NormalCycle,
+    ]
+    .into_iter()
+    .find(|priority| shown.iter().any(|message| message.priority == *priority));
+
+    let mut lines: Vec<String> = shown
+      .iter()
+      .filter(|message| Some(&message.priority) == priority.as_ref())
+      .map(|message| message.message.content.clone())
+      .collect();
+
+    // The CSMS messages in front hide the session information
+    if priority != Some(MessagePriorityEnumType::AlwaysFront) {
+      lines.extend(
+        self
+          .personal_message
+          .as_ref()
+          .map(|message| message.content.clone()),
+      );
+      lines.extend(
+        self
+          .total_cost
+          .map(|total_cost| format!("Total cost {total_cost} {}", self.currency)),
+      );
+    }
+
+    lines
+  }
+
+  /// Logs the screen when its text changed
+  fn refresh(&mut self) {
+    let screen = format!("[{:?}] {}", self.state, self.screen().join(" | "));
+    if screen != self.screen {
+      info!("🖥️  {}", screen);
+      self.screen = screen;
+    }
+  }
+}
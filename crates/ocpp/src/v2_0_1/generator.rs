@@ -1,192 +1,1861 @@
-#![cfg(feature = "ocpp2_0_1")]
-
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 
+use anyhow::Result;
 use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
 use chrono::Utc;
-use rust_ocpp::v2_0_1::datatypes::transaction_type::TransactionType;
-use rust_ocpp::v2_0_1::enumerations::firmware_status_enum_type::FirmwareStatusEnumType;
-use rust_ocpp::v2_0_1::enumerations::id_token_enum_type::IdTokenEnumType;
-use rust_ocpp::v2_0_1::enumerations::transaction_event_enum_type::TransactionEventEnumType;
-use rust_ocpp::v2_0_1::enumerations::trigger_reason_enum_type::TriggerReasonEnumType;
-use rust_ocpp::v2_0_1::messages::transaction_event::TransactionEventRequest;
+use common::{ChargePointConfig, SharedData, frame_info, shared_data::ChargePointSettings};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use rust_ocpp::v1_6::types::{MeterValue, ReadingContext};
+use rust_ocpp::v2_0_1::datatypes::{
+  certificate_hash_data_chain_type::CertificateHashDataChainType,
+  charging_station_type::ChargingStationType, evse_type::EVSEType, id_token_type::IdTokenType,
+  meter_value_type::MeterValueType, report_data_type::ReportDataType,
+  sampled_value_type::SampledValueType, set_variable_data_type::SetVariableDataType,
+  signed_meter_value_type::SignedMeterValueType, transaction_type::TransactionType,
+  unit_of_measure_type::UnitOfMeasureType,
+};
+use rust_ocpp::v2_0_1::enumerations::{
+  attribute_enum_type::AttributeEnumType, boot_reason_enum_type::BootReasonEnumType,
+  certificate_action_enum_type::CertificateActionEnumType,
+  certificate_signed_status_enum_type::CertificateSignedStatusEnumType,
+  certificate_signing_use_enum_type::CertificateSigningUseEnumType,
+  charging_profile_status_enum_type::ChargingProfileStatusEnumType,
+  charging_state_enum_type::ChargingStateEnumType,
+  clear_charging_profile_status_enum_type::ClearChargingProfileStatusEnumType,
+  delete_certificate_status_enum_type::DeleteCertificateStatusEnumType,
+  display_message_status_enum_type::DisplayMessageStatusEnumType,
+  generic_device_model_status_enum_type::GenericDeviceModelStatusEnumType,
+  generic_status_enum_type::GenericStatusEnumType,
+  get_display_messages_status_enum_type::GetDisplayMessagesStatusEnumType,
+  get_installed_certificate_status_enum_type::GetInstalledCertificateStatusEnumType,
+  id_token_enum_type::IdTokenEnumType,
+  install_certificate_status_enum_type::InstallCertificateStatusEnumType,
+  measurand_enum_type::MeasurandEnumType, reading_context_enum_type::ReadingContextEnumType,
+  reason_enum_type::ReasonEnumType, reset_status_enum_type::ResetStatusEnumType,
+  transaction_event_enum_type::TransactionEventEnumType,
+  trigger_message_status_enum_type::TriggerMessageStatusEnumType,
+  trigger_reason_enum_type::TriggerReasonEnumType,
+  upload_log_status_enum_type::UploadLogStatusEnumType,
+};
 use rust_ocpp::v2_0_1::messages::{
-  authorize::AuthorizeRequest, boot_notification::BootNotificationRequest,
+  authorize::AuthorizeRequest,
+  boot_notification::BootNotificationRequest,
+  certificate_signed::CertificateSignedResponse,
+  clear_charging_profile::ClearChargingProfileResponse,
+  clear_display_message::{ClearDisplayMessageRequest, ClearDisplayMessageResponse},
+  clear_variable_monitoring::{ClearVariableMonitoringRequest, ClearVariableMonitoringResponse},
+  cost_updated::{CostUpdatedRequest, CostUpdatedResponse},
+  customer_information::CustomerInformationResponse,
   datatransfer::DataTransferRequest,
-  firmware_status_notification::FirmwareStatusNotificationRequest, heartbeat::HeartbeatRequest,
-  meter_values::MeterValuesRequest, status_notification::StatusNotificationRequest,
+  delete_certificate::DeleteCertificateResponse,
+  firmware_status_notification::FirmwareStatusNotificationRequest,
+  get_15118ev_certificate::Get15118EVCertificateRequest,
+  get_base_report::{GetBaseReportRequest, GetBaseReportResponse},
+  get_certificate_status::GetCertificateStatusRequest,
+  get_display_message::{GetDisplayMessagesRequest, GetDisplayMessagesResponse},
+  get_installed_certificate_ids::GetInstalledCertificateIdsResponse,
+  get_log::GetLogResponse,
+  get_monitoring_report::{GetMonitoringReportRequest, GetMonitoringReportResponse},
+  get_report::{GetReportRequest, GetReportResponse},
+  get_transaction_status::{GetTransactionStatusRequest, GetTransactionStatusResponse},
+  get_variables::{GetVariablesRequest, GetVariablesResponse},
+  heartbeat::HeartbeatRequest,
+  install_certificate::InstallCertificateResponse,
+  log_status_notification::LogStatusNotificationRequest,
+  notify_customer_information::NotifyCustomerInformationRequest,
+  notify_display_messages::NotifyDisplayMessagesRequest,
+  notify_event::NotifyEventRequest,
+  notify_monitoring_report::NotifyMonitoringReportRequest,
+  notify_report::NotifyReportRequest,
+  reset::ResetResponse,
+  security_event_notification::SecurityEventNotificationRequest,
+  set_charging_profile::{SetChargingProfileRequest, SetChargingProfileResponse},
+  set_display_message::{SetDisplayMessageRequest, SetDisplayMessageResponse},
+  set_monitoring_base::{SetMonitoringBaseRequest, SetMonitoringBaseResponse},
+  set_monitoring_level::{SetMonitoringLevelRequest, SetMonitoringLevelResponse},
+  set_variable_monitoring::{SetVariableMonitoringRequest, SetVariableMonitoringResponse},
+  set_variables::{SetVariablesRequest, SetVariablesResponse},
+  sign_certificate::SignCertificateRequest,
+  status_notification::StatusNotificationRequest,
+  transaction_event::TransactionEventRequest,
+  trigger_message::TriggerMessageResponse,
+  update_firmware::UpdateFirmwareResponse,
 };
-
-use rust_ocpp::v2_0_1::datatypes::charging_station_type::ChargingStationType;
-use rust_ocpp::v2_0_1::datatypes::id_token_type::IdTokenType;
-use rust_ocpp::v2_0_1::enumerations::boot_reason_enum_type::BootReasonEnumType;
-
 use serde::Serialize;
 use serde_json::{Value, json};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
 
-use super::types::OcppAction;
+use super::device_model::DeviceModel;
+use super::display::DisplayMessages;
+use super::types::{OcppAction, meter_value_from_v16};
 use crate::generator::MessageGenerator;
-use crate::types::CommonConnectorStatusType;
-use uuid::Uuid;
+use crate::mock_data::{MeterReading, MeterValueMockData};
+use crate::signed_meter::{MeterSigner, OCMF_SIGNATURE_ALGORITHM};
+use crate::template::CallTemplate;
+use crate::types::{
+  CommonCertificateStatus, CommonConfigurationStatus, CommonConnectorStatusType,
+  CommonCustomerInformationStatus, CommonFirmwareStatus, CommonLogStatus, CommonOcspRequestData,
+  CommonSecurityEvent, CommonSettingChange, CommonStopReason, CommonTransactionTrigger,
+  CommonTriggerMessageStatus, CommonUpdateFirmwareStatus, CommonUploadLogStatus, ConnectorFault,
+  InstalledCertificate,
+};
+
+const EVSE_ID: i32 = 1;
+const CONNECTOR_ID: i32 = 1;
+/// Report entries sent per `NotifyReport` and `NotifyMonitoringReport`, events per
+/// `NotifyEvent`
+const NOTIFY_REPORT_ITEMS: usize = 20;
+/// Characters of customer data sent per `NotifyCustomerInformation`
+const NOTIFY_CUSTOMER_INFORMATION_DATA: usize = 512;
+
+static HEARTBEAT: LazyLock<CallTemplate> =
+  LazyLock::new(|| CallTemplate::new(OcppAction::Heartbeat, HeartbeatRequest {}));
+
+async fn build_call<T>(
+  shared_data: &SharedData<OcppAction>,
+  ocpp_action: OcppAction,
+  payload: T,
+) -> (String, Value)
+where
+  T: Debug + Serialize,
+{
+  build_tagged_call(shared_data, ocpp_action, payload, None).await
+}
+
+/// Call made for a tag, its answer is matched to the tag by the message id
+async fn build_tagged_call<T>(
+  shared_data: &SharedData<OcppAction>,
+  ocpp_action: OcppAction,
+  payload: T,
+  tag_id: Option<String>,
+) -> (String, Value)
+where
+  T: Debug + Serialize,
+{
+  frame_info!("➡️  [🔵 Call] {}", ocpp_action);
+  debug!(action = %ocpp_action, ?payload);
+
+  let msg_id = Uuid::new_v4().to_string();
+  shared_data
+    .insert_tagged_msg(&msg_id, ocpp_action.clone(), tag_id)
+    .await;
+
+  let call = json!([2, msg_id, ocpp_action, payload]);
+  (msg_id, call)
+}
+
+async fn build_template_call(
+  shared_data: &SharedData<OcppAction>,
+  ocpp_action: OcppAction,
+  template: &CallTemplate,
+) -> String {
+  frame_info!("➡️  [🔵 Call] {}", ocpp_action);
+
+  let msg_id = Uuid::new_v4().to_string();
+  let call = template.render(&msg_id);
+  shared_data.insert_msg(&msg_id, ocpp_action).await;
+  call
+}
+
+pub fn build_call_result<T: Serialize>(message_id: &str, payload: T) -> Value {
+  json!([3, message_id, payload])
+}
+
+pub fn build_call_error(
+  message_id: &str,
+  error_code: &str,
+  error_description: &str,
+  error_details: Option<Value>,
+) -> Value {
+  json!([
+    4,
+    message_id,
+    error_code,
+    error_description,
+    error_details.unwrap_or_else(|| json!({}))
+  ])
+}
 
 pub struct V201MessageGenerator {
-  config: MessageGeneratorConfig,
+  config: ChargePointConfig,
+  shared_data: SharedData<OcppAction>,
+  meter_signer: Option<MeterSigner>,
   id_counter: AtomicUsize,
+  /// Sequence number of the `TransactionEvent` messages of the current transaction
+  seq_no: AtomicI32,
+  /// Whether the connection to the CSMS is lost
+  offline: AtomicBool,
+  transaction: Mutex<TransactionState>,
+  device_model: Mutex<DeviceModel>,
+  /// Shared with the handler, which applies the personal messages and costs of responses
+  display: Arc<Mutex<DisplayMessages>>,
 }
 
-struct FrameBuilder;
+/// What the CSMS knows about the transaction of the EVSE
+#[derive(Default)]
+struct TransactionState {
+  ev_connected: bool,
+  authorized: bool,
+  /// Last `chargingState` reported
+  charging_state: Option<ChargingStateEnumType>,
+  /// Reason of a deauthorized transaction that ends once the EV departs
+  stopped_reason: Option<ReasonEnumType>,
+}
 
-impl FrameBuilder {
-  pub fn build_call<T: Serialize>(action: impl ToString, payload: T) -> Value {
-    json!({
-      "messageTypeId": 2,
-      "messageId": Uuid::new_v4().to_string(),
-      "action": action.to_string(),
-      "payload": payload
-    })
+#[async_trait]
+impl MessageGenerator for V201MessageGenerator {
+  // 🔌 Core / Timing
+
+  async fn heartbeat_interval(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.heartbeat_interval = value)
+      .await;
   }
 
-  #[allow(unused)]
-  pub fn build_call_result<T: Serialize>(message_id: &str, payload: T) -> Value {
-    json!({
-      "messageTypeId": 3,
-      "messageId": message_id,
-      "payload": payload
-    })
+  async fn connection_timeout(&self, timeout: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.connection_timeout = timeout)
+      .await;
   }
 
-  #[allow(unused)]
-  pub fn build_call_error(
-    message_id: &str,
-    error_code: &str,
-    error_description: &str,
-    error_details: Option<Value>,
-  ) -> Value {
-    json!({
-      "messageTypeId": 4,
-      "messageId": message_id,
-      "errorCode": error_code,
-      "errorDescription": error_description,
-      "errorDetails": error_details.unwrap_or_else(|| json!({}))
-    })
+  async fn reset_retries(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.reset_retries = value)
+      .await;
+  }
+
+  async fn websocket_ping_interval(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.websocket_ping_interval = value)
+      .await;
+  }
+
+  // ⚡ Metering
+
+  async fn meter_value_sample_interval(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.meter_value_sample_interval = value)
+      .await;
+  }
+
+  async fn clock_aligned_data_interval(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.clock_aligned_data_interval = value)
+      .await;
+  }
+
+  async fn meter_values_sampled_data(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| {
+        data.settings.meter_values_sampled_data = value
+          .split(',')
+          .map(|measurand| measurand.trim().to_string())
+          .collect::<HashSet<String>>()
+      })
+      .await;
+  }
+
+  async fn meter_values_aligned_data(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| data.settings.meter_values_aligned_data = value)
+      .await;
+  }
+
+  async fn stop_txn_sampled_data(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| data.settings.stop_txn_sampled_data = value)
+      .await;
+  }
+
+  async fn stop_txn_aligned_data(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| data.settings.stop_txn_aligned_data = value)
+      .await;
+  }
+
+  // 🔄 Transaction behavior
+
+  async fn transaction_message_attempts(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.transaction_message_attempts = value)
+      .await;
+  }
+
+  async fn transaction_message_retry_interval(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.transaction_message_retry_interval = value)
+      .await;
+  }
+
+  async fn max_energy_on_invalid_id(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.max_energy_on_invalid_id = value)
+      .await;
+  }
+
+  // 🔐 Authorization
+
+  async fn authorize_remote_tx_requests(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.authorize_remote_tx_requests = state)
+      .await;
+  }
+
+  async fn stop_transaction_on_ev_side_disconnect(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.stop_transaction_on_ev_side_disconnect = state)
+      .await;
+  }
+
+  async fn stop_transaction_on_invalid_id(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.stop_transaction_on_invalid_id = state)
+      .await;
+  }
+
+  async fn allow_offline_tx_for_unknown_id(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.allow_offline_tx_for_unknown_id = state)
+      .await;
+  }
+
+  async fn local_authorize_offline(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.local_authorize_offline = state)
+      .await;
+  }
+
+  async fn local_pre_authorize(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.local_pre_authorize = state)
+      .await;
+  }
+
+  async fn authorization_cache_enabled(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.authorization_cache_enabled = state)
+      .await;
+  }
+
+  // 💳 Local Authorization List
+
+  async fn local_auth_list_enabled(&self, state: bool) {
+    self
+      .shared_data
+      .write(|data| data.settings.local_auth_list_enabled = state)
+      .await;
+  }
+
+  async fn local_auth_list_version(&self, value: i32) {
+    self
+      .shared_data
+      .write(|data| data.settings.local_auth_list_version = value)
+      .await;
+  }
+
+  async fn send_local_list_max_length(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.send_local_list_max_length = value)
+      .await;
+  }
+
+  async fn local_auth_list_max_length(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.local_auth_list_max_length = value)
+      .await;
+  }
+
+  // 🔌 Connector / Hardware
+
+  async fn number_of_connectors(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.number_of_connectors = value)
+      .await;
+  }
+
+  async fn connector_phase_rotation(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| data.settings.connector_phase_rotation = value)
+      .await;
+  }
+
+  // ⚡ Smart Charging
+
+  async fn charge_profile_max_stack_level(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.charge_profile_max_stack_level = value)
+      .await;
+  }
+
+  async fn charging_schedule_allowed_charging_rate_unit(&self, value: String) {
+    self
+      .shared_data
+      .write(|data| data.settings.charging_schedule_allowed_charging_rate_unit = value)
+      .await;
+  }
+
+  async fn charging_schedule_max_periods(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.charging_schedule_max_periods = value)
+      .await;
+  }
+
+  // 📊 Limits / Misc
+
+  async fn get_configuration_max_keys(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.get_configuration_max_keys = value)
+      .await;
+  }
+
+  // 🛡️ Security
+
+  async fn security_profile(&self, value: u32) {
+    self
+      .shared_data
+      .write(|data| data.settings.security_profile = value)
+      .await;
+  }
+
+  async fn settings(&self) -> ChargePointSettings {
+    let mut settings = self.shared_data.read(|data| data.settings.clone()).await;
+
+    // The TxCtrlr variables may have been given another value in the configuration
+    let device_model = self.device_model.lock().unwrap();
+    if let Some(value) = device_model
+      .actual("TxCtrlr", "EVConnectionTimeOut", None)
+      .and_then(|value| value.parse().ok())
+    {
+      settings.connection_timeout = value;
+    }
+    if let Some(state) = device_model
+      .actual("TxCtrlr", "StopTxOnEVSideDisconnect", None)
+      .and_then(|value| value.parse().ok())
+    {
+      settings.stop_transaction_on_ev_side_disconnect = state;
+    }
+
+    settings
   }
-}
 
-#[async_trait]
-impl MessageGenerator for V201MessageGenerator {
-  // Charger -> CSMS
   async fn boot_notification(&self) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::BootNotification,
-      BootNotificationRequest {
-        reason: BootReasonEnumType::PowerUp,
-        charging_station: ChargingStationType {
-          model: self.config.model.clone(),
-          vendor_name: self.config.vendor.clone(),
-          firmware_version: Some("1.2.3".to_string()),
-          ..Default::default()
+    self
+      .build_call(
+        OcppAction::BootNotification,
+        BootNotificationRequest {
+          reason: BootReasonEnumType::PowerUp,
+          charging_station: ChargingStationType {
+            model: self.config.model.clone(),
+            vendor_name: self.config.vendor.clone(),
+            ..Default::default()
+          },
         },
-      },
-    )
+      )
+      .await
   }
 
-  async fn heartbeat(&self) -> Value {
-    FrameBuilder::build_call(OcppAction::Heartbeat, HeartbeatRequest {})
+  async fn heartbeat(&self) -> String {
+    build_template_call(&self.shared_data, OcppAction::Heartbeat, &HEARTBEAT).await
   }
 
-  async fn authorize(&self) -> Value {
-    FrameBuilder::build_call(
+  async fn authorize(&self, tag_id: Option<&str>) -> Value {
+    let id_tag = tag_id
+      .map(String::from)
+      .unwrap_or_else(|| self.config.id_tag.clone());
+
+    self
+      .shared_data
+      .write(|data| data.tag_id = Some(id_tag.clone()))
+      .await;
+
+    build_tagged_call(
+      &self.shared_data,
       OcppAction::Authorize,
       AuthorizeRequest {
-        id_token: IdTokenType {
-          id_token: self.config.id_tag.clone(),
-          additional_info: None,
-          kind: IdTokenEnumType::Central,
-        },
+        id_token: self.id_token(id_tag.clone()),
         ..Default::default()
       },
+      Some(id_tag),
     )
+    .await
+    .1
+  }
+
+  async fn start_transaction(&self) -> Value {
+    // The power path closes: starts the transaction unless a start point already did
+    let (id_tag, energy_wh, transaction_id) = self
+      .shared_data
+      .write(|data| {
+        let state = &mut data.charging_session_state;
+        state.last_update = Instant::now();
+        state.last_sample_energy_wh = state.energy_wh;
+        state.soc = self.config.meter.initial_soc;
+
+        (
+          data.tag_id.clone().unwrap(),
+          state.energy_wh,
+          data.transaction_id,
+        )
+      })
+      .await;
+
+    let started = transaction_id.is_none();
+    let transaction_id = match transaction_id {
+      Some(transaction_id) => transaction_id,
+      None => self.begin_transaction().await,
+    };
+
+    self.transaction.lock().unwrap().charging_state = Some(ChargingStateEnumType::Charging);
+
+    let signed = self
+      .meter_signer
+      .as_ref()
+      .map(|meter_signer| meter_signer.begin(&id_tag, energy_wh));
+
+    let (msg_id, call) = self
+      .transaction_event(TransactionEventRequest {
+        event_type: match started {
+          true => TransactionEventEnumType::Started,
+          false => TransactionEventEnumType::Updated,
+        },
+        timestamp: Utc::now(),
+        trigger_reason: TriggerReasonEnumType::ChargingStateChanged,
+        seq_no: self.next_seq_no(),
+        transaction_info: TransactionType {
+          transaction_id: transaction_id.to_string(),
+          charging_state: Some(ChargingStateEnumType::Charging),
+          ..Default::default()
+        },
+        id_token: started.then(|| self.id_token(id_tag)),
+        evse: started.then_some(EVSEType {
+          id: EVSE_ID,
+          connector_id: Some(CONNECTOR_ID),
+        }),
+        meter_value: Some(vec![self.energy_meter_value(
+          energy_wh,
+          ReadingContextEnumType::TransactionBegin,
+          signed,
+        )]),
+        ..Default::default()
+      })
+      .await;
+
+    self
+      .shared_data
+      .write(|data| data.start_transaction_msg_id = Some(msg_id))
+      .await;
+
+    call
+  }
+
+  async fn stop_transaction(&self, reason: CommonStopReason) -> Value {
+    self
+      .shared_data
+      .write(|data| data.start_transaction_msg_id = None)
+      .await;
+
+    // Without `Authorized`, `PowerPathClosed` or `EnergyTransfer` stop points a deauthorized
+    // transaction lasts until the EV departs
+    let stop_points = self.tx_points("TxStopPoint");
+    let deferrable = matches!(reason, CommonStopReason::Local | CommonStopReason::Remote)
+      && !stop_points.iter().any(|point| {
+        matches!(
+          point.as_str(),
+          "Authorized" | "PowerPathClosed" | "EnergyTransfer"
+        )
+      });
+
+    let deferred = {
+      let mut transaction = self.transaction.lock().unwrap();
+      transaction.authorized = false;
+      if reason == CommonStopReason::EVDisconnected {
+        transaction.ev_connected = false;
+      }
+
+      let deferred = deferrable && transaction.ev_connected;
+      if deferred {
+        transaction.stopped_reason = Some(reason.into());
+        transaction.charging_state = Some(ChargingStateEnumType::EVConnected);
+      }
+      deferred
+    };
+    let transaction_id = self.shared_data.read(|data| data.transaction_id).await;
+
+    match transaction_id {
+      Some(transaction_id) if deferred => {
+        self
+          .transaction_event(TransactionEventRequest {
+            event_type: TransactionEventEnumType::Updated,
+            timestamp: Utc::now(),
+            trigger_reason: reason.into(),
+            seq_no: self.next_seq_no(),
+            transaction_info: TransactionType {
+              transaction_id: transaction_id.to_string(),
+              charging_state: Some(ChargingStateEnumType::EVConnected),
+              ..Default::default()
+            },
+            ..Default::default()
+          })
+          .await
+          .1
+      }
+      _ => self.end_transaction(reason.into(), reason.into()).await,
+    }
+  }
+
+  async fn status_notification(
+    &self,
+    status: CommonConnectorStatusType,
+    _fault: Option<&ConnectorFault>,
+  ) -> Value {
+    self.display.lock().unwrap().set_status(status);
+
+    self
+      .build_call(
+        OcppAction::StatusNotification,
+        StatusNotificationRequest {
+          timestamp: Utc::now(),
+          connector_status: status.into(),
+          evse_id: EVSE_ID,
+          connector_id: CONNECTOR_ID,
+        },
+      )
+      .await
   }
 
-  async fn start_transaction(&self, tag_id: Option<&str>) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::TransactionEvent,
-      TransactionEventRequest {
-        event_type: TransactionEventEnumType::Started,
-        timestamp: chrono::Utc::now(),
-        trigger_reason: TriggerReasonEnumType::CablePluggedIn,
-        seq_no: 1,
+  async fn meter_values(&self) -> Value {
+    let (transaction_id, meter_values_sampled_data, soc) = self
+      .shared_data
+      .read(|data| {
+        (
+          data.transaction_id,
+          data.settings.meter_values_sampled_data.clone(),
+          data.charging_session_state.soc,
+        )
+      })
+      .await;
+
+    let Some(transaction_id) = transaction_id else {
+      return Value::Null;
+    };
+
+    let mut reading = MeterReading::sample(&self.config.meter, soc);
+
+    self
+      .shared_data
+      .write(|data| {
+        let state = &mut data.charging_session_state;
+        state.update_energy(&self.config.meter, reading.power_active_w());
+
+        reading.energy_wh = state.energy_wh;
+        reading.interval_wh = state.energy_wh - state.last_sample_energy_wh;
+        reading.soc = state.soc;
+        state.last_sample_energy_wh = state.energy_wh;
+      })
+      .await;
+
+    let mut meter_value = meter_value_from_v16(MeterValue::mock_data(
+      &self.config.meter,
+      &meter_values_sampled_data,
+      &reading,
+      ReadingContext::SamplePeriodic,
+    ));
+
+    if let Some(meter_signer) = &self.meter_signer {
+      let signed = meter_signer.sample(reading.energy_wh);
+      meter_value.sampled_value.push(self.energy_sampled_value(
+        reading.energy_wh,
+        ReadingContextEnumType::SamplePeriodic,
+        Some(signed),
+      ));
+    }
+
+    // Meter values of a transaction are part of its events in 2.0.1
+    let charging_state = self.transaction.lock().unwrap().charging_state.clone();
+    self
+      .transaction_event(TransactionEventRequest {
+        event_type: TransactionEventEnumType::Updated,
+        timestamp: Utc::now(),
+        trigger_reason: TriggerReasonEnumType::MeterValuePeriodic,
+        seq_no: self.next_seq_no(),
         transaction_info: TransactionType {
-          transaction_id: "42".to_string(),
+          transaction_id: transaction_id.to_string(),
+          charging_state,
           ..Default::default()
         },
+        meter_value: Some(vec![meter_value]),
         ..Default::default()
+      })
+      .await
+      .1
+  }
+
+  async fn session(&self) -> (Option<i32>, f64) {
+    self
+      .shared_data
+      .read(|data| (data.transaction_id, data.charging_session_state.energy_wh))
+      .await
+  }
+
+  async fn diagnostics_status_notification(&self) -> Value {
+    self
+      .build_call(
+        OcppAction::LogStatusNotification,
+        LogStatusNotificationRequest {
+          status: UploadLogStatusEnumType::Uploaded,
+          request_id: None,
+        },
+      )
+      .await
+  }
+
+  async fn firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    request_id: Option<i32>,
+  ) -> Value {
+    self
+      .build_call(
+        OcppAction::FirmwareStatusNotification,
+        FirmwareStatusNotificationRequest {
+          status: status.into(),
+          request_id,
+        },
+      )
+      .await
+  }
+
+  async fn data_transfer(
+    &self,
+    vendor_id: Option<&str>,
+    message_id: Option<&str>,
+    data: Option<&str>,
+  ) -> Value {
+    self
+      .build_call(
+        OcppAction::DataTransfer,
+        DataTransferRequest {
+          vendor_id: vendor_id.unwrap_or(&self.config.vendor).to_string(),
+          message_id: message_id.map(String::from),
+          data: data.map(String::from),
+        },
+      )
+      .await
+  }
+
+  async fn custom_call(&self, action: &str, payload: Value) -> Option<Value> {
+    let action = OcppAction::from_str(action).ok()?;
+    Some(self.build_call(action, payload).await)
+  }
+
+  async fn change_configuration(&self, msg_id: &str, _status: CommonConfigurationStatus) -> Value {
+    // Configuration keys are replaced by the device model in 2.0.1
+    warn!("ChangeConfiguration is not part of OCPP 2.0.1");
+    build_call_error(
+      msg_id,
+      "NotSupported",
+      "ChangeConfiguration is not part of OCPP 2.0.1",
+      None,
+    )
+  }
+
+  async fn reset(&self, msg_id: &str) -> Value {
+    Self::call_result(
+      OcppAction::Reset,
+      msg_id,
+      ResetResponse {
+        status: ResetStatusEnumType::Accepted,
+        status_info: None,
       },
     )
   }
 
-  async fn stop_transaction(&self) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::TransactionEvent,
-      TransactionEventRequest {
-        event_type: TransactionEventEnumType::Ended,
-        timestamp: chrono::Utc::now(),
-        trigger_reason: TriggerReasonEnumType::RemoteStop,
-        seq_no: 1,
+  async fn update_firmware(&self, msg_id: &str, status: CommonUpdateFirmwareStatus) -> Value {
+    Self::call_result(
+      OcppAction::UpdateFirmware,
+      msg_id,
+      UpdateFirmwareResponse {
+        status: status.into(),
+        status_info: None,
+      },
+    )
+  }
+
+  // 🧾 Transaction events
+
+  async fn transaction_trigger(
+    &self,
+    trigger: CommonTransactionTrigger,
+    status: CommonConnectorStatusType,
+  ) -> Value {
+    let start_points = self.tx_points("TxStartPoint");
+    let transaction_id = self.shared_data.read(|data| data.transaction_id).await;
+
+    let (authorized, charging_state) = {
+      let mut transaction = self.transaction.lock().unwrap();
+      match trigger {
+        CommonTransactionTrigger::CablePluggedIn => transaction.ev_connected = true,
+        CommonTransactionTrigger::Authorized => transaction.authorized = true,
+        CommonTransactionTrigger::ChargingStateChanged => {}
+        CommonTransactionTrigger::EVDeparted => transaction.ev_connected = false,
+        CommonTransactionTrigger::EVConnectTimeout => {
+          transaction.ev_connected = false;
+          transaction.authorized = false;
+        }
+      }
+
+      let charging_state = Self::charging_state(status, transaction.ev_connected);
+      if trigger == CommonTransactionTrigger::ChargingStateChanged
+        && transaction.charging_state.as_ref() == Some(&charging_state)
+      {
+        return Value::Null;
+      }
+      (transaction.authorized, charging_state)
+    };
+
+    let charging = matches!(
+      status,
+      CommonConnectorStatusType::Charging
+        | CommonConnectorStatusType::SuspendedEV
+        | CommonConnectorStatusType::SuspendedEVSE
+    );
+
+    let started = transaction_id.is_none();
+    let (trigger_reason, transaction_id) = match (trigger, transaction_id) {
+      // Ends once the EV left, unless the transaction is still charging or suspended
+      (CommonTransactionTrigger::EVDeparted, Some(_)) if !charging => {
+        let stopped_reason = self.transaction.lock().unwrap().stopped_reason.take();
+        return self
+          .end_transaction(
+            TriggerReasonEnumType::EVDeparted,
+            stopped_reason.unwrap_or(ReasonEnumType::EVDisconnected),
+          )
+          .await;
+      }
+      (CommonTransactionTrigger::EVDeparted, Some(transaction_id)) => {
+        (TriggerReasonEnumType::EVCommunicationLost, transaction_id)
+      }
+      (CommonTransactionTrigger::EVConnectTimeout, Some(_)) => {
+        return self
+          .end_transaction(
+            TriggerReasonEnumType::EVConnectTimeout,
+            ReasonEnumType::Timeout,
+          )
+          .await;
+      }
+      (CommonTransactionTrigger::CablePluggedIn, transaction_id) => (
+        TriggerReasonEnumType::CablePluggedIn,
+        match transaction_id {
+          Some(transaction_id) => transaction_id,
+          None if start_points.iter().any(|point| point == "EVConnected") => {
+            self.begin_transaction().await
+          }
+          None => return Value::Null,
+        },
+      ),
+      (CommonTransactionTrigger::Authorized, transaction_id) => (
+        TriggerReasonEnumType::Authorized,
+        match transaction_id {
+          Some(transaction_id) => transaction_id,
+          None if start_points.iter().any(|point| point == "Authorized") => {
+            self.begin_transaction().await
+          }
+          None => return Value::Null,
+        },
+      ),
+      (CommonTransactionTrigger::ChargingStateChanged, Some(transaction_id)) => {
+        (TriggerReasonEnumType::ChargingStateChanged, transaction_id)
+      }
+      (_, None) => return Value::Null,
+    };
+
+    self.transaction.lock().unwrap().charging_state = Some(charging_state.clone());
+
+    let id_tag = self.shared_data.read(|data| data.tag_id.clone()).await;
+    let id_token = match trigger {
+      CommonTransactionTrigger::Authorized => id_tag,
+      _ if started && authorized => id_tag,
+      _ => None,
+    };
+
+    self
+      .transaction_event(TransactionEventRequest {
+        event_type: match started {
+          true => TransactionEventEnumType::Started,
+          false => TransactionEventEnumType::Updated,
+        },
+        timestamp: Utc::now(),
+        trigger_reason,
+        seq_no: self.next_seq_no(),
         transaction_info: TransactionType {
-          transaction_id: "42".to_string(),
+          transaction_id: transaction_id.to_string(),
+          charging_state: Some(charging_state),
           ..Default::default()
         },
+        id_token: id_token.map(|id_token| self.id_token(id_token)),
+        evse: started.then_some(EVSEType {
+          id: EVSE_ID,
+          connector_id: Some(CONNECTOR_ID),
+        }),
         ..Default::default()
+      })
+      .await
+      .1
+  }
+
+  async fn set_offline(&self, offline: bool) {
+    self.offline.store(offline, Ordering::Relaxed);
+  }
+
+  async fn queued_transaction_events(&self) -> Vec<Value> {
+    let (queued, start_transaction_msg_id) = self
+      .shared_data
+      .write(|data| {
+        (
+          std::mem::take(&mut data.transaction_events),
+          data.start_transaction_msg_id.clone(),
+        )
+      })
+      .await;
+
+    if !queued.is_empty() {
+      info!("Sending {} queued transaction events", queued.len());
+    }
+
+    let mut messages = Vec::with_capacity(queued.len());
+    for (msg_id, call) in queued {
+      let Ok(request) = serde_json::from_value::<TransactionEventRequest>(call[3].clone()) else {
+        continue;
+      };
+
+      let (queued_msg_id, call) = self.transaction_event(request).await;
+      if start_transaction_msg_id.as_ref() == Some(&msg_id) {
+        self
+          .shared_data
+          .write(|data| data.start_transaction_msg_id = Some(queued_msg_id))
+          .await;
+      }
+      messages.push(call);
+    }
+    messages
+  }
+
+  async fn get_transaction_status(&self, msg_id: &str, payload: Value) -> Value {
+    let request: GetTransactionStatusRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let (ongoing, messages_in_queue) = self
+      .shared_data
+      .read(|data| {
+        let queued_transaction_ids = data
+          .transaction_events
+          .iter()
+          .map(|(_, call)| &call[3]["transactionInfo"]["transactionId"]);
+
+        match &request.transaction_id {
+          Some(transaction_id) => (
+            Some(
+              data
+                .transaction_id
+                .is_some_and(|ongoing| ongoing.to_string() == *transaction_id),
+            ),
+            queued_transaction_ids
+              .into_iter()
+              .any(|queued| queued == transaction_id.as_str()),
+          ),
+          None => (None, !data.transaction_events.is_empty()),
+        }
+      })
+      .await;
+
+    Self::call_result(
+      OcppAction::GetTransactionStatus,
+      msg_id,
+      GetTransactionStatusResponse {
+        ongoing_indicator: ongoing,
+        messages_in_queue,
       },
     )
   }
 
-  async fn status_notification(&self, status: CommonConnectorStatusType) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::StatusNotification,
-      StatusNotificationRequest {
-        timestamp: Utc::now(),
-        evse_id: 1,
-        connector_id: 1,
-        connector_status: status.into(),
+  // 🔏 Signed firmware update
+
+  async fn signed_update_firmware(
+    &self,
+    msg_id: &str,
+    status: CommonUpdateFirmwareStatus,
+  ) -> Value {
+    // The signature is part of UpdateFirmware in 2.0.1
+    self.update_firmware(msg_id, status).await
+  }
+
+  async fn signed_firmware_status_notification(
+    &self,
+    status: CommonFirmwareStatus,
+    request_id: i32,
+  ) -> Value {
+    self
+      .firmware_status_notification(status, Some(request_id))
+      .await
+  }
+
+  // 🚨 Security events
+
+  async fn security_event_notification(
+    &self,
+    event: &CommonSecurityEvent,
+    tech_info: Option<&str>,
+  ) -> Value {
+    self
+      .build_call(
+        OcppAction::SecurityEventNotification,
+        SecurityEventNotificationRequest {
+          kind: event.v2_0_1_name().to_string(),
+          timestamp: Utc::now(),
+          tech_info: tech_info.map(String::from),
+        },
+      )
+      .await
+  }
+
+  // 🔏 Certificate management
+
+  async fn sign_certificate(&self, csr: String) -> Value {
+    self
+      .build_call(
+        OcppAction::SignCertificate,
+        SignCertificateRequest {
+          csr,
+          certificate_type: Some(CertificateSigningUseEnumType::ChargingStationCertificate),
+        },
+      )
+      .await
+  }
+
+  async fn certificate_signed(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => CertificateSignedStatusEnumType::Accepted,
+      _ => CertificateSignedStatusEnumType::Rejected,
+    };
+
+    Self::call_result(
+      OcppAction::CertificateSigned,
+      msg_id,
+      CertificateSignedResponse {
+        status,
+        status_info: None,
       },
     )
   }
 
-  async fn meter_values(&self) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::MeterValues,
-      MeterValuesRequest {
-        evse_id: 1,
-        meter_value: Default::default(),
+  async fn install_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => InstallCertificateStatusEnumType::Accepted,
+      CommonCertificateStatus::Rejected => InstallCertificateStatusEnumType::Rejected,
+      CommonCertificateStatus::Failed | CommonCertificateStatus::NotFound => {
+        InstallCertificateStatusEnumType::Failed
+      }
+    };
+
+    Self::call_result(
+      OcppAction::InstallCertificate,
+      msg_id,
+      InstallCertificateResponse {
+        status,
+        status_info: None,
       },
     )
   }
 
-  async fn diagnostics_status_notification(&self) -> Value {
-    FrameBuilder::build_call(OcppAction::Heartbeat, HeartbeatRequest {})
+  async fn delete_certificate(&self, msg_id: &str, status: CommonCertificateStatus) -> Value {
+    let status = match status {
+      CommonCertificateStatus::Accepted => DeleteCertificateStatusEnumType::Accepted,
+      CommonCertificateStatus::NotFound => DeleteCertificateStatusEnumType::NotFound,
+      CommonCertificateStatus::Rejected | CommonCertificateStatus::Failed => {
+        DeleteCertificateStatusEnumType::Failed
+      }
+    };
+
+    Self::call_result(
+      OcppAction::DeleteCertificate,
+      msg_id,
+      DeleteCertificateResponse {
+        status,
+        status_info: None,
+      },
+    )
   }
 
-  async fn firmware_status_notification(&self) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::FirmwareStatusNotification,
-      FirmwareStatusNotificationRequest {
-        status: FirmwareStatusEnumType::Installed,
+  async fn get_installed_certificate_ids(
+    &self,
+    msg_id: &str,
+    certificates: Vec<InstalledCertificate>,
+  ) -> Value {
+    let response = if certificates.is_empty() {
+      GetInstalledCertificateIdsResponse {
+        status: GetInstalledCertificateStatusEnumType::NotFound,
         ..Default::default()
+      }
+    } else {
+      GetInstalledCertificateIdsResponse {
+        status: GetInstalledCertificateStatusEnumType::Accepted,
+        certificate_hash_data_chain: Some(
+          certificates
+            .into_iter()
+            .map(|certificate| CertificateHashDataChainType {
+              certificate_type: certificate.certificate_type.into(),
+              certificate_hash_data: certificate.hash_data.into(),
+              child_certificate_hash_data: None,
+            })
+            .collect(),
+        ),
+        status_info: None,
+      }
+    };
+
+    Self::call_result(OcppAction::GetInstalledCertificateIds, msg_id, response)
+  }
+
+  async fn trigger_message(&self, msg_id: &str, status: CommonTriggerMessageStatus) -> Value {
+    let status = match status {
+      CommonTriggerMessageStatus::Accepted => TriggerMessageStatusEnumType::Accepted,
+      CommonTriggerMessageStatus::Rejected => TriggerMessageStatusEnumType::Rejected,
+      CommonTriggerMessageStatus::NotImplemented => TriggerMessageStatusEnumType::NotImplemented,
+    };
+
+    Self::call_result(
+      OcppAction::TriggerMessage,
+      msg_id,
+      TriggerMessageResponse {
+        status,
+        status_info: None,
+      },
+    )
+  }
+
+  // 🧩 Device model
+
+  async fn get_variables(&self, msg_id: &str, payload: Value) -> Value {
+    let request: GetVariablesRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let get_variable_result = {
+      let device_model = self.device_model.lock().unwrap();
+      if request.get_variable_data.len()
+        > Self::items_per_message(&device_model, "DeviceDataCtrlr", "GetVariables")
+      {
+        return build_call_error(
+          msg_id,
+          "OccurrenceConstraintViolation",
+          "Too many variables requested",
+          None,
+        );
+      }
+
+      request
+        .get_variable_data
+        .iter()
+        .map(|data| device_model.get(data))
+        .collect()
+    };
+
+    Self::call_result(
+      OcppAction::GetVariables,
+      msg_id,
+      GetVariablesResponse {
+        get_variable_result,
+      },
+    )
+  }
+
+  async fn set_variables(&self, msg_id: &str, payload: Value) -> (Value, Vec<CommonSettingChange>) {
+    let request: SetVariablesRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return (
+          build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+          Vec::new(),
+        );
+      }
+    };
+
+    let (set_variable_result, accepted): (Vec<_>, Vec<_>) = {
+      let mut device_model = self.device_model.lock().unwrap();
+      if request.set_variable_data.len()
+        > Self::items_per_message(&device_model, "DeviceDataCtrlr", "SetVariables")
+      {
+        return (
+          build_call_error(
+            msg_id,
+            "OccurrenceConstraintViolation",
+            "Too many variables to set",
+            None,
+          ),
+          Vec::new(),
+        );
+      }
+
+      request
+        .set_variable_data
+        .iter()
+        .map(|data| {
+          let (result, accepted) = device_model.set(data);
+          (result, accepted.then_some(data))
+        })
+        .unzip()
+    };
+
+    let mut changes = Vec::new();
+    for data in accepted.into_iter().flatten() {
+      if let Some(change) = self.apply_variable(data).await {
+        changes.push(change);
+      }
+    }
+
+    let response = Self::call_result(
+      OcppAction::SetVariables,
+      msg_id,
+      SetVariablesResponse {
+        set_variable_result,
+      },
+    );
+    (response, changes)
+  }
+
+  async fn get_base_report(&self, msg_id: &str, payload: Value) -> Vec<Value> {
+    let request: GetBaseReportRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return vec![build_call_error(
+          msg_id,
+          "FormationViolation",
+          &err.to_string(),
+          None,
+        )];
+      }
+    };
+
+    let report_data = self
+      .device_model
+      .lock()
+      .unwrap()
+      .base_report(&request.report_base);
+
+    let response = Self::call_result(
+      OcppAction::GetBaseReport,
+      msg_id,
+      GetBaseReportResponse {
+        status: GenericDeviceModelStatusEnumType::Accepted,
+        status_info: None,
+      },
+    );
+    let mut messages = vec![response];
+    messages.extend(self.notify_report(request.request_id, report_data).await);
+    messages
+  }
+
+  async fn get_report(&self, msg_id: &str, payload: Value) -> Vec<Value> {
+    let request: GetReportRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return vec![build_call_error(
+          msg_id,
+          "FormationViolation",
+          &err.to_string(),
+          None,
+        )];
+      }
+    };
+
+    let component_variable = request.component_variable.unwrap_or_default();
+    let (status, report_data) = {
+      let device_model = self.device_model.lock().unwrap();
+      if component_variable.len()
+        > Self::items_per_message(&device_model, "DeviceDataCtrlr", "GetReport")
+      {
+        (GenericDeviceModelStatusEnumType::Rejected, Vec::new())
+      } else {
+        let report_data = device_model.report(
+          &request.component_criteria.unwrap_or_default(),
+          &component_variable,
+        );
+        match report_data.is_empty() {
+          true => (
+            GenericDeviceModelStatusEnumType::EmptyResultSet,
+            report_data,
+          ),
+          false => (GenericDeviceModelStatusEnumType::Accepted, report_data),
+        }
+      }
+    };
+
+    let response = Self::call_result(
+      OcppAction::GetReport,
+      msg_id,
+      GetReportResponse {
+        status,
+        status_info: None,
+      },
+    );
+    let mut messages = vec![response];
+    messages.extend(self.notify_report(request.request_id, report_data).await);
+    messages
+  }
+
+  // 📡 Monitoring
+
+  async fn set_variable_monitoring(&self, msg_id: &str, payload: Value) -> Value {
+    let request: SetVariableMonitoringRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let set_monitoring_result = {
+      let mut device_model = self.device_model.lock().unwrap();
+      let max_items =
+        Self::items_per_message(&device_model, "MonitoringCtrlr", "SetVariableMonitoring");
+      if request.set_monitoring_data.len() > max_items {
+        return build_call_error(
+          msg_id,
+          "OccurrenceConstraintViolation",
+          "Too many monitors to set",
+          None,
+        );
+      }
+
+      request
+        .set_monitoring_data
+        .iter()
+        .map(|data| device_model.set_monitor(data))
+        .collect()
+    };
+
+    Self::call_result(
+      OcppAction::SetVariableMonitoring,
+      msg_id,
+      SetVariableMonitoringResponse {
+        set_monitoring_result,
+      },
+    )
+  }
+
+  async fn clear_variable_monitoring(&self, msg_id: &str, payload: Value) -> Value {
+    let request: ClearVariableMonitoringRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let clear_monitoring_result = {
+      let mut device_model = self.device_model.lock().unwrap();
+      let max_items =
+        Self::items_per_message(&device_model, "MonitoringCtrlr", "ClearVariableMonitoring");
+      if request.id.len() > max_items {
+        return build_call_error(
+          msg_id,
+          "OccurrenceConstraintViolation",
+          "Too many monitors to clear",
+          None,
+        );
+      }
+
+      request
+        .id
+        .iter()
+        .map(|id| device_model.clear_monitor(*id))
+        .collect()
+    };
+
+    Self::call_result(
+      OcppAction::ClearVariableMonitoring,
+      msg_id,
+      ClearVariableMonitoringResponse {
+        clear_monitoring_result,
+      },
+    )
+  }
+
+  async fn set_monitoring_base(&self, msg_id: &str, payload: Value) -> Value {
+    let request: SetMonitoringBaseRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    self
+      .device_model
+      .lock()
+      .unwrap()
+      .set_monitoring_base(&request.monitoring_base);
+
+    Self::call_result(
+      OcppAction::SetMonitoringBase,
+      msg_id,
+      SetMonitoringBaseResponse {
+        status: GenericDeviceModelStatusEnumType::Accepted,
+        status_info: None,
+      },
+    )
+  }
+
+  async fn set_monitoring_level(&self, msg_id: &str, payload: Value) -> Value {
+    let request: SetMonitoringLevelRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let accepted = self
+      .device_model
+      .lock()
+      .unwrap()
+      .set_monitoring_level(request.severity);
+
+    Self::call_result(
+      OcppAction::SetMonitoringLevel,
+      msg_id,
+      SetMonitoringLevelResponse {
+        status: match accepted {
+          true => GenericStatusEnumType::Accepted,
+          false => GenericStatusEnumType::Rejected,
+        },
+        status_info: None,
+      },
+    )
+  }
+
+  async fn get_monitoring_report(&self, msg_id: &str, payload: Value) -> Vec<Value> {
+    let request: GetMonitoringReportRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return vec![build_call_error(
+          msg_id,
+          "FormationViolation",
+          &err.to_string(),
+          None,
+        )];
+      }
+    };
+
+    let monitors = self.device_model.lock().unwrap().monitoring_report(
+      &request.monitoring_criteria.unwrap_or_default(),
+      &request.component_variable.unwrap_or_default(),
+    );
+
+    let response = Self::call_result(
+      OcppAction::GetMonitoringReport,
+      msg_id,
+      GetMonitoringReportResponse {
+        status: match monitors.is_empty() {
+          true => GenericDeviceModelStatusEnumType::EmptyResultSet,
+          false => GenericDeviceModelStatusEnumType::Accepted,
+        },
+        status_info: None,
+      },
+    );
+
+    let pages: Vec<_> = monitors.chunks(NOTIFY_REPORT_ITEMS).collect();
+    let generated_at = Utc::now();
+
+    let mut messages = vec![response];
+    for (seq_no, page) in pages.iter().enumerate() {
+      messages.push(
+        self
+          .build_call(
+            OcppAction::NotifyMonitoringReport,
+            NotifyMonitoringReportRequest {
+              request_id: request.request_id,
+              tbc: Some(seq_no + 1 < pages.len()),
+              seq_no: seq_no as i32,
+              generated_at,
+              monitor: Some(page.to_vec()),
+            },
+          )
+          .await,
+      );
+    }
+    messages
+  }
+
+  async fn has_monitors(&self) -> bool {
+    self.device_model.lock().unwrap().has_monitors()
+  }
+
+  async fn monitoring_events(&self) -> Vec<Value> {
+    let (transaction_id, soc) = self
+      .shared_data
+      .read(|data| (data.transaction_id, data.charging_session_state.soc))
+      .await;
+
+    // The EVSE draws no power and cools down without a transaction
+    let meter = &self.config.meter;
+    let (power, temperature) = match transaction_id {
+      Some(_) => {
+        let reading = MeterReading::sample(meter, soc);
+        (reading.power_active_w(), reading.temperature)
+      }
+      None => (0.0, meter.temperature),
+    };
+
+    let transaction_id = transaction_id.map(|transaction_id| transaction_id.to_string());
+    let event_data =
+      self
+        .device_model
+        .lock()
+        .unwrap()
+        .sample(power, temperature, transaction_id.as_deref());
+    let pages: Vec<_> = event_data.chunks(NOTIFY_REPORT_ITEMS).collect();
+    let generated_at = Utc::now();
+
+    let mut messages = Vec::with_capacity(pages.len());
+    for (seq_no, page) in pages.iter().enumerate() {
+      messages.push(
+        self
+          .build_call(
+            OcppAction::NotifyEvent,
+            NotifyEventRequest {
+              generated_at,
+              tbc: Some(seq_no + 1 < pages.len()),
+              seq_no: seq_no as i32,
+              event_data: page.to_vec(),
+            },
+          )
+          .await,
+      );
+    }
+    messages
+  }
+
+  // ⚡ Smart charging
+
+  async fn set_charging_profile(&self, msg_id: &str, payload: Value) -> (Value, Option<f64>) {
+    let request: SetChargingProfileRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return (
+          build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+          None,
+        );
+      }
+    };
+
+    let limit = request
+      .charging_profile
+      .charging_schedule
+      .first()
+      .and_then(|schedule| {
+        schedule
+          .charging_schedule_period
+          .iter()
+          .min_by_key(|period| period.start_period)
+      })
+      .and_then(|period| period.limit.to_f64());
+
+    let response = Self::call_result(
+      OcppAction::SetChargingProfile,
+      msg_id,
+      SetChargingProfileResponse {
+        status: ChargingProfileStatusEnumType::Accepted,
+        status_info: None,
+      },
+    );
+    (response, limit)
+  }
+
+  async fn clear_charging_profile(&self, msg_id: &str, _payload: Value) -> Value {
+    Self::call_result(
+      OcppAction::ClearChargingProfile,
+      msg_id,
+      ClearChargingProfileResponse {
+        status: ClearChargingProfileStatusEnumType::Accepted,
+        status_info: None,
+      },
+    )
+  }
+
+  // 🖥️ Display messages
+
+  async fn set_display_message(&self, msg_id: &str, payload: Value) -> Value {
+    let request: SetDisplayMessageRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let status = {
+      let mut device_model = self.device_model.lock().unwrap();
+      if device_model.actual("DisplayMessageCtrlr", "Enabled", None) == Some("false") {
+        DisplayMessageStatusEnumType::Rejected
+      } else {
+        let mut display = self.display.lock().unwrap();
+        let status = display.set(request.message);
+        device_model.set_actual(
+          "DisplayMessageCtrlr",
+          "DisplayMessages",
+          &display.len().to_string(),
+        );
+        status
+      }
+    };
+
+    Self::call_result(
+      OcppAction::SetDisplayMessage,
+      msg_id,
+      SetDisplayMessageResponse {
+        status,
+        status_info: None,
+      },
+    )
+  }
+
+  async fn get_display_messages(&self, msg_id: &str, payload: Value) -> Vec<Value> {
+    let request: GetDisplayMessagesRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => {
+        return vec![build_call_error(
+          msg_id,
+          "FormationViolation",
+          &err.to_string(),
+          None,
+        )];
+      }
+    };
+
+    let message_info = self.display.lock().unwrap().get(&request);
+
+    let mut messages = vec![Self::call_result(
+      OcppAction::GetDisplayMessages,
+      msg_id,
+      GetDisplayMessagesResponse {
+        status: match message_info.is_empty() {
+          true => GetDisplayMessagesStatusEnumType::Unknown,
+          false => GetDisplayMessagesStatusEnumType::Accepted,
+        },
+        status_info: None,
+      },
+    )];
+
+    let pages: Vec<_> = message_info.chunks(NOTIFY_REPORT_ITEMS).collect();
+    for (index, page) in pages.iter().enumerate() {
+      messages.push(
+        self
+          .build_call(
+            OcppAction::NotifyDisplayMessages,
+            NotifyDisplayMessagesRequest {
+              request_id: request.request_id,
+              tbc: Some(index + 1 < pages.len()),
+              message_info: Some(page.to_vec()),
+            },
+          )
+          .await,
+      );
+    }
+    messages
+  }
+
+  async fn clear_display_message(&self, msg_id: &str, payload: Value) -> Value {
+    let request: ClearDisplayMessageRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    let status = {
+      let mut display = self.display.lock().unwrap();
+      let status = display.clear(request.id);
+      self.device_model.lock().unwrap().set_actual(
+        "DisplayMessageCtrlr",
+        "DisplayMessages",
+        &display.len().to_string(),
+      );
+      status
+    };
+
+    Self::call_result(
+      OcppAction::ClearDisplayMessage,
+      msg_id,
+      ClearDisplayMessageResponse {
+        status,
+        status_info: None,
       },
     )
   }
 
-  async fn data_transfer(&self) -> Value {
-    FrameBuilder::build_call(
-      OcppAction::DataTransfer,
-      DataTransferRequest {
-        vendor_id: self.config.vendor.clone(),
-        data: Some("test".to_string()),
+  async fn cost_updated(&self, msg_id: &str, payload: Value) -> Value {
+    let request: CostUpdatedRequest = match serde_json::from_value(payload) {
+      Ok(request) => request,
+      Err(err) => return build_call_error(msg_id, "FormationViolation", &err.to_string(), None),
+    };
+
+    self
+      .display
+      .lock()
+      .unwrap()
+      .set_total_cost(request.total_cost, Some(&request.transaction_id));
+
+    Self::call_result(OcppAction::CostUpdated, msg_id, CostUpdatedResponse {})
+  }
+
+  // 🗂️ Logs and customer information
+
+  async fn get_log(
+    &self,
+    msg_id: &str,
+    status: CommonLogStatus,
+    filename: Option<String>,
+  ) -> Value {
+    Self::call_result(
+      OcppAction::GetLog,
+      msg_id,
+      GetLogResponse {
+        status: status.into(),
+        filename,
+        status_info: None,
+      },
+    )
+  }
+
+  async fn log_status_notification(&self, status: CommonUploadLogStatus, request_id: i32) -> Value {
+    self
+      .build_call(
+        OcppAction::LogStatusNotification,
+        LogStatusNotificationRequest {
+          status: status.into(),
+          request_id: Some(request_id),
+        },
+      )
+      .await
+  }
+
+  async fn customer_information(
+    &self,
+    msg_id: &str,
+    status: CommonCustomerInformationStatus,
+  ) -> Value {
+    Self::call_result(
+      OcppAction::CustomerInformation,
+      msg_id,
+      CustomerInformationResponse {
+        status: status.into(),
+        status_info: None,
+      },
+    )
+  }
+
+  async fn notify_customer_information(&self, request_id: i32, data: &str) -> Vec<Value> {
+    let generated_at = Utc::now();
+    let chars: Vec<char> = data.chars().collect();
+    let parts: Vec<String> = match chars.is_empty() {
+      true => vec![String::new()],
+      false => chars
+        .chunks(NOTIFY_CUSTOMER_INFORMATION_DATA)
+        .map(|part| part.iter().collect())
+        .collect(),
+    };
+
+    let mut messages = Vec::new();
+    for (seq_no, part) in parts.iter().enumerate() {
+      messages.push(
+        self
+          .build_call(
+            OcppAction::NotifyCustomerInformation,
+            NotifyCustomerInformationRequest {
+              data: part.clone(),
+              tbc: Some(seq_no + 1 < parts.len()),
+              seq_no: seq_no as i32,
+              generated_at,
+              request_id,
+            },
+          )
+          .await,
+      );
+    }
+    messages
+  }
+
+  // 🔌 ISO 15118 Plug & Charge
+
+  async fn get_15118_ev_certificate(&self, schema_version: &str, exi_request: String) -> Value {
+    self
+      .build_call(
+        OcppAction::Get15118EVCertificate,
+        Get15118EVCertificateRequest {
+          iso_15118_schema_version: schema_version.to_string(),
+          action: CertificateActionEnumType::Install,
+          exi_request,
+        },
+      )
+      .await
+  }
+
+  async fn get_certificate_status(&self, ocsp_request: CommonOcspRequestData) -> Value {
+    self
+      .build_call(
+        OcppAction::GetCertificateStatus,
+        GetCertificateStatusRequest {
+          ocsp_request_data: ocsp_request.into(),
+        },
+      )
+      .await
+  }
+
+  async fn authorize_contract(
+    &self,
+    emaid: &str,
+    certificate_hash_data: Vec<CommonOcspRequestData>,
+  ) -> Value {
+    self
+      .shared_data
+      .write(|data| data.tag_id = Some(emaid.to_string()))
+      .await;
+
+    build_tagged_call(
+      &self.shared_data,
+      OcppAction::Authorize,
+      AuthorizeRequest {
+        id_token: self.id_token(emaid.to_string()),
+        iso_15118_certificate_hash_data: Some(
+          certificate_hash_data.into_iter().map(Into::into).collect(),
+        ),
         ..Default::default()
       },
+      Some(emaid.to_string()),
     )
+    .await
+    .1
   }
 
   fn next_id(&self) -> String {
@@ -195,10 +1864,467 @@ impl MessageGenerator for V201MessageGenerator {
 }
 
 impl V201MessageGenerator {
-  pub fn new(config: MessageGeneratorConfig) -> Self {
-    Self {
+  pub fn new(config: ChargePointConfig, shared_data: SharedData<OcppAction>) -> Result<Self> {
+    let device_model = DeviceModel::new(&config)?;
+    let display = DisplayMessages::new(
+      device_model
+        .actual("TariffCostCtrlr", "Currency", None)
+        .unwrap_or_default(),
+    );
+
+    Ok(Self {
+      meter_signer: MeterSigner::from_config(&config)?,
+      device_model: Mutex::new(device_model),
+      display: Arc::new(Mutex::new(display)),
       config,
+      shared_data,
       id_counter: AtomicUsize::new(1),
+      seq_no: AtomicI32::new(0),
+      offline: AtomicBool::new(false),
+      transaction: Mutex::new(TransactionState::default()),
+    })
+  }
+
+  pub fn display(&self) -> Arc<Mutex<DisplayMessages>> {
+    self.display.clone()
+  }
+
+  /// The eMAID of the Plug & Charge contract is a contract id, any other token an RFID tag
+  fn id_token(&self, id_token: String) -> IdTokenType {
+    let contract = self
+      .config
+      .plug_and_charge
+      .as_ref()
+      .is_some_and(|plug_and_charge| plug_and_charge.emaid == id_token);
+
+    IdTokenType {
+      id_token,
+      kind: match contract {
+        true => IdTokenEnumType::EMAID,
+        false => IdTokenEnumType::ISO14443,
+      },
+      additional_info: None,
+    }
+  }
+
+  /// `ItemsPerMessage` limit of the device model for a request
+  fn items_per_message(device_model: &DeviceModel, component: &str, action: &str) -> usize {
+    device_model
+      .actual(component, "ItemsPerMessage", Some(action))
+      .and_then(|value| value.parse().ok())
+      .unwrap_or(usize::MAX)
+  }
+
+  /// Applies a variable set by the CSMS to the settings of the charge point, returns the
+  /// changes the charge point applies at runtime
+  async fn apply_variable(&self, data: &SetVariableDataType) -> Option<CommonSettingChange> {
+    if data
+      .attribute_type
+      .as_ref()
+      .is_some_and(|kind| *kind != AttributeEnumType::Actual)
+    {
+      return None;
+    }
+
+    let value = data.attribute_value.clone();
+    let number = value.parse::<u32>().ok();
+    let state = value == "true";
+
+    match (
+      data.component.name.as_str(),
+      data.variable.name.as_str(),
+      data.variable.instance.as_deref(),
+    ) {
+      ("OCPPCommCtrlr", "HeartbeatInterval", _) => {
+        self.heartbeat_interval(number?).await;
+        return Some(CommonSettingChange::HeartbeatInterval(number?));
+      }
+      ("OCPPCommCtrlr", "WebSocketPingInterval", _) => {
+        self.websocket_ping_interval(number?).await;
+        return Some(CommonSettingChange::WebSocketPingInterval(number?));
+      }
+      ("OCPPCommCtrlr", "ResetRetries", _) => self.reset_retries(number?).await,
+      ("OCPPCommCtrlr", "MessageAttempts", Some("TransactionEvent")) => {
+        self.transaction_message_attempts(number?).await
+      }
+      ("OCPPCommCtrlr", "MessageAttemptInterval", Some("TransactionEvent")) => {
+        self.transaction_message_retry_interval(number?).await
+      }
+      ("TxCtrlr", "EVConnectionTimeOut", _) => {
+        self.connection_timeout(number?).await;
+        return Some(CommonSettingChange::ConnectionTimeOut(number?));
+      }
+      ("TxCtrlr", "StopTxOnEVSideDisconnect", _) => {
+        self.stop_transaction_on_ev_side_disconnect(state).await;
+        return Some(CommonSettingChange::StopTransactionOnEVSideDisconnect(
+          state,
+        ));
+      }
+      ("TxCtrlr", "StopTxOnInvalidId", _) => self.stop_transaction_on_invalid_id(state).await,
+      ("TxCtrlr", "MaxEnergyOnInvalidId", _) => self.max_energy_on_invalid_id(number?).await,
+      ("SampledDataCtrlr", "TxUpdatedInterval", _) => {
+        self.meter_value_sample_interval(number?).await;
+        return Some(CommonSettingChange::MeterValueSampleInterval(number?));
+      }
+      ("SampledDataCtrlr", "TxUpdatedMeasurands", _) => self.meter_values_sampled_data(value).await,
+      ("SampledDataCtrlr", "TxEndedMeasurands", _) => self.stop_txn_sampled_data(value).await,
+      ("AuthCtrlr", "AuthorizeRemoteStart", _) => self.authorize_remote_tx_requests(state).await,
+      ("AuthCtrlr", "LocalAuthorizeOffline", _) => self.local_authorize_offline(state).await,
+      ("AuthCtrlr", "LocalPreAuthorize", _) => self.local_pre_authorize(state).await,
+      ("AuthCtrlr", "OfflineTxForUnknownIdEnabled", _) => {
+        self.allow_offline_tx_for_unknown_id(state).await
+      }
+      _ => {}
+    }
+
+    None
+  }
+
+  /// `NotifyReport` pages of a report, none for an empty report
+  async fn notify_report(&self, request_id: i32, report_data: Vec<ReportDataType>) -> Vec<Value> {
+    let pages: Vec<_> = report_data.chunks(NOTIFY_REPORT_ITEMS).collect();
+    let generated_at = Utc::now();
+
+    let mut messages = Vec::with_capacity(pages.len());
+    for (seq_no, page) in pages.iter().enumerate() {
+      messages.push(
+        self
+          .build_call(
+            OcppAction::NotifyReport,
+            NotifyReportRequest {
+              request_id,
+              tbc: Some(seq_no + 1 < pages.len()),
+              seq_no: seq_no as i32,
+              generated_at,
+              report_data: Some(page.to_vec()),
+            },
+          )
+          .await,
+      );
+    }
+    messages
+  }
+
+  /// Points of `TxCtrlr.TxStartPoint` or `TxCtrlr.TxStopPoint`
+  fn tx_points(&self, variable: &str) -> Vec<String> {
+    self
+      .device_model
+      .lock()
+      .unwrap()
+      .actual("TxCtrlr", variable, None)
+      .map(|points| {
+        points
+          .split(',')
+          .map(|point| point.trim().to_string())
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  fn charging_state(
+    status: CommonConnectorStatusType,
+    ev_connected: bool,
+  ) -> ChargingStateEnumType {
+    match status {
+      _ if !ev_connected => ChargingStateEnumType::Idle,
+      CommonConnectorStatusType::Charging => ChargingStateEnumType::Charging,
+      CommonConnectorStatusType::SuspendedEV => ChargingStateEnumType::SuspendedEV,
+      CommonConnectorStatusType::SuspendedEVSE => ChargingStateEnumType::SuspendedEVSE,
+      _ => ChargingStateEnumType::EVConnected,
+    }
+  }
+
+  /// Assigns the id of a new transaction, its events are numbered from 0
+  async fn begin_transaction(&self) -> i32 {
+    // Transaction ids are assigned by the charging station in 2.0.1
+    let transaction_id = rand::random_range(1..i32::MAX);
+
+    self
+      .shared_data
+      .write(|data| data.transaction_id = Some(transaction_id))
+      .await;
+    self.seq_no.store(0, Ordering::Relaxed);
+    self
+      .display
+      .lock()
+      .unwrap()
+      .set_transaction(Some(transaction_id.to_string()));
+
+    transaction_id
+  }
+
+  /// `TransactionEvent` call, queued until the CSMS confirms it
+  async fn transaction_event(&self, mut request: TransactionEventRequest) -> (String, Value) {
+    if self.offline.load(Ordering::Relaxed) {
+      request.offline = Some(true);
+    }
+    let (msg_id, call) = build_call(&self.shared_data, OcppAction::TransactionEvent, request).await;
+
+    self
+      .shared_data
+      .write(|data| data.transaction_events.push((msg_id.clone(), call.clone())))
+      .await;
+
+    (msg_id, call)
+  }
+
+  /// `Ended` event of the current transaction with the final energy register reading
+  async fn end_transaction(
+    &self,
+    trigger_reason: TriggerReasonEnumType,
+    stopped_reason: ReasonEnumType,
+  ) -> Value {
+    let (energy_wh, transaction_id) = self
+      .shared_data
+      .write(|data| {
+        data.start_transaction_msg_id = None;
+        (
+          data.charging_session_state.energy_wh,
+          data.transaction_id.take().unwrap_or(1),
+        )
+      })
+      .await;
+
+    self.display.lock().unwrap().set_transaction(None);
+
+    let charging_state = {
+      let mut transaction = self.transaction.lock().unwrap();
+      transaction.authorized = false;
+      transaction.charging_state = None;
+      transaction.stopped_reason = None;
+      Self::charging_state(
+        CommonConnectorStatusType::Finishing,
+        transaction.ev_connected,
+      )
+    };
+
+    let signed = self
+      .meter_signer
+      .as_ref()
+      .map(|meter_signer| meter_signer.end(energy_wh).1);
+
+    self
+      .transaction_event(TransactionEventRequest {
+        event_type: TransactionEventEnumType::Ended,
+        timestamp: Utc::now(),
+        trigger_reason,
+        seq_no: self.next_seq_no(),
+        transaction_info: TransactionType {
+          transaction_id: transaction_id.to_string(),
+          charging_state: Some(charging_state),
+          stopped_reason: Some(stopped_reason),
+          ..Default::default()
+        },
+        meter_value: Some(vec![self.energy_meter_value(
+          energy_wh,
+          ReadingContextEnumType::TransactionEnd,
+          signed,
+        )]),
+        ..Default::default()
+      })
+      .await
+      .1
+  }
+
+  fn next_seq_no(&self) -> i32 {
+    self.seq_no.fetch_add(1, Ordering::Relaxed)
+  }
+
+  /// Energy register reading, signed when the charge point signs its meter values
+  fn energy_sampled_value(
+    &self,
+    energy_wh: f64,
+    context: ReadingContextEnumType,
+    signed: Option<String>,
+  ) -> SampledValueType {
+    let signed_meter_value = signed.map(|signed_data| SignedMeterValueType {
+      signed_meter_data: STANDARD.encode(signed_data),
+      signing_method: OCMF_SIGNATURE_ALGORITHM.to_string(),
+      encoding_method: "OCMF".to_string(),
+      public_key: self
+        .meter_signer
+        .as_ref()
+        .and_then(|meter_signer| meter_signer.public_key_hex().ok())
+        .unwrap_or_default(),
+    });
+
+    SampledValueType {
+      value: Decimal::from_f64_retain(energy_wh)
+        .unwrap_or_default()
+        .round_dp(3),
+      context: Some(context),
+      measurand: Some(MeasurandEnumType::EnergyActiveImportRegister),
+      signed_meter_value,
+      unit_of_measure: Some(UnitOfMeasureType {
+        unit: Some("Wh".to_string()),
+        multiplier: None,
+      }),
+      ..Default::default()
+    }
+  }
+
+  fn energy_meter_value(
+    &self,
+    energy_wh: f64,
+    context: ReadingContextEnumType,
+    signed: Option<String>,
+  ) -> MeterValueType {
+    MeterValueType {
+      timestamp: Utc::now(),
+      sampled_value: vec![self.energy_sampled_value(energy_wh, context, signed)],
+    }
+  }
+
+  async fn build_call<T>(&self, ocpp_action: OcppAction, payload: T) -> Value
+  where
+    T: Debug + Serialize,
+  {
+    build_call(&self.shared_data, ocpp_action, payload).await.1
+  }
+
+  fn call_result<T>(ocpp_action: OcppAction, msg_id: &str, payload: T) -> Value
+  where
+    T: Debug + Serialize,
+  {
+    frame_info!("➡️  [🟢 CallResult] {}", ocpp_action);
+    debug!(action = %ocpp_action, ?payload);
+
+    build_call_result(msg_id, payload)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rust_ocpp::v2_0_1::enumerations::report_base_enum_type::ReportBaseEnumType;
+
+  use super::*;
+  use crate::test_config::charge_point_config;
+  use crate::v2_0_1::monitoring::DEBUG_SEVERITY;
+
+  fn generator(overrides: Value) -> V201MessageGenerator {
+    V201MessageGenerator::new(charge_point_config(overrides), SharedData::default()).unwrap()
+  }
+
+  fn get_variable_data(count: usize) -> Value {
+    let data: Vec<_> = (0..count)
+      .map(|_| {
+        json!({
+          "component": { "name": "ChargingStation" },
+          "variable": { "name": "Model" },
+        })
+      })
+      .collect();
+    json!({ "getVariableData": data })
+  }
+
+  #[tokio::test]
+  async fn get_variables_beyond_items_per_message_is_refused() {
+    let generator = generator(json!({
+      "variables": [{
+        "component": "DeviceDataCtrlr",
+        "variable": "ItemsPerMessage",
+        "variable_instance": "GetVariables",
+        "actual": "2",
+      }],
+    }));
+
+    let response = generator.get_variables("1", get_variable_data(2)).await;
+    assert_eq!(response[0], 3);
+    assert_eq!(
+      response[2]["getVariableResult"][1]["attributeValue"],
+      "test-model"
+    );
+
+    let response = generator.get_variables("2", get_variable_data(3)).await;
+    assert_eq!(response[0], 4);
+    assert_eq!(response[1], "2");
+    assert_eq!(response[2], "OccurrenceConstraintViolation");
+  }
+
+  #[tokio::test]
+  async fn base_report_is_paged_by_notify_report() {
+    let generator = generator(json!({}));
+    let variables = generator
+      .device_model
+      .lock()
+      .unwrap()
+      .base_report(&ReportBaseEnumType::FullInventory)
+      .len();
+    assert!(variables > NOTIFY_REPORT_ITEMS);
+
+    let messages = generator
+      .get_base_report(
+        "1",
+        json!({ "requestId": 42, "reportBase": "FullInventory" }),
+      )
+      .await;
+    assert_eq!(messages[0], json!([3, "1", { "status": "Accepted" }]));
+
+    let pages = &messages[1..];
+    assert_eq!(pages.len(), variables.div_ceil(NOTIFY_REPORT_ITEMS));
+    let mut reported = 0;
+    for (seq_no, page) in pages.iter().enumerate() {
+      assert_eq!(page[0], 2);
+      assert_eq!(page[2], "NotifyReport");
+      assert_eq!(page[3]["requestId"], 42);
+      assert_eq!(page[3]["seqNo"], seq_no);
+      assert_eq!(page[3]["tbc"], seq_no + 1 < pages.len());
+      reported += page[3]["reportData"].as_array().unwrap().len();
+    }
+    assert_eq!(reported, variables);
+  }
+
+  #[tokio::test]
+  async fn monitoring_events_are_numbered_like_a_report() {
+    let generator = generator(json!({}));
+    {
+      let mut device_model = generator.device_model.lock().unwrap();
+      // Replaced by the monitors of every severity
+      device_model.clear_monitor(1);
+      let monitors = [
+        (
+          "TemperatureSensor",
+          None,
+          "Temperature",
+          "UpperThreshold",
+          -100,
+        ),
+        (
+          "TemperatureSensor",
+          None,
+          "Temperature",
+          "LowerThreshold",
+          1000,
+        ),
+        ("EVSE", Some(1), "Power", "UpperThreshold", -100),
+      ];
+      for (component, evse, variable, kind, value) in monitors {
+        for severity in 0..=DEBUG_SEVERITY {
+          let data = serde_json::from_value(json!({
+            "value": value,
+            "type": kind,
+            "severity": severity,
+            "component": { "name": component, "evse": evse.map(|id| json!({ "id": id })) },
+            "variable": { "name": variable },
+          }))
+          .unwrap();
+          assert!(device_model.set_monitor(&data).id.is_some());
+        }
+      }
+    }
+
+    let messages = generator.monitoring_events().await;
+    assert_eq!(messages.len(), 2);
+    let events: Vec<_> = messages
+      .iter()
+      .map(|message| message[3]["eventData"].as_array().unwrap().len())
+      .collect();
+    assert_eq!(events, [NOTIFY_REPORT_ITEMS, 3 * 10 - NOTIFY_REPORT_ITEMS]);
+    for (seq_no, message) in messages.iter().enumerate() {
+      assert_eq!(message[2], "NotifyEvent");
+      assert_eq!(message[3]["seqNo"], seq_no);
+      assert_eq!(message[3]["tbc"], seq_no == 0);
     }
+    assert_eq!(messages[0][3]["generatedAt"], messages[1][3]["generatedAt"]);
   }
 }
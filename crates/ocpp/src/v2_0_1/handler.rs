@@ -1,30 +1,139 @@
-#![cfg(feature = "ocpp2_0_1")]
-#![allow(unused)]
-use crate::{
-  handler::{MessageFrameType, MessageHandler},
-  types::CommonOcppResponse,
+use std::{
+  str::FromStr,
+  sync::{Arc, Mutex},
 };
 
+use super::{
+  display::DisplayMessages,
+  generator::{build_call_error, build_call_result},
+  types::OcppAction,
+};
+use crate::{
+  handler::{MessageFrame, MessageFrameType, MessageHandler},
+  types::{AuthorizationStatus, CommonOcppResponse},
+};
 use anyhow::Result;
 use async_trait::async_trait;
+use common::{SharedData, frame_info};
+use rust_decimal::Decimal;
+use rust_ocpp::v2_0_1::{
+  datatypes::message_content_type::MessageContentType,
+  enumerations::{
+    authorize_certificate_status_enum_type::AuthorizeCertificateStatusEnumType,
+    get_certificate_status_enum_type::GetCertificateStatusEnumType,
+    iso15118ev_certificate_status_enum_type::Iso15118EVCertificateStatusEnumType,
+    request_start_stop_status_enum_type::RequestStartStopStatusEnumType,
+  },
+  messages::{
+    authorize::AuthorizeResponse,
+    get_15118ev_certificate::Get15118EVCertificateResponse,
+    get_certificate_status::GetCertificateStatusResponse,
+    request_start_transaction::{RequestStartTransactionRequest, RequestStartTransactionResponse},
+    request_stop_transaction::RequestStopTransactionResponse,
+    transaction_event::TransactionEventResponse,
+  },
+};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use tracing::{debug, warn};
 
-pub struct V201MessageHandler {}
+pub struct V201MessageHandler {
+  shared_data: SharedData<OcppAction>,
+  display: Arc<Mutex<DisplayMessages>>,
+}
 
 impl V201MessageHandler {
-  pub fn new() -> Self {
-    Self {}
+  pub fn new(shared_data: SharedData<OcppAction>, display: Arc<Mutex<DisplayMessages>>) -> Self {
+    Self {
+      shared_data,
+      display,
+    }
   }
 }
 
 #[async_trait]
 impl MessageHandler for V201MessageHandler {
-  fn parse_ocpp_message(&self, text: &str) -> Result<MessageFrameType> {
-    !unimplemented!()
+  async fn parse_raw_ocpp_msg(&self, msg: &str) -> Result<MessageFrameType> {
+    let arr: Vec<Value> = serde_json::from_str(msg)?;
+
+    match arr.first().and_then(|v| v.as_u64()) {
+      Some(2) => {
+        let msg_id = arr[1].as_str().unwrap_or("").to_string();
+        let action_string = arr[2].as_str().unwrap_or("").to_string();
+        let payload = arr[3].clone();
+
+        let action = OcppAction::from_str(action_string.as_str())
+          .map_err(|err| anyhow::anyhow!("Invalid OCPP action: {}", err))?;
+
+        frame_info!("⬅️  [🔵 Call] {}", action);
+
+        Ok(MessageFrameType::V2_0_1(MessageFrame::Call {
+          msg_id,
+          action,
+          payload,
+        }))
+      }
+      Some(3) => {
+        let msg_id = arr[1].as_str().unwrap_or("").to_string();
+        let payload = arr[2].clone();
+
+        frame_info!(
+          "⬅️  [🟢 CallResult] {:?}",
+          self.shared_data.get_msg(&msg_id).await
+        );
+
+        Ok(MessageFrameType::V2_0_1(MessageFrame::CallResult {
+          msg_id,
+          payload,
+        }))
+      }
+      Some(4) => {
+        let msg_id = arr[1].as_str().unwrap_or("").to_string();
+        let error_code = arr[2].as_str().unwrap_or("").to_string();
+        let description = arr[3].as_str().unwrap_or("").to_string();
+
+        // The failed call no longer waits for an answer
+        let ocpp_action = self.shared_data.take_msg(&msg_id).await;
+        frame_info!("⬅️  [🔴 CallError] {} {:?}", error_code, ocpp_action);
+
+        Ok(MessageFrameType::V2_0_1(MessageFrame::CallError {
+          msg_id,
+          error_code,
+          description,
+        }))
+      }
+      _ => anyhow::bail!("Unknown OCPP v2.0.1 message type"),
+    }
   }
 
   async fn handle_text_message(&mut self, text: &str) -> Result<Option<String>> {
-    !unimplemented!()
+    if let MessageFrameType::V2_0_1(ocpp_message) = self.parse_raw_ocpp_msg(text).await? {
+      match ocpp_message {
+        MessageFrame::Call {
+          msg_id,
+          action,
+          payload,
+        } => {
+          debug!(?action, msg_id, ?payload);
+          return Ok(Some(Self::not_implemented(&msg_id, &action)));
+        }
+        MessageFrame::CallResult { msg_id, payload } => {
+          debug!(msg_id, ?payload);
+          self.handle_call_result(&msg_id, &payload).await?;
+          return Ok(None);
+        }
+        MessageFrame::CallError {
+          msg_id,
+          error_code,
+          description,
+        } => {
+          debug!(msg_id, error_code, description);
+          return Ok(None);
+        }
+      }
+    }
+
+    anyhow::bail!("Invalid text message")
   }
 
   async fn handle_call_result(
@@ -32,6 +141,151 @@ impl MessageHandler for V201MessageHandler {
     msg_id: &str,
     payload: &Value,
   ) -> Result<Option<CommonOcppResponse>> {
-    !unimplemented!()
+    let call = self.shared_data.take_tagged_msg(msg_id).await;
+    match call {
+      Some((ocpp_action, tag_id)) => match ocpp_action {
+        OcppAction::TransactionEvent => {
+          let res: TransactionEventResponse = serde_json::from_value(payload.clone())?;
+          self.show_session_info(
+            res
+              .updated_personal_message
+              .or(res.id_token_info.and_then(|info| info.personal_message)),
+            res.total_cost,
+          );
+
+          // Only the confirmation of the event closing the power path starts charging
+          let transaction_id = self
+            .shared_data
+            .write(|data| {
+              data
+                .transaction_events
+                .retain(|(event_msg_id, _)| event_msg_id != msg_id);
+              data
+                .start_transaction_msg_id
+                .as_deref()
+                .filter(|start_msg_id| *start_msg_id == msg_id)
+                .and(data.transaction_id)
+            })
+            .await;
+
+          Ok(
+            transaction_id
+              .map(|transaction_id| CommonOcppResponse::StartTransaction { transaction_id }),
+          )
+        }
+        OcppAction::Authorize => {
+          let res: AuthorizeResponse = serde_json::from_value(payload.clone())?;
+          self.show_session_info(res.id_token_info.personal_message.clone(), None);
+
+          // A contract certificate the CSMS does not accept fails the authorization
+          let status = match res.certificate_status {
+            Some(certificate_status)
+              if certificate_status != AuthorizeCertificateStatusEnumType::Accepted =>
+            {
+              warn!(
+                "Contract certificate not accepted: {:?}",
+                certificate_status
+              );
+              AuthorizationStatus::Invalid
+            }
+            _ => res.id_token_info.status.into(),
+          };
+
+          Ok(Some(CommonOcppResponse::Authorize {
+            id_tag: tag_id.unwrap_or_default(),
+            status,
+            parent_id_tag: res
+              .id_token_info
+              .group_id_token
+              .map(|group_id_token| group_id_token.id_token),
+          }))
+        }
+        OcppAction::Get15118EVCertificate => {
+          let res: Get15118EVCertificateResponse = serde_json::from_value(payload.clone())?;
+          Ok(Some(CommonOcppResponse::Iso15118EVCertificate {
+            accepted: res.status == Iso15118EVCertificateStatusEnumType::Accepted,
+          }))
+        }
+        OcppAction::GetCertificateStatus => {
+          let res: GetCertificateStatusResponse = serde_json::from_value(payload.clone())?;
+          Ok(Some(CommonOcppResponse::CertificateStatus {
+            accepted: res.status == GetCertificateStatusEnumType::Accepted
+              && res.ocsp_result.is_some(),
+          }))
+        }
+        _ => Ok(None),
+      },
+      None => {
+        warn!("No call waiting for the answer {msg_id}");
+        Ok(None)
+      }
+    }
+  }
+}
+
+impl V201MessageHandler {
+  /// Shows the personal message and cost the CSMS sent with a response
+  fn show_session_info(
+    &self,
+    personal_message: Option<MessageContentType>,
+    total_cost: Option<Decimal>,
+  ) {
+    let mut display = self.display.lock().unwrap();
+    if let Some(personal_message) = personal_message {
+      display.set_personal_message(personal_message);
+    }
+    if let Some(total_cost) = total_cost {
+      display.set_total_cost(total_cost, None);
+    }
+  }
+
+  /// CallError for requests the simulated charging station does not support
+  pub fn not_implemented(msg_id: &str, action: &OcppAction) -> String {
+    warn!("No support for {}", action);
+    build_call_error(
+      msg_id,
+      "NotImplemented",
+      &format!("{} is not supported", action),
+      None,
+    )
+    .to_string()
+  }
+
+  /// CallResult of a `RequestStartTransaction` or `RequestStopTransaction`
+  pub fn request_start_stop_response(msg_id: &str, action: &OcppAction, accepted: bool) -> String {
+    let status = if accepted {
+      RequestStartStopStatusEnumType::Accepted
+    } else {
+      RequestStartStopStatusEnumType::Rejected
+    };
+
+    frame_info!("➡️  [🟢 CallResult] {} {:?}", action, status);
+
+    let payload = match action {
+      OcppAction::RequestStartTransaction => {
+        serde_json::to_value(RequestStartTransactionResponse {
+          status,
+          transaction_id: None,
+          status_info: None,
+        })
+      }
+      _ => serde_json::to_value(RequestStopTransactionResponse {
+        status,
+        status_info: None,
+      }),
+    };
+
+    build_call_result(msg_id, payload.unwrap_or_default()).to_string()
+  }
+
+  pub fn parse_payload<T: DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    let payload: T = serde_json::from_value(value)?;
+    Ok(payload)
+  }
+
+  pub fn parse_request_start_transaction_payload(
+    payload: serde_json::Value,
+  ) -> Result<RequestStartTransactionRequest> {
+    V201MessageHandler::parse_payload::<RequestStartTransactionRequest>(payload)
   }
 }
@@ -1,3 +1,6 @@
+pub mod device_model;
+pub mod display;
 pub mod generator;
 pub mod handler;
+pub mod monitoring;
 pub mod types;
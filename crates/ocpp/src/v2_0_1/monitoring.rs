@@ -0,0 +1,480 @@
+//! OCPP 2.0.1 variable monitoring: monitors set by the CSMS or preconfigured, evaluated
+//! against the sampled values of the device model
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use rust_ocpp::v2_0_1::{
+  datatypes::{
+    clear_monitoring_result_type::ClearMonitoringResultType, component_type::ComponentType,
+    event_data_type::EventDataType, monitoring_data_type::MonitoringDataType,
+    set_monitoring_data_type::SetMonitoringDataType,
+    variable_monitoring_type::VariableMonitoringType, variable_type::VariableType,
+  },
+  enumerations::{
+    clear_monitoring_status_enum_type::ClearMonitoringStatusEnumType,
+    event_notification_enum_type::EventNotificationEnumType,
+    event_trigger_enum_type::EventTriggerEnumType, monitor_enum_type::MonitorEnumType,
+    monitoring_base_enum_type::MonitoringBaseEnumType,
+    monitoring_criterion_enum_type::MonitoringCriterionEnumType,
+    set_monitoring_status_enum_type::SetMonitoringStatusEnumType,
+  },
+};
+
+/// Least severe level, all monitors report events
+pub const DEBUG_SEVERITY: u8 = 9;
+
+#[derive(Clone)]
+pub struct Monitor {
+  pub id: i32,
+  pub component: ComponentType,
+  pub variable: VariableType,
+  pub kind: MonitorEnumType,
+  pub value: Decimal,
+  pub severity: u8,
+  /// Only evaluated while a transaction is running
+  pub transaction: bool,
+  pub preconfigured: bool,
+  /// Threshold exceeded and not yet cleared
+  tripped: bool,
+  /// Value of the last `Delta` event
+  reported_value: Option<f64>,
+  /// Next event of a periodic monitor
+  due: Option<DateTime<Utc>>,
+}
+
+impl Monitor {
+  pub fn preconfigured(
+    id: i32,
+    component: ComponentType,
+    variable: VariableType,
+    kind: MonitorEnumType,
+    value: Decimal,
+    severity: u8,
+  ) -> Self {
+    Self {
+      id,
+      component,
+      variable,
+      kind,
+      value,
+      severity,
+      transaction: false,
+      preconfigured: true,
+      tripped: false,
+      reported_value: None,
+      due: None,
+    }
+  }
+
+  fn matches(&self, criterion: &MonitoringCriterionEnumType) -> bool {
+    matches!(
+      (criterion, &self.kind),
+      (
+        MonitoringCriterionEnumType::ThresholdMonitoring,
+        MonitorEnumType::UpperThreshold | MonitorEnumType::LowerThreshold
+      ) | (
+        MonitoringCriterionEnumType::DeltaMonitoring,
+        MonitorEnumType::Delta
+      ) | (
+        MonitoringCriterionEnumType::PeriodicMonitoring,
+        MonitorEnumType::Periodic | MonitorEnumType::PeriodicClockAligned
+      )
+    )
+  }
+
+  /// Trigger and whether the event clears a threshold, `None` when nothing happened
+  fn evaluate(&mut self, value: f64, now: DateTime<Utc>) -> Option<(EventTriggerEnumType, bool)> {
+    let limit = self.value.to_f64().unwrap_or_default();
+
+    match self.kind {
+      MonitorEnumType::UpperThreshold | MonitorEnumType::LowerThreshold => {
+        let exceeded = match self.kind {
+          MonitorEnumType::UpperThreshold => value > limit,
+          _ => value < limit,
+        };
+        if exceeded == self.tripped {
+          return None;
+        }
+
+        self.tripped = exceeded;
+        Some((EventTriggerEnumType::Alerting, !exceeded))
+      }
+      MonitorEnumType::Delta => {
+        let reported_value = *self.reported_value.get_or_insert(value);
+        if (value - reported_value).abs() < limit {
+          return None;
+        }
+
+        self.reported_value = Some(value);
+        Some((EventTriggerEnumType::Delta, false))
+      }
+      MonitorEnumType::Periodic | MonitorEnumType::PeriodicClockAligned => {
+        let interval = Duration::seconds(limit.max(1.0) as i64);
+        let due = *self.due.get_or_insert_with(|| match self.kind {
+          // Aligned to multiples of the interval since midnight
+          MonitorEnumType::PeriodicClockAligned => now
+            .duration_trunc(Duration::days(1))
+            .map(|midnight| {
+              let elapsed = (now - midnight).num_seconds() / interval.num_seconds() + 1;
+              midnight + interval * elapsed as i32
+            })
+            .unwrap_or(now + interval),
+          _ => now + interval,
+        });
+        if now < due {
+          return None;
+        }
+
+        self.due = Some(due + interval);
+        Some((EventTriggerEnumType::Periodic, false))
+      }
+    }
+  }
+}
+
+/// Monitors of a charging station with the active monitoring base and level
+pub struct Monitoring {
+  monitors: Vec<Monitor>,
+  /// Restored by `SetMonitoringBase`
+  preconfigured: Vec<Monitor>,
+  pub level: u8,
+  next_id: i32,
+  next_event_id: i32,
+}
+
+impl Monitoring {
+  pub fn new(preconfigured: Vec<Monitor>) -> Self {
+    Self {
+      next_id: preconfigured
+        .iter()
+        .map(|monitor| monitor.id)
+        .max()
+        .unwrap_or(0)
+        + 1,
+      monitors: preconfigured.clone(),
+      preconfigured,
+      level: DEBUG_SEVERITY,
+      next_event_id: 1,
+    }
+  }
+
+  /// Adds a monitor or replaces the one with the given id, returns its id
+  pub fn set(&mut self, data: &SetMonitoringDataType) -> Result<i32, SetMonitoringStatusEnumType> {
+    let positive = data.value > Decimal::ZERO;
+    match data.kind {
+      MonitorEnumType::Delta
+      | MonitorEnumType::Periodic
+      | MonitorEnumType::PeriodicClockAligned
+        if !positive =>
+      {
+        return Err(SetMonitoringStatusEnumType::Rejected);
+      }
+      _ if data.severity > DEBUG_SEVERITY => return Err(SetMonitoringStatusEnumType::Rejected),
+      _ => {}
+    }
+
+    let monitor = Monitor {
+      id: 0,
+      component: data.component.clone(),
+      variable: data.variable.clone(),
+      kind: data.kind.clone(),
+      value: data.value,
+      severity: data.severity,
+      transaction: data.transaction.unwrap_or(false),
+      preconfigured: false,
+      tripped: false,
+      reported_value: None,
+      due: None,
+    };
+
+    match data.id {
+      Some(id) => {
+        let existing = self
+          .monitors
+          .iter_mut()
+          .find(|monitor| monitor.id == id)
+          .ok_or(SetMonitoringStatusEnumType::Rejected)?;
+        *existing = Monitor {
+          id,
+          preconfigured: existing.preconfigured,
+          ..monitor
+        };
+        Ok(id)
+      }
+      None => {
+        let duplicate = self.monitors.iter().any(|existing| {
+          existing.component == monitor.component
+            && existing.variable == monitor.variable
+            && existing.kind == monitor.kind
+            && existing.severity == monitor.severity
+        });
+        if duplicate {
+          return Err(SetMonitoringStatusEnumType::Duplicate);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.monitors.push(Monitor { id, ..monitor });
+        Ok(id)
+      }
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.monitors.is_empty()
+  }
+
+  pub fn clear(&mut self, id: i32) -> ClearMonitoringResultType {
+    let count = self.monitors.len();
+    self.monitors.retain(|monitor| monitor.id != id);
+
+    ClearMonitoringResultType {
+      status: match self.monitors.len() < count {
+        true => ClearMonitoringStatusEnumType::Accepted,
+        false => ClearMonitoringStatusEnumType::NotFound,
+      },
+      id,
+      status_info: None,
+    }
+  }
+
+  /// `All` restores the preconfigured monitors, `FactoryDefault` also clears the custom
+  /// ones and `HardWiredOnly` clears every monitor
+  pub fn set_base(&mut self, base: &MonitoringBaseEnumType) {
+    match base {
+      MonitoringBaseEnumType::All => {}
+      MonitoringBaseEnumType::FactoryDefault => self.monitors.clear(),
+      MonitoringBaseEnumType::HardWiredOnly => {
+        self.monitors.clear();
+        return;
+      }
+    }
+
+    for preconfigured in &self.preconfigured {
+      if !self
+        .monitors
+        .iter()
+        .any(|monitor| monitor.id == preconfigured.id)
+      {
+        self.monitors.push(preconfigured.clone());
+      }
+    }
+  }
+
+  /// Monitors meeting one of the criteria grouped by variable
+  pub fn report(
+    &self,
+    criteria: &[MonitoringCriterionEnumType],
+    selected: impl Fn(&Monitor) -> bool,
+  ) -> Vec<MonitoringDataType> {
+    let mut report: Vec<MonitoringDataType> = Vec::new();
+
+    let monitors = self.monitors.iter().filter(|monitor| {
+      (criteria.is_empty() || criteria.iter().any(|criterion| monitor.matches(criterion)))
+        && selected(monitor)
+    });
+    for monitor in monitors {
+      let variable_monitoring = VariableMonitoringType {
+        id: monitor.id,
+        transaction: monitor.transaction,
+        value: monitor.value,
+        kind: monitor.kind.clone(),
+        severity: monitor.severity,
+      };
+
+      match report
+        .iter_mut()
+        .find(|data| data.component == monitor.component && data.variable == monitor.variable)
+      {
+        Some(data) => data.variable_monitoring.push(variable_monitoring),
+        None => report.push(MonitoringDataType {
+          component: monitor.component.clone(),
+          variable: monitor.variable.clone(),
+          variable_monitoring: vec![variable_monitoring],
+        }),
+      }
+    }
+
+    report
+  }
+
+  /// Events of the monitors of a variable for a new value, monitors less severe than the
+  /// monitoring level are evaluated without reporting
+  pub fn evaluate(
+    &mut self,
+    component: &ComponentType,
+    variable: &VariableType,
+    value: f64,
+    transaction_id: Option<&str>,
+  ) -> Vec<EventDataType> {
+    let now = Utc::now();
+    let mut events = Vec::new();
+
+    let monitors = self.monitors.iter_mut().filter(|monitor| {
+      monitor.component == *component
+        && monitor.variable == *variable
+        && (!monitor.transaction || transaction_id.is_some())
+    });
+    for monitor in monitors {
+      let Some((trigger, cleared)) = monitor.evaluate(value, now) else {
+        continue;
+      };
+      if monitor.severity > self.level {
+        continue;
+      }
+
+      events.push(EventDataType {
+        event_id: self.next_event_id,
+        timestamp: now,
+        trigger,
+        cause: None,
+        actual_value: format!("{value:.1}"),
+        tech_code: None,
+        tech_info: None,
+        cleared: cleared.then_some(true),
+        transaction_id: transaction_id.map(String::from),
+        variable_monitoring_id: Some(monitor.id),
+        event_notification_type: match monitor.preconfigured {
+          true => EventNotificationEnumType::PreconfiguredMonitor,
+          false => EventNotificationEnumType::CustomMonitor,
+        },
+        component: component.clone(),
+        variable: variable.clone(),
+      });
+      self.next_event_id += 1;
+    }
+
+    events
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  fn temperature() -> (ComponentType, VariableType) {
+    (
+      ComponentType {
+        name: "TemperatureSensor".to_string(),
+        ..Default::default()
+      },
+      VariableType {
+        name: "Temperature".to_string(),
+        instance: None,
+      },
+    )
+  }
+
+  fn monitor(kind: &str, value: f64, severity: u8) -> SetMonitoringDataType {
+    serde_json::from_value(json!({
+      "value": value,
+      "type": kind,
+      "severity": severity,
+      "component": { "name": "TemperatureSensor" },
+      "variable": { "name": "Temperature" },
+    }))
+    .unwrap()
+  }
+
+  fn sample(monitoring: &mut Monitoring, value: f64) -> Vec<EventDataType> {
+    let (component, variable) = temperature();
+    monitoring.evaluate(&component, &variable, value, None)
+  }
+
+  #[test]
+  fn threshold_reports_when_crossed_and_when_cleared() {
+    let mut monitoring = Monitoring::new(Vec::new());
+    let id = monitoring.set(&monitor("UpperThreshold", 60.0, 5)).unwrap();
+
+    assert!(sample(&mut monitoring, 40.0).is_empty());
+
+    let events = sample(&mut monitoring, 65.0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].trigger, EventTriggerEnumType::Alerting);
+    assert_eq!(events[0].cleared, None);
+    assert_eq!(events[0].actual_value, "65.0");
+    assert_eq!(events[0].variable_monitoring_id, Some(id));
+    assert_eq!(
+      events[0].event_notification_type,
+      EventNotificationEnumType::CustomMonitor
+    );
+
+    // Still above the threshold, nothing new to report
+    assert!(sample(&mut monitoring, 70.0).is_empty());
+
+    let events = sample(&mut monitoring, 50.0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].cleared, Some(true));
+    assert_eq!(events[0].event_id, 2);
+  }
+
+  #[test]
+  fn delta_reports_changes_from_the_last_reported_value() {
+    let mut monitoring = Monitoring::new(Vec::new());
+    monitoring.set(&monitor("Delta", 5.0, 5)).unwrap();
+
+    assert!(sample(&mut monitoring, 20.0).is_empty());
+    assert!(sample(&mut monitoring, 24.0).is_empty());
+
+    let events = sample(&mut monitoring, 25.0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].trigger, EventTriggerEnumType::Delta);
+
+    // Measured from 25.0 now
+    assert!(sample(&mut monitoring, 21.0).is_empty());
+    assert_eq!(sample(&mut monitoring, 19.0).len(), 1);
+  }
+
+  #[test]
+  fn monitors_less_severe_than_the_level_are_not_reported() {
+    let mut monitoring = Monitoring::new(Vec::new());
+    monitoring.set(&monitor("UpperThreshold", 60.0, 7)).unwrap();
+    monitoring.level = 5;
+
+    assert!(sample(&mut monitoring, 65.0).is_empty());
+  }
+
+  #[test]
+  fn cleared_monitor_reports_nothing() {
+    let mut monitoring = Monitoring::new(Vec::new());
+    let id = monitoring.set(&monitor("UpperThreshold", 60.0, 5)).unwrap();
+
+    let result = monitoring.clear(id);
+    assert_eq!(result.status, ClearMonitoringStatusEnumType::Accepted);
+    assert!(monitoring.is_empty());
+    assert!(sample(&mut monitoring, 65.0).is_empty());
+
+    let result = monitoring.clear(id);
+    assert_eq!(result.status, ClearMonitoringStatusEnumType::NotFound);
+  }
+
+  #[test]
+  fn factory_default_base_restores_only_the_preconfigured_monitors() {
+    let (component, variable) = temperature();
+    let preconfigured = Monitor::preconfigured(
+      1,
+      component,
+      variable,
+      MonitorEnumType::UpperThreshold,
+      Decimal::from(60),
+      5,
+    );
+    let mut monitoring = Monitoring::new(vec![preconfigured]);
+    monitoring.set(&monitor("Delta", 5.0, 5)).unwrap();
+    monitoring.clear(1);
+
+    monitoring.set_base(&MonitoringBaseEnumType::FactoryDefault);
+    let events = sample(&mut monitoring, 65.0);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].variable_monitoring_id, Some(1));
+    assert_eq!(
+      events[0].event_notification_type,
+      EventNotificationEnumType::PreconfiguredMonitor
+    );
+
+    monitoring.set_base(&MonitoringBaseEnumType::HardWiredOnly);
+    assert!(monitoring.is_empty());
+  }
+}
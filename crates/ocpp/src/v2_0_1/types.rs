@@ -1,7 +1,34 @@
 use std::str::FromStr;
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+use rust_ocpp::{
+  v1_6::types::{MeterValue, SampledValue},
+  v2_0_1::{
+    datatypes::{
+      certificate_hash_data_type::CertificateHashDataType, meter_value_type::MeterValueType,
+      ocsp_request_data_type::OCSPRequestDataType, sampled_value_type::SampledValueType,
+      unit_of_measure_type::UnitOfMeasureType,
+    },
+    enumerations::{
+      authorization_status_enum_type::AuthorizationStatusEnumType,
+      customer_information_status_enum_type::CustomerInformationStatusEnumType,
+      get_certificate_id_use_enum_type::GetCertificateIdUseEnumType,
+      hash_algorithm_enum_type::HashAlgorithmEnumType,
+      install_certificate_use_enum_type::InstallCertificateUseEnumType,
+      log_status_enum_type::LogStatusEnumType, reason_enum_type::ReasonEnumType,
+      trigger_reason_enum_type::TriggerReasonEnumType,
+      upload_log_status_enum_type::UploadLogStatusEnumType,
+    },
+  },
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::types::{
+  AuthorizationStatus, CommonCertificateHashData, CommonCertificateUse,
+  CommonCustomerInformationStatus, CommonLogStatus, CommonOcspRequestData, CommonStopReason,
+  CommonUploadLogStatus,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum OcppAction {
@@ -25,7 +52,7 @@ pub enum OcppAction {
   GetCertificateStatus,
   GetChargingProfile,
   GetCompositeSchedule,
-  GetDisplayMessage,
+  GetDisplayMessages,
   GetInstalledCertificateIds,
   GetLocalListVersion,
   GetLog,
@@ -105,7 +132,7 @@ impl FromStr for OcppAction {
       "GetCertificateStatus" => Ok(GetCertificateStatus),
       "GetChargingProfile" => Ok(GetChargingProfile),
       "GetCompositeSchedule" => Ok(GetCompositeSchedule),
-      "GetDisplayMessage" => Ok(GetDisplayMessage),
+      "GetDisplayMessages" => Ok(GetDisplayMessages),
       "GetInstalledCertificateIds" => Ok(GetInstalledCertificateIds),
       "GetLocalListVersion" => Ok(GetLocalListVersion),
       "GetLog" => Ok(GetLog),
@@ -155,3 +182,191 @@ impl FromStr for OcppAction {
     }
   }
 }
+
+impl From<AuthorizationStatusEnumType> for AuthorizationStatus {
+  fn from(value: AuthorizationStatusEnumType) -> Self {
+    match value {
+      AuthorizationStatusEnumType::Accepted => AuthorizationStatus::Accepted,
+      AuthorizationStatusEnumType::Blocked => AuthorizationStatus::Blocked,
+      AuthorizationStatusEnumType::ConcurrentTx => AuthorizationStatus::ConcurrentTx,
+      AuthorizationStatusEnumType::Expired => AuthorizationStatus::Expired,
+      // The remaining statuses have no 1.6 counterpart, the tag is not usable here and now
+      AuthorizationStatusEnumType::Invalid
+      | AuthorizationStatusEnumType::NoCredit
+      | AuthorizationStatusEnumType::NotAllowedTypeEVSE
+      | AuthorizationStatusEnumType::NotAtThisLocation
+      | AuthorizationStatusEnumType::NotAtThisTime
+      | AuthorizationStatusEnumType::Unknown => AuthorizationStatus::Invalid,
+    }
+  }
+}
+
+impl From<InstallCertificateUseEnumType> for CommonCertificateUse {
+  fn from(value: InstallCertificateUseEnumType) -> Self {
+    match value {
+      InstallCertificateUseEnumType::V2GRootCertificate => CommonCertificateUse::V2GRoot,
+      InstallCertificateUseEnumType::MORootCertificate => CommonCertificateUse::MORoot,
+      InstallCertificateUseEnumType::CSMSRootCertificate => CommonCertificateUse::CentralSystemRoot,
+      InstallCertificateUseEnumType::ManufacturerRootCertificate => {
+        CommonCertificateUse::ManufacturerRoot
+      }
+    }
+  }
+}
+
+impl From<GetCertificateIdUseEnumType> for CommonCertificateUse {
+  fn from(value: GetCertificateIdUseEnumType) -> Self {
+    match value {
+      GetCertificateIdUseEnumType::V2GRootCertificate => CommonCertificateUse::V2GRoot,
+      GetCertificateIdUseEnumType::MORootCertificate => CommonCertificateUse::MORoot,
+      GetCertificateIdUseEnumType::CSMSRootCertificate => CommonCertificateUse::CentralSystemRoot,
+      GetCertificateIdUseEnumType::V2GCertificateChain => CommonCertificateUse::V2GCertificateChain,
+      GetCertificateIdUseEnumType::ManufacturerRootCertificate => {
+        CommonCertificateUse::ManufacturerRoot
+      }
+    }
+  }
+}
+
+impl From<CommonCertificateUse> for GetCertificateIdUseEnumType {
+  fn from(value: CommonCertificateUse) -> Self {
+    match value {
+      CommonCertificateUse::CentralSystemRoot => GetCertificateIdUseEnumType::CSMSRootCertificate,
+      CommonCertificateUse::ManufacturerRoot => {
+        GetCertificateIdUseEnumType::ManufacturerRootCertificate
+      }
+      CommonCertificateUse::V2GRoot => GetCertificateIdUseEnumType::V2GRootCertificate,
+      CommonCertificateUse::MORoot => GetCertificateIdUseEnumType::MORootCertificate,
+      CommonCertificateUse::V2GCertificateChain => GetCertificateIdUseEnumType::V2GCertificateChain,
+    }
+  }
+}
+
+impl From<CertificateHashDataType> for CommonCertificateHashData {
+  fn from(value: CertificateHashDataType) -> Self {
+    Self {
+      issuer_name_hash: value.issuer_name_hash,
+      issuer_key_hash: value.issuer_key_hash,
+      serial_number: value.serial_number,
+    }
+  }
+}
+
+impl From<CommonCertificateHashData> for CertificateHashDataType {
+  fn from(value: CommonCertificateHashData) -> Self {
+    Self {
+      hash_algorithm: HashAlgorithmEnumType::SHA256,
+      issuer_name_hash: value.issuer_name_hash,
+      issuer_key_hash: value.issuer_key_hash,
+      serial_number: value.serial_number,
+    }
+  }
+}
+
+impl From<CommonOcspRequestData> for OCSPRequestDataType {
+  fn from(value: CommonOcspRequestData) -> Self {
+    Self {
+      hash_algorithm: HashAlgorithmEnumType::SHA256,
+      issuer_name_hash: value.hash_data.issuer_name_hash,
+      issuer_key_hash: value.hash_data.issuer_key_hash,
+      serial_number: value.hash_data.serial_number,
+      responder_url: value.responder_url,
+    }
+  }
+}
+
+impl From<CommonStopReason> for ReasonEnumType {
+  fn from(value: CommonStopReason) -> Self {
+    match value {
+      CommonStopReason::Local => ReasonEnumType::Local,
+      CommonStopReason::Remote => ReasonEnumType::Remote,
+      CommonStopReason::EVDisconnected => ReasonEnumType::EVDisconnected,
+      CommonStopReason::Other => ReasonEnumType::Other,
+      CommonStopReason::HardReset | CommonStopReason::SoftReset => ReasonEnumType::ImmediateReset,
+      CommonStopReason::Reboot => ReasonEnumType::Reboot,
+    }
+  }
+}
+
+impl From<CommonStopReason> for TriggerReasonEnumType {
+  fn from(value: CommonStopReason) -> Self {
+    match value {
+      CommonStopReason::Local => TriggerReasonEnumType::StopAuthorized,
+      CommonStopReason::Remote => TriggerReasonEnumType::RemoteStop,
+      CommonStopReason::EVDisconnected => TriggerReasonEnumType::EVCommunicationLost,
+      CommonStopReason::Other | CommonStopReason::Reboot => {
+        TriggerReasonEnumType::AbnormalCondition
+      }
+      CommonStopReason::HardReset | CommonStopReason::SoftReset => {
+        TriggerReasonEnumType::ResetCommand
+      }
+    }
+  }
+}
+
+/// Measurands, contexts, phases, locations and units are spelled the same in 1.6 and 2.0.1
+fn convert<T: Serialize, U: DeserializeOwned>(value: T) -> Option<U> {
+  serde_json::to_value(value)
+    .and_then(serde_json::from_value)
+    .ok()
+}
+
+/// Converts a sampled value of the meter mock data into its 2.0.1 form
+pub fn sampled_value_from_v16(value: SampledValue) -> Option<SampledValueType> {
+  Some(SampledValueType {
+    value: Decimal::from_str(&value.value).ok()?,
+    context: value.context.and_then(convert),
+    measurand: value.measurand.and_then(convert),
+    phase: value.phase.and_then(convert),
+    location: value.location.and_then(convert),
+    signed_meter_value: None,
+    unit_of_measure: value.unit.and_then(convert).map(|unit| UnitOfMeasureType {
+      unit: Some(unit),
+      multiplier: None,
+    }),
+  })
+}
+
+pub fn meter_value_from_v16(value: MeterValue) -> MeterValueType {
+  MeterValueType {
+    timestamp: value.timestamp,
+    sampled_value: value
+      .sampled_value
+      .into_iter()
+      .filter_map(sampled_value_from_v16)
+      .collect(),
+  }
+}
+
+impl From<CommonLogStatus> for LogStatusEnumType {
+  fn from(value: CommonLogStatus) -> Self {
+    match value {
+      CommonLogStatus::Accepted => LogStatusEnumType::Accepted,
+      CommonLogStatus::Rejected => LogStatusEnumType::Rejected,
+      CommonLogStatus::AcceptedCanceled => LogStatusEnumType::AcceptedCanceled,
+    }
+  }
+}
+
+impl From<CommonUploadLogStatus> for UploadLogStatusEnumType {
+  fn from(value: CommonUploadLogStatus) -> Self {
+    match value {
+      CommonUploadLogStatus::Uploading => UploadLogStatusEnumType::Uploading,
+      CommonUploadLogStatus::Uploaded => UploadLogStatusEnumType::Uploaded,
+      CommonUploadLogStatus::UploadFailure => UploadLogStatusEnumType::UploadFailure,
+      CommonUploadLogStatus::BadMessage => UploadLogStatusEnumType::BadMessage,
+      CommonUploadLogStatus::PermissionDenied => UploadLogStatusEnumType::PermissionDenied,
+      CommonUploadLogStatus::AcceptedCanceled => UploadLogStatusEnumType::AcceptedCanceled,
+    }
+  }
+}
+
+impl From<CommonCustomerInformationStatus> for CustomerInformationStatusEnumType {
+  fn from(value: CommonCustomerInformationStatus) -> Self {
+    match value {
+      CommonCustomerInformationStatus::Accepted => CustomerInformationStatusEnumType::Accepted,
+      CommonCustomerInformationStatus::Rejected => CustomerInformationStatusEnumType::Rejected,
+      CommonCustomerInformationStatus::Invalid => CustomerInformationStatusEnumType::Invalid,
+    }
+  }
+}
@@ -93,8 +93,8 @@ impl MessageGenerator for V21MessageGenerator {
     )
   }
 
-  async fn heartbeat(&self) -> Value {
-    FrameBuilder::build_call(OcppAction::Heartbeat, HeartbeatRequest {})
+  async fn heartbeat(&self) -> String {
+    FrameBuilder::build_call(OcppAction::Heartbeat, HeartbeatRequest {}).to_string()
   }
 
   async fn authorize(&self) -> Value {
@@ -1,4 +1,4 @@
-#![cfg(feature = "ocpp2_0_1")]
+#![cfg(feature = "ocpp2_1")]
 #![allow(unused)]
 use crate::{
   handler::{MessageFrameType, MessageHandler},